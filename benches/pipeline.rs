@@ -0,0 +1,93 @@
+//! Stages of the receive pipeline on synthetic data: framing the bytes read
+//! into lines, decoding them and laying out the output pane. `cargo bench`
+//! compares each run with the one before, so a regression shows up as a
+//! change in throughput. `--bench-internal` measures them together.
+
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use ratatui::{
+    Terminal,
+    backend::TestBackend,
+    widgets::{Block, Borders, Paragraph, Wrap},
+};
+use serial_monitor::{
+    bench,
+    framing::{Decoder, Framer, Framing, LengthPrefix},
+    output,
+    transport::Encoding,
+};
+
+/// Lines in each sample of the framing benches.
+const LINES: u64 = 1000;
+
+/// Line lengths benched, from short status lines to long JSON dumps.
+const LENGTHS: [usize; 3] = [16, 80, 512];
+
+fn framing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("framing");
+    for length in LENGTHS {
+        let data = bench::synthetic(LINES, length);
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        // Reads arrive in pieces of a USB packet or so
+        group.bench_with_input(BenchmarkId::new("lines", length), &data, |b, data| {
+            b.iter(|| {
+                let mut framer = Framer::new(Framing::Lines, LengthPrefix::Varint);
+                let mut frames = Vec::new();
+                for chunk in data.chunks(64) {
+                    framer.push(black_box(chunk), &mut frames);
+                }
+                frames
+            })
+        });
+    }
+    group.finish();
+}
+
+fn decoding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decoding");
+    for length in LENGTHS {
+        let data = bench::synthetic(LINES, length);
+        let mut framer = Framer::new(Framing::Lines, LengthPrefix::Varint);
+        let mut frames = Vec::new();
+        framer.push(&data, &mut frames);
+        let decoder = Decoder::Text(Encoding::Utf8);
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::new("utf8", length), &frames, |b, frames| {
+            b.iter(|| frames.iter().map(|frame| decoder.decode(black_box(frame))).collect::<Vec<_>>())
+        });
+    }
+    group.finish();
+}
+
+fn rendering(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rendering");
+    for length in LENGTHS {
+        let entries: Vec<_> = (0..10_000)
+            .map(|seq| bench::entry(bench::line(seq, std::time::Duration::from_micros(seq), length)))
+            .collect();
+        let view = bench::view(158, 46);
+        group.bench_with_input(BenchmarkId::new("visible_lines", length), &entries, |b, entries| {
+            b.iter(|| output::visible_lines(black_box(entries), &view, view.height as usize, 0))
+        });
+        let mut terminal = Terminal::new(TestBackend::new(160, 48)).expect("test backend");
+        group.bench_with_input(BenchmarkId::new("frame", length), &entries, |b, entries| {
+            b.iter(|| {
+                terminal
+                    .draw(|f| {
+                        let (visible, skip) = output::visible_lines(entries, &view, view.height as usize, 0);
+                        let pane = Paragraph::new(visible)
+                            .block(Block::default().title("Monitor").borders(Borders::ALL))
+                            .wrap(Wrap { trim: false })
+                            .scroll((skip, 0));
+                        f.render_widget(pane, f.area());
+                    })
+                    .map(|_| ())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, framing, decoding, rendering);
+criterion_main!(benches);
@@ -0,0 +1,214 @@
+//! Authentication and TLS for the network-facing servers.
+//!
+//! The RFC 2217 server (`--rfc2217-server` and `bridge`), the HTTP API and
+//! the gRPC service share one [`Access`]. With `--tls-cert` and `--tls-key`
+//! they only accept TLS connections; with `--auth-token` clients must prove
+//! they know the token: as `Authorization: Bearer TOKEN` over HTTP and gRPC,
+//! and over RFC 2217 as an `AUTH TOKEN` line sent before the Telnet
+//! negotiation, e.g. `(echo "AUTH $TOKEN"; cat) | socat - OPENSSL:host:2217`.
+//!
+//! The monitor's own RFC 2217 client sends `--remote-token` that way and
+//! speaks TLS to `rfc2217s://` ports, trusting the certificates in
+//! `--remote-ca`.
+
+use std::{io, sync::{Arc, OnceLock}, time::Duration};
+
+use anyhow::{Context, Result, anyhow, bail};
+use rustls_pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    time,
+};
+use tokio_rustls::{
+    TlsAcceptor, TlsConnector,
+    rustls::{ClientConfig, RootCertStore, ServerConfig, crypto::ring},
+};
+
+/// Longest wait for a client's TLS handshake or `AUTH` line.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest `AUTH` line accepted.
+const MAX_LOGIN: usize = 1024;
+
+/// A connection, with or without TLS.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// Reads a secret option: the value itself, or a file holding it so it
+/// doesn't show up in the process list.
+pub fn load_secret(value: &str) -> Result<String> {
+    let secret = match std::fs::metadata(value) {
+        Ok(meta) if meta.is_file() => std::fs::read_to_string(value)
+            .with_context(|| format!("Cannot read the token file {value}"))?
+            .trim()
+            .to_string(),
+        _ => value.to_string(),
+    };
+    if secret.is_empty() {
+        bail!("The token is empty");
+    }
+    Ok(secret)
+}
+
+/// Compares without stopping at the first difference, so response times
+/// don't reveal how much of a guess was right.
+fn same_token(given: &str, expected: &str) -> bool {
+    given.len() == expected.len() && given.bytes().zip(expected.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn tls_config() -> Result<tokio_rustls::rustls::ConfigBuilder<ServerConfig, tokio_rustls::rustls::WantsVerifier>> {
+    Ok(ServerConfig::builder_with_provider(Arc::new(ring::default_provider())).with_safe_default_protocol_versions()?)
+}
+
+/// Who may connect to the servers, and how.
+#[derive(Default)]
+pub struct Access {
+    token: Option<String>,
+    tls: Option<TlsAcceptor>,
+    /// PEM certificate chain and key, for the gRPC server's own TLS setup.
+    identity: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Access {
+    pub fn load(token: Option<&str>, cert: Option<&str>, key: Option<&str>) -> Result<Self> {
+        let token = token.map(load_secret).transpose()?;
+        let (tls, identity) = match (cert, key) {
+            (Some(cert), Some(key)) => {
+                let cert_pem = std::fs::read(cert).with_context(|| format!("Cannot read the TLS certificate {cert}"))?;
+                let key_pem = std::fs::read(key).with_context(|| format!("Cannot read the TLS key {key}"))?;
+                let chain = CertificateDer::pem_slice_iter(&cert_pem)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| anyhow!("Invalid TLS certificate {cert}: {e}"))?;
+                let private = PrivateKeyDer::from_pem_slice(&key_pem).map_err(|e| anyhow!("Invalid TLS key {key}: {e}"))?;
+                let config = tls_config()?
+                    .with_no_client_auth()
+                    .with_single_cert(chain, private)
+                    .with_context(|| format!("{cert} and {key} don't make a usable TLS identity"))?;
+                (Some(TlsAcceptor::from(Arc::new(config))), Some((cert_pem, key_pem)))
+            }
+            _ => (None, None),
+        };
+        Ok(Self { token, tls, identity })
+    }
+
+    pub fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Whether an HTTP `Authorization` header value grants access.
+    pub fn authorized(&self, header: Option<&str>) -> bool {
+        match &self.token {
+            None => true,
+            Some(token) => header
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|given| same_token(given.trim(), token)),
+        }
+    }
+
+    /// The TLS certificate chain and key in PEM, if TLS is enabled.
+    pub fn identity(&self) -> Option<(&[u8], &[u8])> {
+        self.identity.as_ref().map(|(cert, key)| (cert.as_slice(), key.as_slice()))
+    }
+
+    /// Completes the TLS handshake, if TLS is enabled.
+    pub async fn accept(&self, tcp: TcpStream) -> io::Result<Box<dyn Stream>> {
+        match &self.tls {
+            Some(acceptor) => match time::timeout(LOGIN_TIMEOUT, acceptor.accept(tcp)).await {
+                Ok(tls) => Ok(Box::new(tls?)),
+                Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out")),
+            },
+            None => Ok(Box::new(tcp)),
+        }
+    }
+
+    /// Checks the `AUTH TOKEN` line an RFC 2217 client sends first, if a
+    /// token is required. Reads no further than the line, so the Telnet
+    /// negotiation that follows is left for the caller.
+    pub async fn login(&self, stream: &mut (impl AsyncRead + AsyncWrite + Unpin)) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        let read_line = async {
+            let mut line = Vec::new();
+            let mut byte = [0];
+            while line.len() < MAX_LOGIN {
+                stream.read_exact(&mut byte).await?;
+                if byte[0] == b'\n' {
+                    return Ok(line);
+                }
+                line.push(byte[0]);
+            }
+            Err(io::Error::new(io::ErrorKind::InvalidData, "AUTH line too long"))
+        };
+        let granted = match time::timeout(LOGIN_TIMEOUT, read_line).await {
+            Ok(Ok(line)) => String::from_utf8_lossy(&line)
+                .trim_end()
+                .strip_prefix("AUTH ")
+                .is_some_and(|given| same_token(given.trim(), token)),
+            _ => false,
+        };
+        if !granted {
+            let _ = stream.write_all(b"Authentication failed\r\n").await;
+            let _ = stream.shutdown().await;
+        }
+        granted
+    }
+}
+
+/// How the RFC 2217 client authenticates to servers.
+#[derive(Default)]
+pub struct Credentials {
+    token: Option<String>,
+    tls: Option<TlsConnector>,
+}
+
+static CREDENTIALS: OnceLock<Credentials> = OnceLock::new();
+
+/// Sets the token and trusted certificates used for remote ports.
+pub fn set_credentials(token: Option<&str>, ca: Option<&str>) -> Result<()> {
+    let token = token.map(load_secret).transpose()?;
+    let tls = match ca {
+        Some(ca) => {
+            let pem = std::fs::read(ca).with_context(|| format!("Cannot read the CA certificates {ca}"))?;
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_slice_iter(&pem) {
+                roots
+                    .add(cert.map_err(|e| anyhow!("Invalid certificate in {ca}: {e}"))?)
+                    .map_err(|e| anyhow!("Unusable certificate in {ca}: {e}"))?;
+            }
+            let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            Some(TlsConnector::from(Arc::new(config)))
+        }
+        None => None,
+    };
+    let _ = CREDENTIALS.set(Credentials { token, tls });
+    Ok(())
+}
+
+/// Connects to `addr` (`host:port`), over TLS if `tls`, and sends the
+/// `AUTH` line if a token was set.
+pub async fn connect(addr: &str, tls: bool) -> io::Result<Box<dyn Stream>> {
+    let credentials = CREDENTIALS.get_or_init(Credentials::default);
+    let tcp = TcpStream::connect(addr).await?;
+    tcp.set_nodelay(true)?;
+    let mut stream: Box<dyn Stream> = match (tls, &credentials.tls) {
+        (false, _) => Box::new(tcp),
+        (true, Some(connector)) => {
+            let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host).trim_matches(['[', ']']);
+            let name = ServerName::try_from(host.to_string())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{host}: {e}")))?;
+            Box::new(connector.connect(name, tcp).await?)
+        }
+        (true, None) => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "rfc2217s:// ports need --remote-ca"));
+        }
+    };
+    if let Some(token) = &credentials.token {
+        stream.write_all(format!("AUTH {token}\r\n").as_bytes()).await?;
+    }
+    Ok(stream)
+}
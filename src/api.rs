@@ -0,0 +1,305 @@
+//! HTTP API for scripts driving a live session.
+//!
+//! `GET /lines?since=SEQ` returns the received and sent lines recorded after
+//! sequence number `SEQ` as JSON, and `POST /send?port=N` sends each line of
+//! the request body to the port as if it was typed, while the TUI keeps
+//! running. Every request needs `Authorization: Bearer TOKEN`, and TLS is
+//! used if configured (see [`crate::access`]). One request is served per
+//! connection.
+//!
+//! The [`Journal`] of lines and the [`SendRequest`]s are shared with the gRPC
+//! service in [`crate::grpc`].
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::{broadcast, mpsc, oneshot},
+    time,
+};
+
+use crate::access::{Access, Stream};
+
+/// Lines kept for clients polling with `since`.
+const JOURNAL_LINES: usize = 10_000;
+/// Largest request accepted, headers and body.
+const MAX_REQUEST: usize = 64 * 1024;
+/// Longest wait for a client to send its whole request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Lines buffered for each streaming subscriber before it lags.
+const SUBSCRIBER_LINES: usize = 1024;
+
+#[derive(Clone)]
+pub struct Line {
+    pub seq: u64,
+    pub time: DateTime<Local>,
+    pub port: usize,
+    pub sent: bool,
+    pub text: String,
+}
+
+/// Recent lines of the session, numbered in the order they were recorded.
+pub struct Journal {
+    entries: VecDeque<Line>,
+    next: u64,
+    live: broadcast::Sender<Line>,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            next: 0,
+            live: broadcast::channel(SUBSCRIBER_LINES).0,
+        }
+    }
+}
+
+impl Journal {
+    pub fn record(&mut self, time: DateTime<Local>, port: usize, sent: bool, text: &str) {
+        self.next += 1;
+        let line = Line {
+            seq: self.next,
+            time,
+            port,
+            sent,
+            text: text.to_string(),
+        };
+        let _ = self.live.send(line.clone());
+        self.entries.push_back(line);
+        if self.entries.len() > JOURNAL_LINES {
+            self.entries.pop_front();
+        }
+    }
+
+    /// The kept lines after `seq` and the lines recorded from now on, with
+    /// nothing missed in between.
+    pub fn subscribe(&self, seq: u64) -> (Vec<Line>, broadcast::Receiver<Line>) {
+        let start = self.entries.partition_point(|line| line.seq <= seq);
+        (self.entries.range(start..).cloned().collect(), self.live.subscribe())
+    }
+
+    fn since(&self, seq: u64, ports: &[String]) -> Value {
+        let start = self.entries.partition_point(|line| line.seq <= seq);
+        let lines: Vec<Value> = self
+            .entries
+            .range(start..)
+            .map(|line| {
+                json!({
+                    "seq": line.seq,
+                    "time": line.time.to_rfc3339(),
+                    "port": ports.get(line.port),
+                    "direction": if line.sent { "tx" } else { "rx" },
+                    "text": line.text,
+                })
+            })
+            .collect();
+        // Lines dropped before the client asked for them are counted, not lost silently
+        let first = self.entries.front().map_or(self.next + 1, |line| line.seq);
+        json!({
+            "lines": lines,
+            "next": self.next,
+            "missed": first.saturating_sub(seq + 1),
+        })
+    }
+}
+
+/// Lines to send, handed to the monitor loop.
+pub struct SendRequest {
+    /// Index of the port, or the active one.
+    pub port: Option<usize>,
+    pub text: String,
+    reply: oneshot::Sender<Result<(), String>>,
+}
+
+impl SendRequest {
+    /// A request and the receiver of the monitor's answer.
+    pub fn new(port: Option<usize>, text: String) -> (Self, oneshot::Receiver<Result<(), String>>) {
+        let (reply, result) = oneshot::channel();
+        (Self { port, text, reply }, result)
+    }
+
+    pub fn respond(self, result: Result<(), String>) {
+        let _ = self.reply.send(result);
+    }
+}
+
+struct Shared {
+    access: Arc<Access>,
+    journal: Arc<Mutex<Journal>>,
+    ports: Vec<String>,
+    requests: mpsc::UnboundedSender<SendRequest>,
+}
+
+/// Serves the API on `listener` for as long as the monitor runs.
+pub fn spawn(
+    listener: TcpListener,
+    access: Arc<Access>,
+    journal: Arc<Mutex<Journal>>,
+    ports: Vec<String>,
+    requests: mpsc::UnboundedSender<SendRequest>,
+) {
+    let shared = Arc::new(Shared {
+        access,
+        journal,
+        ports,
+        requests,
+    });
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let shared = shared.clone();
+            tokio::spawn(async move {
+                if let Ok(stream) = shared.access.accept(stream).await {
+                    serve(stream, shared).await;
+                }
+            });
+        }
+    });
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+async fn serve(mut stream: Box<dyn Stream>, shared: Arc<Shared>) {
+    let (status, body) = match time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Ok(request)) => respond(request, &shared).await,
+        Ok(Err(e)) => (400, json!({ "error": e })),
+        Err(_) => (408, json!({ "error": "the request didn't arrive in time" })),
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        _ => "Service Unavailable",
+    };
+    let body = format!("{body}\n");
+    let response = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn respond(request: Request, shared: &Shared) -> (u16, Value) {
+    if !shared.access.authorized(request.authorization.as_deref()) {
+        return (401, json!({ "error": "missing or wrong bearer token" }));
+    }
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/lines") => {
+            let since = match request.param("since").map(str::parse::<u64>) {
+                None => 0,
+                Some(Ok(since)) => since,
+                Some(Err(_)) => return (400, json!({ "error": "since must be a sequence number" })),
+            };
+            (200, shared.journal.lock().unwrap().since(since, &shared.ports))
+        }
+        ("POST", "/send") => {
+            let port = match request.param("port").map(str::parse::<usize>) {
+                None => None,
+                Some(Ok(port)) => Some(port),
+                Some(Err(_)) => return (400, json!({ "error": "port must be an index" })),
+            };
+            let Ok(text) = String::from_utf8(request.body) else {
+                return (400, json!({ "error": "the body must be UTF-8 text" }));
+            };
+            let mut sent = 0;
+            for line in text.lines().filter(|line| !line.is_empty()) {
+                let (request, result) = SendRequest::new(port, line.to_string());
+                if shared.requests.send(request).is_err() {
+                    return (503, json!({ "error": "the monitor is shutting down" }));
+                }
+                match result.await {
+                    Ok(Ok(())) => sent += 1,
+                    Ok(Err(e)) => return (400, json!({ "error": e, "sent": sent })),
+                    Err(_) => return (503, json!({ "error": "the monitor is shutting down" })),
+                }
+            }
+            (200, json!({ "sent": sent }))
+        }
+        (_, "/lines" | "/send") => (405, json!({ "error": "method not allowed" })),
+        _ => (404, json!({ "error": "unknown path; use GET /lines or POST /send" })),
+    }
+}
+
+async fn read_request(stream: &mut Box<dyn Stream>) -> Result<Request, String> {
+    let mut data = Vec::new();
+    let mut buf = [0; 4096];
+    let header_end = loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        if data.len() > MAX_REQUEST {
+            return Err("request too large".to_string());
+        }
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return Err("incomplete request".to_string()),
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+        }
+    };
+    let head = String::from_utf8_lossy(&data[..header_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut parts = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err("malformed request line".to_string());
+    };
+    let mut authorization = None;
+    let mut length = 0;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "authorization" => authorization = Some(value.trim().to_string()),
+            "content-length" => length = value.trim().parse().map_err(|_| "invalid Content-Length".to_string())?,
+            _ => {}
+        }
+    }
+    if length > MAX_REQUEST {
+        return Err("request too large".to_string());
+    }
+    let mut body = data.split_off(header_end + 4);
+    while body.len() < length {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return Err("incomplete body".to_string()),
+            Ok(n) => body.extend_from_slice(&buf[..n]),
+        }
+    }
+    body.truncate(length);
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        authorization,
+        body,
+    })
+}
@@ -0,0 +1,107 @@
+//! AT command helper: pairs sent commands with the modem's final result code.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use ratatui::style::{Color, Style};
+
+/// Final result codes reporting a failed command.
+const FAILURE_CODES: &[&str] = &["ERROR", "NO CARRIER", "BUSY", "NO ANSWER", "NO DIALTONE"];
+const FAILURE_PREFIXES: &[&str] = &["+CME ERROR", "+CMS ERROR"];
+
+pub enum Outcome {
+    Ok(String),
+    Error(String),
+    Timeout,
+}
+
+pub struct AtResult {
+    pub command: String,
+    pub outcome: Outcome,
+    pub elapsed: Duration,
+}
+
+impl AtResult {
+    pub fn text(&self) -> String {
+        format!("{} {}", self.command, self.outcome_text())
+    }
+
+    /// The result without the command, e.g. `-> OK (12 ms)`.
+    pub fn outcome_text(&self) -> String {
+        let ms = self.elapsed.as_millis();
+        match &self.outcome {
+            Outcome::Ok(code) | Outcome::Error(code) => format!("-> {} ({} ms)", code, ms),
+            Outcome::Timeout => format!("-> TIMEOUT after {} ms", ms),
+        }
+    }
+
+    pub fn style(&self) -> Style {
+        match self.outcome {
+            Outcome::Ok(_) => Style::default().fg(Color::Cyan),
+            Outcome::Error(_) => Style::default().fg(Color::Red),
+            Outcome::Timeout => Style::default().fg(Color::Magenta),
+        }
+    }
+}
+
+pub struct AtTracker {
+    pending: VecDeque<(String, Instant)>,
+    timeout: Duration,
+}
+
+impl AtTracker {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            timeout,
+        }
+    }
+
+    /// Records a sent line if it is an AT command.
+    pub fn sent(&mut self, command: &str, now: Instant) {
+        if command.trim_start().get(..2).is_some_and(|p| p.eq_ignore_ascii_case("AT")) {
+            self.pending.push_back((command.trim().to_string(), now));
+        }
+    }
+
+    /// Matches a received line against the oldest pending command.
+    pub fn received(&mut self, line: &str, now: Instant) -> Option<AtResult> {
+        let line = line.trim();
+        let outcome = if line == "OK" || line.starts_with("CONNECT") {
+            Outcome::Ok(line.to_string())
+        } else if FAILURE_CODES.contains(&line) || FAILURE_PREFIXES.iter().any(|p| line.starts_with(p)) {
+            Outcome::Error(line.to_string())
+        } else {
+            return None;
+        };
+        let (command, sent_at) = self.pending.pop_front()?;
+        Some(AtResult {
+            command,
+            outcome,
+            elapsed: now.duration_since(sent_at),
+        })
+    }
+
+    /// Removes and reports commands that have waited longer than the timeout.
+    pub fn expire(&mut self, now: Instant) -> Vec<AtResult> {
+        let mut expired = Vec::new();
+        while self
+            .pending
+            .front()
+            .is_some_and(|(_, sent_at)| now.duration_since(*sent_at) >= self.timeout)
+        {
+            let Some((command, sent_at)) = self.pending.pop_front() else {
+                break;
+            };
+            let elapsed = now.duration_since(sent_at);
+            expired.push(AtResult {
+                command,
+                outcome: Outcome::Timeout,
+                elapsed,
+            });
+        }
+        expired
+    }
+}
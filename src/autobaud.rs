@@ -0,0 +1,249 @@
+//! Spotting a baud rate mismatch and finding the rate the device uses.
+//!
+//! Read at the wrong baud rate, text turns into bytes that are mostly
+//! control characters and invalid UTF-8, which would otherwise fill the
+//! output pane with replacement characters without saying why. When most
+//! of the last bytes received on a port are unprintable, a banner says the
+//! rate is likely wrong, and Ctrl+S tries the other rates in turn, most
+//! common first, keeping the first at which the device sends readable text.
+
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::{Clear, Paragraph},
+};
+
+use crate::transport::{Encoding, VALID_BAUD_RATES};
+
+/// Bytes judged together.
+const WINDOW: usize = 256;
+
+/// Share of unprintable bytes in a window that hints at a wrong rate.
+const GARBLED: f64 = 0.3;
+
+/// Share of unprintable bytes below which a rate reads as text.
+const READABLE: f64 = 0.05;
+
+/// Rates in the order they are tried.
+const COMMON: [u32; 9] = [115200, 9600, 57600, 38400, 19200, 4800, 2400, 1200, 300];
+
+/// How long each rate is listened to.
+const TRIAL: Duration = Duration::from_secs(1);
+
+/// Time after a change of rate whose bytes are ignored, as they may have
+/// been read at the previous one.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Bytes received at a rate before it is kept early.
+const ENOUGH: usize = 64;
+
+/// Unprintable bytes in `data`: control characters other than whitespace
+/// and escape sequences, and bytes that aren't valid UTF-8.
+fn unprintable(data: &[u8], encoding: Encoding) -> usize {
+    let control = |c: char| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x1b');
+    match encoding {
+        Encoding::Latin1 => data.iter().filter(|&&b| control(char::from(b))).count(),
+        Encoding::Utf8 => data
+            .utf8_chunks()
+            .map(|chunk| chunk.valid().chars().filter(|&c| control(c)).count() + chunk.invalid().len())
+            .sum(),
+    }
+}
+
+#[derive(Default)]
+struct Window {
+    bytes: usize,
+    unprintable: usize,
+    garbled: bool,
+}
+
+impl Window {
+    fn share(&self) -> f64 {
+        self.unprintable as f64 / self.bytes.max(1) as f64
+    }
+}
+
+/// A search for the rate of one port.
+struct Search {
+    port: usize,
+    /// The rate before the search, restored if none reads as text.
+    original: u32,
+    rate: u32,
+    /// The rates still to try.
+    rates: Vec<u32>,
+    since: Instant,
+    window: Window,
+}
+
+/// What the monitor does next for a search.
+pub enum Step {
+    /// Switch the port to this rate.
+    Try(usize, u32),
+    /// The port reads as text at this rate.
+    Found(usize, u32),
+    /// No rate read as text; the port is switched back to this one.
+    Failed(usize, u32),
+}
+
+/// Mismatch detection and rate search for the ports of the monitor.
+pub struct AutoBaud {
+    encoding: Encoding,
+    /// The rate each port is set to.
+    rates: Vec<u32>,
+    windows: Vec<Window>,
+    search: Option<Search>,
+}
+
+impl AutoBaud {
+    /// Watches ports opened at `rates`, whose text is decoded with
+    /// `encoding`.
+    pub fn new(rates: Vec<u32>, encoding: Encoding) -> Self {
+        Self {
+            windows: rates.iter().map(|_| Window::default()).collect(),
+            rates,
+            encoding,
+            search: None,
+        }
+    }
+
+    /// Counts the bytes received on `port`, returning a hint when it starts
+    /// to look garbled; ports are called by their `names`.
+    pub fn record(&mut self, port: usize, data: &[u8], names: &[String], now: Instant) -> Option<String> {
+        let unprintable = unprintable(data, self.encoding);
+        if let Some(search) = &mut self.search {
+            if search.port == port && now.duration_since(search.since) >= SETTLE {
+                search.window.bytes += data.len();
+                search.window.unprintable += unprintable;
+            }
+            return None;
+        }
+        let window = self.windows.get_mut(port)?;
+        window.bytes += data.len();
+        window.unprintable += unprintable;
+        if window.bytes < WINDOW {
+            return None;
+        }
+        let was = window.garbled;
+        window.garbled = match window.share() {
+            share if share >= GARBLED => true,
+            share if share <= READABLE => false,
+            _ => was,
+        };
+        let share = window.share();
+        window.bytes = 0;
+        window.unprintable = 0;
+        (window.garbled && !was).then(|| {
+            format!(
+                "{}: {:.0}% of the bytes received are unprintable; {} baud is likely the wrong rate, Ctrl+S tries the others",
+                names[port],
+                share * 100.0,
+                self.rates[port]
+            )
+        })
+    }
+
+    /// Starts searching the rate of the garbled port, or else of `active`,
+    /// returning the first rate to switch it to.
+    pub fn start(&mut self, active: usize, names: &[String], now: Instant) -> Result<Step, String> {
+        if let Some(search) = &self.search {
+            return Err(format!("Already trying the baud rates of {}", names[search.port]));
+        }
+        let port = self.windows.iter().position(|window| window.garbled).unwrap_or(active);
+        let original = self.rates[port];
+        let mut rates: Vec<u32> = COMMON
+            .into_iter()
+            .filter(|rate| *rate != original && VALID_BAUD_RATES.contains(rate))
+            .rev()
+            .collect();
+        let Some(rate) = rates.pop() else {
+            return Err("No other baud rate to try".to_string());
+        };
+        self.search = Some(Search { port, original, rate, rates, since: now, window: Window::default() });
+        Ok(Step::Try(port, rate))
+    }
+
+    /// The rate `port` is set to.
+    pub fn rate(&self, port: usize) -> u32 {
+        self.rates[port]
+    }
+
+    /// Starts over on `port`, opened again at `rate`.
+    pub fn reopened(&mut self, port: usize, rate: u32) {
+        self.rates[port] = rate;
+        self.windows[port] = Window::default();
+        if self.search.as_ref().is_some_and(|search| search.port == port) {
+            self.search = None;
+        }
+    }
+
+    /// Whether a rate is being tried.
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// Moves the search on once the current rate was listened to long
+    /// enough.
+    pub fn poll(&mut self, now: Instant) -> Option<Step> {
+        let search = self.search.as_mut()?;
+        let elapsed = now.duration_since(search.since);
+        let window = &search.window;
+        let readable = window.bytes > 0 && window.share() <= READABLE;
+        if readable && (window.bytes >= ENOUGH || elapsed >= TRIAL) {
+            let found = Step::Found(search.port, search.rate);
+            return Some(self.finish(found));
+        }
+        if elapsed < TRIAL {
+            return None;
+        }
+        match search.rates.pop() {
+            Some(rate) => {
+                search.rate = rate;
+                search.since = now;
+                search.window = Window::default();
+                Some(Step::Try(search.port, rate))
+            }
+            None => {
+                let failed = Step::Failed(search.port, search.original);
+                Some(self.finish(failed))
+            }
+        }
+    }
+
+    /// Ends the search, the port being left at the rate of `step`.
+    fn finish(&mut self, step: Step) -> Step {
+        if let Step::Found(port, rate) | Step::Failed(port, rate) = step {
+            self.rates[port] = rate;
+            self.windows[port] = Window::default();
+            self.search = None;
+        }
+        step
+    }
+
+    /// The text of the banner shown, if any.
+    fn banner(&self, names: &[String]) -> Option<String> {
+        if let Some(search) = &self.search {
+            return Some(format!(" Trying {} baud on {}... ", search.rate, names[search.port]));
+        }
+        let port = self.windows.iter().position(|window| window.garbled)?;
+        Some(format!(
+            " {} at {} baud receives mostly unprintable bytes - wrong baud rate? Ctrl+S tries the others ",
+            names[port], self.rates[port]
+        ))
+    }
+
+    /// Draws the banner along the bottom of `area`, inside its border.
+    pub fn render(&self, f: &mut Frame, area: Rect, names: &[String]) {
+        let Some(text) = self.banner(names) else {
+            return;
+        };
+        if area.height < 3 || area.width < 3 {
+            return;
+        }
+        let rect = Rect::new(area.x + 1, area.bottom() - 2, area.width - 2, 1);
+        f.render_widget(Clear, rect);
+        f.render_widget(Paragraph::new(text).style(Style::default().fg(Color::Black).bg(Color::Yellow)), rect);
+    }
+}
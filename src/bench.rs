@@ -0,0 +1,233 @@
+//! `--bench-internal`: measures the receive pipeline on synthetic data.
+//!
+//! A simulated device writes numbered log lines at `--bench-rate` (as fast
+//! as the port task takes them by default) for `--bench-seconds`, and they
+//! go through the same port task, framer, decoder, scrollback and renderer
+//! as in the monitor, drawn to an off-screen terminal every time the loop
+//! has something new. The report gives the throughput, how long lines took
+//! from the device to the screen and how long drawing took, so a change to
+//! the reader or renderer can be measured rather than guessed. The criterion
+//! benches in `benches/` time the same stages in isolation.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow, bail};
+use ratatui::{
+    Terminal,
+    backend::TestBackend,
+    widgets::{Block, Borders, Paragraph, Wrap},
+};
+use tokio::{io::AsyncWriteExt, sync::mpsc, time};
+
+use crate::{
+    events::{PortEvent, SerialEvent},
+    framing::{Decoder, Framer, Framing, LengthPrefix},
+    level::{self, Level},
+    output::{self, OutputLine, Pane, View},
+    port::{self, Restart, RestartPolicy},
+    scrollback::{self, Budget},
+    simulator,
+    transport::{Encoding, SerialSettings},
+};
+
+/// How often the simulated device writes when pacing to a rate.
+const TICK: Duration = Duration::from_millis(10);
+
+/// Size of the off-screen terminal.
+const WIDTH: u16 = 160;
+const HEIGHT: u16 = 48;
+
+/// Memory of the scrollback, the monitor's default.
+const SCROLLBACK: usize = 8 * 1024 * 1024;
+
+/// How long the pipeline may take to catch up once the device stops.
+const DRAIN: Duration = Duration::from_secs(2);
+
+pub struct Options {
+    /// Bytes per second the device writes; unlimited if `None`.
+    pub rate: Option<u64>,
+    pub duration: Duration,
+    /// Length of each line without its terminator.
+    pub line_length: usize,
+}
+
+/// Synthetic line `seq`, written `at` after the start, padded to `length`.
+/// The levels vary so the renderer styles lines as it would a real log.
+pub fn line(seq: u64, at: Duration, length: usize) -> String {
+    let level = ['I', 'W', 'E', 'D'][seq as usize % 4];
+    let mut line = format!("{level} ({}) bench: seq={seq} t={} ", at.as_millis(), at.as_micros());
+    let pad = length.saturating_sub(line.len());
+    line.extend(std::iter::repeat_n('x', pad));
+    line
+}
+
+/// `count` synthetic lines, terminated as a device sends them.
+pub fn synthetic(count: u64, length: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    for seq in 0..count {
+        data.extend_from_slice(line(seq, Duration::from_micros(seq), length).as_bytes());
+        data.extend_from_slice(b"\r\n");
+    }
+    data
+}
+
+/// The received entries of `text`, as the monitor shows them.
+pub fn entry(text: String) -> OutputLine {
+    let level = level::parse(&text);
+    OutputLine::received("", text, level)
+}
+
+/// The view of the output pane the benchmark renders.
+pub fn view(width: u16, height: u16) -> View {
+    View {
+        pane: Pane::All,
+        pretty_json: false,
+        conversation: false,
+        reveal: false,
+        min_level: Level::Trace,
+        width,
+        height,
+        selected: None,
+        device: None,
+    }
+}
+
+/// When the line was written, from its `t=`.
+fn written_at(text: &str) -> Option<Duration> {
+    let (_, rest) = text.split_once(" t=")?;
+    let digits = rest.split(' ').next()?;
+    digits.parse().ok().map(Duration::from_micros)
+}
+
+/// Writes lines at the rate for the duration; the number of lines and
+/// bytes written.
+async fn device(mut device: tokio::io::DuplexStream, options: Options, started: Instant) -> Result<(u64, u64)> {
+    let (mut seq, mut bytes) = (0u64, 0u64);
+    let mut chunk = Vec::new();
+    while started.elapsed() < options.duration {
+        let due = match options.rate {
+            Some(rate) => (rate as f64 * started.elapsed().as_secs_f64()) as u64,
+            None => bytes + 64 * 1024,
+        };
+        while bytes + chunk.len() as u64 <= due {
+            chunk.extend_from_slice(line(seq, started.elapsed(), options.line_length).as_bytes());
+            chunk.extend_from_slice(b"\r\n");
+            seq += 1;
+        }
+        device.write_all(&chunk).await?;
+        bytes += chunk.len() as u64;
+        chunk.clear();
+        if options.rate.is_some() {
+            time::sleep(TICK).await;
+        }
+    }
+    device.flush().await?;
+    Ok((seq, bytes))
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Runs the benchmark and prints its report.
+pub async fn run(options: Options) -> Result<()> {
+    if options.duration.is_zero() {
+        bail!("--bench-seconds must be more than 0");
+    }
+    let (transport, simulated) = simulator::duplex();
+    let restart = Restart {
+        policy: RestartPolicy::Never,
+        open: Box::new(|_| Box::pin(async { Err(anyhow!("The benchmark port can't be reopened")) })),
+        recover: None,
+    };
+    let (tx_events, mut rx_events) = mpsc::unbounded_channel::<PortEvent>();
+    let framer = Framer::new(Framing::Lines, LengthPrefix::Varint);
+    let _port = port::spawn(0, transport, restart, SerialSettings::default(), framer, Decoder::Text(Encoding::Utf8), tx_events);
+    let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT))?;
+    let view = view(WIDTH - 2, HEIGHT - 2);
+    println!(
+        "Benchmarking {} for {:.0}s with {}-byte lines",
+        options.rate.map_or("as fast as possible".to_string(), |rate| format!("{}/s", scrollback::format_size(rate as usize))),
+        options.duration.as_secs_f64(),
+        options.line_length
+    );
+
+    let started = Instant::now();
+    let mut writer = tokio::spawn(device(simulated, options, started));
+    let mut written = None;
+    let mut entries: Vec<OutputLine> = Vec::new();
+    let mut budget = Budget::new(SCROLLBACK);
+    let (mut lines, mut bytes) = (0u64, 0u64);
+    let mut latencies = Vec::new();
+    let mut draws = Vec::new();
+    let mut last_progress = Instant::now();
+    loop {
+        let mut received = false;
+        while let Ok(event) = rx_events.try_recv() {
+            if let SerialEvent::Rx { text, raw, .. } = event.event {
+                if let Some(at) = written_at(&text) {
+                    latencies.push(started.elapsed().saturating_sub(at));
+                }
+                lines += 1;
+                bytes += raw.len() as u64;
+                entries.push(entry(text));
+                received = true;
+            }
+        }
+        budget.trim(&mut entries);
+        if received {
+            let began = Instant::now();
+            terminal.draw(|f| {
+                let (visible, skip) = output::visible_lines(&entries, &view, view.height as usize, 0);
+                let pane = Paragraph::new(visible)
+                    .block(Block::default().title("Monitor").borders(Borders::ALL))
+                    .wrap(Wrap { trim: false })
+                    .scroll((skip, 0));
+                f.render_widget(pane, f.area());
+            })?;
+            draws.push(began.elapsed());
+            last_progress = Instant::now();
+        }
+        if written.is_none() && writer.is_finished() {
+            written = Some((&mut writer).await??);
+        }
+        match written {
+            Some((sent, _)) if lines >= sent || last_progress.elapsed() > DRAIN => break,
+            _ => {}
+        }
+        time::sleep(Duration::from_millis(10)).await;
+    }
+    let took = started.elapsed();
+    let (sent, sent_bytes) = written.unwrap_or_default();
+
+    let per_second = |n: u64| n as f64 / took.as_secs_f64();
+    println!("sent      {} in {sent} lines", scrollback::format_size(sent_bytes as usize));
+    println!(
+        "received  {} in {lines} lines in {:.1}s: {}/s, {:.0} lines/s, {} lost",
+        scrollback::format_size(bytes as usize),
+        took.as_secs_f64(),
+        scrollback::format_size(per_second(bytes) as usize),
+        per_second(lines),
+        sent.saturating_sub(lines)
+    );
+    latencies.sort();
+    if let Some(max) = latencies.last() {
+        let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+        let average = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+        println!(
+            "latency   {:.1} ms avg, {:.1} ms p50, {:.1} ms p99, {:.1} ms max",
+            ms(average),
+            ms(percentile(50)),
+            ms(percentile(99)),
+            ms(*max)
+        );
+    }
+    if let Some(max) = draws.iter().max() {
+        let average = draws.iter().sum::<Duration>() / draws.len() as u32;
+        println!("draw      {} frames, {:.2} ms avg, {:.2} ms max", draws.len(), ms(average), ms(*max));
+    }
+    if lines < sent {
+        bail!("{} line(s) never arrived", sent - lines);
+    }
+    Ok(())
+}
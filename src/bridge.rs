@@ -0,0 +1,150 @@
+//! Headless RFC 2217 server for a remote-access box.
+//!
+//! `serial-monitor bridge` opens the port without the TUI and shares it like
+//! `--rfc2217-server` does, printing connection changes until stopped. See
+//! [`crate::daemon`] for running it as a systemd service.
+
+use std::{
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use chrono::Local;
+use tokio::{net::TcpListener, sync::mpsc, time};
+
+use crate::{
+    access::Access,
+    daemon::{self, PidFile},
+    events::{PortEvent, SerialEvent},
+    exit,
+    framing::{Decoder, Framer, Framing, LengthPrefix},
+    port::{self, PortHandle, Recover, Restart, RestartPolicy},
+    rfc2217_server,
+    transport::{self, Encoding, SerialSettings},
+};
+
+/// How often the status file is rewritten with the traffic counters.
+const STATUS_EVERY: Duration = Duration::from_secs(10);
+
+pub struct Service<'a> {
+    /// Address to listen on unless systemd passes a socket.
+    pub listen: &'a str,
+    pub pid_file: Option<&'a str>,
+    pub status_file: Option<&'a str>,
+    pub access: Arc<Access>,
+}
+
+/// What the status file reports.
+struct Status<'a> {
+    name: &'a str,
+    listen: String,
+    connected: bool,
+    since: chrono::DateTime<Local>,
+    last_error: Option<String>,
+}
+
+impl Status<'_> {
+    fn write(&self, path: Option<&str>, port: &PortHandle) {
+        let Some(path) = path else {
+            return;
+        };
+        let status = serde_json::json!({
+            "pid": std::process::id(),
+            "port": self.name,
+            "listen": self.listen,
+            "connected": self.connected,
+            "since": self.since.to_rfc3339(),
+            "rx_bytes": port.counters.rx_bytes.load(Ordering::Relaxed),
+            "tx_bytes": port.counters.tx_bytes.load(Ordering::Relaxed),
+            "last_error": self.last_error,
+            "updated": Local::now().to_rfc3339(),
+        });
+        if let Err(e) = daemon::write_status(path, &status) {
+            eprintln!("Cannot write the status file {path}: {e}");
+        }
+    }
+}
+
+pub async fn run(
+    name: &str,
+    settings: SerialSettings,
+    policy: RestartPolicy,
+    recover: Option<(u32, Recover)>,
+    service: Service<'_>,
+) -> Result<()> {
+    let listener = match daemon::activated_listener()? {
+        Some(listener) => listener,
+        None => TcpListener::bind(service.listen)
+            .await
+            .map_err(|e| anyhow!("Cannot listen on {}: {e}", service.listen))?,
+    };
+    let listen = listener.local_addr().map_or_else(|_| service.listen.to_string(), |addr| addr.to_string());
+    let _pid_file = service.pid_file.map(PidFile::create).transpose()?;
+    let transport = transport::open(name, &settings).await?;
+    let restart = Restart {
+        policy,
+        open: {
+            let name = name.to_string();
+            Box::new(move |settings| {
+                let name = name.clone();
+                Box::pin(async move { transport::open(&name, &settings).await })
+            })
+        },
+        recover,
+    };
+    let (tx_events, mut rx_events) = mpsc::unbounded_channel::<PortEvent>();
+    let framer = Framer::new(Framing::Lines, LengthPrefix::Varint);
+    let port = port::spawn(0, transport, restart, settings, framer, Decoder::Text(Encoding::Utf8), tx_events);
+    rfc2217_server::spawn(listener, port.clone(), service.access.clone());
+    println!("Sharing {name} at {} baud on {listen} (Ctrl+C to stop)", settings.baud_rate);
+    daemon::notify(&format!("READY=1\nSTATUS=Sharing {name} on {listen}"));
+
+    let mut status = Status {
+        name,
+        listen,
+        connected: false,
+        since: Local::now(),
+        last_error: None,
+    };
+    let mut refresh = time::interval(STATUS_EVERY);
+    let shutdown = daemon::shutdown();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => {
+                daemon::notify("STOPPING=1");
+                return Ok(());
+            }
+            _ = refresh.tick() => status.write(service.status_file, &port),
+            event = rx_events.recv() => {
+                let Some(event) = event else {
+                    return Err(exit::failure(exit::Status::Disconnected, format!("{name} was closed")));
+                };
+                let time = event.time.format("%H:%M:%S");
+                match event.event {
+                    SerialEvent::Connected => {
+                        println!("[{time}] {name} connected");
+                        daemon::notify(&format!("STATUS=Sharing {name} on {}", status.listen));
+                        status.connected = true;
+                        status.since = event.time;
+                    }
+                    SerialEvent::Disconnected => {
+                        println!("[{time}] {name} disconnected");
+                        daemon::notify(&format!("STATUS={name} disconnected, waiting for it"));
+                        status.connected = false;
+                        status.since = event.time;
+                    }
+                    SerialEvent::Error(e) => {
+                        eprintln!("[{time}] {name}: {e}");
+                        status.last_error = Some(e);
+                    }
+                    SerialEvent::LineErrors(errors) => eprintln!("[{time}] {name}: {}", errors.describe()),
+                    SerialEvent::Notice(text) => println!("[{time}] {text}"),
+                    _ => continue,
+                }
+                status.write(service.status_file, &port);
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Devices sharing a multi-drop bus.
+//!
+//! On an RS-485 bus a master polls many slaves over one pair of wires, so
+//! every device's frames arrive on the same port. `--bus-address REGEX`
+//! picks the address out of each frame, as the regex's first group or its
+//! whole match, e.g. `^([0-9A-F]{2}) ` for hex-dumped Modbus RTU frames, and
+//! `--bus-device ADDR=NAME` names the devices. Received frames are tagged
+//! with the device instead of the port, and `:bus NAME` shows only the
+//! frames to and from one device, so a bus with many slaves stays readable.
+
+use std::collections::{BTreeMap, HashMap};
+
+use regex::Regex;
+
+/// Addresses told apart before further ones are taken for noise: a regex
+/// matching garbled frames would otherwise add a device for every one.
+const MAX_DEVICES: usize = 256;
+
+/// Parses `--bus-device ADDR=NAME`.
+pub fn parse_device(spec: &str) -> Result<(String, String), String> {
+    match spec.split_once('=') {
+        Some((address, name)) if !address.trim().is_empty() && !name.trim().is_empty() => {
+            Ok((address.trim().to_ascii_uppercase(), name.trim().to_string()))
+        }
+        _ => Err(format!("Invalid bus device {spec:?}. Expected ADDR=NAME, e.g. 01=pump")),
+    }
+}
+
+struct Device {
+    /// The name given with `--bus-device`, or else the address.
+    name: &'static str,
+    tag: &'static str,
+    /// Frames received from the device.
+    frames: u64,
+}
+
+pub struct Bus {
+    regex: Option<Regex>,
+    /// Names of the addresses, which are compared in upper case.
+    names: HashMap<String, String>,
+    /// Devices by address, once seen or looked for.
+    devices: BTreeMap<String, Device>,
+}
+
+impl Bus {
+    pub fn new(regex: Option<Regex>, names: &[(String, String)]) -> Self {
+        Self {
+            regex,
+            names: names.iter().cloned().collect(),
+            devices: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.regex.is_some()
+    }
+
+    /// The device a frame is addressed to or came from.
+    fn device(&mut self, text: &str) -> Option<&mut Device> {
+        let captures = self.regex.as_ref()?.captures(text)?;
+        let address = captures.get(1).or_else(|| captures.get(0))?.as_str().trim().to_ascii_uppercase();
+        let known = self.devices.contains_key(&address) || self.names.contains_key(&address);
+        if !known && self.devices.len() >= MAX_DEVICES {
+            return None;
+        }
+        Some(self.intern(address))
+    }
+
+    fn intern(&mut self, address: String) -> &mut Device {
+        let name = self.names.get(&address).cloned();
+        self.devices.entry(address).or_insert_with_key(|address| {
+            let name: &'static str = name.unwrap_or_else(|| address.clone()).leak();
+            Device {
+                name,
+                tag: format!("[{name}] ").leak(),
+                frames: 0,
+            }
+        })
+    }
+
+    /// The name and tag of the device a received frame came from.
+    pub fn received(&mut self, text: &str) -> Option<(&'static str, &'static str)> {
+        let device = self.device(text)?;
+        device.frames += 1;
+        Some((device.name, device.tag))
+    }
+
+    /// The name of the device a sent frame is addressed to.
+    pub fn sent(&mut self, text: &str) -> Option<&'static str> {
+        self.device(text).map(|device| device.name)
+    }
+
+    /// The device called `name` or at address `name`, for `:bus NAME`.
+    pub fn find(&mut self, name: &str) -> Option<&'static str> {
+        let address = self
+            .names
+            .iter()
+            .find(|(_, named)| named.eq_ignore_ascii_case(name))
+            .map(|(address, _)| address.clone())
+            .or_else(|| {
+                let address = name.to_ascii_uppercase();
+                (self.devices.contains_key(&address) || self.names.contains_key(&address)).then_some(address)
+            })?;
+        Some(self.intern(address).name)
+    }
+
+    /// The devices heard from so far and how many frames each sent.
+    pub fn describe(&self) -> String {
+        let heard: Vec<String> = self
+            .devices
+            .iter()
+            .filter(|(_, device)| device.frames > 0)
+            .map(|(address, device)| {
+                let frames = match device.frames {
+                    1 => "1 frame".to_string(),
+                    n => format!("{n} frames"),
+                };
+                match device.name == address {
+                    true => format!("{address}: {frames}"),
+                    false => format!("{address} {}: {frames}", device.name),
+                }
+            })
+            .collect();
+        match heard.is_empty() {
+            true => "No device on the bus has answered yet".to_string(),
+            false => format!("Bus devices: {}", heard.join(", ")),
+        }
+    }
+}
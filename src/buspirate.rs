@@ -0,0 +1,363 @@
+//! I2C and SPI transactions through a Bus Pirate.
+//!
+//! `--bus-pirate i2c` or `--bus-pirate spi` puts a Bus Pirate (or another
+//! bridge speaking its binary protocol) on the first port into that mode,
+//! and lines typed are transactions run on the bus instead of text sent to
+//! the port:
+//!
+//! - I2C: `ADDR [w BYTES…] [r COUNT]` in hex, e.g. `50 w 00 10 r 4` writes
+//!   the address 0010 to the EEPROM at 0x50, then reads 4 bytes after a
+//!   repeated start.
+//! - SPI: `BYTES…` in hex, e.g. `9F 00 00 00`, clocked out with CS low, the
+//!   bytes read back shown next to them.
+//!
+//! Each transaction is shown as one entry such as `[I2C] 0x50 W 00 10 R 12 34
+//! 56 78`, with the byte a slave didn't acknowledge marked. The adapter's
+//! own protocol bytes are not shown. USB bridges without a serial protocol,
+//! like the FT232H in MPSSE mode, can't be driven through a serial port.
+
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use ratatui::style::{Color, Style};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    time::{self, Instant},
+};
+
+use crate::{framing, output::OutputLine, port::PortHandle};
+
+/// How long the adapter may take to answer a command.
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Most bytes one bulk command moves.
+const BULK: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Protocol {
+    I2c,
+    Spi,
+}
+
+impl Protocol {
+    fn name(self) -> &'static str {
+        match self {
+            Protocol::I2c => "I2C",
+            Protocol::Spi => "SPI",
+        }
+    }
+}
+
+/// Bus speeds in kHz by their setting in the protocol's speed command.
+fn speeds(protocol: Protocol) -> &'static [u32] {
+    match protocol {
+        Protocol::I2c => &[5, 50, 100, 400],
+        Protocol::Spi => &[30, 125, 250, 1000, 2000, 2600, 4000, 8000],
+    }
+}
+
+/// The adapter's mode and bus settings.
+#[derive(Debug, Clone, Copy)]
+pub struct Adapter {
+    protocol: Protocol,
+    khz: u32,
+    /// Setting of the speed command.
+    speed: u8,
+    /// Whether the power supplies (and the I2C pull-ups) are turned on.
+    power: bool,
+}
+
+impl Adapter {
+    /// The adapter for `protocol` at `khz`, 100 kHz for I2C and 1 MHz for
+    /// SPI by default.
+    pub fn new(protocol: Protocol, khz: Option<u32>, power: bool) -> Result<Self> {
+        let khz = khz.unwrap_or(match protocol {
+            Protocol::I2c => 100,
+            Protocol::Spi => 1000,
+        });
+        let Some(speed) = speeds(protocol).iter().position(|&speed| speed == khz) else {
+            bail!("Invalid --bus-pirate-speed {khz}. The {} speeds are {:?} kHz", protocol.name(), speeds(protocol));
+        };
+        Ok(Self {
+            protocol,
+            khz,
+            speed: speed as u8,
+            power,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum Transaction {
+    I2c { address: u8, write: Vec<u8>, read: usize },
+    Spi(Vec<u8>),
+}
+
+/// Bytes written in hex, e.g. `00 7F` or `0x00 0x7F`.
+fn hex_bytes<'a>(words: impl Iterator<Item = &'a str>) -> Result<Vec<u8>, String> {
+    words
+        .map(|word| {
+            let digits = word.strip_prefix("0x").unwrap_or(word);
+            u8::from_str_radix(digits, 16).map_err(|_| format!("{word:?} is not a byte in hex"))
+        })
+        .collect()
+}
+
+/// Parses a typed transaction.
+fn parse(protocol: Protocol, text: &str) -> Result<Transaction, String> {
+    let mut words = text.split_whitespace().peekable();
+    match protocol {
+        Protocol::Spi => {
+            let data = hex_bytes(words)?;
+            if data.is_empty() {
+                return Err("Expected the bytes to clock out in hex, e.g. 9F 00 00 00".to_string());
+            }
+            Ok(Transaction::Spi(data))
+        }
+        Protocol::I2c => {
+            let usage = || "Expected ADDR [w BYTES…] [r COUNT] in hex, e.g. 50 w 00 10 r 4".to_string();
+            let address = hex_bytes(words.next().into_iter()).map_err(|_| usage())?;
+            let address = address.first().copied().filter(|address| *address < 0x80).ok_or_else(usage)?;
+            let mut write = Vec::new();
+            let mut read = 0;
+            if words.next_if(|word| word.eq_ignore_ascii_case("w")).is_some() {
+                write = hex_bytes(std::iter::from_fn(|| words.next_if(|word| !word.eq_ignore_ascii_case("r"))))?;
+            }
+            if words.next_if(|word| word.eq_ignore_ascii_case("r")).is_some() {
+                read = words.next().and_then(|count| usize::from_str_radix(count, 16).ok()).ok_or_else(usage)?;
+            }
+            if words.next().is_some() {
+                return Err(usage());
+            }
+            Ok(Transaction::I2c { address, write, read })
+        }
+    }
+}
+
+/// Typed transactions going to the adapter.
+pub struct Handle {
+    protocol: Protocol,
+    transactions: mpsc::UnboundedSender<Transaction>,
+}
+
+impl Handle {
+    /// Queues the transaction typed as `text`.
+    pub fn submit(&self, text: &str) -> Result<(), String> {
+        let transaction = parse(self.protocol, text)?;
+        self.transactions.send(transaction).map_err(|_| "The Bus Pirate isn't answering any more".to_string())
+    }
+}
+
+/// Starts driving the adapter on `port`, whose received bytes are subscribed
+/// to in `received`; setup failures and transactions are shown through
+/// `annotations`.
+pub fn start(
+    adapter: Adapter,
+    port: PortHandle,
+    received: broadcast::Receiver<Vec<u8>>,
+    annotations: mpsc::UnboundedSender<OutputLine>,
+) -> Handle {
+    let (transactions, requests) = mpsc::unbounded_channel();
+    let link = Link {
+        adapter,
+        port,
+        received,
+        pending: Vec::new(),
+        annotations,
+    };
+    tokio::spawn(link.run(requests));
+    Handle {
+        protocol: adapter.protocol,
+        transactions,
+    }
+}
+
+struct Link {
+    adapter: Adapter,
+    port: PortHandle,
+    received: broadcast::Receiver<Vec<u8>>,
+    /// Bytes received but not yet taken as an answer.
+    pending: Vec<u8>,
+    annotations: mpsc::UnboundedSender<OutputLine>,
+}
+
+impl Link {
+    async fn run(mut self, mut requests: mpsc::UnboundedReceiver<Transaction>) {
+        match self.setup().await {
+            Ok(()) => {
+                let power = if self.adapter.power { ", power on" } else { "" };
+                let text = format!("{} mode at {} kHz{power}", self.adapter.protocol.name(), self.adapter.khz);
+                self.note("[Bus Pirate] ", text, Color::Green);
+            }
+            Err(e) => {
+                self.note("[Bus Pirate] ", e, Color::Red);
+                return;
+            }
+        }
+        while let Some(transaction) = requests.recv().await {
+            let (tag, result) = match transaction {
+                Transaction::I2c { address, write, read } => ("[I2C] ", self.i2c(address, &write, read).await),
+                Transaction::Spi(data) => ("[SPI] ", self.spi(&data).await),
+            };
+            match result {
+                Ok(text) => self.note(tag, text, Color::Cyan),
+                Err(e) => self.note(tag, e, Color::Red),
+            }
+        }
+    }
+
+    /// Enters binary mode, then the protocol's mode, and sets up the bus.
+    async fn setup(&mut self) -> Result<(), String> {
+        // Twenty zeros leave the terminal for binary mode, from any mode
+        self.port.forward(vec![0; 20]);
+        self.expect("BBIO1").await.map_err(|_| "No binary mode answer, is it a Bus Pirate?".to_string())?;
+        // The zeros after the first are answered too
+        time::sleep(Duration::from_millis(100)).await;
+        self.drain();
+        let (command, answer) = match self.adapter.protocol {
+            Protocol::I2c => (0x02, "I2C1"),
+            Protocol::Spi => (0x01, "SPI1"),
+        };
+        self.port.forward(vec![command]);
+        self.expect(answer).await.map_err(|_| format!("The adapter has no {} mode", self.adapter.protocol.name()))?;
+        // Peripherals: power and pull-ups for I2C, power and CS high for SPI
+        let peripherals = match (self.adapter.protocol, self.adapter.power) {
+            (Protocol::I2c, true) => 0x4C,
+            (Protocol::I2c, false) => 0x40,
+            (Protocol::Spi, true) => 0x49,
+            (Protocol::Spi, false) => 0x41,
+        };
+        self.command(peripherals).await?;
+        self.command(0x60 | self.adapter.speed).await?;
+        if self.adapter.protocol == Protocol::Spi {
+            // 3.3 V outputs, clock idle low, data changing on the falling edge
+            self.command(0x8A).await?;
+        }
+        Ok(())
+    }
+
+    /// Discards whatever arrived before the next command.
+    fn drain(&mut self) {
+        self.pending.clear();
+        while let Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) = self.received.try_recv() {}
+    }
+
+    /// Reads until `answer` arrives.
+    async fn expect(&mut self, answer: &str) -> Result<(), String> {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            if let Some(at) = self.pending.windows(answer.len()).position(|window| window == answer.as_bytes()) {
+                self.pending.drain(..at + answer.len());
+                return Ok(());
+            }
+            self.receive(deadline).await?;
+        }
+    }
+
+    /// Waits for more bytes until `deadline`.
+    async fn receive(&mut self, deadline: Instant) -> Result<(), String> {
+        match time::timeout_at(deadline, self.received.recv()).await {
+            Ok(Ok(data)) => {
+                self.pending.extend_from_slice(&data);
+                Ok(())
+            }
+            Ok(Err(RecvError::Lagged(_))) => Ok(()),
+            Ok(Err(RecvError::Closed)) => Err("The port was closed".to_string()),
+            Err(_) => Err(format!("No answer from the Bus Pirate within {} ms", TIMEOUT.as_millis())),
+        }
+    }
+
+    /// Writes `data` and reads the `length` bytes answering it.
+    async fn exchange(&mut self, data: Vec<u8>, length: usize) -> Result<Vec<u8>, String> {
+        self.port.forward(data);
+        let deadline = Instant::now() + TIMEOUT;
+        while self.pending.len() < length {
+            self.receive(deadline).await?;
+        }
+        Ok(self.pending.drain(..length).collect())
+    }
+
+    /// Sends a command answered by 0x01 on success.
+    async fn command(&mut self, command: u8) -> Result<(), String> {
+        match self.exchange(vec![command], 1).await?[..] {
+            [0x01] => Ok(()),
+            _ => Err(format!("The adapter refused command {command:#04X}")),
+        }
+    }
+
+    /// Writes `data` in bulk commands, returning the byte answering each.
+    async fn bulk(&mut self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut answers = Vec::new();
+        for chunk in data.chunks(BULK) {
+            let mut command = vec![0x10 | (chunk.len() - 1) as u8];
+            command.extend_from_slice(chunk);
+            let answer = self.exchange(command, 1 + chunk.len()).await?;
+            if answer[0] != 0x01 {
+                return Err("The adapter refused a bulk transfer".to_string());
+            }
+            answers.extend_from_slice(&answer[1..]);
+        }
+        Ok(answers)
+    }
+
+    async fn i2c(&mut self, address: u8, write: &[u8], read: usize) -> Result<String, String> {
+        self.drain();
+        let mut text = format!("{address:#04x}");
+        // A start, the address and the bytes written, each to be acknowledged
+        let mut written = vec![address << 1];
+        written.extend_from_slice(write);
+        let result = self.i2c_transfer(&written, read, address, &mut text).await;
+        // The bus is released whatever happened
+        let stopped = self.command(0x03).await;
+        result.and(stopped).map(|()| text)
+    }
+
+    async fn i2c_transfer(&mut self, written: &[u8], read: usize, address: u8, text: &mut String) -> Result<(), String> {
+        if written.len() > 1 || read == 0 {
+            self.command(0x02).await?;
+            let acks = self.bulk(written).await?;
+            if let Some(nack) = acks.iter().position(|&ack| ack != 0x00) {
+                return Err(match nack {
+                    0 => format!("{text}: no slave acknowledged the address"),
+                    n => format!("{text} W {}: NACK at byte {n}", framing::hex(&written[1..])),
+                });
+            }
+            if !written[1..].is_empty() {
+                text.push_str(&format!(" W {}", framing::hex(&written[1..])));
+            }
+        }
+        if read > 0 {
+            // A (repeated) start and the address to read from
+            self.command(0x02).await?;
+            if self.bulk(&[address << 1 | 1]).await? != [0x00] {
+                return Err(format!("{text}: no slave acknowledged the address for reading"));
+            }
+            let mut data = Vec::with_capacity(read);
+            for i in 0..read {
+                data.extend(self.exchange(vec![0x04], 1).await?);
+                // The last byte read is not acknowledged, ending the read
+                self.command(if i + 1 < read { 0x06 } else { 0x07 }).await?;
+            }
+            text.push_str(&format!(" R {}", framing::hex(&data)));
+        }
+        Ok(())
+    }
+
+    async fn spi(&mut self, data: &[u8]) -> Result<String, String> {
+        self.drain();
+        self.command(0x02).await?;
+        let result = self.bulk(data).await;
+        let released = self.command(0x03).await;
+        let read = result?;
+        released?;
+        Ok(format!("MOSI {} MISO {}", framing::hex(data), framing::hex(&read)))
+    }
+
+    fn note(&self, tag: &'static str, text: String, color: Color) {
+        let _ = self.annotations.send(OutputLine::new(tag, text, Style::default().fg(color)));
+    }
+}
@@ -0,0 +1,455 @@
+//! Raw capture files: every chunk read from and written to the ports, with
+//! its time, for replay and offline analysis.
+//!
+//! Layout (integers little-endian):
+//!
+//! ```text
+//! header   magic "SMCAP\r\n\x1a", u16 version, u32 length, settings JSON, u32 CRC-32
+//! record   u8 kind, u8 port, i64 time (µs since the epoch), u32 length, data, u32 CRC-32
+//! trailer  index record, u64 offset of the index record, magic "SMCAPIDX"
+//! ```
+//!
+//! The index record lists the time and offset of a record about every second,
+//! so readers can seek to a timestamp without scanning the file. A file cut
+//! short, e.g. by a power loss, has no trailer: readers rebuild the index by
+//! scanning and stop at the first record whose checksum doesn't match.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, TimeZone};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+const MAGIC: &[u8; 8] = b"SMCAP\r\n\x1a";
+const INDEX_MAGIC: &[u8; 8] = b"SMCAPIDX";
+const VERSION: u16 = 1;
+/// Microseconds between index entries.
+const INDEX_EVERY: i64 = 1_000_000;
+/// Bytes of a record besides its data.
+const RECORD_OVERHEAD: u64 = 1 + 1 + 8 + 4 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Rx,
+    Tx,
+    Index,
+}
+
+impl Kind {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Kind::Rx),
+            1 => Some(Kind::Tx),
+            2 => Some(Kind::Index),
+            _ => None,
+        }
+    }
+
+    fn byte(self) -> u8 {
+        match self {
+            Kind::Rx => 0,
+            Kind::Tx => 1,
+            Kind::Index => 2,
+        }
+    }
+}
+
+pub struct Record {
+    pub kind: Kind,
+    pub port: u8,
+    /// Microseconds since the Unix epoch.
+    pub time: i64,
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    pub fn local_time(&self) -> DateTime<Local> {
+        Local.timestamp_micros(self.time).single().unwrap_or_default()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.data.len() + RECORD_OVERHEAD as usize);
+        bytes.push(self.kind.byte());
+        bytes.push(self.port);
+        bytes.extend_from_slice(&self.time.to_le_bytes());
+        bytes.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+        let crc = crc32fast::hash(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+}
+
+pub struct Writer {
+    file: BufWriter<File>,
+    offset: u64,
+    index: Vec<(i64, u64)>,
+    last_time: i64,
+}
+
+impl Writer {
+    /// Creates `path` with a header describing the session.
+    pub fn create(path: &str, settings: &serde_json::Value) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create capture file {path}"))?;
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        let settings = settings.to_string();
+        header.extend_from_slice(&(settings.len() as u32).to_le_bytes());
+        header.extend_from_slice(settings.as_bytes());
+        let crc = crc32fast::hash(&header);
+        header.extend_from_slice(&crc.to_le_bytes());
+        let mut file = BufWriter::new(file);
+        file.write_all(&header)?;
+        Ok(Self {
+            file,
+            offset: header.len() as u64,
+            index: Vec::new(),
+            last_time: 0,
+        })
+    }
+
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        if self.index.last().is_none_or(|&(time, _)| record.time - time >= INDEX_EVERY) {
+            self.index.push((record.time, self.offset));
+        }
+        let bytes = record.encode();
+        self.file.write_all(&bytes)?;
+        self.offset += bytes.len() as u64;
+        self.last_time = self.last_time.max(record.time);
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    /// Appends the index, timed at the last record, and the trailer.
+    pub fn finish(mut self) -> io::Result<()> {
+        let data = self
+            .index
+            .iter()
+            .flat_map(|&(time, offset)| time.to_le_bytes().into_iter().chain(offset.to_le_bytes()))
+            .collect();
+        let index = Record {
+            kind: Kind::Index,
+            port: 0,
+            time: self.last_time,
+            data,
+        };
+        self.file.write_all(&index.encode())?;
+        self.file.write_all(&self.offset.to_le_bytes())?;
+        self.file.write_all(INDEX_MAGIC)?;
+        self.file.into_inner().map_err(|e| e.into_error())?.sync_all()
+    }
+}
+
+/// Where a damaged file stops being readable.
+pub struct Damage {
+    pub offset: u64,
+    pub reason: String,
+}
+
+pub struct Reader {
+    file: BufReader<File>,
+    /// Session settings written when the capture started.
+    pub header: serde_json::Value,
+    index: Vec<(i64, u64)>,
+    data_start: u64,
+    /// Offset of the index record, where the records end.
+    data_end: Option<u64>,
+    /// Length of the file when it was opened or last refreshed.
+    len: u64,
+    pub damage: Option<Damage>,
+    /// Whether the index came from the trailer rather than a scan.
+    pub indexed: bool,
+    /// Times of the first and last record.
+    pub span: Option<(i64, i64)>,
+}
+
+impl Reader {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open capture file {path}"))?;
+        let mut file = BufReader::new(file);
+        let mut fixed = [0; 14];
+        file.read_exact(&mut fixed).with_context(|| format!("{path} is not a capture file"))?;
+        if &fixed[..8] != MAGIC {
+            bail!("{path} is not a capture file");
+        }
+        let version = u16::from_le_bytes([fixed[8], fixed[9]]);
+        if version != VERSION {
+            bail!("{path}: unsupported capture version {version}");
+        }
+        let length = u32::from_le_bytes(fixed[10..14].try_into().unwrap()) as usize;
+        let len = file.get_ref().metadata()?.len();
+        if 14 + length as u64 + 4 > len {
+            bail!("{path}: truncated header");
+        }
+        let mut settings = vec![0; length + 4];
+        file.read_exact(&mut settings).with_context(|| format!("{path}: truncated header"))?;
+        let crc = u32::from_le_bytes(settings[length..].try_into().unwrap());
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&fixed);
+        hasher.update(&settings[..length]);
+        if hasher.finalize() != crc {
+            bail!("{path}: header checksum mismatch");
+        }
+        let header = serde_json::from_slice(&settings[..length]).with_context(|| format!("{path}: invalid header"))?;
+        let mut reader = Self {
+            file,
+            header,
+            index: Vec::new(),
+            data_start: 14 + length as u64 + 4,
+            data_end: None,
+            len,
+            damage: None,
+            indexed: false,
+            span: None,
+        };
+        if !reader.read_index()? {
+            reader.rebuild_index()?;
+        }
+        reader.file.seek(SeekFrom::Start(reader.data_start))?;
+        Ok(reader)
+    }
+
+    /// Moves to the record at or after `time` (µs since the epoch) and
+    /// returns it: the index gives a nearby offset, at most about a second of
+    /// records before the target.
+    pub fn seek(&mut self, time: i64) -> io::Result<Option<Record>> {
+        let entry = self.index.partition_point(|&(t, _)| t <= time).saturating_sub(1);
+        let offset = self.index.get(entry).map_or(self.data_start, |&(_, offset)| offset);
+        self.file.seek(SeekFrom::Start(offset))?;
+        while let Some(record) = self.next_record()? {
+            if record.time >= time {
+                return Ok(Some(record));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Number of index entries.
+    pub fn index_len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Loads the index from the trailer; false if the file has none.
+    fn read_index(&mut self) -> Result<bool> {
+        let length = self.file.seek(SeekFrom::End(0))?;
+        if length < self.data_start + 16 {
+            return Ok(false);
+        }
+        let mut trailer = [0; 16];
+        self.file.seek(SeekFrom::End(-16))?;
+        self.file.read_exact(&mut trailer)?;
+        if &trailer[8..] != INDEX_MAGIC {
+            return Ok(false);
+        }
+        let offset = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+        self.file.seek(SeekFrom::Start(offset))?;
+        let Ok(Some(index)) = self.read_record() else {
+            return Ok(false);
+        };
+        if index.kind != Kind::Index {
+            return Ok(false);
+        }
+        if let Some(first) = index.data.first_chunk::<8>() {
+            self.span = Some((i64::from_le_bytes(*first), index.time));
+        }
+        self.index = index
+            .data
+            .chunks_exact(16)
+            .map(|entry| {
+                (
+                    i64::from_le_bytes(entry[..8].try_into().unwrap()),
+                    u64::from_le_bytes(entry[8..].try_into().unwrap()),
+                )
+            })
+            .collect();
+        self.data_end = Some(offset);
+        self.indexed = true;
+        Ok(true)
+    }
+
+    /// Picks up records appended since the file was opened, for a capture
+    /// that is still being written.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        if self.indexed {
+            return Ok(());
+        }
+        let position = self.file.stream_position()?;
+        self.len = self.file.get_ref().metadata()?.len();
+        self.damage = None;
+        self.scan(self.data_end.unwrap_or(self.data_start))?;
+        self.file.seek(SeekFrom::Start(position))?;
+        Ok(())
+    }
+
+    /// Scans the records of a file without an index, noting where it is damaged.
+    fn rebuild_index(&mut self) -> Result<()> {
+        Ok(self.scan(self.data_start)?)
+    }
+
+    /// Indexes the records from `offset` on.
+    fn scan(&mut self, mut offset: u64) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        loop {
+            match self.read_record() {
+                Ok(Some(record)) => {
+                    if self.index.last().is_none_or(|&(time, _)| record.time - time >= INDEX_EVERY) {
+                        self.index.push((record.time, offset));
+                    }
+                    let first = self.span.map_or(record.time, |(first, _)| first);
+                    self.span = Some((first, record.time.max(first)));
+                    offset += RECORD_OVERHEAD + record.data.len() as u64;
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    self.damage = Some(Damage {
+                        offset,
+                        reason: e.to_string(),
+                    });
+                    break;
+                }
+            }
+        }
+        self.data_end = Some(offset);
+        Ok(())
+    }
+
+    /// Reads the record at the current position; `None` at the end.
+    fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let mut fixed = [0; 14];
+        match self.file.read_exact(&mut fixed[..1]) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        let truncated = |e: io::Error| match e.kind() {
+            io::ErrorKind::UnexpectedEof => io::Error::new(io::ErrorKind::InvalidData, "record cut short"),
+            _ => e,
+        };
+        self.file.read_exact(&mut fixed[1..]).map_err(truncated)?;
+        let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+        let kind = Kind::from_byte(fixed[0]).ok_or_else(|| invalid("unknown record kind"))?;
+        let length = u32::from_le_bytes(fixed[10..14].try_into().unwrap()) as usize;
+        // The length isn't checked by the CRC yet: a damaged one mustn't
+        // allocate more than the file holds
+        let left = self.len.saturating_sub(self.file.stream_position()?);
+        if length as u64 + 4 > left {
+            return Err(invalid("record length beyond the end of the file"));
+        }
+        let mut data = vec![0; length + 4];
+        self.file.read_exact(&mut data).map_err(truncated)?;
+        let crc = u32::from_le_bytes(data[length..].try_into().unwrap());
+        data.truncate(length);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&fixed);
+        hasher.update(&data);
+        if hasher.finalize() != crc {
+            return Err(invalid("checksum mismatch"));
+        }
+        Ok(Some(Record {
+            kind,
+            port: fixed[1],
+            time: i64::from_le_bytes(fixed[2..10].try_into().unwrap()),
+            data,
+        }))
+    }
+
+    /// The next RX or TX record; `None` after the last one.
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        let position = self.file.stream_position()?;
+        if self.data_end.is_some_and(|end| position >= end) {
+            return Ok(None);
+        }
+        Ok(self.read_record()?.filter(|record| record.kind != Kind::Index))
+    }
+}
+
+/// Labelled facts about the capture at `path`, checking every record.
+pub fn describe(path: &str) -> Result<Vec<(&'static str, String)>> {
+    let mut reader = Reader::open(path)?;
+    let (mut records, mut rx_bytes, mut tx_bytes) = (0, 0, 0);
+    let mut span: Option<(DateTime<Local>, DateTime<Local>)> = None;
+    let mut damage = reader.damage.take();
+    loop {
+        let offset = reader.file.stream_position()?;
+        match reader.next_record() {
+            Ok(Some(record)) => {
+                records += 1;
+                match record.kind {
+                    Kind::Tx => tx_bytes += record.data.len(),
+                    _ => rx_bytes += record.data.len(),
+                }
+                let time = record.local_time();
+                span = Some(span.map_or((time, time), |(first, _)| (first, time)));
+            }
+            Ok(None) => break,
+            Err(e) => {
+                damage.get_or_insert(Damage {
+                    offset,
+                    reason: e.to_string(),
+                });
+                break;
+            }
+        }
+    }
+    let mut facts = vec![
+        ("Settings", reader.header.to_string()),
+        ("Records", format!("{records} (RX {rx_bytes} bytes, TX {tx_bytes} bytes)")),
+    ];
+    if let Some((first, last)) = span {
+        facts.push(("Span", format!("{} to {}", first.to_rfc3339(), last.to_rfc3339())));
+    }
+    let source = if reader.indexed { "from the trailer" } else { "rebuilt, no trailer" };
+    facts.push(("Index", format!("{} entries ({source})", reader.index_len())));
+    facts.push((
+        "Integrity",
+        match damage {
+            Some(damage) => format!("damaged at offset {}: {}", damage.offset, damage.reason),
+            None => "all checksums match".to_string(),
+        },
+    ));
+    Ok(facts)
+}
+
+/// Capture of the running session, written on a blocking thread.
+pub struct Capture {
+    sender: mpsc::UnboundedSender<Record>,
+    task: JoinHandle<io::Result<()>>,
+}
+
+impl Capture {
+    pub fn start(path: &str, settings: &serde_json::Value) -> Result<Self> {
+        let mut writer = Writer::create(path, settings)?;
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Record>();
+        let task = tokio::task::spawn_blocking(move || {
+            while let Some(record) = receiver.blocking_recv() {
+                writer.write(&record)?;
+                // Keeps the file readable by `:history` while the session runs
+                if receiver.is_empty() {
+                    writer.flush()?;
+                }
+            }
+            writer.finish()
+        });
+        Ok(Self { sender, task })
+    }
+
+    pub fn record(&self, kind: Kind, port: usize, time: DateTime<Local>, data: Vec<u8>) {
+        let _ = self.sender.send(Record {
+            kind,
+            port: port as u8,
+            time: time.timestamp_micros(),
+            data,
+        });
+    }
+
+    /// Writes the remaining records and the index.
+    pub async fn finish(self) -> Result<()> {
+        drop(self.sender);
+        self.task.await?.context("Cannot complete the capture file")
+    }
+}
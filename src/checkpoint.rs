@@ -0,0 +1,238 @@
+//! Checkpoints of the scrollback and input history for `--resume`.
+//!
+//! With `--resume` or `--session-dir`, the lines kept in memory and the
+//! input history are written every few seconds (when something changed)
+//! and on exit to a file in the session directory named after the ports,
+//! so a crash or an accidental quit doesn't lose them: started again on the
+//! same ports with `--resume`, the monitor shows them above the new
+//! session's lines. Lines are saved as shown, with secrets already masked,
+//! and history entries a `--mask` pattern matches are masked as well.
+//! Sessions run with `--no-log` or `--log-encrypt` aren't checkpointed, as
+//! the file would hold their text in plaintext. With `--log-direction rx`,
+//! neither the lines sent nor the input history are saved.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use ratatui::style::{Color, Style};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::{level::Level, mask::Masks, output::OutputLine};
+
+/// How often a changed session is checkpointed.
+const INTERVAL: Duration = Duration::from_secs(5);
+
+/// `$XDG_STATE_HOME/serial-monitor`, `~/.local/state/serial-monitor` or
+/// `%LOCALAPPDATA%\serial-monitor` on Windows.
+pub fn default_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))
+    };
+    base.map(|dir| dir.join("serial-monitor"))
+}
+
+/// The checkpoint of a session on `ports`, e.g. `ttyUSB0.json`.
+pub fn path(dir: &Path, ports: &[String]) -> PathBuf {
+    let name: Vec<String> = ports
+        .iter()
+        .map(|port| {
+            port.rsplit(['/', '\\'])
+                .next()
+                .unwrap_or(port)
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+                .collect()
+        })
+        .collect();
+    dir.join(format!("{}.json", name.join("+")))
+}
+
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    /// RFC 3339 time of the checkpoint.
+    saved: String,
+    lines: Vec<SavedLine>,
+    history: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedLine {
+    prefix: String,
+    body: String,
+    /// Received lines are styled from their content again when restored.
+    #[serde(default)]
+    received: bool,
+    #[serde(default)]
+    level: Option<Level>,
+    #[serde(default)]
+    ending: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    background: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tag_color: Option<String>,
+    #[serde(default)]
+    repeated: usize,
+}
+
+impl SavedLine {
+    fn new(line: &OutputLine) -> Self {
+        let style = line.style();
+        let received = line.is_received();
+        Self {
+            prefix: line.prefix.to_string(),
+            body: line.body.clone(),
+            received,
+            level: line.level,
+            ending: line.ending.to_string(),
+            color: style.fg.filter(|_| !received).map(|color| color.to_string()),
+            background: style.bg.filter(|_| !received).map(|color| color.to_string()),
+            tag_color: line.tag_color.map(|color| color.to_string()),
+            repeated: line.repeated,
+        }
+    }
+
+    /// The entry again; `intern` turns prefixes and endings into the
+    /// `'static` strings entries hold, once per distinct value.
+    fn restore(self, intern: &mut impl FnMut(String) -> &'static str) -> OutputLine {
+        let prefix = intern(self.prefix);
+        let mut line = if self.received {
+            OutputLine::received(prefix, self.body, self.level)
+        } else {
+            let mut style = Style::default();
+            if let Some(color) = self.color.as_deref().and_then(|c| Color::from_str(c).ok()) {
+                style = style.fg(color);
+            }
+            if let Some(color) = self.background.as_deref().and_then(|c| Color::from_str(c).ok()) {
+                style = style.bg(color);
+            }
+            OutputLine::new(prefix, self.body, style)
+        }
+        .with_tag_color(self.tag_color.as_deref().and_then(|c| Color::from_str(c).ok()));
+        line.ending = intern(self.ending);
+        line.repeated = self.repeated;
+        line
+    }
+}
+
+/// A previous session read back for `--resume`.
+pub struct Restored {
+    pub saved: DateTime<Local>,
+    pub lines: Vec<OutputLine>,
+    pub history: Vec<String>,
+}
+
+/// Reads the checkpoint at `path`; `None` if there is none.
+pub fn load(path: &Path) -> Result<Option<Restored>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Cannot read the checkpoint {}", path.display())),
+    };
+    let saved: Saved =
+        serde_json::from_str(&text).with_context(|| format!("The checkpoint {} is damaged", path.display()))?;
+    let mut interned: HashMap<String, &'static str> = HashMap::new();
+    let mut intern = |text: String| *interned.entry(text).or_insert_with_key(|text| text.clone().leak());
+    let time = DateTime::parse_from_rfc3339(&saved.saved)
+        .with_context(|| format!("The checkpoint {} is damaged", path.display()))?;
+    Ok(Some(Restored {
+        saved: time.with_timezone(&Local),
+        lines: saved.lines.into_iter().map(|line| line.restore(&mut intern)).collect(),
+        history: saved.history,
+    }))
+}
+
+/// Writes checkpoints of the running session.
+pub struct Checkpointer {
+    path: PathBuf,
+    last: Instant,
+    /// What the scrollback and history looked like at the last checkpoint.
+    written: Option<(usize, usize, usize, usize)>,
+    writing: Option<JoinHandle<io::Result<()>>>,
+    /// Whether lines sent and the input history are saved.
+    sent: bool,
+}
+
+impl Checkpointer {
+    pub fn new(path: PathBuf, sent: bool) -> Self {
+        Self {
+            path,
+            sent,
+            last: Instant::now(),
+            written: None,
+            writing: None,
+        }
+    }
+
+    /// Writes a checkpoint in the background if one is due and the session
+    /// changed since the last. `used` is the scrollback's memory use, which
+    /// changes with every line added.
+    pub fn poll(&mut self, lines: &[OutputLine], history: &[String], masks: &Masks, used: usize) -> Option<String> {
+        let mut failed = None;
+        if let Some(writing) = self.writing.take_if(|writing| writing.is_finished()) {
+            failed = match futures::FutureExt::now_or_never(writing) {
+                Some(Ok(Err(e))) => Some(format!("Cannot write the checkpoint {}: {e}", self.path.display())),
+                _ => None,
+            };
+        }
+        let state = (used, lines.len(), history.len(), lines.last().map_or(0, |line| line.repeated));
+        if self.writing.is_some() || self.last.elapsed() < INTERVAL || self.written == Some(state) {
+            return failed;
+        }
+        self.last = Instant::now();
+        self.written = Some(state);
+        let data = encode(lines, history, masks, self.sent);
+        let path = self.path.clone();
+        self.writing = Some(tokio::task::spawn_blocking(move || write(&path, &data)));
+        failed
+    }
+
+    /// Writes the final checkpoint, waiting for one still being written.
+    pub async fn finish(mut self, lines: &[OutputLine], history: &[String], masks: &Masks) -> Result<()> {
+        if let Some(writing) = self.writing.take() {
+            let _ = writing.await;
+        }
+        write(&self.path, &encode(lines, history, masks, self.sent))
+            .with_context(|| format!("Cannot write the checkpoint {}", self.path.display()))
+    }
+}
+
+fn encode(lines: &[OutputLine], history: &[String], masks: &Masks, sent: bool) -> Vec<u8> {
+    let history = match sent {
+        true => history.iter().map(|entry| masks.apply(entry).into_owned()).collect(),
+        false => Vec::new(),
+    };
+    let saved = Saved {
+        saved: Local::now().to_rfc3339(),
+        lines: lines.iter().filter(|line| sent || !line.is_sent()).map(SavedLine::new).collect(),
+        history,
+    };
+    serde_json::to_vec(&saved).unwrap_or_default()
+}
+
+/// Replaces the checkpoint in one step, readable only by the user on Unix.
+fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension("json.tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    io::Write::write_all(&mut options.open(&temporary)?, data)?;
+    fs::rename(&temporary, path)
+}
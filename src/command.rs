@@ -0,0 +1,113 @@
+//! Monitor commands entered in the input field with a leading `:`.
+//!
+//! Input starting with `::` is sent to the device with one colon removed.
+
+use crate::{
+    macros,
+    ping::{self, PingSpec},
+    pipe::{self, PipeSpec},
+    replay::{self, SeekTarget},
+    scrollback,
+    stress::{self, Pattern},
+};
+
+pub enum Command {
+    /// `:stress <pattern> <rate>`
+    Stress { pattern: Pattern, rate: u64 },
+    /// `:stress stop`
+    StressStop,
+    /// `:ping [INTERVAL] [PROBE] [=> EXPECT]`
+    Ping(PingSpec),
+    /// `:ping stop`
+    PingStop,
+    /// `:pipe [all|N] | COMMAND`
+    Pipe(PipeSpec),
+    /// `:seek TIME` while replaying a capture
+    Seek(SeekTarget),
+    /// `:history [TIME]` to browse the capture of the session
+    History(Option<SeekTarget>),
+    /// `:scrollback [SIZE]` to show or change the scrollback budget
+    Scrollback(Option<usize>),
+    /// `:bus` to list the devices heard from on the bus
+    BusDevices,
+    /// `:bus NAME` to show only the frames of one device, `:bus all` to show all
+    BusFilter(Option<String>),
+    /// `:record NAME` to start recording the lines sent as a macro
+    Record(String),
+    /// `:record stop` to save the macro being recorded
+    RecordStop,
+    /// `:play NAME` to send the lines of a macro again
+    Play(String),
+    /// `:play stop`
+    PlayStop,
+    /// `:macros` to list the macros saved
+    Macros,
+    /// `:hud` to show or hide the performance HUD
+    Hud,
+    /// `:traffic` to show or hide the lines received per minute
+    Traffic,
+    /// `:save-session FILE` to save the ports, view and filters for `--session`
+    SaveSession(String),
+    /// `:save-layout` to write the pane layout to the config file
+    SaveLayout,
+    /// `:reset NAME` to run a control line sequence on the active port, `:reset`
+    /// to list them
+    Reset(Option<String>),
+    /// `:power-cycle` to switch the active port's USB power off and on
+    PowerCycle,
+}
+
+/// Returns the command in `input`, or `None` if the input is meant for the device.
+pub fn parse(input: &str) -> Option<Result<Command, String>> {
+    let line = input.trim().strip_prefix(':').filter(|rest| !rest.starts_with(':'))?;
+    let words: Vec<&str> = line.split_whitespace().collect();
+    Some(match words.as_slice() {
+        ["bus"] => Ok(Command::BusDevices),
+        ["bus", "all"] => Ok(Command::BusFilter(None)),
+        ["bus", name] => Ok(Command::BusFilter(Some(name.to_string()))),
+        ["bus", ..] => Err("Usage: :bus [NAME | ADDR | all]".to_string()),
+        ["history"] => Ok(Command::History(None)),
+        ["history", target] => replay::parse_seek(target).map(|target| Command::History(Some(target))),
+        ["history", ..] => Err("Usage: :history [HH:MM:SS[.fff] | -N[s|ms|m]]".to_string()),
+        ["hud"] => Ok(Command::Hud),
+        ["traffic"] => Ok(Command::Traffic),
+        ["macros"] => Ok(Command::Macros),
+        ["ping", "stop"] => Ok(Command::PingStop),
+        ["ping", ..] => Ok(Command::Ping(ping::parse_spec(line.trim_start()["ping".len()..].trim()))),
+        ["pipe", ..] => pipe::parse_spec(&line.trim_start()["pipe".len()..]).map(Command::Pipe),
+        ["play", "stop"] => Ok(Command::PlayStop),
+        ["play", name] => macros::validate_name(name).map(|()| Command::Play(name.to_string())),
+        ["play", ..] => Err("Usage: :play NAME | :play stop".to_string()),
+        ["record", "stop"] => Ok(Command::RecordStop),
+        ["record", name] => macros::validate_name(name).map(|()| Command::Record(name.to_string())),
+        ["record", ..] => Err("Usage: :record NAME | :record stop".to_string()),
+        ["power-cycle"] => Ok(Command::PowerCycle),
+        ["reset"] => Ok(Command::Reset(None)),
+        ["reset", name] => Ok(Command::Reset(Some(name.to_string()))),
+        ["reset", ..] => Err("Usage: :reset [NAME]".to_string()),
+        ["save-layout"] => Ok(Command::SaveLayout),
+        ["save-session", ..] => match line.trim_start()["save-session".len()..].trim() {
+            "" => Err("Usage: :save-session FILE".to_string()),
+            path => Ok(Command::SaveSession(path.to_string())),
+        },
+        ["scrollback"] => Ok(Command::Scrollback(None)),
+        ["scrollback", size] => scrollback::parse_size(size).map(|size| Command::Scrollback(Some(size))),
+        ["scrollback", ..] => Err("Usage: :scrollback [SIZE, e.g. 64MB]".to_string()),
+        ["seek", target] => replay::parse_seek(target).map(Command::Seek),
+        ["seek", ..] => Err("Usage: :seek <HH:MM:SS[.fff] | +N[s|ms|m] | -N[s|ms|m]>".to_string()),
+        ["stress", "stop"] => Ok(Command::StressStop),
+        ["stress", pattern, rate] => Pattern::parse(pattern)
+            .and_then(|pattern| Ok(Command::Stress { pattern, rate: stress::parse_rate(rate)? })),
+        ["stress", ..] => Err("Usage: :stress <random|incremental|55aa> <rate, e.g. 1KB/s> | :stress stop".to_string()),
+        [name, ..] => Err(format!("Unknown command :{name}")),
+        [] => Err("Empty command".to_string()),
+    })
+}
+
+/// Removes the escaping colon from input that starts with `::`.
+pub fn unescape(input: &str) -> &str {
+    match input.strip_prefix("::") {
+        Some(_) => &input[1..],
+        None => input,
+    }
+}
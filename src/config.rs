@@ -0,0 +1,325 @@
+//! Configuration file with named profiles.
+//!
+//! ```toml
+//! [profiles.esp32]
+//! port = "/dev/ttyUSB0"
+//! baud_rate = 115200
+//! alias = "esp"
+//! color = "magenta"
+//! csv_header = ["time", "temp", "humidity"]
+//! watch = ["rssi=RSSI: (-?[0-9]+)", "vbat=VBAT=([0-9.]+)"]
+//! alert = ["vbat<3.3 => bell"]
+//! min_level = "info"
+//! scrollback = "64MB"
+//!
+//! [profiles.rs485]
+//! port = "/dev/ttyUSB1"
+//! bus_address = "^([0-9A-F]{2}) "
+//! bus_device = ["01=pump", "02=valve"]
+//!
+//! [profiles.lights]
+//! port = "/dev/ttyUSB2"
+//! preset = "dmx"
+//!
+//! [profiles.default]         # used when no --profile is given
+//! port = "/dev/ttyACM0"
+//! line_ending = "crlf"
+//! identify = "ATI"
+//! encoding = "latin1"
+//! no_log = true
+//!
+//! [layout]
+//! input_height = 3
+//! input_position = "bottom"
+//! side_width = 48
+//! side_position = "right"
+//! show_rates = false
+//! show_watch = true
+//!
+//! [[proxy.rules]]            # what --proxy passes on
+//! match = "^ACK"
+//! drop = true
+//!
+//! [sequences]                # run with :reset NAME
+//! stm32-boot = "dtr=1 rts=1 100ms rts=0 50ms dtr=0"
+//! ```
+//!
+//! Values from the selected profile replace the built-in defaults; options
+//! given on the command line take precedence over both. The layout is
+//! written back by `:save-layout`, and the first-run setup
+//! writes the `default` profile. The monitor watches the file and applies
+//! changes to the profile's watches, alerts, masks and level filter and to
+//! the layout as it is saved.
+//!
+//! A `.serial-monitor.toml` in the current directory or the nearest of its
+//! parents is merged over the user's file: its tables are merged key by key
+//! and its other values replace the user's, so a firmware repository can
+//! ship the team's defaults, e.g. `[profiles.default]` with the board's
+//! port and baud rate, while each developer keeps their own layout. As
+//! the file comes with whatever was cloned or unpacked, it can't run
+//! commands: an alert with an `exec:` action in it is refused.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use tokio::{sync::mpsc, time};
+
+use crate::{layout::PaneLayout, level::Level, preset::Preset, proxy, transport::{Encoding, LineEnding}};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    #[serde(default)]
+    pub layout: PaneLayout,
+    /// Rules acting on what `--proxy` passes on.
+    #[serde(default)]
+    pub proxy: proxy::Config,
+    /// Control line sequences run with `:reset NAME`, by name.
+    #[serde(default)]
+    pub sequences: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct Profile {
+    pub port: Option<String>,
+    pub baud_rate: Option<u32>,
+    /// Tag for the port's lines, shown instead of the device name.
+    pub alias: Option<String>,
+    /// Color of the port's tag, e.g. `red` or `#ff8800`.
+    pub color: Option<String>,
+    /// Column names for the CSV table view.
+    pub csv_header: Option<Vec<String>>,
+    /// Watch panel extractors as `NAME=REGEX`.
+    pub watch: Option<Vec<String>>,
+    /// Alert rules on watched values, e.g. `temp>80 => bell`.
+    pub alert: Option<Vec<String>>,
+    /// Lines below this level are hidden until the filter is changed.
+    pub min_level: Option<Level>,
+    /// Patterns of secrets masked in the display and logs.
+    pub mask: Option<Vec<String>>,
+    /// Appended to each line sent.
+    pub line_ending: Option<LineEnding>,
+    /// Character encoding of the text sent and received.
+    pub encoding: Option<Encoding>,
+    /// Whether logging to a file is turned off.
+    pub no_log: Option<bool>,
+    /// Memory budget of the scrollback, e.g. `64MB`.
+    pub scrollback: Option<String>,
+    /// Regex picking the address out of each frame on a multi-drop bus.
+    pub bus_address: Option<String>,
+    /// Names of the devices on the bus as `ADDR=NAME`.
+    pub bus_device: Option<Vec<String>>,
+    /// Line settings and decoder of a protocol, `midi` or `dmx`.
+    pub preset: Option<Preset>,
+    /// Query identifying the device when the port connects, e.g. `ATI`.
+    pub identify: Option<String>,
+    /// Regex picking the identity out of the answer.
+    pub identify_match: Option<String>,
+}
+
+/// Profile used when none is selected with `--profile`.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// `$XDG_CONFIG_HOME/serial-monitor/config.toml`, `~/.config/...` or
+/// `%APPDATA%\serial-monitor\config.toml` on Windows.
+pub fn default_path() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|dir| dir.join("serial-monitor").join("config.toml"))
+}
+
+/// Per-project config file, looked for in the current directory and its
+/// parents.
+pub const PROJECT_FILE: &str = ".serial-monitor.toml";
+
+/// The project's config file nearest to the current directory.
+pub fn project_path() -> Option<PathBuf> {
+    let dir = std::env::current_dir().ok()?;
+    dir.ancestors().map(|dir| dir.join(PROJECT_FILE)).find(|path| path.is_file())
+}
+
+/// The files `load` reads, the user's first: `path`, or the default file if
+/// it exists, and the project's file, with whether each is the project's.
+fn paths(path: Option<&str>) -> Vec<(PathBuf, bool)> {
+    let user = match path {
+        Some(path) => Some(PathBuf::from(path)),
+        None => default_path().filter(|path| path.exists()),
+    };
+    user.map(|path| (path, false)).into_iter().chain(project_path().map(|path| (path, true))).collect()
+}
+
+fn read(path: &Path) -> Result<toml::Table> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    // Checked on its own, so an error names the file it is in
+    toml::from_str::<Config>(&text).with_context(|| format!("Invalid config file {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+}
+
+/// Merges `over` into `base`: tables key by key, any other value replaced.
+fn merge(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Refuses the alerts of a project file that run commands.
+fn check_project(path: &Path, table: &toml::Table) -> Result<()> {
+    let profiles = table.get("profiles").and_then(toml::Value::as_table).into_iter().flatten();
+    for (name, profile) in profiles {
+        let alerts = profile.get("alert").and_then(toml::Value::as_array).into_iter().flatten();
+        for alert in alerts.filter_map(toml::Value::as_str) {
+            let exec = alert.split_once("=>").is_some_and(|(_, action)| action.trim().starts_with("exec:"));
+            if exec {
+                anyhow::bail!(
+                    "{}: profile {name} has the alert {alert:?}, but a project file can't run commands; \
+                     move it to your own config file",
+                    path.display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Loads `path`, or the default file if it exists, with the project's file
+/// merged over it.
+pub fn load(path: Option<&str>) -> Result<Config> {
+    let mut merged = toml::Table::new();
+    for (path, project) in paths(path) {
+        let table = read(&path)?;
+        if project {
+            check_project(&path, &table)?;
+        }
+        merge(&mut merged, table);
+    }
+    merged.try_into().context("Invalid merged configuration")
+}
+
+/// How often the config file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Grace period for an editor to finish writing a changed file.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Sends the config file at `path` (or the default file) merged with the
+/// project's, loaded anew, every time either is saved; a configuration that
+/// fails to load is sent as the error.
+pub fn watch(path: Option<&str>, changes: mpsc::UnboundedSender<Result<Config>>) {
+    let path = path.map(str::to_string);
+    let files: Vec<PathBuf> = path.clone().map(PathBuf::from).or_else(default_path).into_iter().chain(project_path()).collect();
+    if files.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        let stamp = || {
+            let stamps = files.iter().map(|file| std::fs::metadata(file).ok().map(|meta| (meta.modified().ok(), meta.len())));
+            stamps.collect::<Vec<_>>()
+        };
+        let mut seen = stamp();
+        loop {
+            time::sleep(WATCH_INTERVAL).await;
+            if changes.is_closed() {
+                return;
+            }
+            let now = stamp();
+            // A removed file keeps the session's settings
+            if now == seen || now.iter().zip(&seen).any(|(now, seen)| now.is_none() && seen.is_some()) {
+                continue;
+            }
+            time::sleep(SETTLE).await;
+            seen = stamp();
+            if changes.send(load(path.as_deref())).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Stores `layout` in the `[layout]` table, preserving the rest of the file.
+pub fn save_layout(path: Option<&str>, layout: &PaneLayout) -> Result<()> {
+    edit(path, |document| {
+        let table = document
+            .entry("layout")
+            .or_insert_with(toml_edit::table)
+            .as_table_mut()
+            .context("`layout` in the config file is not a table")?;
+        layout.store(table);
+        Ok(())
+    })
+}
+
+/// Stores the port, baud rate, line ending and logging choice of `profile`
+/// as `[profiles.NAME]`, preserving the rest of the file.
+pub fn save_profile(path: Option<&str>, name: &str, profile: &Profile) -> Result<()> {
+    edit(path, |document| {
+        let profiles = document
+            .entry("profiles")
+            .or_insert_with(toml_edit::table)
+            .as_table_mut()
+            .context("`profiles` in the config file is not a table")?;
+        profiles.set_implicit(true);
+        let table = profiles
+            .entry(name)
+            .or_insert_with(toml_edit::table)
+            .as_table_mut()
+            .with_context(|| format!("`profiles.{name}` in the config file is not a table"))?;
+        if let Some(port) = &profile.port {
+            table["port"] = toml_edit::value(port.as_str());
+        }
+        if let Some(baud_rate) = profile.baud_rate {
+            table["baud_rate"] = toml_edit::value(i64::from(baud_rate));
+        }
+        if let Some(ending) = profile.line_ending {
+            table["line_ending"] = toml_edit::value(format!("{ending:?}").to_lowercase());
+        }
+        if let Some(no_log) = profile.no_log {
+            table["no_log"] = toml_edit::value(no_log);
+        }
+        Ok(())
+    })
+}
+
+/// Applies `change` to the config file, creating it if needed.
+fn edit(path: Option<&str>, change: impl FnOnce(&mut toml_edit::DocumentMut) -> Result<()>) -> Result<()> {
+    let path = path.map(PathBuf::from).or_else(default_path).context("No configuration directory")?;
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read config file {}", path.display())),
+    };
+    let mut document: toml_edit::DocumentMut = text
+        .parse()
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    change(&mut document)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, document.to_string())
+        .with_context(|| format!("Failed to write config file {}", path.display()))
+}
+
+impl Config {
+    pub fn profile(&self, name: &str) -> Result<Profile> {
+        self.profiles.get(name).cloned().ok_or_else(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            anyhow!("Unknown profile {name:?}. Defined profiles: {known:?}")
+        })
+    }
+}
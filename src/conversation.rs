@@ -0,0 +1,104 @@
+//! Grouping of sent commands with the lines received in response.
+//!
+//! An exchange starts with a line sent to a port and takes every line that
+//! port returns until the next command, a line matching the prompt, or a
+//! quiet period. In conversation mode each exchange can be collapsed to its
+//! command line.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use regex::Regex;
+
+use crate::output::OutputLine;
+
+/// Exchange membership of an output entry.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Exchange {
+    pub id: u64,
+    /// Whether the entry is the command that opened the exchange.
+    pub command: bool,
+}
+
+struct Open {
+    id: u64,
+    port: usize,
+    last: DateTime<Local>,
+}
+
+pub struct Conversation {
+    next_id: u64,
+    open: Option<Open>,
+    prompt: Option<Regex>,
+    timeout: Duration,
+}
+
+impl Conversation {
+    pub fn new(prompt: Option<Regex>, timeout: Duration) -> Self {
+        Self {
+            next_id: 0,
+            open: None,
+            prompt,
+            timeout,
+        }
+    }
+
+    /// Opens an exchange for a command sent to `port`.
+    pub fn sent(&mut self, port: usize, time: DateTime<Local>) -> Exchange {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.open = Some(Open { id, port, last: time });
+        Exchange { id, command: true }
+    }
+
+    /// The exchange a line received from `port` belongs to, if any.
+    pub fn received(&mut self, port: usize, line: &str, time: DateTime<Local>) -> Option<Exchange> {
+        let open = self.open.as_mut().filter(|open| open.port == port)?;
+        if (time - open.last).to_std().is_ok_and(|quiet| quiet > self.timeout) {
+            self.open = None;
+            return None;
+        }
+        open.last = time;
+        let id = open.id;
+        // The prompt ends the response and is shown with it
+        if self.prompt.as_ref().is_some_and(|prompt| prompt.is_match(line)) {
+            self.open = None;
+        }
+        Some(Exchange { id, command: false })
+    }
+}
+
+/// Adds a reply to the command entry of its exchange. Returns whether the
+/// exchange is collapsed.
+pub fn count_reply(entries: &mut [OutputLine], id: u64) -> bool {
+    match entries
+        .iter_mut()
+        .rev()
+        .find(|e| e.exchange == Some(Exchange { id, command: true }))
+    {
+        Some(command) => {
+            command.replies += 1;
+            command.collapsed
+        }
+        None => false,
+    }
+}
+
+/// Collapses or expands exchange `id`.
+pub fn toggle(entries: &mut [OutputLine], id: u64) {
+    let collapsed = entries
+        .iter()
+        .find(|e| e.exchange.is_some_and(|x| x.id == id))
+        .is_some_and(|e| e.collapsed);
+    for entry in entries.iter_mut().filter(|e| e.exchange.is_some_and(|x| x.id == id)) {
+        entry.collapsed = !collapsed;
+    }
+}
+
+/// Collapses every exchange, or expands them all if any is collapsed.
+pub fn toggle_all(entries: &mut [OutputLine]) {
+    let collapse = !entries.iter().any(|e| e.collapsed);
+    for entry in entries.iter_mut().filter(|e| e.exchange.is_some()) {
+        entry.collapsed = collapse;
+    }
+}
@@ -0,0 +1,101 @@
+//! Columnar view of CSV telemetry.
+//!
+//! Received lines with at least two delimited fields become table rows. Column
+//! names come from the configured header, or from the first line whose fields
+//! are all non-numeric.
+
+use std::collections::VecDeque;
+
+use ratatui::{
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Row, Table},
+    Frame,
+};
+
+/// Rows kept for the table, matching the output pane's memory cap.
+const MAX_ROWS: usize = 1000;
+
+pub struct CsvTable {
+    delimiter: char,
+    header: Option<Vec<String>>,
+    rows: VecDeque<Vec<String>>,
+}
+
+fn is_number(field: &str) -> bool {
+    field.parse::<f64>().is_ok()
+}
+
+impl CsvTable {
+    pub fn new(delimiter: char, header: Vec<String>) -> Self {
+        Self {
+            delimiter,
+            header: (!header.is_empty()).then_some(header),
+            rows: VecDeque::new(),
+        }
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn record(&mut self, line: &str) {
+        let fields: Vec<String> = line.split(self.delimiter).map(|f| f.trim().to_string()).collect();
+        if fields.len() < 2 {
+            return;
+        }
+        if self.header.as_ref().is_some_and(|h| *h == fields) {
+            // Devices often repeat their header after a reset
+            return;
+        }
+        if self.header.is_none() && fields.iter().all(|f| !f.is_empty() && !is_number(f)) {
+            self.header = Some(fields);
+            return;
+        }
+        self.rows.push_back(fields);
+        if self.rows.len() > MAX_ROWS {
+            self.rows.pop_front();
+        }
+    }
+
+    /// Draws the rows ending `scroll_offset` rows above the newest one.
+    pub fn render(&self, f: &mut Frame, area: Rect, title: &str, scroll_offset: usize) {
+        let height = area.height.saturating_sub(3) as usize;
+        let end = self.rows.len().saturating_sub(scroll_offset);
+        let start = end.saturating_sub(height);
+        let visible: Vec<&Vec<String>> = self.rows.range(start..end).collect();
+
+        let header = self.header.as_deref().unwrap_or_default();
+        let columns = visible.iter().map(|r| r.len()).max().unwrap_or(0).max(header.len());
+        let names: Vec<String> = (0..columns)
+            .map(|i| header.get(i).cloned().unwrap_or_else(|| format!("#{}", i + 1)))
+            .collect();
+        let widths: Vec<usize> = (0..columns)
+            .map(|i| {
+                visible
+                    .iter()
+                    .filter_map(|r| r.get(i))
+                    .map(|f| f.chars().count())
+                    .chain([names[i].chars().count()])
+                    .max()
+                    .unwrap_or(1)
+            })
+            .collect();
+
+        // Numbers are right-aligned so digits line up across rows
+        let rows = visible.into_iter().map(|row| {
+            Row::new(row.iter().zip(&widths).map(|(field, &width)| {
+                if is_number(field) {
+                    format!("{field:>width$}")
+                } else {
+                    field.clone()
+                }
+            }))
+            .style(Style::default().fg(Color::Green))
+        });
+        let table = Table::new(rows, widths.iter().map(|&w| Constraint::Length(w as u16)))
+            .header(Row::new(names).style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)))
+            .block(Block::default().title(title).borders(Borders::ALL));
+        f.render_widget(table, area);
+    }
+}
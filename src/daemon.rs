@@ -0,0 +1,104 @@
+//! Running the bridge as a service.
+//!
+//! Under systemd the listening socket can be passed in by a `.socket` unit
+//! (`LISTEN_FDS`), readiness and state are reported with `sd_notify`
+//! (`Type=notify`), and SIGTERM stops the bridge cleanly. Outside systemd a
+//! PID file and a JSON status file let scripts find and check it.
+
+use std::{fs, io, path::PathBuf};
+
+use anyhow::{Context, Result};
+use tokio::net::TcpListener;
+
+/// First file descriptor passed by socket activation.
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// The listening socket passed by systemd socket activation, if any.
+#[cfg(unix)]
+pub fn activated_listener() -> Result<Option<TcpListener>> {
+    use std::os::fd::FromRawFd;
+
+    let for_us = std::env::var("LISTEN_PID").is_ok_and(|pid| pid == std::process::id().to_string());
+    let count: i32 = std::env::var("LISTEN_FDS").ok().and_then(|n| n.parse().ok()).unwrap_or(0);
+    if !for_us || count < 1 {
+        return Ok(None);
+    }
+    // SAFETY: systemd passes the descriptors open and owned by this process
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    listener.set_nonblocking(true).context("The socket passed by systemd is not a TCP listener")?;
+    Ok(Some(TcpListener::from_std(listener)?))
+}
+
+#[cfg(not(unix))]
+pub fn activated_listener() -> Result<Option<TcpListener>> {
+    Ok(None)
+}
+
+/// Sends `state` (e.g. `READY=1`, `STATUS=...`) to the service manager when
+/// running under systemd with `Type=notify`.
+#[cfg(unix)]
+pub fn notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let Ok(socket) = UnixDatagram::unbound() else {
+        return;
+    };
+    let path = path.to_string_lossy();
+    // Names starting with @ are in the abstract namespace
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.strip_prefix('@') {
+        use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+        if let Ok(addr) = SocketAddr::from_abstract_name(name) {
+            let _ = socket.send_to_addr(state.as_bytes(), &addr);
+        }
+        return;
+    }
+    let _ = socket.send_to(state.as_bytes(), &*path);
+}
+
+#[cfg(not(unix))]
+pub fn notify(_state: &str) {}
+
+/// Resolves when the bridge is asked to stop: Ctrl+C, or SIGTERM on Unix.
+pub async fn shutdown() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// A file holding the process ID, removed when dropped.
+pub struct PidFile(PathBuf);
+
+impl PidFile {
+    pub fn create(path: &str) -> Result<Self> {
+        fs::write(path, format!("{}\n", std::process::id())).with_context(|| format!("Cannot write the PID file {path}"))?;
+        Ok(Self(PathBuf::from(path)))
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Replaces the status file at `path` in one step, so readers never see a
+/// partly written one.
+pub fn write_status(path: &str, status: &serde_json::Value) -> io::Result<()> {
+    let temporary = format!("{path}.tmp");
+    fs::write(&temporary, format!("{status:#}\n"))?;
+    fs::rename(&temporary, path)
+}
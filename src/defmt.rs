@@ -0,0 +1,94 @@
+//! Decoding of defmt-encoded log streams using the firmware ELF.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result, anyhow};
+use defmt_decoder::{DecodeError, Encoding, Frame, Locations, Table};
+
+pub struct DefmtDecoder {
+    table: Arc<Table>,
+    locations: Option<Locations>,
+    /// Bytes received that don't make a whole frame yet.
+    buf: Vec<u8>,
+    malformed: bool,
+}
+
+impl DefmtDecoder {
+    pub fn load(elf_path: &str) -> Result<Self> {
+        let elf = std::fs::read(elf_path).with_context(|| format!("Failed to read ELF file {elf_path}"))?;
+        let table = Table::parse(&elf)
+            .with_context(|| format!("Failed to parse defmt data in {elf_path}"))?
+            .ok_or_else(|| anyhow!("{elf_path} contains no defmt data"))?;
+        let locations = table.get_locations(&elf).ok().filter(|l| !l.is_empty());
+        Ok(Self {
+            table: Arc::new(table),
+            locations,
+            buf: Vec::new(),
+            malformed: false,
+        })
+    }
+
+    /// Feeds received bytes and appends every completed log message to `out`.
+    pub fn push(&mut self, data: &[u8], out: &mut Vec<String>) {
+        self.buf.extend_from_slice(data);
+        loop {
+            let decoded = match self.table.encoding() {
+                Encoding::Raw => match self.table.decode(&self.buf) {
+                    Ok((frame, consumed)) => {
+                        let text = format_frame(&frame, self.locations.as_ref());
+                        self.buf.drain(..consumed);
+                        Some(text)
+                    }
+                    Err(DecodeError::UnexpectedEof) => break,
+                    // Raw frames have no separator: resynchronize byte by byte
+                    Err(DecodeError::Malformed) => {
+                        self.buf.remove(0);
+                        None
+                    }
+                },
+                // rzCOBS, the default, ends frames with a zero byte
+                _ => {
+                    let Some(end) = self.buf.iter().position(|&b| b == 0) else {
+                        break;
+                    };
+                    let encoded: Vec<u8> = self.buf.drain(..=end).collect();
+                    if encoded.len() == 1 {
+                        continue;
+                    }
+                    let mut stream = self.table.new_stream_decoder();
+                    stream.received(&encoded);
+                    stream.decode().ok().map(|frame| format_frame(&frame, self.locations.as_ref()))
+                }
+            };
+            match decoded {
+                Some(text) => {
+                    self.malformed = false;
+                    out.push(text);
+                }
+                // Reported once per burst, the frames after it decoded as usual
+                None => {
+                    if !self.malformed {
+                        out.push("<malformed defmt frame>".to_string());
+                    }
+                    self.malformed = true;
+                }
+            }
+        }
+    }
+}
+
+/// Renders a frame as `<timestamp> <LEVEL> <message> (<file>:<line>)`.
+fn format_frame(frame: &Frame, locations: Option<&Locations>) -> String {
+    let mut text = String::new();
+    if let Some(timestamp) = frame.display_timestamp() {
+        text.push_str(&format!("{timestamp} "));
+    }
+    if let Some(level) = frame.level() {
+        text.push_str(&format!("{:<5} ", level.as_str().to_uppercase()));
+    }
+    text.push_str(&frame.display_message().to_string());
+    if let Some(location) = locations.and_then(|l| l.get(&frame.index())) {
+        text.push_str(&format!("  ({}:{})", location.file.display(), location.line));
+    }
+    text
+}
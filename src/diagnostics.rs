@@ -0,0 +1,169 @@
+//! Actionable explanations for ports that fail to open.
+//!
+//! Permission problems name the device's group and how to join it, busy
+//! devices name the process holding them, and missing devices list the
+//! serial ports that do exist.
+
+use std::{io, path::Path};
+
+use tokio_serial::{Error, ErrorKind};
+
+use crate::{discovery, exit::Status};
+
+pub struct Diagnosis {
+    pub problem: String,
+    /// The exit status of the failure, if it has one of its own.
+    pub status: Option<Status>,
+    pub details: Vec<String>,
+    pub hints: Vec<String>,
+}
+
+impl Diagnosis {
+    /// Renders the diagnosis as a headline followed by a framed report.
+    pub fn report(&self, port: &str) -> String {
+        let mut lines = self.details.clone();
+        if !self.hints.is_empty() {
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push("What to try:".to_string());
+            lines.extend(self.hints.iter().map(|h| format!("  - {h}")));
+        }
+        let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let rule = "─".repeat(width + 2);
+        let mut report = format!("Cannot open {port}: {}\n┌{rule}┐\n", self.problem);
+        for line in &lines {
+            report.push_str(&format!("│ {line:<width$} │\n"));
+        }
+        report.push_str(&format!("└{rule}┘"));
+        report
+    }
+}
+
+pub fn diagnose(port: &str, error: &Error) -> Diagnosis {
+    let name = discovery::device_name(port);
+    let exists = Path::new(port).exists() || discovery::list().iter().any(|p| p.name.eq_ignore_ascii_case(name));
+    match error.kind {
+        ErrorKind::Io(io::ErrorKind::PermissionDenied) => permission_denied(port),
+        ErrorKind::NoDevice if exists => busy(port),
+        ErrorKind::Io(io::ErrorKind::NotFound) | ErrorKind::NoDevice => missing(port),
+        _ if !exists => missing(port),
+        _ => Diagnosis {
+            problem: error.description.clone(),
+            status: None,
+            details: Vec::new(),
+            hints: vec!["Check that the device is a serial port and supports the requested settings".to_string()],
+        },
+    }
+}
+
+fn permission_denied(port: &str) -> Diagnosis {
+    let mut details = Vec::new();
+    let mut hints = Vec::new();
+    match device_group(port) {
+        Some(group) => {
+            details.push(format!("The device belongs to group '{group}'"));
+            hints.push(format!("Add yourself to the group: sudo usermod -aG {group} $USER"));
+            hints.push("Log out and back in (or run `newgrp`) for the membership to apply".to_string());
+        }
+        None => hints.push("Add yourself to the group owning the device (usually dialout or uucp)".to_string()),
+    }
+    hints.push("Alternatively, install a udev rule granting access to this device".to_string());
+    Diagnosis {
+        problem: "permission denied".to_string(),
+        status: Some(Status::PermissionDenied),
+        details,
+        hints,
+    }
+}
+
+fn busy(port: &str) -> Diagnosis {
+    let holders = holding_processes(port);
+    let details = if holders.is_empty() {
+        vec!["Another program has opened the port exclusively".to_string()]
+    } else {
+        holders.iter().map(|(pid, name)| format!("Held by PID {pid} ({name})")).collect()
+    };
+    let mut hints = vec!["Close the other serial monitor, IDE serial console or flashing tool".to_string()];
+    if holders.iter().any(|(_, name)| name == "ModemManager") {
+        hints.push("ModemManager probes new ACM devices; stop it or mark the device with ID_MM_DEVICE_IGNORE".to_string());
+    }
+    Diagnosis {
+        problem: "the device is busy".to_string(),
+        status: Some(Status::PortBusy),
+        details,
+        hints,
+    }
+}
+
+fn missing(port: &str) -> Diagnosis {
+    let mut ports = discovery::list();
+    let details = if ports.is_empty() {
+        vec!["No serial ports were found".to_string()]
+    } else {
+        let stem = discovery::device_name(port).trim_end_matches(|c: char| c.is_ascii_digit());
+        // Ports of the same kind (e.g. other /dev/ttyUSB*) first
+        ports.sort_by_key(|p| !p.name.starts_with(stem));
+        std::iter::once("Available ports:".to_string())
+            .chain(ports.into_iter().map(|p| format!("  {}  {}", p.name, p.description).trim_end().to_string()))
+            .collect()
+    };
+    Diagnosis {
+        problem: "no such device".to_string(),
+        status: Some(Status::PortNotFound),
+        details,
+        hints: vec![
+            "Check the cable and that the board is powered".to_string(),
+            "Pick one of the available ports with --port".to_string(),
+        ],
+    }
+}
+
+#[cfg(unix)]
+fn device_group(port: &str) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let gid = std::fs::metadata(port).ok()?.gid();
+    let groups = std::fs::read_to_string("/etc/group").ok()?;
+    groups.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let id = fields.nth(1)?.parse::<u32>().ok()?;
+        (id == gid).then(|| name.to_string())
+    })
+}
+
+#[cfg(not(unix))]
+fn device_group(_port: &str) -> Option<String> {
+    None
+}
+
+/// Finds processes with the device open by scanning `/proc/<pid>/fd`.
+#[cfg(target_os = "linux")]
+fn holding_processes(port: &str) -> Vec<(u32, String)> {
+    let Ok(device) = std::fs::canonicalize(port) else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let mut holders = Vec::new();
+    for entry in entries.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|n| n.parse::<u32>().ok()) else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        if fds.flatten().any(|fd| std::fs::read_link(fd.path()).is_ok_and(|target| target == device)) {
+            let name = std::fs::read_to_string(entry.path().join("comm")).unwrap_or_default();
+            holders.push((pid, name.trim().to_string()));
+        }
+    }
+    holders
+}
+
+#[cfg(not(target_os = "linux"))]
+fn holding_processes(_port: &str) -> Vec<(u32, String)> {
+    Vec::new()
+}
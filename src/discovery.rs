@@ -0,0 +1,61 @@
+//! Enumeration of the serial ports present on the system.
+//!
+//! On Windows the description is the device's friendly name (e.g.
+//! `USB Serial Device (COM7)`); elsewhere it is built from the USB product and
+//! manufacturer strings where available.
+
+use tokio_serial::SerialPortType;
+
+/// Prefix of Win32 device paths, required for COM ports above COM9.
+pub const WIN32_DEVICE_PREFIX: &str = r"\\.\";
+
+pub struct PortInfo {
+    pub name: String,
+    pub description: String,
+}
+
+pub fn list() -> Vec<PortInfo> {
+    let mut ports: Vec<PortInfo> = tokio_serial::available_ports()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|port| PortInfo {
+            description: describe(&port.port_type),
+            name: port.port_name,
+        })
+        .collect();
+    ports.sort_by_key(|port| natural_key(&port.name));
+    ports
+}
+
+fn describe(port_type: &SerialPortType) -> String {
+    match port_type {
+        SerialPortType::UsbPort(usb) => {
+            let ids = format!("USB {:04x}:{:04x}", usb.vid, usb.pid);
+            match (&usb.product, &usb.manufacturer) {
+                // Windows friendly names already carry the product
+                (Some(product), Some(manufacturer)) if !product.contains(manufacturer.as_str()) => {
+                    format!("{product} - {manufacturer} ({ids})")
+                }
+                (Some(product), _) => format!("{product} ({ids})"),
+                (None, Some(manufacturer)) => format!("{manufacturer} ({ids})"),
+                (None, None) => ids,
+            }
+        }
+        SerialPortType::BluetoothPort => "Bluetooth".to_string(),
+        SerialPortType::PciPort => "PCI".to_string(),
+        SerialPortType::Unknown => String::new(),
+    }
+}
+
+/// Sorts `COM10` after `COM9` and `ttyUSB10` after `ttyUSB2`.
+fn natural_key(name: &str) -> (String, u64) {
+    let digits = name.len() - name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (stem, number) = name.split_at(name.len() - digits);
+    (stem.to_string(), number.parse().unwrap_or(0))
+}
+
+/// Strips a `\\.\` prefix from Windows port names; the serial driver adds it
+/// itself, which is what makes `COM10` and above openable.
+pub fn device_name(port: &str) -> &str {
+    port.strip_prefix(WIN32_DEVICE_PREFIX).unwrap_or(port)
+}
@@ -0,0 +1,40 @@
+//! DMX512 frames.
+//!
+//! A DMX512 universe is sent as a break, a start code and up to 512 channel
+//! levels at 250000 baud, 8N2, over and over. A UART reads the break as a
+//! zero byte with a framing error, and idle framing cuts the stream at the
+//! gap after each frame, so a frame is shown as its start code and the
+//! levels of the channels that aren't off, e.g. `start 00, 512 ch: 1=255 4=128`.
+
+/// Channels listed before the rest of a frame is only counted.
+const MOST_SHOWN: usize = 32;
+
+pub fn decode(frame: &[u8]) -> String {
+    // The zero read for the break, when the frame is too long without it
+    let frame = match frame {
+        [0, rest @ ..] if rest.len() > 512 => rest,
+        frame => frame,
+    };
+    let Some((&start, channels)) = frame.split_first() else {
+        return "<empty frame>".to_string();
+    };
+    let kind = match start {
+        0x00 => "",
+        0x17 => " (text)",
+        0xCC => " (RDM)",
+        0xCF => " (system information)",
+        _ => " (alternate)",
+    };
+    let lit: Vec<String> = channels
+        .iter()
+        .enumerate()
+        .filter(|(_, level)| **level > 0)
+        .map(|(channel, level)| format!("{}={level}", channel + 1))
+        .collect();
+    let levels = match lit.len() {
+        0 => "all off".to_string(),
+        n if n > MOST_SHOWN => format!("{} … {} more", lit[..MOST_SHOWN].join(" "), n - MOST_SHOWN),
+        _ => lit.join(" "),
+    };
+    format!("start {start:02X}{kind}, {} ch: {levels}", channels.len())
+}
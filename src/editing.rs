@@ -0,0 +1,54 @@
+//! Cursor arithmetic of the input line.
+//!
+//! The cursor counts characters rather than bytes, so text typed with dead
+//! keys, AltGr or an input method (accented letters, CJK, emoji) is edited
+//! a whole character at a time. On screen each character takes the columns
+//! the terminal gives it, two for wide ones and none for combining marks.
+
+use ratatui::text::Span;
+
+/// Number of characters in `text`, the cursor position at its end.
+pub fn len(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Byte offset of the character at `cursor`; the end of `text` past it.
+pub fn byte_index(text: &str, cursor: usize) -> usize {
+    text.char_indices().nth(cursor).map_or(text.len(), |(i, _)| i)
+}
+
+/// Columns taken by `text` on screen.
+pub fn width(text: &str) -> usize {
+    Span::raw(text).width()
+}
+
+/// `text` wrapped into rows of at most `columns` columns, and the column
+/// and row of the cursor. A wide character that doesn't fit at the end of
+/// a row starts the next one.
+pub fn wrap(text: &str, cursor: usize, columns: usize) -> (Vec<String>, (usize, usize)) {
+    let mut rows = vec![String::new()];
+    let mut used = 0;
+    let mut at = (0, 0);
+    let mut buf = [0; 4];
+    for (i, c) in text.chars().enumerate() {
+        let w = width(c.encode_utf8(&mut buf));
+        if used + w > columns && used > 0 {
+            rows.push(String::new());
+            used = 0;
+        }
+        if i == cursor {
+            at = (used, rows.len() - 1);
+        }
+        rows.last_mut().expect("there is always a row").push(c);
+        used += w;
+    }
+    if cursor >= len(text) {
+        // The cursor after a full row goes to the start of the next
+        if used >= columns && used > 0 {
+            rows.push(String::new());
+            used = 0;
+        }
+        at = (used, rows.len() - 1);
+    }
+    (rows, at)
+}
@@ -0,0 +1,51 @@
+//! Composing payloads in an external editor.
+//!
+//! The editor named by `$VISUAL` or `$EDITOR` is opened on a temporary file
+//! holding the current input; what is saved there is sent when it exits.
+
+use std::{fs, process::Command};
+
+use anyhow::{Context, Result, bail};
+
+fn editor() -> String {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_string())
+}
+
+/// Lets the user edit `draft` and returns the saved text, or `None` if the
+/// file was left empty. The terminal must be out of raw mode meanwhile.
+pub fn compose(draft: &str) -> Result<Option<String>> {
+    let path = std::env::temp_dir().join(format!("serial-monitor-{}.txt", std::process::id()));
+    fs::write(&path, draft).with_context(|| format!("Cannot write {}", path.display()))?;
+    let editor = editor();
+    // The variable may carry arguments, e.g. "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Cannot start the editor {editor:?}"));
+    let text = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    let status = status?;
+    if !status.success() {
+        bail!("{editor:?} exited with {status}; nothing sent");
+    }
+    let text = text.with_context(|| format!("Cannot read {}", path.display()))?;
+    Ok(Some(text).filter(|t| !t.trim().is_empty()))
+}
+
+/// Splits composed text into the lines to send. A JSON document is sent
+/// compacted on one line, since line-based firmware reads one line at a time.
+pub fn lines(text: &str) -> Vec<String> {
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(text)
+        && (value.is_object() || value.is_array())
+    {
+        return vec![value.to_string()];
+    }
+    text.trim_end_matches(['\r', '\n']).lines().map(str::to_string).collect()
+}
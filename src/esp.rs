@@ -0,0 +1,93 @@
+//! ESP-IDF panic backtrace symbolization.
+//!
+//! Code addresses in `Backtrace:` lines and register dumps are resolved with
+//! an addr2line-compatible tool against the firmware ELF, and the resulting
+//! `function at file:line` entries are shown below the crash output.
+
+use ratatui::style::{Color, Style};
+use tokio::{process::Command, sync::mpsc};
+
+use crate::output::OutputLine;
+
+/// Registers whose values are code addresses in Xtensa and RISC-V panic dumps.
+const CODE_REGISTERS: &[&str] = &["PC", "MEPC", "RA", "EPC1", "EPC2", "EPC3", "EPC4"];
+
+pub struct Symbolizer {
+    elf: String,
+    addr2line: String,
+    annotations: mpsc::UnboundedSender<OutputLine>,
+}
+
+/// Returns true for lines from an ESP crash report that carry code addresses.
+fn is_crash_line(line: &str) -> bool {
+    let line = line.trim_start();
+    if line.contains("Backtrace:") {
+        return true;
+    }
+    CODE_REGISTERS.iter().any(|reg| {
+        line.strip_prefix(reg)
+            .is_some_and(|rest| rest.trim_start().starts_with(':'))
+    })
+}
+
+/// Extracts every `0x4xxxxxxx` address (ESP32 code regions) from `line`.
+fn code_addresses(line: &str) -> Vec<String> {
+    let mut addresses: Vec<String> = Vec::new();
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while let Some(pos) = line[i..].find("0x") {
+        let start = i + pos;
+        let digits = &bytes[start + 2..];
+        let len = digits.iter().take_while(|b| b.is_ascii_hexdigit()).count();
+        if len == 8 && digits[0] == b'4' {
+            let address = line[start..start + 10].to_ascii_lowercase();
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+        i = start + 2 + len;
+    }
+    addresses
+}
+
+impl Symbolizer {
+    pub fn new(elf: String, addr2line: String, annotations: mpsc::UnboundedSender<OutputLine>) -> Self {
+        Self {
+            elf,
+            addr2line,
+            annotations,
+        }
+    }
+
+    /// Resolves the addresses of a crash line in the background.
+    pub fn inspect(&self, line: &str) {
+        if !is_crash_line(line) {
+            return;
+        }
+        let addresses = code_addresses(line);
+        if addresses.is_empty() {
+            return;
+        }
+        let mut command = Command::new(&self.addr2line);
+        command.args(["-pfiaC", "-e", &self.elf]).args(&addresses);
+        let annotations = self.annotations.clone();
+        let tool = self.addr2line.clone();
+        tokio::spawn(async move {
+            let style = Style::default().fg(Color::LightMagenta);
+            match command.output().await {
+                Ok(output) if output.status.success() => {
+                    for symbol in String::from_utf8_lossy(&output.stdout).lines() {
+                        let _ = annotations.send(OutputLine::new("[ESP] ", symbol.to_string(), style));
+                    }
+                }
+                Ok(output) => {
+                    let error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                    let _ = annotations.send(OutputLine::new("[ESP] ", format!("{tool} failed: {error}"), style));
+                }
+                Err(e) => {
+                    let _ = annotations.send(OutputLine::new("[ESP] ", format!("Cannot run {tool}: {e}"), style));
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,44 @@
+//! Events published by the port tasks.
+//!
+//! Everything that happens on a port (decoded frames, sent data, the
+//! connection coming and going, I/O errors) reaches the UI, the logs and
+//! other consumers as a [`PortEvent`] on one channel, stamped when it
+//! happened so events from several ports can be ordered chronologically.
+
+use std::borrow::Cow;
+
+use chrono::{DateTime, Local};
+
+use crate::transport::LineErrors;
+
+pub enum SerialEvent {
+    /// A decoded frame and the line terminator that ended it, if any, with
+    /// the bytes it was decoded from and how that went.
+    Rx {
+        text: String,
+        ending: &'static str,
+        raw: Vec<u8>,
+        decoded: Cow<'static, str>,
+    },
+    /// Bytes as read from the device, before framing.
+    Chunk(Vec<u8>),
+    /// Bytes written to the device.
+    Tx(Vec<u8>),
+    Connected,
+    /// The remote side closed the connection or the port was closed.
+    Disconnected,
+    /// A failed read or write; the port stays open.
+    Error(String),
+    /// Something done for the port that isn't a failure, e.g. the device
+    /// power-cycled while it couldn't be reopened.
+    Notice(String),
+    /// Receive errors the driver counted since the last report.
+    LineErrors(LineErrors),
+}
+
+pub struct PortEvent {
+    /// Index of the port in the order the ports were opened.
+    pub port: usize,
+    pub time: DateTime<Local>,
+    pub event: SerialEvent,
+}
@@ -0,0 +1,58 @@
+//! Exit statuses for scripts that run the monitor.
+//!
+//! Failures a wrapper script can act on end the process with a status of
+//! their own instead of 1, so it doesn't have to parse the message on
+//! stderr to tell a missing board from one it may not open:
+//!
+//! | Status | Meaning |
+//! |-------:|:--------|
+//! | 0 | Success |
+//! | 1 | Any other error |
+//! | 2 | Invalid command line |
+//! | 3 | The port doesn't exist |
+//! | 4 | Permission to open the port was denied |
+//! | 5 | A port was disconnected for good, e.g. with `--fail-on-disconnect` |
+//! | 6 | A test failed: an `expect` timed out or a `check` failed, or the lines differed from `--expect` |
+//! | 7 | The port is held by another program |
+
+use std::fmt;
+
+/// A failure with a status of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    PortNotFound = 3,
+    PermissionDenied = 4,
+    Disconnected = 5,
+    TestFailed = 6,
+    PortBusy = 7,
+}
+
+/// An error ending the process with `status`.
+#[derive(Debug)]
+pub struct Failure {
+    pub status: Status,
+    message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// An error with `message` that ends the process with `status`.
+pub fn failure(status: Status, message: impl Into<String>) -> anyhow::Error {
+    Failure { status, message: message.into() }.into()
+}
+
+/// The status of its own `error` ends the process with, if it has one.
+pub fn status(error: &anyhow::Error) -> Option<Status> {
+    error.chain().find_map(|cause| cause.downcast_ref::<Failure>()).map(|failure| failure.status)
+}
+
+/// The exit status of the process ending with `error`.
+pub fn code(error: &anyhow::Error) -> u8 {
+    status(error).map_or(1, |status| status as u8)
+}
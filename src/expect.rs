@@ -0,0 +1,160 @@
+//! Received lines checked against a golden file.
+//!
+//! With `--expect boot.txt` every line received from the first port is
+//! compared, in order, with the next line of the file, and lines that
+//! differ are highlighted with the line expected instead, so a boot log
+//! that strays from its golden sequence is seen as it happens. Parts that
+//! change from run to run, such as timestamps, are left out of the
+//! comparison with `--expect-ignore`.
+//!
+//! Like a diff, the check resynchronizes: a line matching one a few lines
+//! further down the file skips to it, and the expected lines passed over
+//! are reported missing unless differing lines were received in their
+//! place. Once the end of the file is reached the result is reported and
+//! later lines aren't compared. Blank lines of the file aren't expected, as
+//! no line received is empty.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+
+/// How far down the file, past the lines the differing ones received stand
+/// for, a received line is looked for when it isn't the next one expected.
+const LOOKAHEAD: usize = 16;
+
+pub struct Expected {
+    /// Name of the golden file, for messages.
+    name: String,
+    lines: Vec<String>,
+    /// Where the lines are in the file, counting from 1.
+    numbers: Vec<usize>,
+    /// The lines as compared, with the ignored parts removed.
+    keys: Vec<String>,
+    ignore: Vec<Regex>,
+    /// Index of the next line expected.
+    next: usize,
+    /// Differing lines received since the last match.
+    differing: usize,
+    matched: usize,
+    differed: usize,
+    missing: usize,
+}
+
+/// The outcome of checking a received line.
+#[derive(Default)]
+pub struct Checked {
+    /// The line expected instead, if the received one differs.
+    pub expected: Option<String>,
+    /// Expected lines found missing.
+    pub missing: Vec<String>,
+    /// The result, once the end of the file is reached.
+    pub finished: Option<String>,
+}
+
+impl Expected {
+    /// The golden file at `path`, compared without the matches of `ignore`.
+    pub fn load(path: &str, ignore: Vec<Regex>) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read expected output {path}"))?;
+        let (numbers, lines): (Vec<usize>, Vec<String>) = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| (index + 1, line.to_string()))
+            .unzip();
+        if lines.is_empty() {
+            bail!("The expected output {path} is empty");
+        }
+        let name = Path::new(path).file_name().map_or(path.to_string(), |name| name.to_string_lossy().into_owned());
+        let mut expected = Self {
+            name,
+            keys: Vec::new(),
+            lines,
+            numbers,
+            ignore,
+            next: 0,
+            differing: 0,
+            matched: 0,
+            differed: 0,
+            missing: 0,
+        };
+        expected.keys = expected.lines.iter().map(|line| expected.key(line)).collect();
+        Ok(expected)
+    }
+
+    /// `line` as compared: without the ignored parts and trailing spaces.
+    fn key(&self, line: &str) -> String {
+        let mut key = line.to_string();
+        for pattern in &self.ignore {
+            key = pattern.replace_all(&key, "").into_owned();
+        }
+        key.trim_end().to_string()
+    }
+
+    /// Whether every expected line was received or found missing.
+    fn is_done(&self) -> bool {
+        self.next == self.lines.len()
+    }
+
+    /// Compares the next received line with the file.
+    pub fn check(&mut self, line: &str) -> Checked {
+        let mut checked = Checked::default();
+        if self.is_done() {
+            return checked;
+        }
+        let key = self.key(line);
+        let ahead = self.keys[self.next..].iter().take(self.differing + LOOKAHEAD).position(|expected| *expected == key);
+        match ahead {
+            Some(skipped) => {
+                // The differing lines stand for the first lines passed over
+                let changed = skipped.min(self.differing);
+                for (index, expected) in self.lines.iter().enumerate().skip(self.next + changed).take(skipped - changed) {
+                    checked.missing.push(format!("Missing line {} of {}: {expected}", self.numbers[index], self.name));
+                }
+                self.missing += skipped - changed;
+                self.matched += 1;
+                self.next += skipped + 1;
+                self.differing = 0;
+            }
+            None => {
+                let index = (self.next + self.differing).min(self.lines.len() - 1);
+                checked.expected = Some(self.lines[index].clone());
+                self.differing += 1;
+                self.differed += 1;
+            }
+        }
+        if self.is_done() {
+            checked.finished = Some(match self.passed() {
+                true => format!("All {} lines of {} matched", self.lines.len(), self.name),
+                false => format!("End of {}: {}", self.name, self.summary()),
+            });
+        }
+        checked
+    }
+
+    /// Whether no line differed or was missing so far.
+    pub fn passed(&self) -> bool {
+        self.differed + self.missing == 0
+    }
+
+    /// Whether every expected line was received as expected.
+    pub fn complete(&self) -> bool {
+        self.is_done() && self.passed()
+    }
+
+    /// Counts of the lines matched, differing and missing, e.g. for the
+    /// session summary.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{}/{} lines matched, {} differing, {} missing",
+            self.matched,
+            self.lines.len(),
+            self.differed,
+            self.missing
+        );
+        if !self.is_done() {
+            summary.push_str(&format!(", {} not received yet", self.lines.len() - self.next));
+        }
+        summary
+    }
+}
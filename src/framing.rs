@@ -0,0 +1,423 @@
+//! Splitting of the received byte stream into frames and their display text.
+
+use std::{borrow::Cow, time::Duration};
+
+use clap::ValueEnum;
+
+use crate::{defmt::DefmtDecoder, dmx, midi::MidiDecoder, proto::ProtoDecoder, slcan, transport::Encoding};
+
+/// Frames announcing a larger payload are treated as corruption.
+const MAX_FRAME_LEN: usize = 64 * 1024;
+
+/// Bytes of the longest varint length read: four hold 28 bits, far more
+/// than `MAX_FRAME_LEN`, and fit a 32-bit `usize`.
+const VARINT_BYTES: usize = 4;
+
+/// Lines longer than this are split into parts unless set otherwise.
+pub const MAX_LINE_LEN: usize = 4096;
+
+/// Marks the end of a part of a split line; the line goes on below.
+pub const CONTINUED: &str = " \u{21a9}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Framing {
+    /// Frames are terminated by `\n` or `\r`
+    Lines,
+    /// Each frame starts with its payload length
+    LengthPrefixed,
+    /// Frames end with the --delimiter byte sequence
+    Delimiter,
+    /// Frames are --frame-length bytes long
+    Fixed,
+    /// Frames end when no byte arrives for --frame-timeout
+    Idle,
+}
+
+/// Parses a delimiter such as `\x03` or `\r\n`: text with `\xNN`, `\r`, `\n`,
+/// `\t`, `\0` and `\\` escapes.
+pub fn parse_delimiter(text: &str) -> Result<Vec<u8>, String> {
+    let invalid = || format!("Invalid delimiter {text:?}. Expected e.g. \\x03 or \\r\\n");
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut utf8 = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+            continue;
+        }
+        bytes.push(match chars.next().ok_or_else(invalid)? {
+            'r' => b'\r',
+            'n' => b'\n',
+            't' => b'\t',
+            '0' => 0,
+            '\\' => b'\\',
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                u8::from_str_radix(&hex, 16).map_err(|_| invalid())?
+            }
+            _ => return Err(invalid()),
+        });
+    }
+    if bytes.is_empty() {
+        return Err(invalid());
+    }
+    Ok(bytes)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LengthPrefix {
+    /// Protobuf-style base-128 varint
+    Varint,
+    U8,
+    U16Be,
+    U16Le,
+    U32Be,
+    U32Le,
+}
+
+impl LengthPrefix {
+    /// Parses a length at the start of `buf`, returning `(length, prefix size)`
+    /// or `None` if more bytes are needed.
+    fn parse(self, buf: &[u8]) -> Option<(usize, usize)> {
+        let fixed = |n: usize| buf.get(..n);
+        Some(match self {
+            LengthPrefix::Varint => {
+                let mut value = 0usize;
+                for (i, &b) in buf.iter().enumerate().take(VARINT_BYTES) {
+                    value |= ((b & 0x7f) as usize) << (7 * i);
+                    if b & 0x80 == 0 {
+                        return Some((value, i + 1));
+                    }
+                }
+                // A longer varint can't be a sane length, resync below.
+                return (buf.len() >= VARINT_BYTES).then_some((usize::MAX, VARINT_BYTES));
+            }
+            LengthPrefix::U8 => (fixed(1)?[0] as usize, 1),
+            LengthPrefix::U16Be => (u16::from_be_bytes(fixed(2)?.try_into().ok()?) as usize, 2),
+            LengthPrefix::U16Le => (u16::from_le_bytes(fixed(2)?.try_into().ok()?) as usize, 2),
+            LengthPrefix::U32Be => (u32::from_be_bytes(fixed(4)?.try_into().ok()?) as usize, 4),
+            LengthPrefix::U32Le => (u32::from_le_bytes(fixed(4)?.try_into().ok()?) as usize, 4),
+        })
+    }
+}
+
+/// A unit produced by the framer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Data(Vec<u8>),
+    /// A line and the terminator that ended it.
+    Line(Vec<u8>, &'static str),
+    /// A part of a line longer than the maximum length; the rest follows in
+    /// the next frames.
+    Part(Vec<u8>),
+    /// The stream announced an implausible length; the buffer was discarded.
+    Invalid(usize),
+    /// A message already decoded while framing (defmt).
+    Text(String),
+}
+
+impl Frame {
+    /// Terminator of a line frame, empty for other frames.
+    pub fn ending(&self) -> &'static str {
+        match self {
+            Frame::Line(_, ending) => ending,
+            _ => "",
+        }
+    }
+
+    /// The bytes as received, terminator included; empty for frames
+    /// decoded while framing or discarded.
+    pub fn raw(&self) -> Vec<u8> {
+        match self {
+            Frame::Data(data) | Frame::Part(data) => data.clone(),
+            Frame::Line(data, ending) => [data.as_slice(), ending.as_bytes()].concat(),
+            Frame::Invalid(_) | Frame::Text(_) => Vec::new(),
+        }
+    }
+}
+
+/// Incremental frame assembler.
+pub struct Framer {
+    framing: Framing,
+    prefix: LengthPrefix,
+    delimiter: Vec<u8>,
+    length: usize,
+    timeout: Duration,
+    /// Quiet time after which a partial line is shown, e.g. a prompt.
+    line_timeout: Option<Duration>,
+    /// Length at which a line, delimited or idle frame is passed on as a
+    /// part, so a device sending without terminators doesn't grow the
+    /// buffer without bound.
+    max_line: usize,
+    /// Whether parts end at a UTF-8 character boundary.
+    utf8: bool,
+    buf: Vec<u8>,
+    /// Whether `buf` is a line whose CR ended the last read, held until the
+    /// next byte shows whether an LF follows.
+    cr: bool,
+    defmt: Option<DefmtDecoder>,
+}
+
+impl Framer {
+    pub fn new(framing: Framing, prefix: LengthPrefix) -> Self {
+        Self {
+            framing,
+            prefix,
+            delimiter: vec![b'\n'],
+            length: 16,
+            timeout: Duration::from_millis(20),
+            line_timeout: None,
+            max_line: MAX_LINE_LEN,
+            utf8: false,
+            buf: Vec::new(),
+            cr: false,
+            defmt: None,
+        }
+    }
+
+    /// Sets the byte sequence ending `delimiter` frames; it stays part of the frame.
+    pub fn with_delimiter(mut self, delimiter: Vec<u8>) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Sets the length of `fixed` frames.
+    pub fn with_length(mut self, length: usize) -> Self {
+        self.length = length.max(1);
+        self
+    }
+
+    /// Sets the quiet time ending `idle` frames.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the quiet time after which `lines` framing passes on a line
+    /// without its terminator.
+    pub fn with_line_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.line_timeout = timeout;
+        self
+    }
+
+    /// Sets the length at which `lines`, `delimiter` and `idle` framing
+    /// split a frame into parts.
+    pub fn with_max_line(mut self, length: usize) -> Self {
+        self.max_line = length.max(1);
+        self
+    }
+
+    /// Sets the encoding of the text, so UTF-8 parts don't cut a character
+    /// in two.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.utf8 = encoding == Encoding::Utf8;
+        self
+    }
+
+    /// How long after the last byte the partial frame is passed on, for
+    /// `idle` framing and lines with a timeout.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        if self.buf.is_empty() || self.defmt.is_some() {
+            return None;
+        }
+        match self.framing {
+            Framing::Idle => Some(self.timeout),
+            Framing::Lines if self.cr => Some(self.timeout),
+            Framing::Lines => self.line_timeout,
+            _ => None,
+        }
+    }
+
+    /// Passes on the partial frame once the line has been quiet.
+    pub fn flush(&mut self, out: &mut Vec<Frame>) {
+        if std::mem::take(&mut self.cr) {
+            out.push(Frame::Line(std::mem::take(&mut self.buf), "\r"));
+        } else if !self.buf.is_empty() {
+            out.push(Frame::Data(std::mem::take(&mut self.buf)));
+        }
+    }
+
+    /// Passes on the buffer as a part of a frame too long, keeping back a
+    /// character or delimiter it ends in the middle of for the next part.
+    fn split(&mut self, out: &mut Vec<Frame>) {
+        let mut cut = self.buf.len();
+        if self.framing == Framing::Delimiter {
+            cut -= (1..self.delimiter.len())
+                .rev()
+                .find(|&n| self.buf.ends_with(&self.delimiter[..n]))
+                .unwrap_or(0);
+        }
+        if self.utf8 {
+            // The lead byte of a sequence the part would end in the middle of
+            let start = self.buf[..cut].iter().rev().take(4).position(|b| b & 0xc0 != 0x80).map(|n| cut - 1 - n);
+            if let Some(start) = start {
+                let needed = match self.buf[start] {
+                    0xf0.. => 4,
+                    0xe0.. => 3,
+                    0xc0.. => 2,
+                    _ => 1,
+                };
+                if cut - start < needed {
+                    cut = start;
+                }
+            }
+        }
+        if cut == 0 {
+            cut = self.buf.len();
+        }
+        let rest = self.buf.split_off(cut);
+        out.push(Frame::Part(std::mem::replace(&mut self.buf, rest)));
+    }
+
+    /// A framer for defmt streams, whose framing is part of the encoding.
+    pub fn defmt(decoder: DefmtDecoder) -> Self {
+        Self {
+            defmt: Some(decoder),
+            ..Self::new(Framing::Lines, LengthPrefix::Varint)
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8], out: &mut Vec<Frame>) {
+        if let Some(defmt) = &mut self.defmt {
+            let mut messages = Vec::new();
+            defmt.push(data, &mut messages);
+            out.extend(messages.into_iter().map(Frame::Text));
+            return;
+        }
+        match self.framing {
+            Framing::Lines => {
+                let mut bytes = data.iter().copied().peekable();
+                // A CR\LF split across reads still ends a single line
+                if self.cr && !data.is_empty() {
+                    self.cr = false;
+                    let ending = if bytes.next_if_eq(&b'\n').is_some() { "\r\n" } else { "\r" };
+                    out.push(Frame::Line(std::mem::take(&mut self.buf), ending));
+                }
+                while let Some(b) = bytes.next() {
+                    let ending = match b {
+                        b'\r' if bytes.next_if_eq(&b'\n').is_some() => "\r\n",
+                        b'\r' if bytes.peek().is_none() && !self.buf.is_empty() => {
+                            self.cr = true;
+                            continue;
+                        }
+                        b'\r' => "\r",
+                        b'\n' => "\n",
+                        _ => {
+                            if self.buf.len() >= self.max_line {
+                                self.split(out);
+                            }
+                            self.buf.push(b);
+                            continue;
+                        }
+                    };
+                    if !self.buf.is_empty() {
+                        out.push(Frame::Line(std::mem::take(&mut self.buf), ending));
+                    }
+                }
+            }
+            Framing::LengthPrefixed => {
+                self.buf.extend_from_slice(data);
+                while let Some((len, header)) = self.prefix.parse(&self.buf) {
+                    // Resynchronize a byte at a time, reporting a run of
+                    // invalid lengths once
+                    if len > MAX_FRAME_LEN {
+                        self.buf.remove(0);
+                        if !matches!(out.last(), Some(Frame::Invalid(_))) {
+                            out.push(Frame::Invalid(len));
+                        }
+                        continue;
+                    }
+                    if self.buf.len() < header + len {
+                        break;
+                    }
+                    let frame = self.buf[header..header + len].to_vec();
+                    self.buf.drain(..header + len);
+                    out.push(Frame::Data(frame));
+                }
+            }
+            Framing::Delimiter => {
+                for &b in data {
+                    self.buf.push(b);
+                    if self.buf.ends_with(&self.delimiter) {
+                        out.push(Frame::Data(std::mem::take(&mut self.buf)));
+                    } else if self.buf.len() >= self.max_line {
+                        self.split(out);
+                    }
+                }
+            }
+            Framing::Fixed => {
+                self.buf.extend_from_slice(data);
+                while self.buf.len() >= self.length {
+                    let rest = self.buf.split_off(self.length);
+                    out.push(Frame::Data(std::mem::replace(&mut self.buf, rest)));
+                }
+            }
+            Framing::Idle => {
+                for &b in data {
+                    self.buf.push(b);
+                    if self.buf.len() >= self.max_line {
+                        self.split(out);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Turns frames into the text shown in the output pane.
+pub enum Decoder {
+    Text(Encoding),
+    Hex,
+    Proto(ProtoDecoder),
+    Slcan,
+    Midi(MidiDecoder),
+    Dmx,
+}
+
+impl Decoder {
+    /// The display text of `frame` and how it was decoded, e.g. whether
+    /// invalid UTF-8 had to be replaced.
+    pub fn decode(&self, frame: &Frame) -> (String, Cow<'static, str>) {
+        let (data, part) = match frame {
+            Frame::Data(data) | Frame::Line(data, _) => (data, false),
+            Frame::Part(data) => (data, true),
+            Frame::Invalid(len) => {
+                return (
+                    format!("<invalid frame length {len}, resynchronizing>"),
+                    format!("Invalid frame length {len}; the buffer was discarded").into(),
+                );
+            }
+            Frame::Text(text) => return (text.clone(), "defmt frame".into()),
+        };
+        let (mut text, decoded) = match self {
+            Decoder::Text(Encoding::Latin1) => {
+                (data.iter().map(|&b| char::from(b)).collect(), "Latin-1 text".into())
+            }
+            Decoder::Text(Encoding::Utf8) => {
+                let invalid = data.utf8_chunks().filter(|chunk| !chunk.invalid().is_empty()).count();
+                let status = match invalid {
+                    0 => "UTF-8 text".into(),
+                    n => format!("Invalid UTF-8: {n} sequence(s) replaced with U+FFFD").into(),
+                };
+                (String::from_utf8_lossy(data).into_owned(), status)
+            }
+            Decoder::Hex => (hex(data), "Shown as hex".into()),
+            Decoder::Proto(proto) => match proto.decode(data) {
+                Ok(text) => (text, "Protobuf message".into()),
+                Err(e) => (format!("<{e}> {}", hex(data)), format!("Protobuf decoding failed: {e}").into()),
+            },
+            Decoder::Slcan => (slcan::decode(&String::from_utf8_lossy(data)), "slcan frame".into()),
+            Decoder::Midi(midi) => (midi.decode(data), "MIDI messages".into()),
+            Decoder::Dmx => (dmx::decode(data), "DMX512 frame".into()),
+        };
+        if !part {
+            return (text, decoded);
+        }
+        text.push_str(CONTINUED);
+        (text, format!("{decoded}; part of a frame longer than --max-line-length, continued below").into())
+    }
+}
+
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" ")
+}
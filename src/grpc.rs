@@ -0,0 +1,217 @@
+//! gRPC service for lab tooling, an alternative to the HTTP API.
+//!
+//! `proto/serial_monitor.proto` defines the service; the messages and the
+//! routing below are written by hand to the same shape `tonic-build` would
+//! generate, so building needs no `protoc`. Calls are authorized with the
+//! same bearer token and TLS identity as the HTTP API.
+
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use anyhow::{Context as _, Result};
+use futures::StreamExt;
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast::error::RecvError, mpsc},
+};
+use tonic::{
+    Request, Response, Status,
+    body::Body,
+    codegen::{BoxFuture, BoxStream, Service, StdError, http},
+    server::{Grpc, NamedService, ServerStreamingService, UnaryService},
+    transport::{Identity, Server, ServerTlsConfig, server::TcpIncoming},
+};
+use tonic_prost::ProstCodec;
+
+use crate::{
+    access::Access,
+    api::{Journal, Line, SendRequest},
+    toast::{self, Severity},
+};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EventsRequest {
+    #[prost(uint64, tag = "1")]
+    pub since: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub enum Direction {
+    Rx = 0,
+    Tx = 1,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Event {
+    #[prost(uint64, tag = "1")]
+    pub seq: u64,
+    #[prost(int64, tag = "2")]
+    pub time_us: i64,
+    #[prost(string, tag = "3")]
+    pub port: String,
+    #[prost(enumeration = "Direction", tag = "4")]
+    pub direction: i32,
+    #[prost(string, tag = "5")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SendCommandRequest {
+    #[prost(string, tag = "1")]
+    pub text: String,
+    #[prost(uint32, optional, tag = "2")]
+    pub port: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SendCommandReply {}
+
+struct Shared {
+    access: Arc<Access>,
+    journal: Arc<Mutex<Journal>>,
+    ports: Vec<String>,
+    requests: mpsc::UnboundedSender<SendRequest>,
+}
+
+impl Shared {
+    fn event(&self, line: Line) -> Event {
+        Event {
+            seq: line.seq,
+            time_us: line.time.timestamp_micros(),
+            port: self.ports.get(line.port).cloned().unwrap_or_default(),
+            direction: if line.sent { Direction::Tx } else { Direction::Rx } as i32,
+            text: line.text,
+        }
+    }
+}
+
+/// Serves the gRPC service on `listener` for as long as the monitor runs.
+pub fn spawn(
+    listener: TcpListener,
+    access: Arc<Access>,
+    journal: Arc<Mutex<Journal>>,
+    ports: Vec<String>,
+    requests: mpsc::UnboundedSender<SendRequest>,
+) -> Result<()> {
+    let mut server = Server::builder();
+    if let Some((cert, key)) = access.identity() {
+        server = server
+            .tls_config(ServerTlsConfig::new().identity(Identity::from_pem(cert, key)))
+            .context("Cannot set up TLS for the gRPC service")?;
+    }
+    let service = SerialMonitorServer(Arc::new(Shared {
+        access,
+        journal,
+        ports,
+        requests,
+    }));
+    tokio::spawn(async move {
+        let served = server
+            .add_service(service)
+            .serve_with_incoming(TcpIncoming::from(listener))
+            .await;
+        if let Err(e) = served {
+            toast::notify(Severity::Error, format!("gRPC service stopped: {e}"));
+        }
+    });
+    Ok(())
+}
+
+#[derive(Clone)]
+struct SerialMonitorServer(Arc<Shared>);
+
+impl NamedService for SerialMonitorServer {
+    const NAME: &'static str = "serial_monitor.v1.SerialMonitor";
+}
+
+impl<B> Service<http::Request<B>> for SerialMonitorServer
+where
+    B: tonic::codegen::Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let authorization = request
+            .headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+        if !self.0.access.authorized(authorization) {
+            return Box::pin(async { Ok(Status::unauthenticated("missing or wrong bearer token").into_http()) });
+        }
+        let shared = self.0.clone();
+        match request.uri().path() {
+            "/serial_monitor.v1.SerialMonitor/Events" => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::<Event, EventsRequest>::default());
+                Ok(grpc.server_streaming(Events(shared), request).await)
+            }),
+            "/serial_monitor.v1.SerialMonitor/SendCommand" => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::<SendCommandReply, SendCommandRequest>::default());
+                Ok(grpc.unary(SendCommand(shared), request).await)
+            }),
+            _ => Box::pin(async { Ok(Status::unimplemented("unknown method").into_http()) }),
+        }
+    }
+}
+
+struct Events(Arc<Shared>);
+
+impl ServerStreamingService<EventsRequest> for Events {
+    type Response = Event;
+    type ResponseStream = BoxStream<Event>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<EventsRequest>) -> Self::Future {
+        let shared = self.0.clone();
+        Box::pin(async move {
+            let (kept, live) = shared.journal.lock().unwrap().subscribe(request.get_ref().since);
+            let kept = futures::stream::iter(kept);
+            // A subscriber that lags skips ahead; the gap shows in `seq`
+            let live = futures::stream::unfold(live, |mut live| async move {
+                loop {
+                    match live.recv().await {
+                        Ok(line) => return Some((line, live)),
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            });
+            let events = kept.chain(live).map(move |line| Ok(shared.event(line)));
+            Ok(Response::new(Box::pin(events) as Self::ResponseStream))
+        })
+    }
+}
+
+struct SendCommand(Arc<Shared>);
+
+impl UnaryService<SendCommandRequest> for SendCommand {
+    type Response = SendCommandReply;
+    type Future = BoxFuture<Response<SendCommandReply>, Status>;
+
+    fn call(&mut self, request: Request<SendCommandRequest>) -> Self::Future {
+        let shared = self.0.clone();
+        Box::pin(async move {
+            let SendCommandRequest { text, port } = request.into_inner();
+            let (request, result) = SendRequest::new(port.map(|port| port as usize), text);
+            shared
+                .requests
+                .send(request)
+                .map_err(|_| Status::unavailable("the monitor is shutting down"))?;
+            match result.await {
+                Ok(Ok(())) => Ok(Response::new(SendCommandReply {})),
+                Ok(Err(e)) => Err(Status::invalid_argument(e)),
+                Err(_) => Err(Status::unavailable("the monitor is shutting down")),
+            }
+        })
+    }
+}
@@ -0,0 +1,434 @@
+//! `serial-monitor run`: a macro as a device test, without the TUI.
+//!
+//! The steps of a macro file (see [`crate::script`]) are run against the
+//! port, printing what is sent and received, and the outcome of each
+//! `expect` and `check` is reported: with `--junit FILE` as JUnit XML and
+//! with `--tap FILE` in the Test Anything Protocol, so a CI job testing a
+//! device shows which assertion failed. The run fails when any did.
+//!
+//! Given several `--port` values, the macro runs against all of them at
+//! once, or each port runs its own macro when as many are given, so a hub
+//! full of boards is provisioned in one go. Every port is a suite of its
+//! own in the reports, and a port that can't be opened fails its suite
+//! without stopping the others. The run then exits with the status of the
+//! port's failure, e.g. 3 for a port that doesn't exist or 5 for one
+//! disconnected, rather than that of a failed assertion.
+//!
+//! With `--output json` the progress is printed as one JSON object per
+//! line instead, for `jq` and log shippers: `rx` and `tx` events carry the
+//! text received or sent, `status` events the connection, errors, captured
+//! variables and control line changes, and a `result` event per port ends
+//! the run with the outcome of each assertion:
+//!
+//! ```text
+//! {"time":"2026-10-14T12:00:00.120+02:00","port":"/dev/ttyUSB0","event":"tx","text":"AT+GSN"}
+//! {"time":"2026-10-14T12:00:00.180+02:00","port":"/dev/ttyUSB0","event":"rx","text":"861234567890123"}
+//! {"time":"2026-10-14T12:00:00.181+02:00","port":"/dev/ttyUSB0","event":"status","status":"captured","name":"serial","value":"861234567890123"}
+//! ```
+
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::Local;
+use clap::ValueEnum;
+use serde_json::{Value, json};
+use tokio::{sync::mpsc, task::JoinSet, time};
+
+use crate::{
+    events::{PortEvent, SerialEvent},
+    exit::{self, Status},
+    framing::{Decoder, Framer, Framing, LengthPrefix},
+    mask::Masks,
+    placeholders::Placeholders,
+    port::{self, Restart, RestartPolicy},
+    script::{self, Assertion, Outcome, Progress, Runner},
+    secrets::Secrets,
+    simulator,
+    transport::{self, Encoding, LineEnding, SerialSettings},
+};
+
+/// How often the runner checks for lines due when nothing arrives.
+const TICK: Duration = Duration::from_millis(10);
+
+/// How the progress of a run is printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    /// Lines for people to read
+    Text,
+    /// One JSON object per event
+    Json,
+}
+
+/// Something that happened during a run.
+enum Event<'a> {
+    Rx(&'a str),
+    Tx(&'a str),
+    Connected,
+    Disconnected,
+    Error(&'a str),
+    /// A variable captured by an `expect`.
+    Captured(&'a str, &'a str),
+    /// Control line changes made.
+    Pins(String),
+    /// The macro stopped, or the port couldn't be opened.
+    Failed(&'a str),
+}
+
+/// Prints the events of the run against one port.
+struct Printer {
+    output: Output,
+    port: String,
+    /// Prefix of the lines printed, naming the port when there are several.
+    label: String,
+}
+
+impl Printer {
+    fn print(&self, event: Event<'_>) {
+        let time = Local::now();
+        let label = &self.label;
+        match self.output {
+            Output::Text => {
+                let text = match event {
+                    Event::Rx(text) => format!("{label}< {text}"),
+                    Event::Tx(text) => format!("{label}> {text}"),
+                    Event::Connected | Event::Disconnected => return,
+                    Event::Error(e) => format!("{label}{}: {e}", self.port),
+                    Event::Captured(name, value) => format!("{label}  {name} = {value}"),
+                    Event::Pins(steps) => format!("{label}  pins {steps}"),
+                    Event::Failed(e) => format!("{label}FAILED: {e}"),
+                };
+                println!("[{}] {text}", time.format("%H:%M:%S%.3f"));
+            }
+            Output::Json => {
+                let (kind, details) = match event {
+                    Event::Rx(text) => ("rx", json!({ "text": text })),
+                    Event::Tx(text) => ("tx", json!({ "text": text })),
+                    Event::Connected => ("status", json!({ "status": "connected" })),
+                    Event::Disconnected => ("status", json!({ "status": "disconnected" })),
+                    Event::Error(e) => ("status", json!({ "status": "error", "message": e })),
+                    Event::Captured(name, value) => ("status", json!({ "status": "captured", "name": name, "value": value })),
+                    Event::Pins(steps) => ("status", json!({ "status": "pins", "steps": steps })),
+                    Event::Failed(e) => ("status", json!({ "status": "failed", "message": e })),
+                };
+                print_json(json!({ "time": time.to_rfc3339(), "port": self.port, "event": kind }), details);
+            }
+        }
+    }
+}
+
+/// Prints `event` with the fields of `details` added, on one line.
+fn print_json(mut event: Value, details: Value) {
+    if let (Some(event), Value::Object(details)) = (event.as_object_mut(), details) {
+        event.extend(details);
+    }
+    println!("{event}");
+}
+
+pub struct Test<'a> {
+    /// One macro for every port, or one for each.
+    pub scripts: &'a [String],
+    pub ports: &'a [String],
+    /// Simulation script played instead of opening the ports.
+    pub simulate: Option<&'a str>,
+    pub secrets: Secrets,
+    pub settings: SerialSettings,
+    pub line_ending: LineEnding,
+    pub junit: Option<&'a str>,
+    pub tap: Option<&'a str>,
+    pub output: Output,
+}
+
+/// The assertions of a run against one port.
+struct Suite {
+    name: String,
+    port: String,
+    assertions: Vec<Assertion>,
+    took: Duration,
+    /// Why the port couldn't be tested, if that has an exit status of its own.
+    failure: Option<(Status, String)>,
+}
+
+pub async fn run(test: Test<'_>) -> Result<()> {
+    if test.scripts.len() != 1 && test.scripts.len() != test.ports.len() {
+        bail!("Give one macro for all ports or one for each of the {} ports", test.ports.len());
+    }
+    // A report would break the stream of JSON lines on stdout
+    if test.output == Output::Json && [test.junit, test.tap].contains(&Some("-")) {
+        bail!("--output json prints its events to stdout; write --junit and --tap reports to a file");
+    }
+    let mut runs = JoinSet::new();
+    for (index, port) in test.ports.iter().enumerate() {
+        let script = test.scripts.get(index).unwrap_or(&test.scripts[0]);
+        let text = fs::read_to_string(script).with_context(|| format!("Cannot read {script}"))?;
+        let steps = script::parse(&text, script)?;
+        let file = script.rsplit(['/', '\\']).next().unwrap_or(script);
+        let run = Run {
+            name: match test.ports.len() {
+                1 => file.to_string(),
+                _ => format!("{port}: {file}"),
+            },
+            printer: Printer {
+                output: test.output,
+                port: port.clone(),
+                label: match test.ports.len() {
+                    1 => String::new(),
+                    _ => format!("{port} "),
+                },
+            },
+            port: port.clone(),
+            simulate: test.simulate.map(str::to_string),
+            secrets: test.secrets.clone(),
+            settings: test.settings,
+            line_ending: test.line_ending,
+        };
+        runs.spawn(async move { (index, run.run(steps).await) });
+    }
+    let mut suites: Vec<(usize, Suite)> = runs.join_all().await;
+    suites.sort_by_key(|(index, _)| *index);
+    let suites: Vec<Suite> = suites.into_iter().map(|(_, suite)| suite).collect();
+    let count = |suite: &Suite, f: fn(&Outcome) -> bool| suite.assertions.iter().filter(|a| f(&a.outcome)).count();
+    let mut failed = 0;
+    for suite in &suites {
+        let failures = count(suite, |outcome| matches!(outcome, Outcome::Failed(_)));
+        let passed = count(suite, |outcome| matches!(outcome, Outcome::Passed));
+        failed += failures;
+        match test.output {
+            Output::Text => println!(
+                "{} {}: {} assertions, {passed} passed, {failures} failed, {} skipped in {:.1}s",
+                if failures > 0 { "FAILED" } else { "ok    " },
+                suite.name,
+                suite.assertions.len(),
+                suite.assertions.len() - passed - failures,
+                suite.took.as_secs_f64()
+            ),
+            Output::Json => print_json(
+                json!({ "time": Local::now().to_rfc3339(), "port": suite.port, "event": "result" }),
+                result(suite),
+            ),
+        }
+    }
+    if let Some(path) = test.junit {
+        write(path, &junit(&suites))?;
+    }
+    if let Some(path) = test.tap {
+        write(path, &tap(&suites))?;
+    }
+    if let Some((status, message)) = suites.iter().find_map(|suite| suite.failure.clone()) {
+        return Err(exit::failure(status, message));
+    }
+    if failed > 0 {
+        let boards = suites.iter().filter(|suite| count(suite, |outcome| matches!(outcome, Outcome::Failed(_))) > 0);
+        let message = format!("{failed} assertion(s) failed on {} of {} port(s)", boards.count(), suites.len());
+        return Err(exit::failure(Status::TestFailed, message));
+    }
+    Ok(())
+}
+
+/// A macro running against one port.
+struct Run {
+    /// The suite's name in the reports.
+    name: String,
+    printer: Printer,
+    port: String,
+    simulate: Option<String>,
+    secrets: Secrets,
+    settings: SerialSettings,
+    line_ending: LineEnding,
+}
+
+impl Run {
+    async fn run(self, steps: Vec<script::Step>) -> Suite {
+        let started = Instant::now();
+        let mut failure = None;
+        let assertions = match self.run_steps(steps).await {
+            Ok(assertions) => assertions,
+            Err(e) => {
+                self.printer.print(Event::Failed(&e.to_string()));
+                failure = exit::status(&e).map(|status| (status, format!("{e:#}")));
+                vec![Assertion {
+                    name: format!("open {}", self.port),
+                    outcome: Outcome::Failed(e.to_string()),
+                    took: started.elapsed(),
+                }]
+            }
+        };
+        Suite {
+            name: self.name,
+            port: self.port,
+            assertions,
+            took: started.elapsed(),
+            failure,
+        }
+    }
+
+    async fn run_steps(&self, steps: Vec<script::Step>) -> Result<Vec<Assertion>> {
+        let transport = match &self.simulate {
+            Some(script) => simulator::open(script)?,
+            None => transport::open(&self.port, &self.settings).await?,
+        };
+        let restart = Restart {
+            policy: RestartPolicy::Never,
+            open: Box::new(|_| Box::pin(async { Err(anyhow!("A test run doesn't reopen its port")) })),
+            recover: None,
+        };
+        let (tx_events, mut rx_events) = mpsc::unbounded_channel::<PortEvent>();
+        let framer = Framer::new(Framing::Lines, LengthPrefix::Varint);
+        let port = port::spawn(0, transport, restart, self.settings, framer, Decoder::Text(Encoding::Utf8), tx_events);
+        let mut runner = Runner::new(steps, self.secrets.clone());
+        // Credentials the macro sends are never printed
+        let mut masks = Masks::new(Vec::new());
+        for value in self.secrets.values().map(str::to_string).chain(runner.secret_values()) {
+            masks.hide(&value);
+        }
+        let mut placeholders = Placeholders::default();
+        let printer = &self.printer;
+        loop {
+            let (lines, pins) = match runner.poll(Instant::now()) {
+                Progress::Send(lines) => (lines, None),
+                Progress::Pins(lines, sequence) => (lines, Some(sequence)),
+                Progress::Failed(e) => {
+                    printer.print(Event::Failed(&e));
+                    (Vec::new(), None)
+                }
+            };
+            for line in lines {
+                let line = placeholders.expand(&line);
+                printer.print(Event::Tx(&masks.apply(&line)));
+                port.write(format!("{line}{}", self.line_ending.as_str()).into_bytes());
+            }
+            if let Some(sequence) = pins {
+                printer.print(Event::Pins(sequence.describe()));
+                if let Err(e) = sequence.run(&port).await {
+                    printer.print(Event::Error(&e.to_string()));
+                }
+            }
+            if runner.is_done() {
+                break;
+            }
+            let event = match time::timeout(TICK, rx_events.recv()).await {
+                Ok(Some(event)) => event.event,
+                Ok(None) => return Err(exit::failure(Status::Disconnected, format!("{} was closed", self.port))),
+                Err(_) => continue,
+            };
+            match event {
+                SerialEvent::Rx { text, .. } => {
+                    printer.print(Event::Rx(&masks.apply(&text)));
+                    if let Some(captured) = runner.received(&text, Instant::now()) {
+                        for (name, value) in captured {
+                            printer.print(Event::Captured(&name, &masks.apply(&value)));
+                        }
+                    }
+                }
+                SerialEvent::Connected => printer.print(Event::Connected),
+                SerialEvent::Disconnected => printer.print(Event::Disconnected),
+                SerialEvent::Error(e) => printer.print(Event::Error(&e)),
+                _ => {}
+            }
+        }
+        Ok(runner.assertions().to_vec())
+    }
+}
+
+/// The fields of a `result` event: the counts and each assertion's outcome.
+fn result(suite: &Suite) -> Value {
+    let assertions: Vec<Value> = suite
+        .assertions
+        .iter()
+        .map(|assertion| match &assertion.outcome {
+            Outcome::Passed => json!({ "name": assertion.name, "outcome": "passed" }),
+            Outcome::Failed(e) => json!({ "name": assertion.name, "outcome": "failed", "message": e }),
+            Outcome::Skipped => json!({ "name": assertion.name, "outcome": "skipped" }),
+        })
+        .collect();
+    let count = |outcome: &str| assertions.iter().filter(|a| a["outcome"] == outcome).count();
+    json!({
+        "suite": suite.name,
+        "passed": count("passed"),
+        "failed": count("failed"),
+        "skipped": count("skipped"),
+        "seconds": suite.took.as_secs_f64(),
+        "assertions": assertions,
+    })
+}
+
+/// Writes a report to `path`, or to stdout for `-`.
+fn write(path: &str, report: &str) -> Result<()> {
+    match path {
+        "-" => {
+            print!("{report}");
+            Ok(())
+        }
+        path => fs::write(path, report).with_context(|| format!("Cannot write {path}")),
+    }
+}
+
+/// `text` as XML attribute or element content. Control characters other
+/// than tab and line breaks can't be in an XML document at all, so those a
+/// line received held are replaced.
+fn xml_escape(text: &str) -> String {
+    text.chars()
+        .map(|c| if c < ' ' && !matches!(c, '\t' | '\n' | '\r') { '\u{fffd}' } else { c })
+        .collect::<String>()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn junit(suites: &[Suite]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+    for suite in suites {
+        let count = |f: fn(&Outcome) -> bool| suite.assertions.iter().filter(|a| f(&a.outcome)).count();
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\" timestamp=\"{}\">\n",
+            xml_escape(&suite.name),
+            suite.assertions.len(),
+            count(|outcome| matches!(outcome, Outcome::Failed(_))),
+            count(|outcome| matches!(outcome, Outcome::Skipped)),
+            suite.took.as_secs_f64(),
+            Local::now().format("%Y-%m-%dT%H:%M:%S"),
+        ));
+        for (i, assertion) in suite.assertions.iter().enumerate() {
+            let case = format!(
+                "    <testcase classname=\"{}\" name=\"{}: {}\" time=\"{:.3}\"",
+                xml_escape(&suite.name),
+                i + 1,
+                xml_escape(&assertion.name),
+                assertion.took.as_secs_f64()
+            );
+            match &assertion.outcome {
+                Outcome::Passed => xml.push_str(&format!("{case}/>\n")),
+                Outcome::Failed(e) => {
+                    xml.push_str(&format!("{case}>\n      <failure message=\"{}\"/>\n    </testcase>\n", xml_escape(e)));
+                }
+                Outcome::Skipped => xml.push_str(&format!("{case}>\n      <skipped/>\n    </testcase>\n")),
+            }
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn tap(suites: &[Suite]) -> String {
+    let total: usize = suites.iter().map(|suite| suite.assertions.len()).sum();
+    let mut text = format!("TAP version 13\n1..{total}\n");
+    let assertions = suites.iter().flat_map(|suite| suite.assertions.iter().map(move |a| (suite, a)));
+    for (i, (suite, assertion)) in assertions.enumerate() {
+        let name = match suites.len() {
+            1 => assertion.name.clone(),
+            _ => format!("{}: {}", suite.name, assertion.name),
+        };
+        match &assertion.outcome {
+            Outcome::Passed => text.push_str(&format!("ok {} - {name}\n", i + 1)),
+            Outcome::Failed(e) => {
+                text.push_str(&format!("not ok {} - {name}\n  ---\n  message: {e:?}\n  ...\n", i + 1));
+            }
+            Outcome::Skipped => text.push_str(&format!("ok {} - {name} # SKIP an earlier step failed\n", i + 1)),
+        }
+    }
+    text
+}
@@ -0,0 +1,44 @@
+//! Notifications about serial devices appearing and disappearing.
+//!
+//! The system's port enumeration (udev on Linux, SetupAPI on Windows) is
+//! polled, so this also works where no hotplug events can be subscribed to.
+
+use std::{collections::HashSet, time::Duration};
+
+use tokio::{sync::mpsc, task, time};
+
+use crate::discovery::{self, PortInfo};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub enum Hotplug {
+    Attached(PortInfo),
+    Detached(String),
+}
+
+pub fn spawn(events: mpsc::UnboundedSender<Hotplug>) {
+    tokio::spawn(async move {
+        let mut known: Option<HashSet<String>> = None;
+        loop {
+            let Ok(ports) = task::spawn_blocking(discovery::list).await else {
+                return;
+            };
+            let names: HashSet<String> = ports.iter().map(|p| p.name.clone()).collect();
+            // The first scan only establishes what was already there
+            if let Some(known) = &known {
+                for port in ports.into_iter().filter(|p| !known.contains(&p.name)) {
+                    if events.send(Hotplug::Attached(port)).is_err() {
+                        return;
+                    }
+                }
+                for name in known.difference(&names) {
+                    if events.send(Hotplug::Detached(name.clone())).is_err() {
+                        return;
+                    }
+                }
+            }
+            known = Some(names);
+            time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,106 @@
+//! Performance HUD for tuning the monitor on slow terminals and SSH links.
+//!
+//! `:hud` (or `--hud`) shows in the bottom right of the output how often the
+//! screen was redrawn in the last second and how long drawing took, how
+//! often a redraw was skipped as nothing changed, the event loop's lag (the
+//! longest it was busy before getting back to the keyboard and the ports)
+//! and how many events wait in the channels feeding it. A growing queue
+//! means the loop can't keep up with the ports.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+/// How far back the rates and maximums look.
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+pub struct Hud {
+    /// When each frame of the last second was drawn and how long it took.
+    frames: VecDeque<(Instant, Duration)>,
+    /// Iterations of the last second with nothing new to draw.
+    skipped: VecDeque<Instant>,
+    /// How long each loop iteration of the last second was busy.
+    busy: VecDeque<(Instant, Duration)>,
+    /// Start of the current iteration.
+    iteration: Option<Instant>,
+    /// Time of the current iteration spent waiting for input.
+    waited: Duration,
+}
+
+fn trim(samples: &mut VecDeque<(Instant, Duration)>, now: Instant) {
+    while samples.front().is_some_and(|(at, _)| now.duration_since(*at) > WINDOW) {
+        samples.pop_front();
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Hud {
+    /// Starts an iteration of the event loop, ending the one before.
+    pub fn iteration(&mut self, now: Instant) {
+        if let Some(started) = self.iteration {
+            self.busy.push_back((now, now.duration_since(started).saturating_sub(self.waited)));
+            trim(&mut self.busy, now);
+        }
+        self.iteration = Some(now);
+        self.waited = Duration::ZERO;
+    }
+
+    /// Notes time the loop spent waiting for input rather than working.
+    pub fn waited(&mut self, duration: Duration) {
+        self.waited += duration;
+    }
+
+    /// Notes a frame drawn at `now` in `took`.
+    pub fn frame(&mut self, now: Instant, took: Duration) {
+        self.frames.push_back((now, took));
+        trim(&mut self.frames, now);
+    }
+
+    /// Notes an iteration at `now` that left the screen as it was.
+    pub fn skipped(&mut self, now: Instant) {
+        self.skipped.push_back(now);
+        while self.skipped.front().is_some_and(|at| now.duration_since(*at) > WINDOW) {
+            self.skipped.pop_front();
+        }
+    }
+
+    /// Draws the HUD in the bottom right of `area`, with the length of each
+    /// named queue.
+    pub fn render(&self, f: &mut Frame, area: Rect, queues: &[(&str, usize)]) {
+        let draws = self.frames.iter().map(|(_, took)| *took);
+        let average = match self.frames.len() {
+            0 => Duration::ZERO,
+            frames => draws.clone().sum::<Duration>() / frames as u32,
+        };
+        let lag = self.busy.iter().map(|(_, busy)| *busy).max().unwrap_or_default();
+        let queued: Vec<String> = queues.iter().map(|(name, length)| format!("{name} {length}")).collect();
+        let text = [
+            format!("{} fps, {} redraws skipped", self.frames.len(), self.skipped.len()),
+            format!("draw {:.1} ms avg {:.1} max", ms(average), ms(draws.max().unwrap_or_default())),
+            format!("loop lag {:.1} ms max", ms(lag)),
+            format!("queued: {}", queued.join(", ")),
+        ];
+        let width = (text.iter().map(|line| line.len()).max().unwrap_or(0) as u16 + 2).min(area.width.saturating_sub(2));
+        let height = (text.len() as u16 + 2).min(area.height.saturating_sub(2));
+        let rect = Rect::new(area.right().saturating_sub(width + 1), area.bottom().saturating_sub(height + 1), width, height);
+        f.render_widget(Clear, rect);
+        f.render_widget(
+            Paragraph::new(text.join("\n"))
+                .style(Style::default().fg(Color::Green))
+                .block(Block::default().title("HUD").borders(Borders::ALL)),
+            rect,
+        );
+    }
+}
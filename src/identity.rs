@@ -0,0 +1,78 @@
+//! Identities of the devices in a session.
+//!
+//! With `--identify ATI` the query is sent to each port when it connects,
+//! including reconnects to the next board on the same adapter, and the
+//! device's answer is recorded in the log and the session summary, so a log
+//! can be traced back to the board it came from. `--identify-match` picks
+//! the identity out of the answer: its named groups as `name=value`, else
+//! its first group, else the whole match, e.g. `^FIRMWARE_NAME:(.*)$` for
+//! the answer to a 3D printer's `M115`. Without it the first line that
+//! isn't the echoed query or `OK` is the identity.
+
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+/// How long a device has to answer the query.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Identify {
+    pub query: String,
+    pattern: Option<Regex>,
+    /// When the query to each port stops being waited for, if it is.
+    waiting: Vec<Option<Instant>>,
+}
+
+impl Identify {
+    pub fn new(query: String, pattern: Option<Regex>, ports: usize) -> Self {
+        Self {
+            query,
+            pattern,
+            waiting: vec![None; ports],
+        }
+    }
+
+    /// Notes the query being sent to `port` at `now`.
+    pub fn sent(&mut self, port: usize, now: Instant) {
+        if let Some(waiting) = self.waiting.get_mut(port) {
+            *waiting = Some(now + TIMEOUT);
+        }
+    }
+
+    /// The identity in a line received from `port`, if it answers the query.
+    pub fn received(&mut self, port: usize, line: &str) -> Option<String> {
+        let waiting = self.waiting.get_mut(port)?;
+        waiting.as_ref()?;
+        let line = line.trim();
+        let identity = match &self.pattern {
+            Some(pattern) => {
+                let captures = pattern.captures(line)?;
+                let named: Vec<String> = pattern
+                    .capture_names()
+                    .flatten()
+                    .filter_map(|name| Some(format!("{name}={}", captures.name(name)?.as_str())))
+                    .collect();
+                match named.is_empty() {
+                    false => named.join(" "),
+                    true => captures.get(1).or_else(|| captures.get(0))?.as_str().to_string(),
+                }
+            }
+            None if line.is_empty() || line == self.query || line == "OK" => return None,
+            None => line.to_string(),
+        };
+        *waiting = None;
+        Some(identity)
+    }
+
+    /// Ports whose answer didn't arrive in time.
+    pub fn expire(&mut self, now: Instant) -> Vec<usize> {
+        let mut expired = Vec::new();
+        for (port, waiting) in self.waiting.iter_mut().enumerate() {
+            if waiting.is_some_and(|deadline| now >= deadline) {
+                *waiting = None;
+                expired.push(port);
+            }
+        }
+        expired
+    }
+}
@@ -0,0 +1,86 @@
+//! Watchdog over ports that stop sending.
+//!
+//! Some devices go to sleep unless they are poked, others hang without a
+//! sound. With `--idle-timeout 30` a port from which nothing was received
+//! for 30 seconds counts as idle: an alert is shown and the `--on-idle`
+//! action, if any, fires. `--keep-alive TEXT` sends it a line each time it
+//! stays quiet for that long, and then the alert waits for the port to stay
+//! quiet for another timeout after the first poke, so a device that only
+//! needed waking doesn't raise it. Anything received makes the port active
+//! again. A disconnected port isn't watched until it is back.
+
+use std::time::{Duration, Instant};
+
+struct Port {
+    /// When something was last received.
+    received: Instant,
+    /// When the timeout last started: on receiving, connecting or being
+    /// found quiet.
+    since: Instant,
+    /// Timeouts in a row the port stayed quiet.
+    quiet: u32,
+    connected: bool,
+}
+
+/// A port that stayed quiet for the timeout.
+pub struct Quiet {
+    pub port: usize,
+    /// Whether the port just went idle: the alert is due.
+    pub alert: bool,
+    /// How long nothing was received.
+    pub silent: Duration,
+}
+
+pub struct Watchdog {
+    timeout: Duration,
+    /// Timeouts in a row after which a port is idle.
+    alert_after: u32,
+    ports: Vec<Port>,
+}
+
+impl Watchdog {
+    /// Watches `ports` ports for quiet `timeout`s, the ports being poked
+    /// after each one if `poked`.
+    pub fn new(timeout: Duration, ports: usize, poked: bool, now: Instant) -> Self {
+        Self {
+            timeout,
+            alert_after: if poked { 2 } else { 1 },
+            ports: (0..ports).map(|_| Port { received: now, since: now, quiet: 0, connected: true }).collect(),
+        }
+    }
+
+    /// Notes something received on `port`, returning how long it was
+    /// silent if it was idle.
+    pub fn received(&mut self, port: usize, now: Instant) -> Option<Duration> {
+        let state = self.ports.get_mut(port)?;
+        state.since = now;
+        let silent = now.duration_since(std::mem::replace(&mut state.received, now));
+        (std::mem::take(&mut state.quiet) >= self.alert_after).then_some(silent)
+    }
+
+    /// Notes `port` connecting or disconnecting; the timeout starts over
+    /// once it is connected.
+    pub fn connected(&mut self, port: usize, connected: bool, now: Instant) {
+        if let Some(state) = self.ports.get_mut(port) {
+            state.connected = connected;
+            state.received = now;
+            state.since = now;
+        }
+    }
+
+    /// The connected ports quiet for the timeout since they last received
+    /// something or were last found quiet.
+    pub fn poll(&mut self, now: Instant) -> Vec<Quiet> {
+        let mut quiet = Vec::new();
+        for (port, state) in self.ports.iter_mut().enumerate() {
+            if !state.connected || now.duration_since(state.since) < self.timeout {
+                continue;
+            }
+            state.since = now;
+            state.quiet += 1;
+            let alert = state.quiet == self.alert_after;
+            quiet.push(Quiet { port, alert, silent: now.duration_since(state.received) });
+        }
+        quiet
+    }
+}
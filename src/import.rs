@@ -0,0 +1,330 @@
+//! Captures recorded by other tools, converted for replay.
+//!
+//! `serial-monitor replay` reads its own capture files directly; anything
+//! else is converted into a temporary capture first:
+//!
+//! - pcap and pcapng files, e.g. from `log pcap` or other serial sniffers:
+//!   one port per interface, with the direction from the packet flags or the
+//!   RTAC serial header
+//! - `script` typescripts with a timing file (`script -T timing` or
+//!   `--log-timing`); in the advanced format, input logged with `-B` is
+//!   shown as sent
+//! - plain text such as minicom captures, which have no timing and play
+//!   all at once
+
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use clap::ValueEnum;
+
+use crate::capture::{Kind, Record, Writer};
+
+const CAPTURE_MAGIC: &[u8] = b"SMCAP\r\n\x1a";
+const PCAPNG_MAGIC: &[u8] = &[0x0A, 0x0D, 0x0D, 0x0A];
+const LINKTYPE_RTAC_SERIAL: u16 = 250;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Detect the format from the file's contents
+    Auto,
+    /// serial-monitor capture file (--capture)
+    Capture,
+    /// pcap or pcapng
+    Pcap,
+    /// `script` typescript, replayed with --timing
+    Script,
+    /// Plain text or raw bytes without timing, e.g. a minicom capture
+    Text,
+}
+
+/// A file ready for replay; a converted one is deleted when dropped.
+pub struct Imported {
+    pub path: String,
+    temporary: bool,
+}
+
+impl Drop for Imported {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Converts the file at `path` into a capture unless it is one already.
+pub fn prepare(path: &str, format: Format, timing: Option<&str>) -> Result<Imported> {
+    let data = fs::read(path).with_context(|| format!("Failed to open {path}"))?;
+    let format = match format {
+        Format::Auto if data.starts_with(CAPTURE_MAGIC) => Format::Capture,
+        Format::Auto if data.starts_with(PCAPNG_MAGIC) || pcap_header(&data).is_some() => Format::Pcap,
+        Format::Auto if timing.is_some() || data.starts_with(b"Script started on") => Format::Script,
+        Format::Auto => Format::Text,
+        format => format,
+    };
+    let name = Path::new(path).file_name().map_or(path.into(), |name| name.to_string_lossy());
+    let modified = fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .unwrap_or_else(|_| SystemTime::now())
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_micros() as i64);
+    let (ports, mut records) = match format {
+        Format::Capture | Format::Auto => {
+            return Ok(Imported {
+                path: path.to_string(),
+                temporary: false,
+            });
+        }
+        Format::Pcap if data.starts_with(PCAPNG_MAGIC) => read_pcapng(&data).with_context(|| format!("{path} is not a valid pcapng file"))?,
+        Format::Pcap => read_pcap(&data, &name).with_context(|| format!("{path} is not a valid pcap file"))?,
+        Format::Script => {
+            let timing = timing.ok_or_else(|| anyhow!("Replaying a typescript needs its timing file (--timing)"))?;
+            let log = fs::read_to_string(timing).with_context(|| format!("Failed to open the timing file {timing}"))?;
+            (vec![name.to_string()], read_script(&data, &log, modified)?)
+        }
+        Format::Text => (vec![name.to_string()], read_text(&data, modified)),
+    };
+    if records.is_empty() {
+        bail!("{path} holds no data to replay");
+    }
+    // Interfaces of a pcapng file may interleave out of order
+    records.sort_by_key(|record| record.time);
+
+    let output = std::env::temp_dir().join(format!("serial-monitor-import-{}.cap", std::process::id()));
+    let output = output.to_string_lossy().into_owned();
+    let imported = Imported {
+        path: output.clone(),
+        temporary: true,
+    };
+    let mut writer = Writer::create(&output, &serde_json::json!({ "ports": ports, "imported": path }))?;
+    for record in &records {
+        writer.write(record)?;
+    }
+    writer.finish()?;
+    Ok(imported)
+}
+
+/// Byte order and timestamp units (per second) of a pcap file.
+fn pcap_header(data: &[u8]) -> Option<(bool, i64)> {
+    match data.get(..4)? {
+        [0xd4, 0xc3, 0xb2, 0xa1] => Some((false, 1_000_000)),
+        [0xa1, 0xb2, 0xc3, 0xd4] => Some((true, 1_000_000)),
+        [0x4d, 0x3c, 0xb2, 0xa1] => Some((false, 1_000_000_000)),
+        [0xa1, 0xb2, 0x3c, 0x4d] => Some((true, 1_000_000_000)),
+        _ => None,
+    }
+}
+
+/// Reads integers in a file's byte order.
+struct Bytes<'a> {
+    data: &'a [u8],
+    big: bool,
+}
+
+impl Bytes<'_> {
+    fn u16(&self, at: usize) -> Result<u16> {
+        let bytes = self.data.get(at..at + 2).ok_or_else(|| anyhow!("truncated at offset {at}"))?;
+        let bytes = [bytes[0], bytes[1]];
+        Ok(if self.big { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(&self, at: usize) -> Result<u32> {
+        let bytes = self.data.get(at..at + 4).ok_or_else(|| anyhow!("truncated at offset {at}"))?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(if self.big { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn slice(&self, at: usize, len: usize) -> Result<&[u8]> {
+        self.data.get(at..at + len).ok_or_else(|| anyhow!("truncated at offset {at}"))
+    }
+}
+
+/// A packet's record: RTAC serial packets carry their direction in the
+/// header, others are received data unless `outbound`.
+fn packet(port: u8, time: i64, link: u16, data: &[u8], outbound: bool) -> Option<Record> {
+    let (kind, data) = match link {
+        LINKTYPE_RTAC_SERIAL => match data.get(8)? {
+            0x01 => (Kind::Tx, data.get(12..)?),
+            0x02 => (Kind::Rx, data.get(12..)?),
+            // Status changes and the like carry no data
+            _ => return None,
+        },
+        _ if outbound => (Kind::Tx, data),
+        _ => (Kind::Rx, data),
+    };
+    (!data.is_empty()).then(|| Record {
+        kind,
+        port,
+        time,
+        data: data.to_vec(),
+    })
+}
+
+fn read_pcap(data: &[u8], name: &str) -> Result<(Vec<String>, Vec<Record>)> {
+    let (big, units) = pcap_header(data).ok_or_else(|| anyhow!("unknown magic number"))?;
+    let bytes = Bytes { data, big };
+    let link = bytes.u32(20)? as u16;
+    let mut records = Vec::new();
+    let mut at = 24;
+    while at < data.len() {
+        let seconds = bytes.u32(at)? as i64;
+        let fraction = bytes.u32(at + 4)? as i64;
+        let length = bytes.u32(at + 8)? as usize;
+        let time = seconds * 1_000_000 + fraction * 1_000_000 / units;
+        records.extend(packet(0, time, link, bytes.slice(at + 16, length)?, false));
+        at += 16 + length;
+    }
+    Ok((vec![name.to_string()], records))
+}
+
+struct Interface {
+    link: u16,
+    /// Timestamp units per second.
+    units: i64,
+}
+
+fn read_pcapng(data: &[u8]) -> Result<(Vec<String>, Vec<Record>)> {
+    let mut names = Vec::new();
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut records = Vec::new();
+    let mut bytes = Bytes { data, big: false };
+    let mut at = 0;
+    while at + 12 <= data.len() {
+        let kind = bytes.u32(at)?;
+        if kind == 0x0A0D_0D0A {
+            // Every section states its byte order, and numbers its interfaces anew
+            bytes.big = bytes.slice(at + 8, 4)? == [0x1A, 0x2B, 0x3C, 0x4D];
+            interfaces.clear();
+        }
+        let length = bytes.u32(at + 4)? as usize;
+        if length < 12 || !length.is_multiple_of(4) {
+            bail!("invalid block length {length} at offset {at}");
+        }
+        let body = at + 8;
+        let end = at + length - 4;
+        match kind {
+            // Interface description
+            1 => {
+                let link = bytes.u16(body)?;
+                let mut name = format!("interface {}", names.len());
+                let mut units = 1_000_000;
+                for (code, value) in options(&bytes, body + 8, end)? {
+                    match code {
+                        2 => name = String::from_utf8_lossy(value).trim_end_matches('\0').to_string(),
+                        9 => {
+                            let resolution = *value.first().unwrap_or(&6);
+                            units = match resolution & 0x80 {
+                                0 => 10i64.pow(u32::from(resolution.min(18))),
+                                _ => 1i64 << (resolution & 0x7f).min(62),
+                            };
+                        }
+                        _ => {}
+                    }
+                }
+                if names.len() > u8::MAX as usize {
+                    bail!("more than 256 interfaces");
+                }
+                interfaces.push(Interface { link, units });
+                names.push(name);
+            }
+            // Enhanced packet
+            6 => {
+                let id = bytes.u32(body)? as usize;
+                let interface = interfaces.get(id).ok_or_else(|| anyhow!("packet of undescribed interface {id}"))?;
+                let stamp = (u64::from(bytes.u32(body + 4)?) << 32) | u64::from(bytes.u32(body + 8)?);
+                let time = (i128::from(stamp) * 1_000_000 / i128::from(interface.units)) as i64;
+                let captured = bytes.u32(body + 12)? as usize;
+                let payload = bytes.slice(body + 20, captured)?;
+                let mut outbound = false;
+                for (code, value) in options(&bytes, body + 20 + captured.next_multiple_of(4), end)? {
+                    if code == 2 && value.len() == 4 {
+                        let flags = Bytes { data: value, big: bytes.big }.u32(0)?;
+                        outbound = flags & 3 == 2;
+                    }
+                }
+                // Ports of later sections follow those of earlier ones
+                let port = (names.len() - interfaces.len() + id) as u8;
+                records.extend(packet(port, time, interface.link, payload, outbound));
+            }
+            _ => {}
+        }
+        at += length;
+    }
+    Ok((names, records))
+}
+
+/// The options between `at` and `end`: code and value.
+fn options<'a>(bytes: &Bytes<'a>, mut at: usize, end: usize) -> Result<Vec<(u16, &'a [u8])>> {
+    let mut options = Vec::new();
+    while at + 4 <= end {
+        let code = bytes.u16(at)?;
+        let length = bytes.u16(at + 2)? as usize;
+        if code == 0 {
+            break;
+        }
+        let value = bytes.data.get(at + 4..at + 4 + length).ok_or_else(|| anyhow!("truncated option at offset {at}"))?;
+        options.push((code, value));
+        at += 4 + length.next_multiple_of(4);
+    }
+    Ok(options)
+}
+
+/// A typescript and its timing log, classic (`DELAY BYTES`) or advanced
+/// (`O DELAY BYTES`, `I ...`, `H ...`, `S ...`). The session is taken to
+/// have ended when the file was last written.
+fn read_script(data: &[u8], timing: &str, modified: i64) -> Result<Vec<Record>> {
+    // The header line isn't covered by the timing
+    let mut at = match data.starts_with(b"Script started on") {
+        true => data.iter().position(|&b| b == b'\n').map_or(data.len(), |end| end + 1),
+        false => 0,
+    };
+    let mut chunks = Vec::new();
+    let mut elapsed = 0.0;
+    for (number, line) in timing.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let invalid = || anyhow!("Invalid timing line {}: {line}", number + 1);
+        let (kind, delay, length) = match fields.as_slice() {
+            [delay, length] => (Kind::Rx, *delay, *length),
+            ["O", delay, length, ..] => (Kind::Rx, *delay, *length),
+            ["I", delay, length, ..] => (Kind::Tx, *delay, *length),
+            // Headers and signals carry no data
+            [_, delay, ..] => {
+                elapsed += delay.parse::<f64>().map_err(|_| invalid())?;
+                continue;
+            }
+            _ => return Err(invalid()),
+        };
+        elapsed += delay.parse::<f64>().map_err(|_| invalid())?;
+        let length: usize = length.parse().map_err(|_| invalid())?;
+        let end = (at + length).min(data.len());
+        chunks.push((kind, elapsed, &data[at..end]));
+        at = end;
+    }
+    let start = modified - (elapsed * 1e6) as i64;
+    Ok(chunks
+        .into_iter()
+        .filter(|(_, _, chunk)| !chunk.is_empty())
+        .map(|(kind, offset, chunk)| Record {
+            kind,
+            port: 0,
+            time: start + (offset * 1e6) as i64,
+            data: chunk.to_vec(),
+        })
+        .collect())
+}
+
+/// Text without timing: one chunk per line, all at the time the file was
+/// last written.
+fn read_text(data: &[u8], modified: i64) -> Vec<Record> {
+    data.split_inclusive(|&b| b == b'\n')
+        .map(|line| Record {
+            kind: Kind::Rx,
+            port: 0,
+            time: modified,
+            data: line.to_vec(),
+        })
+        .collect()
+}
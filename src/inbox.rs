@@ -0,0 +1,46 @@
+//! Channels the monitor loop sleeps on.
+//!
+//! The loop handles everything that arrived in one pass and then waits for
+//! the next thing to do. Waiting on a channel takes its next message, so an
+//! [`Inbox`] holds that message until the pass that handles it, which reads
+//! the inbox as it would the channel.
+
+use tokio::sync::mpsc::UnboundedReceiver;
+
+pub struct Inbox<T> {
+    rx: UnboundedReceiver<T>,
+    /// The message that ended the last wait.
+    held: Option<T>,
+}
+
+impl<T> Inbox<T> {
+    pub fn new(rx: UnboundedReceiver<T>) -> Self {
+        Self { rx, held: None }
+    }
+
+    /// Waits until a message is there to be read. A closed channel never
+    /// has one.
+    pub async fn ready(&mut self) {
+        if self.held.is_some() {
+            return;
+        }
+        match self.rx.recv().await {
+            Some(message) => self.held = Some(message),
+            None => std::future::pending().await,
+        }
+    }
+
+    /// The next message, if one is there.
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.held.take().or_else(|| self.rx.try_recv().ok())
+    }
+
+    /// Messages waiting to be read.
+    pub fn len(&self) -> usize {
+        self.held.is_some() as usize + self.rx.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_none() && self.rx.is_empty()
+    }
+}
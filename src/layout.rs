@@ -0,0 +1,136 @@
+//! Arrangement of the panes, adjustable at runtime and saved in the config
+//! file's `[layout]` table.
+
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use serde::Deserialize;
+
+const MIN_INPUT_HEIGHT: u16 = 3;
+const MAX_INPUT_HEIGHT: u16 = 10;
+const MIN_SIDE_WIDTH: u16 = 20;
+const MAX_SIDE_WIDTH: u16 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputPosition {
+    Top,
+    Bottom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SidePosition {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PaneLayout {
+    /// Height of the input box including its border; taller boxes wrap the input.
+    pub input_height: u16,
+    pub input_position: InputPosition,
+    /// Width of the side panels (message rates, watch values).
+    pub side_width: u16,
+    pub side_position: SidePosition,
+    pub show_rates: bool,
+    /// Whether the watch panel is shown when watches are configured.
+    pub show_watch: bool,
+}
+
+impl Default for PaneLayout {
+    fn default() -> Self {
+        Self {
+            input_height: MIN_INPUT_HEIGHT,
+            input_position: InputPosition::Bottom,
+            side_width: 48,
+            side_position: SidePosition::Right,
+            show_rates: false,
+            show_watch: true,
+        }
+    }
+}
+
+/// Screen areas of the panes.
+pub struct Areas {
+    pub output: Rect,
+    pub side: Option<Rect>,
+    pub input: Rect,
+}
+
+impl PaneLayout {
+    pub fn split(&self, area: Rect, side: bool) -> Areas {
+        let input_height = Constraint::Length(self.input_height.clamp(MIN_INPUT_HEIGHT, MAX_INPUT_HEIGHT));
+        let (rows, main, input) = match self.input_position {
+            InputPosition::Top => ([input_height, Constraint::Min(1)], 1, 0),
+            InputPosition::Bottom => ([Constraint::Min(1), input_height], 0, 1),
+        };
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(rows)
+            .margin(1)
+            .split(area);
+        if !side {
+            return Areas {
+                output: rows[main],
+                side: None,
+                input: rows[input],
+            };
+        }
+        // Narrow panes split the width instead of squeezing the output
+        let side_width = Constraint::Length(self.side_width.min(rows[main].width / 2));
+        let (columns, output, side) = match self.side_position {
+            SidePosition::Left => ([side_width, Constraint::Min(20)], 1, 0),
+            SidePosition::Right => ([Constraint::Min(20), side_width], 0, 1),
+        };
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(columns)
+            .split(rows[main]);
+        Areas {
+            output: columns[output],
+            side: Some(columns[side]),
+            input: rows[input],
+        }
+    }
+
+    pub fn resize_input(&mut self, delta: i16) {
+        self.input_height = self
+            .input_height
+            .saturating_add_signed(delta)
+            .clamp(MIN_INPUT_HEIGHT, MAX_INPUT_HEIGHT);
+    }
+
+    pub fn resize_side(&mut self, delta: i16) {
+        self.side_width = self.side_width.saturating_add_signed(delta).clamp(MIN_SIDE_WIDTH, MAX_SIDE_WIDTH);
+    }
+
+    pub fn flip_input(&mut self) {
+        self.input_position = match self.input_position {
+            InputPosition::Top => InputPosition::Bottom,
+            InputPosition::Bottom => InputPosition::Top,
+        };
+    }
+
+    pub fn flip_side(&mut self) {
+        self.side_position = match self.side_position {
+            SidePosition::Left => SidePosition::Right,
+            SidePosition::Right => SidePosition::Left,
+        };
+    }
+
+    /// Writes the layout into `table`, keeping the rest of the document.
+    pub fn store(&self, table: &mut toml_edit::Table) {
+        table["input_height"] = toml_edit::value(i64::from(self.input_height));
+        table["input_position"] = toml_edit::value(match self.input_position {
+            InputPosition::Top => "top",
+            InputPosition::Bottom => "bottom",
+        });
+        table["side_width"] = toml_edit::value(i64::from(self.side_width));
+        table["side_position"] = toml_edit::value(match self.side_position {
+            SidePosition::Left => "left",
+            SidePosition::Right => "right",
+        });
+        table["show_rates"] = toml_edit::value(self.show_rates);
+        table["show_watch"] = toml_edit::value(self.show_watch);
+    }
+}
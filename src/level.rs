@@ -0,0 +1,104 @@
+//! Recognition of log-level prefixes in received lines.
+//!
+//! Understands ESP-IDF (`E (123) tag: ...`), logcat (`W/tag: ...`), Zephyr
+//! (`[00:00:01.234,000] <err> ...`), defmt and bracketed or bare level words
+//! (`[ERROR] ...`, `WARN: ...`), optionally preceded by timestamps.
+
+use clap::ValueEnum;
+use ratatui::style::{Color, Style};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+
+    pub fn style(self) -> Style {
+        let color = match self {
+            Level::Trace => Color::DarkGray,
+            Level::Debug => Color::Cyan,
+            Level::Info => Color::Green,
+            Level::Warn => Color::Yellow,
+            Level::Error => Color::Red,
+        };
+        Style::default().fg(color)
+    }
+
+    /// The next stricter minimum level, wrapping back to showing everything.
+    pub fn next(self) -> Self {
+        match self {
+            Level::Trace => Level::Debug,
+            Level::Debug => Level::Info,
+            Level::Info => Level::Warn,
+            Level::Warn => Level::Error,
+            Level::Error => Level::Trace,
+        }
+    }
+
+    fn from_letter(letter: char) -> Option<Self> {
+        Some(match letter {
+            'E' | 'F' => Level::Error,
+            'W' => Level::Warn,
+            'I' => Level::Info,
+            'D' => Level::Debug,
+            'V' | 'T' => Level::Trace,
+            _ => return None,
+        })
+    }
+
+    fn from_word(word: &str) -> Option<Self> {
+        Some(match word.to_ascii_lowercase().as_str() {
+            "error" | "err" | "fatal" | "crit" | "critical" => Level::Error,
+            "warn" | "warning" | "wrn" => Level::Warn,
+            "info" | "inf" | "notice" => Level::Info,
+            "debug" | "dbg" => Level::Debug,
+            "trace" | "trc" | "verbose" | "vrb" => Level::Trace,
+            _ => return None,
+        })
+    }
+}
+
+/// Returns the level announced at the start of `line`, if any.
+pub fn parse(line: &str) -> Option<Level> {
+    let line = line.trim_start();
+
+    // Single-letter forms: "I (1234) wifi: ..." and "I/wifi: ..."
+    let mut chars = line.chars();
+    if let (Some(letter), Some(sep)) = (chars.next(), chars.next())
+        && (sep == '/' || sep == ' ' && chars.next() == Some('('))
+        && let Some(level) = Level::from_letter(letter)
+    {
+        return Some(level);
+    }
+
+    // Level words, possibly behind timestamps
+    for token in line.split_whitespace().take(4) {
+        let word = token.trim_matches(|c: char| "[]<>():|".contains(c));
+        if let Some(level) = Level::from_word(word) {
+            return Some(level);
+        }
+        let is_timestamp = token
+            .trim_start_matches(['[', '('])
+            .starts_with(|c: char| c.is_ascii_digit());
+        if !is_timestamp {
+            break;
+        }
+    }
+    None
+}
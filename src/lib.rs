@@ -0,0 +1,86 @@
+//! Serial monitor for embedded development: a TUI over one or more serial
+//! ports, RFC 2217 servers or TCP sockets, with logging, decoders, device
+//! scripting and a headless bridge. The binary is a thin layer over these
+//! modules, which the benches and integration tests drive directly.
+
+pub mod access;
+pub mod api;
+pub mod at;
+pub mod autobaud;
+pub mod bench;
+pub mod bus;
+pub mod buspirate;
+pub mod bridge;
+pub mod capture;
+pub mod checkpoint;
+pub mod command;
+pub mod config;
+pub mod conversation;
+pub mod csv;
+pub mod daemon;
+pub mod defmt;
+pub mod diagnostics;
+pub mod dmx;
+pub mod discovery;
+pub mod editing;
+pub mod editor;
+pub mod esp;
+pub mod events;
+pub mod exit;
+pub mod expect;
+pub mod framing;
+pub mod grpc;
+pub mod headless;
+pub mod hotplug;
+pub mod hud;
+pub mod identity;
+pub mod idle;
+pub mod import;
+pub mod inbox;
+pub mod layout;
+pub mod level;
+pub mod logfile;
+pub mod macros;
+pub mod mask;
+pub mod midi;
+pub mod mock;
+pub mod modbus;
+pub mod mode;
+pub mod output;
+pub mod paste;
+pub mod pcap;
+pub mod ping;
+pub mod pipe;
+pub mod placeholders;
+pub mod port;
+pub mod power;
+pub mod preset;
+pub mod proxy;
+pub mod proto;
+pub mod quiet;
+pub mod rates;
+pub mod replay;
+pub mod rfc2217;
+pub mod rfc2217_server;
+pub mod script;
+pub mod scrollback;
+pub mod secrets;
+pub mod selftest;
+pub mod session;
+pub mod signals;
+pub mod simulator;
+pub mod slcan;
+pub mod stats;
+pub mod stress;
+pub mod syslog;
+pub mod tee;
+pub mod throttle;
+pub mod timeline;
+pub mod timing;
+pub mod toast;
+pub mod traffic;
+pub mod transport;
+pub mod trigger;
+pub mod turnaround;
+pub mod watch;
+pub mod wizard;
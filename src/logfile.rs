@@ -0,0 +1,296 @@
+//! Log files, either appended to in plaintext or encrypted with age.
+//!
+//! An age file can't be appended to, so each encrypted session gets its own
+//! file next to the configured path (`serial_monitor-20250101-120000.log.age`).
+//! Its final chunk is written when the monitor exits; decrypt it with
+//! `serial-monitor log decrypt` or `age -d -i key.txt`.
+//!
+//! Escape sequences from colorized firmware output can be stripped from the
+//! text written, or escaped as `\x1b`, so `less` and `grep` work on the log.
+
+use std::{borrow::Cow, fs, io::Write, path::Path, sync::Arc};
+
+use age::{Encryptor, stream::StreamWriter, x25519};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, sync::Mutex};
+
+use crate::toast::{self, Severity};
+
+/// What happens to control characters in logged text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogControl {
+    /// Written as received
+    Keep,
+    /// ANSI escape sequences and other control characters are removed
+    Strip,
+    /// Control characters are written as `\xNN`
+    Escape,
+}
+
+impl LogControl {
+    pub fn apply<'a>(self, text: &'a str) -> Cow<'a, str> {
+        if self == LogControl::Keep || !text.contains(is_control) {
+            return Cow::Borrowed(text);
+        }
+        let mut clean = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                c if !is_control(c) => clean.push(c),
+                c if self == LogControl::Escape => clean.push_str(&format!("\\x{:02x}", c as u32)),
+                // CSI: parameters up to a final byte, e.g. ESC [ 1 ; 31 m
+                '\x1b' if chars.next_if_eq(&'[').is_some() => {
+                    while chars.next().is_some_and(|c| !('\x40'..='\x7e').contains(&c)) {}
+                }
+                // OSC: up to BEL or ESC \, e.g. a window title or hyperlink
+                '\x1b' if chars.next_if_eq(&']').is_some() => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                // Others are ESC, any intermediate bytes and a final one, e.g. ESC ( B
+                '\x1b' => {
+                    while chars.next_if(|c| ('\x20'..='\x2f').contains(c)).is_some() {}
+                    chars.next();
+                }
+                _ => {}
+            }
+        }
+        Cow::Owned(clean)
+    }
+}
+
+/// Which directions are logged, so sent lines (which may hold credentials)
+/// can be kept out of a shared log, or the device's chatter out of a log of
+/// what was sent. Ctrl+B cycles through them during a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogDirection {
+    /// Received and sent lines
+    Both,
+    /// Received lines only
+    Rx,
+    /// Sent lines only
+    Tx,
+}
+
+impl LogDirection {
+    pub fn next(self) -> Self {
+        match self {
+            LogDirection::Both => LogDirection::Rx,
+            LogDirection::Rx => LogDirection::Tx,
+            LogDirection::Tx => LogDirection::Both,
+        }
+    }
+
+    pub fn rx(self) -> bool {
+        self != LogDirection::Tx
+    }
+
+    pub fn tx(self) -> bool {
+        self != LogDirection::Rx
+    }
+
+    /// Shown in the status bar, e.g. `log: RX only`.
+    pub fn label(self) -> &'static str {
+        match self {
+            LogDirection::Both => "log: RX+TX",
+            LogDirection::Rx => "log: RX only",
+            LogDirection::Tx => "log: TX only",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            LogDirection::Both => "Logging received and sent lines",
+            LogDirection::Rx => "Logging received lines only; sent lines are left out",
+            LogDirection::Tx => "Logging sent lines only; received lines are left out",
+        }
+    }
+}
+
+fn is_control(c: char) -> bool {
+    c.is_control() && c != '\t'
+}
+
+pub enum LogFile {
+    Plain(tokio::fs::File),
+    /// `None` once finished.
+    Encrypted(Option<StreamWriter<fs::File>>),
+}
+
+/// Parses `--log-encrypt` values: `age1...` recipients or files listing them
+/// one per line, as written by `age-keygen`.
+pub fn parse_recipients(values: &[String]) -> Result<Vec<x25519::Recipient>> {
+    let mut recipients = Vec::new();
+    for value in values {
+        if value.starts_with("age1") {
+            recipients.push(value.parse().map_err(|e| anyhow::anyhow!("Invalid age recipient {value:?}: {e}"))?);
+            continue;
+        }
+        let text = fs::read_to_string(value).with_context(|| format!("Cannot read the recipients file {value}"))?;
+        for line in text.lines().map(str::trim) {
+            // Key files written by age-keygen name the public key in a comment
+            let line = line.strip_prefix("# public key: ").unwrap_or(line);
+            if line.is_empty() || line.starts_with('#') || line.starts_with("AGE-SECRET-KEY-") {
+                continue;
+            }
+            recipients.push(line.parse().map_err(|e| anyhow::anyhow!("{value}: invalid age recipient {line:?}: {e}"))?);
+        }
+    }
+    if recipients.is_empty() && !values.is_empty() {
+        bail!("No age recipients found in --log-encrypt");
+    }
+    Ok(recipients)
+}
+
+impl LogFile {
+    /// Opens `path` for appending, or a new encrypted file beside it if
+    /// `recipients` are given.
+    pub async fn open(path: &str, recipients: &[x25519::Recipient]) -> Result<Self> {
+        if recipients.is_empty() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await
+                .with_context(|| format!("Failed to open log file {path}"))?;
+            return Ok(LogFile::Plain(file));
+        }
+        let path = encrypted_path(path);
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(|| format!("Failed to create encrypted log file {path}"))?;
+        let encryptor = Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))?;
+        let writer = encryptor.wrap_output(file).with_context(|| format!("Failed to write {path}"))?;
+        Ok(LogFile::Encrypted(Some(writer)))
+    }
+
+    pub async fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            LogFile::Plain(file) => file.write_all(data).await,
+            // Buffered in memory and written out in 64 KiB chunks
+            LogFile::Encrypted(Some(writer)) => writer.write_all(data),
+            LogFile::Encrypted(None) => Ok(()),
+        }
+    }
+
+    /// Completes an encrypted file, which can't be decrypted without this,
+    /// or waits for the writes to a plaintext one.
+    pub async fn finish(&mut self) -> std::io::Result<()> {
+        match self {
+            LogFile::Encrypted(writer) => match writer.take() {
+                Some(writer) => writer.finish()?.sync_all(),
+                None => Ok(()),
+            },
+            LogFile::Plain(file) => file.flush().await,
+        }
+    }
+}
+
+/// An encrypted file left unfinished, e.g. by an error ending the session,
+/// is completed all the same, or nothing in it could be decrypted.
+impl Drop for LogFile {
+    fn drop(&mut self) {
+        if let LogFile::Encrypted(writer) = self
+            && let Some(writer) = writer.take()
+        {
+            let _ = writer.finish().and_then(|file| file.sync_all());
+        }
+    }
+}
+
+/// Appends `text` to `file` as a line stamped with `time`.
+async fn log_to_file(file: &Arc<Mutex<LogFile>>, time: DateTime<Local>, text: &str) {
+    let timestamp = time.to_rfc3339();
+    let log_line = format!("[{}] {}\n", timestamp, text);
+    let mut file = file.lock().await;
+    if let Err(e) = file.write_all(log_line.as_bytes()).await {
+        toast::notify(Severity::Error, format!("Log write error: {e}"));
+    }
+}
+
+/// Opens a log as [`LogFile::open`] does, to be shared between tasks.
+pub async fn open_log(path: &str, recipients: &[x25519::Recipient]) -> Result<Arc<Mutex<LogFile>>> {
+    Ok(Arc::new(Mutex::new(LogFile::open(path, recipients).await?)))
+}
+
+/// The full log and the log of lines that pass the display filters.
+pub struct Logs {
+    pub all: Option<Arc<Mutex<LogFile>>>,
+    pub filtered: Option<Arc<Mutex<LogFile>>>,
+    pub control: LogControl,
+    pub direction: LogDirection,
+}
+
+impl Logs {
+    pub async fn write(&self, text: &str, shown: bool) {
+        self.write_at(Local::now(), text, shown).await;
+    }
+
+    pub async fn write_at(&self, time: DateTime<Local>, text: &str, shown: bool) {
+        let text = &self.control.apply(text);
+        if let Some(file) = &self.all {
+            log_to_file(file, time, text).await;
+        }
+        if let Some(file) = &self.filtered
+            && shown
+        {
+            log_to_file(file, time, text).await;
+        }
+    }
+
+    /// Logs a received line unless only sent lines are logged.
+    pub async fn write_rx(&self, time: DateTime<Local>, text: &str, shown: bool) {
+        if self.direction.rx() {
+            self.write_at(time, text, shown).await;
+        }
+    }
+
+    /// Logs a sent line unless only received lines are logged.
+    pub async fn write_tx(&self, time: DateTime<Local>, text: &str) {
+        if self.direction.tx() {
+            self.write_at(time, text, true).await;
+        }
+    }
+
+    /// Completes the logs.
+    pub async fn finish(&self) -> Result<()> {
+        for file in self.all.iter().chain(&self.filtered) {
+            file.lock().await.finish().await.context("Cannot complete the log")?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the plaintext of the encrypted log at `path` to `out`, using the
+/// identities in the key file at `identity` (as written by `age-keygen`).
+pub fn decrypt(path: &str, identity: &str, out: &mut impl Write) -> Result<()> {
+    let identities = age::IdentityFile::from_file(identity.to_string())
+        .with_context(|| format!("Cannot read the identity file {identity}"))?
+        .into_identities()?;
+    let file = fs::File::open(path).with_context(|| format!("Failed to open {path}"))?;
+    let decryptor = age::Decryptor::new(std::io::BufReader::new(file)).with_context(|| format!("{path} is not an age file"))?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|i| i.as_ref() as &dyn age::Identity))
+        .with_context(|| format!("Cannot decrypt {path} with {identity}"))?;
+    // A log cut off before its final chunk fails here, after what was readable
+    std::io::copy(&mut reader, out).with_context(|| format!("{path} is incomplete or damaged"))?;
+    Ok(())
+}
+
+/// `dir/name-YYYYmmdd-HHMMSS.ext.age` for `dir/name.ext`.
+fn encrypted_path(path: &str) -> String {
+    let stamp = Local::now().format("%Y%m%d-%H%M%S");
+    let file = Path::new(path);
+    let name = match (file.file_stem(), file.extension()) {
+        (Some(stem), Some(ext)) => format!("{}-{stamp}.{}.age", stem.to_string_lossy(), ext.to_string_lossy()),
+        _ => format!("{}-{stamp}.age", file.file_name().map(|n| n.to_string_lossy()).unwrap_or_default()),
+    };
+    file.with_file_name(name).to_string_lossy().into_owned()
+}
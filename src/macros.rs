@@ -0,0 +1,116 @@
+//! Recorded sequences of sent lines.
+//!
+//! `:record NAME` starts recording the lines sent from then on, typed,
+//! pasted or composed in the editor, along with the time between them;
+//! `:record stop` saves them as `NAME.macro` in the macro directory, and
+//! `:play NAME` sends them again to the active port at the same pace, so a
+//! provisioning sequence typed once by hand can be repeated on the next
+//! device. Each line of a macro file is the delay in milliseconds before a
+//! line and the line, which makes recorded macros easy to edit:
+//!
+//! ```text
+//! 0 AT
+//! 1500 AT+CWMODE=1
+//! 250 AT+CWJAP="lab","secret"
+//! ```
+//!
+//! Edited macros may also wait for responses and reuse parts of them, as
+//! described in [`crate::script`].
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::script::{self, Step};
+
+/// `macros` next to the config file, e.g. `~/.config/serial-monitor/macros`.
+pub fn default_dir() -> Option<PathBuf> {
+    crate::config::default_path().and_then(|path| path.parent().map(|dir| dir.join("macros")))
+}
+
+/// Checks a macro name, which becomes a file name.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    match !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        true => Ok(()),
+        false => Err(format!("Invalid macro name {name:?}: use letters, digits, - and _")),
+    }
+}
+
+fn path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.macro"))
+}
+
+/// A macro being recorded.
+pub struct Recorder {
+    pub name: String,
+    lines: Vec<(Duration, String)>,
+    /// When the last line was sent, or the recording started.
+    last: Instant,
+}
+
+impl Recorder {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            lines: Vec::new(),
+            last: Instant::now(),
+        }
+    }
+
+    /// Records a line sent at `now`.
+    pub fn sent(&mut self, line: &str, now: Instant) {
+        // The wait before the first line is when the recording started, not
+        // a delay the device needs
+        let delay = match self.lines.is_empty() {
+            true => Duration::ZERO,
+            false => now - self.last,
+        };
+        self.lines.push((delay, line.to_string()));
+        self.last = now;
+    }
+
+    /// Writes the macro to `dir`, returning the file and its line count.
+    pub fn save(self, dir: &Path) -> Result<(PathBuf, usize)> {
+        if self.lines.is_empty() {
+            bail!("Nothing was sent while recording {}; no macro saved", self.name);
+        }
+        fs::create_dir_all(dir).with_context(|| format!("Cannot create the macro directory {}", dir.display()))?;
+        let text: String = self
+            .lines
+            .iter()
+            .map(|(delay, line)| format!("{} {line}\n", delay.as_millis()))
+            .collect();
+        let path = path(dir, &self.name);
+        fs::write(&path, text).with_context(|| format!("Cannot write {}", path.display()))?;
+        Ok((path, self.lines.len()))
+    }
+}
+
+/// The steps of macro `name` in `dir`.
+pub fn load(dir: &Path, name: &str) -> Result<Vec<Step>> {
+    let path = path(dir, name);
+    let text = fs::read_to_string(&path).with_context(|| format!("No macro {name} ({})", path.display()))?;
+    script::parse(&text, &path.display().to_string())
+}
+
+/// The names of the macros saved in `dir`.
+pub fn list(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            match path.extension() {
+                Some(extension) if extension == "macro" => Some(path.file_stem()?.to_string_lossy().into_owned()),
+                _ => None,
+            }
+        })
+        .collect();
+    names.sort();
+    names
+}
@@ -1,34 +1,165 @@
 use std::{
-    sync::Arc,
+    collections::HashMap,
+    process::ExitCode,
+    sync::{Arc, Mutex, OnceLock},
     time::{Duration, Instant},
     fs::metadata,
+    path::PathBuf,
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
+use futures::StreamExt;
 use chrono::Local;
-use clap::Parser;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, parser::ValueSource};
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{DisableBracketedPaste, EnableBracketedPaste, Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode},
     cursor::{Hide, Show},
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Paragraph, Wrap},
     Terminal,
 };
+use regex::Regex;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    sync::{mpsc, Mutex},
+    net::TcpListener,
+    sync::mpsc,
     time,
-    fs::OpenOptions,
 };
-use tokio_serial::{SerialPortBuilderExt, DataBits, FlowControl, Parity, StopBits};
 
-const VALID_BAUD_RATES: &[u32] = &[300, 1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200];
+
+use serial_monitor::{
+    access,
+    api,
+    at,
+    autobaud,
+    bench,
+    bus,
+    buspirate,
+    bridge,
+    capture,
+    checkpoint,
+    command,
+    config,
+    conversation,
+    csv,
+    defmt,
+    discovery,
+    editing,
+    editor,
+    esp,
+    events,
+    exit::{self, Status},
+    expect::Expected,
+    framing,
+    grpc,
+    headless,
+    hotplug,
+    hud,
+    identity,
+    idle,
+    import,
+    inbox,
+    layout,
+    level,
+    logfile,
+    macros,
+    mask,
+    midi,
+    modbus,
+    mode,
+    output,
+    paste,
+    pcap,
+    ping,
+    pipe,
+    placeholders,
+    port,
+    power,
+    preset,
+    proxy,
+    proto,
+    quiet,
+    rates,
+    replay,
+    rfc2217_server,
+    script,
+    scrollback,
+    secrets,
+    selftest,
+    session::{self, Session},
+    signals::{self, Sequence},
+    simulator,
+    slcan,
+    stats,
+    stress,
+    syslog,
+    tee,
+    throttle,
+    timeline,
+    timing,
+    toast,
+    traffic,
+    transport,
+    trigger,
+    turnaround,
+    watch,
+    wizard,
+};
+
+use access::Access;
+use api::{Journal, SendRequest};
+use at::AtTracker;
+use autobaud::{AutoBaud, Step};
+use bus::Bus;
+use capture::Capture;
+use checkpoint::Checkpointer;
+use command::Command;
+use config::{Config, Profile};
+use conversation::Conversation;
+use csv::CsvTable;
+use defmt::DefmtDecoder;
+use esp::Symbolizer;
+use ping::Pinger;
+use pipe::Popup;
+use hotplug::Hotplug;
+use hud::Hud;
+use idle::Watchdog;
+use inbox::Inbox;
+use events::{PortEvent, SerialEvent};
+use port::{PortHandle, Recover, Restart, RestartPolicy};
+use power::{HubPort, PowerCycle};
+use preset::Preset;
+use replay::Player;
+use scrollback::Budget;
+use framing::{Decoder, Framer, Framing, LengthPrefix};
+use layout::{PaneLayout, SidePosition};
+use level::Level;
+use logfile::{LogControl, LogDirection, Logs, open_log};
+use mask::Masks;
+use midi::MidiDecoder;
+use mode::UiMode;
+use output::{OutputLine, Pane, View};
+use proto::ProtoDecoder;
+use quiet::Activity;
+use rates::MessageRates;
+use placeholders::Placeholders;
+use secrets::Secrets;
+use stats::SessionStats;
+use stress::Stress;
+use syslog::{Direction as SyslogDirection, Syslog};
+use tee::Tee;
+use throttle::{ThrottleSpec, Throttles, Verdict};
+use timeline::Timeline;
+use toast::{Severity, Toasts};
+use traffic::Traffic;
+use turnaround::Turnaround;
+use transport::{Control, Encoding, FlowControlArg, LineEnding, ParityArg, SerialSettings, Transport, TCP_SCHEME, VALID_BAUD_RATES, validate_port};
+use watch::{AlertEvent, AlertSpec, WatchSpec, Watches};
 
 fn validate_baud_rate(baud: &str) -> Result<u32, String> {
     let baud: u32 = baud.parse().map_err(|_| {
@@ -44,39 +175,649 @@ fn validate_baud_rate(baud: &str) -> Result<u32, String> {
     }
 }
 
-fn validate_port(port: &str) -> Result<String, String> {
-    // Check if port matches Unix-like (/dev/tty*) or Windows (COM*) patterns
-    let is_valid_pattern = 
-        port.starts_with("/dev/tty") || // Unix-like systems (Linux/macOS)
-        port.to_uppercase().starts_with("COM"); // Windows (e.g., COM1, COM2)
-    
-    if !is_valid_pattern {
-        return Err(format!(
-            "Invalid port: {}. Must start with '/dev/tty' (Unix) or 'COM' (Windows)", 
-            port
-        ));
-    }
+fn parse_color(color: &str) -> Result<Color, String> {
+    color.parse().map_err(|_| format!("Invalid color: {color}. Use a name (e.g., red, lightblue), an index or #rrggbb"))
+}
 
-    // Optionally check if port exists (non-async, but fine for startup)
-    if metadata(port).is_err() {
-        // Warn but don't fail, as ports may appear dynamically or require permissions
-        eprintln!("Warning: Port '{}' may not exist or is inaccessible", port);
-    }
+#[derive(Parser, Debug)]
+#[command(
+    about = "Serial monitor for Arduino communication",
+    version,
+    after_help = "Without a subcommand the options start the monitor, as with `serial-monitor monitor`."
+)]
+struct Cli {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+
+    #[command(flatten)]
+    global: Global,
 
-    Ok(port.to_string())
+    #[command(flatten)]
+    monitor: Args,
 }
 
-#[derive(Parser, Debug)]
-#[command(about = "Serial monitor for Arduino communication")]
-struct Args {
-    /// Serial port name (e.g., /dev/ttyUSB0 or COM1)
+/// Options shared by every subcommand.
+#[derive(clap::Args, Debug)]
+struct Global {
+    /// Apply a named profile from the config file
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+
+    /// Config file path (default: ~/.config/serial-monitor/config.toml); a
+    /// .serial-monitor.toml found from the current directory up is merged over it
+    #[arg(long, value_name = "FILE", global = true)]
+    config: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Mode {
+    /// Monitor one or more ports in the terminal UI (the default)
+    Monitor(Args),
+    /// List the available serial ports with their descriptions
+    List,
+    /// Inspect capture files and decrypt encrypted logs
+    #[command(subcommand)]
+    Log(LogCommand),
+    /// Replay a capture file (see --capture), or a pcap, typescript or text
+    /// capture of another tool, through the monitor instead of opening ports
+    Replay {
+        /// Capture file to replay
+        file: String,
+
+        /// Format of the file
+        #[arg(long, value_enum, default_value_t = import::Format::Auto)]
+        format: import::Format,
+
+        /// Timing file of a `script` typescript (script -T FILE or --log-timing FILE)
+        #[arg(long, value_name = "FILE")]
+        timing: Option<String>,
+
+        #[command(flatten)]
+        monitor: Args,
+    },
+    /// Share a port as an RFC 2217 server without the terminal UI, e.g. as a
+    /// systemd service
+    Bridge {
+        /// Address to listen on, unless systemd passes a socket (socket activation);
+        /// only this machine by default, give e.g. `0.0.0.0:2217` to share the
+        /// port over the network
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:2217")]
+        listen: String,
+
+        /// Write the process ID to this file while running
+        #[arg(long, value_name = "FILE")]
+        pid_file: Option<String>,
+
+        /// Keep this file updated with the port's state and traffic as JSON
+        #[arg(long, value_name = "FILE")]
+        status_file: Option<String>,
+
+        #[command(flatten)]
+        access: AccessArgs,
+
+        #[command(flatten)]
+        line: LineArgs,
+    },
+    /// Run a macro as a device test without the terminal UI, reporting its
+    /// expect and check assertions, e.g. for CI
+    Run {
+        /// Macro file with the steps to run on every port, or one file per
+        /// --port in the same order
+        #[arg(required = true, num_args = 1..)]
+        script: Vec<String>,
+
+        /// Write the assertions as a JUnit XML report to this file (- for stdout)
+        #[arg(long, value_name = "FILE")]
+        junit: Option<String>,
+
+        /// Write the assertions as a TAP report to this file (- for stdout)
+        #[arg(long, value_name = "FILE")]
+        tap: Option<String>,
+
+        /// How the lines sent and received and the results are printed
+        #[arg(long, value_enum, default_value_t = headless::Output::Text)]
+        output: headless::Output,
+
+        /// Appended to each line sent
+        #[arg(long, value_enum, default_value_t = LineEnding::Lf)]
+        line_ending: LineEnding,
+
+        /// Run against a simulated device described by a script instead of
+        /// the port
+        #[arg(long, value_name = "FILE")]
+        simulate: Option<String>,
+
+        /// File of NAME=VALUE secrets that macros refer to as {{secret:NAME}}
+        /// (default: ~/.config/serial-monitor/secrets)
+        #[arg(long, value_name = "FILE")]
+        secrets: Option<String>,
+
+        #[command(flatten)]
+        line: LineArgs,
+    },
+    /// Check the installation without hardware: port task, RFC 2217, captures and config
+    Selftest,
+    /// Print a completion script for a shell, e.g. `serial-monitor completions bash`
+    Completions { shell: clap_complete::Shell },
+    /// Print the man page in roff format, e.g. `serial-monitor man | man -l -`
+    Man,
+}
+
+#[derive(Subcommand, Debug)]
+enum LogCommand {
+    /// Check a capture file and print its settings, span and integrity
+    Info {
+        /// Capture file written with --capture
+        file: String,
+    },
+    /// Print the plaintext of a log written with --log-encrypt
+    Decrypt {
+        /// Encrypted log file (.age)
+        file: String,
+
+        /// age identity file, as written by age-keygen
+        #[arg(long, short, value_name = "FILE")]
+        identity: String,
+    },
+    /// Convert a capture file to pcapng for Wireshark, e.g.
+    /// `serial-monitor log pcap session.cap - | wireshark -k -i -`
+    Pcap {
+        /// Capture file written with --capture
+        file: String,
+
+        /// pcapng file to write, or - for stdout
+        output: String,
+
+        /// Link-layer header type of the packets
+        #[arg(long, value_enum, default_value_t = pcap::LinkType::User)]
+        link: pcap::LinkType,
+
+        /// Join consecutive chunks in the same direction less than this many
+        /// milliseconds apart into one packet, e.g. to keep Modbus RTU frames whole
+        #[arg(long, value_name = "MS")]
+        gap: Option<u64>,
+    },
+    /// Export the timing of a capture file as CSV: a row per chunk with the
+    /// idle time before it, e.g. to check Modbus RTU's 3.5 character gaps
+    Timing {
+        /// Capture file written with --capture
+        file: String,
+
+        /// CSV file to write, or - for stdout
+        output: String,
+
+        /// A row per byte, with the time it arrived estimated from the line
+        /// settings, instead of per chunk read
+        #[arg(long)]
+        bytes: bool,
+
+        /// Gap in character times flagged as the start of a frame
+        #[arg(long, value_name = "CHARS", default_value_t = timing::FRAME_GAP)]
+        frame_gap: f64,
+    },
+}
+
+/// The port to open and its line settings.
+#[derive(clap::Args, Debug)]
+struct LineArgs {
+    /// Serial port name (e.g., /dev/ttyUSB0, COM1, rfc2217://host:port or
+    /// rfc2217s://host:port over TLS); repeat
+    /// to monitor several ports in one merged view
     #[arg(long, default_value = "/dev/ttyUSB0", value_parser = validate_port)]
-    port: String,
+    port: Vec<String>,
+
+    /// Whether a port is reopened after its device disappears, the connection
+    /// closes or its task fails
+    #[arg(long, value_enum, default_value_t = RestartPolicy::OnFailure)]
+    restart: RestartPolicy,
+
+    /// Exit with status 5 when a port's connection is lost instead of
+    /// reopening it, so a wrapper script learns of it
+    #[arg(long)]
+    fail_on_disconnect: bool,
+
+    /// Power-cycle the device through its USB hub port after this many failed
+    /// attempts in a row to reopen the port, e.g. to recover a locked-up board
+    #[arg(long, value_name = "ATTEMPTS", value_parser = clap::value_parser!(u32).range(1..))]
+    power_cycle_after: Option<u32>,
+
+    /// Command that power-cycles the device; {hub}, {hub_port} and {device}
+    /// (the serial port) are filled in
+    #[arg(long, value_name = "COMMAND", default_value = power::DEFAULT_COMMAND)]
+    power_cycle_command: String,
+
+    /// USB hub and hub port of the device as uhubctl lists them (e.g.
+    /// 1-1.4:2), when not found from the serial port; repeat in --port order
+    #[arg(long, value_name = "HUB:PORT", value_parser = power::parse_hub_port)]
+    usb_hub_port: Vec<HubPort>,
 
     /// Baud rate for serial communication
     #[arg(long, default_value_t = 57600, value_parser = validate_baud_rate)]
     baud_rate: u32,
 
+    /// Number of data bits per character
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u8).range(5..=8))]
+    data_bits: u8,
+
+    /// Parity checking mode
+    #[arg(long, value_enum, default_value_t = ParityArg::None)]
+    parity: ParityArg,
+
+    /// Number of stop bits
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=2))]
+    stop_bits: u8,
+
+    /// Flow control mode
+    #[arg(long, value_enum, default_value_t = FlowControlArg::None)]
+    flow_control: FlowControlArg,
+
+    /// Token sent to RFC 2217 servers started with --auth-token, or a file holding it
+    #[arg(long, value_name = "TOKEN")]
+    remote_token: Option<String>,
+
+    /// PEM certificates trusted for rfc2217s:// ports, e.g. the server's own
+    /// self-signed certificate
+    #[arg(long, value_name = "FILE")]
+    remote_ca: Option<String>,
+}
+
+impl LineArgs {
+    fn settings(&self) -> SerialSettings {
+        SerialSettings {
+            baud_rate: self.baud_rate,
+            data_bits: self.data_bits,
+            parity: self.parity,
+            stop_bits: self.stop_bits,
+            flow_control: self.flow_control,
+        }
+    }
+
+    /// Whether a port is reopened, which --fail-on-disconnect rules out.
+    fn restart(&self) -> RestartPolicy {
+        match self.fail_on_disconnect {
+            true => RestartPolicy::Never,
+            false => self.restart,
+        }
+    }
+
+    /// How the device behind port number `index`, named `name`, is power-cycled.
+    fn power_cycle(&self, index: usize, name: &str) -> PowerCycle {
+        PowerCycle::new(&self.power_cycle_command, name, self.usb_hub_port.get(index).cloned())
+    }
+
+    /// The power cycle run while the port fails to reopen, with --power-cycle-after.
+    fn recover(&self, index: usize, name: &str) -> Option<(u32, Recover)> {
+        let after = self.power_cycle_after?;
+        let power_cycle = self.power_cycle(index, name);
+        let recover: Recover = Box::new(move || {
+            let power_cycle = power_cycle.clone();
+            Box::pin(async move { power_cycle.run().await })
+        });
+        Some((after, recover))
+    }
+}
+
+/// Who may connect to the RFC 2217 server, HTTP API and gRPC service.
+#[derive(clap::Args, Debug)]
+struct AccessArgs {
+    /// Token clients must present (a bearer token over HTTP and gRPC, an
+    /// `AUTH TOKEN` line over RFC 2217), or a file holding it
+    #[arg(long, value_name = "TOKEN")]
+    auth_token: Option<String>,
+
+    /// Serve over TLS with this PEM certificate chain
+    #[arg(long, value_name = "FILE", requires = "tls_key")]
+    tls_cert: Option<String>,
+
+    /// PEM private key of --tls-cert
+    #[arg(long, value_name = "FILE", requires = "tls_cert")]
+    tls_key: Option<String>,
+}
+
+impl AccessArgs {
+    fn load(&self) -> Result<Arc<Access>> {
+        let access = Access::load(self.auth_token.as_deref(), self.tls_cert.as_deref(), self.tls_key.as_deref())?;
+        Ok(Arc::new(access))
+    }
+}
+
+/// Options of the monitor, also taken by `monitor` and `replay`.
+#[derive(clap::Args, Debug)]
+struct Args {
+    #[command(flatten)]
+    line: LineArgs,
+
+    /// Listen passively on two ports tapping the two directions of a link
+    /// (e.g. with a Y-cable) and tag each line with its direction; nothing is
+    /// sent to either
+    #[arg(long, conflicts_with = "proxy")]
+    sniff: bool,
+
+    /// Sit between the devices on two ports (or a device and a tcp://
+    /// socket), passing on what each sends to the other and showing both
+    /// directions; typed lines are injected into the port they're sent to
+    #[arg(long)]
+    proxy: bool,
+
+    /// Replace matches of REGEX in what --proxy passes on, e.g.
+    /// "SPEED=\d+=>SPEED=0" (repeatable); $1 refers to a group
+    #[arg(long, value_name = "REGEX=>REPLACEMENT", requires = "proxy", value_parser = proxy::parse_rewrite)]
+    rewrite: Vec<proxy::Rule>,
+
+    /// Short names tagging each port's lines instead of the device name, in
+    /// --port order (e.g., --alias imu,gps); with --sniff, the names of the
+    /// devices whose transmissions each port taps
+    #[arg(long, value_delimiter = ',')]
+    alias: Vec<String>,
+
+    /// Tag colors for each port in --port order (e.g., red, lightblue or
+    /// #ff8800); by default every port gets a distinct color
+    #[arg(long, value_delimiter = ',', value_parser = parse_color)]
+    port_color: Vec<Color>,
+
+    /// How the received byte stream is split into frames
+    #[arg(long, value_enum, default_value_t = Framing::Lines)]
+    framing: Framing,
+
+    /// Length field format for --framing length-prefixed
+    #[arg(long, value_enum, default_value_t = LengthPrefix::Varint)]
+    length_prefix: LengthPrefix,
+
+    /// Bytes ending each frame for --framing delimiter, e.g. "\x03" for ETX
+    #[arg(long, value_name = "BYTES", default_value = "\\n", value_parser = framing::parse_delimiter)]
+    // Spelled out so clap parses one value rather than a list of bytes
+    delimiter: std::vec::Vec<u8>,
+
+    /// Show a partial line after this many milliseconds without data, so
+    /// prompts without a newline appear (e.g. 200)
+    #[arg(long, value_name = "MS")]
+    line_timeout: Option<u64>,
+
+    /// Split lines, delimited and idle frames longer than this many bytes
+    /// into parts marked ↩, so a device sending without terminators can't
+    /// stall the display
+    #[arg(long, value_name = "BYTES", default_value_t = framing::MAX_LINE_LEN)]
+    max_line_length: usize,
+
+    /// Frame size in bytes for --framing fixed
+    #[arg(long, value_name = "BYTES", default_value_t = 16)]
+    frame_length: usize,
+
+    /// Quiet time in milliseconds ending a frame for --framing idle
+    #[arg(long, value_name = "MS", default_value_t = 20)]
+    frame_timeout: u64,
+
+    /// Protobuf FileDescriptorSet used to decode binary frames (--framing
+    /// length-prefixed or fixed)
+    #[arg(long, value_name = "FILE", requires = "proto_message", conflicts_with_all = ["slcan", "defmt"])]
+    proto_descriptor: Option<String>,
+
+    /// Fully qualified protobuf message name of each frame (e.g., telemetry.Sample)
+    #[arg(long, value_name = "NAME", requires = "proto_descriptor")]
+    proto_message: Option<String>,
+
+    /// Open the port with the line settings of a protocol and decode its
+    /// messages: MIDI at 31250 baud or DMX512 at 250000 baud, 8N2
+    #[arg(long, value_enum, conflicts_with_all = ["slcan", "proto_descriptor", "defmt"])]
+    preset: Option<Preset>,
+
+    /// Decode slcan (LAWICEL) CAN frames and send input in cansend syntax (123#AABB)
+    #[arg(long)]
+    slcan: bool,
+
+    /// Open the slcan CAN channel at this bitrate in kbit/s on startup
+    #[arg(long, value_name = "KBIT", requires = "slcan", value_parser = slcan::validate_bitrate)]
+    slcan_bitrate: Option<u32>,
+
+    /// Pair sent AT commands with their final result code and show round-trip times
+    #[arg(long)]
+    at: bool,
+
+    /// Milliseconds to wait for an AT command's final result code
+    #[arg(long, value_name = "MS", default_value_t = 5000)]
+    at_timeout: u64,
+
+    /// Regex matching the device's prompt, which ends the response to a command
+    /// in conversation mode (e.g., "^> $")
+    #[arg(long, value_name = "REGEX")]
+    prompt: Option<Regex>,
+
+    /// Milliseconds of silence after which a command's response is complete
+    #[arg(long, value_name = "MS", default_value_t = 2000)]
+    reply_timeout: u64,
+
+    /// Read coils (function 1), discrete inputs (2), holding registers (3) or
+    /// input registers (4) from a Modbus RTU slave on the first port every
+    /// INTERVAL (default 1s) into watch NAME, e.g. "temp=1:3:100@500ms"
+    /// (repeatable)
+    #[arg(long, value_name = "NAME=SLAVE:FUNCTION:ADDRESS[:COUNT][@INTERVAL]", value_parser = modbus::parse_poll)]
+    modbus_poll: Vec<modbus::Poll>,
+
+    /// Milliseconds a Modbus slave has to answer a poll
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    modbus_timeout: u64,
+
+    /// Drive a Bus Pirate on the first port in I2C or SPI mode; lines typed are
+    /// then transactions, e.g. "50 w 00 10 r 4" or "9F 00 00 00"
+    #[arg(long, value_enum, value_name = "PROTOCOL", conflicts_with_all = ["sniff", "proxy", "slcan", "preset"])]
+    bus_pirate: Option<buspirate::Protocol>,
+
+    /// Bus speed in kHz for --bus-pirate (default: 100 for I2C, 1000 for SPI)
+    #[arg(long, value_name = "KHZ", requires = "bus_pirate")]
+    bus_pirate_speed: Option<u32>,
+
+    /// Turn on the Bus Pirate's power supplies, and its pull-ups for I2C
+    #[arg(long, requires = "bus_pirate")]
+    bus_pirate_power: bool,
+
+    /// Regex picking the address out of each frame on a multi-drop bus, as its
+    /// first group (e.g., "^([0-9A-F]{2}) "); frames are tagged by device
+    #[arg(long, value_name = "REGEX")]
+    bus_address: Option<Regex>,
+
+    /// Name of the device at a bus address, e.g. "01=pump" (repeatable)
+    #[arg(long, value_name = "ADDR=NAME", requires = "bus_address", value_parser = bus::parse_device)]
+    bus_device: Vec<(String, String)>,
+
+    /// Time how long the device takes to answer each frame written to it and
+    /// show a histogram of the times in the message rates pane
+    #[arg(long)]
+    turnaround: bool,
+
+    /// Milliseconds within which the device must answer; slower answers are
+    /// marked in the output
+    #[arg(long, value_name = "MS", requires = "turnaround")]
+    turnaround_limit: Option<u64>,
+
+    /// Firmware ELF used to symbolize ESP panic backtraces
+    #[arg(long, value_name = "FILE")]
+    elf: Option<String>,
+
+    /// addr2line-compatible tool used with --elf (e.g., xtensa-esp32-elf-addr2line)
+    #[arg(long, value_name = "CMD", default_value = "addr2line")]
+    addr2line: String,
+
+    /// Decode the stream as defmt log frames using the --elf firmware
+    #[arg(long, requires = "elf")]
+    defmt: bool,
+
+    /// Column names for the CSV table view (e.g., time,temp,humidity)
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    csv_header: Vec<String>,
+
+    /// Field separator for the CSV table view
+    #[arg(long, value_name = "CHAR", default_value_t = ',')]
+    csv_delimiter: char,
+
+    /// Show a value extracted from received lines in the watch panel (repeatable),
+    /// e.g. temp=T:([-0-9.]+)
+    #[arg(long, value_name = "NAME=REGEX", value_parser = watch::parse_spec)]
+    watch: Vec<WatchSpec>,
+
+    /// Alert when a watched value leaves its range, optionally firing an action
+    /// (repeatable), e.g. "temp>80 => exec:./cut-power.sh"
+    #[arg(long, value_name = "RULE", value_parser = watch::parse_alert)]
+    alert: Vec<AlertSpec>,
+
+    /// Count a port from which nothing was received for this many seconds
+    /// as idle, showing an alert
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    idle_timeout: Option<u64>,
+
+    /// Send this line to a port each time it stays idle for --idle-timeout,
+    /// for devices that sleep unless poked
+    #[arg(long, value_name = "TEXT", requires = "idle_timeout", conflicts_with = "sniff")]
+    keep_alive: Option<String>,
+
+    /// Fire this action when a port goes idle: bell, send:TEXT or exec:COMMAND
+    /// (with SERIAL_MONITOR_PORT and SERIAL_MONITOR_IDLE set)
+    #[arg(long, value_name = "ACTION", requires = "idle_timeout", value_parser = trigger::parse_action)]
+    on_idle: Option<trigger::Action>,
+
+    /// Compare the lines received from the first port in order with this
+    /// golden file, highlighting those that differ
+    #[arg(long, value_name = "FILE")]
+    expect: Option<String>,
+
+    /// Leave the matches of this regex out of the --expect comparison
+    /// (repeatable), e.g. "^I \(\d+\)" for ESP-IDF timestamps
+    #[arg(long, value_name = "REGEX", requires = "expect")]
+    expect_ignore: Vec<Regex>,
+
+    /// Hide received lines below this log level (change at runtime with Ctrl+L)
+    #[arg(long, value_enum, default_value_t = Level::Trace)]
+    min_level: Level,
+
+    /// Run a scripted fake device instead of opening a port
+    #[arg(long, value_name = "SCRIPT", conflicts_with = "port")]
+    simulate: Option<String>,
+
+    /// Record every raw chunk read and written, with timing, to this capture file
+    #[arg(long, value_name = "FILE")]
+    capture: Option<String>,
+
+    /// Stream the bytes received to this named pipe as they arrive, created
+    /// if missing, for other programs to read; repeat in --port order
+    #[arg(long, value_name = "FIFO")]
+    tee: Vec<String>,
+
+    /// Show only per-port throughput and alerts instead of the received text
+    #[arg(long)]
+    quiet: bool,
+
+    /// Show the performance HUD (frame rate, draw time, event loop lag and
+    /// queued events) from the start; :hud toggles it
+    #[arg(long)]
+    hud: bool,
+
+    /// Measure the receive pipeline on synthetic data instead of opening
+    /// ports, and print the throughput, latency and draw time
+    #[arg(long)]
+    bench_internal: bool,
+
+    /// Rate the benchmark's simulated device writes at, e.g. 100KB/s
+    /// (default: as fast as possible)
+    #[arg(long, value_name = "RATE", value_parser = stress::parse_rate, requires = "bench_internal")]
+    bench_rate: Option<u64>,
+
+    /// Seconds the benchmark's device writes for
+    #[arg(long, value_name = "SECS", default_value_t = 5, requires = "bench_internal")]
+    bench_seconds: u64,
+
+    /// Length of the benchmark's lines
+    #[arg(long, value_name = "BYTES", default_value_t = 80, requires = "bench_internal")]
+    bench_line_length: usize,
+
+    /// Memory the scrollback may use, e.g. 512KB or 64MB; the oldest lines
+    /// are dropped beyond it
+    #[arg(long, value_name = "SIZE", default_value = "8MB", value_parser = scrollback::parse_size)]
+    scrollback: usize,
+
+    /// Pastes of more lines than this are confirmed before sending
+    #[arg(long, value_name = "LINES", default_value_t = 5)]
+    paste_lines: usize,
+
+    /// Pastes larger than this are confirmed before sending
+    #[arg(long, value_name = "SIZE", default_value = "1KB", value_parser = scrollback::parse_size)]
+    paste_bytes: usize,
+
+    /// Milliseconds between the lines of a paste sent paced
+    #[arg(long, value_name = "MS", default_value_t = 50)]
+    paste_delay: u64,
+
+    /// Restore the scrollback and input history checkpointed by the last
+    /// session on the same ports, e.g. after a crash, and keep checkpointing
+    #[arg(long)]
+    resume: bool,
+
+    /// Checkpoint the scrollback and input history to this directory for
+    /// --resume (default with --resume: ~/.local/state/serial-monitor)
+    #[arg(long, value_name = "DIR")]
+    session_dir: Option<String>,
+
+    /// Restore the ports, line settings, watches, alerts, masks, filters,
+    /// macro directory, view and layout saved with :save-session; options
+    /// given here take precedence
+    #[arg(long, value_name = "FILE")]
+    session: Option<String>,
+
+    /// Directory of the macros saved with :record (default:
+    /// ~/.config/serial-monitor/macros)
+    #[arg(long, value_name = "DIR")]
+    macro_dir: Option<String>,
+
+    /// Name a sequence of DTR/RTS changes and waits that :reset NAME runs
+    /// on the active port (repeatable), e.g. "boot=dtr=1 rts=1 100ms rts=0"
+    #[arg(long, value_name = "NAME=STEPS", value_parser = signals::parse_named)]
+    sequence: Vec<(String, Sequence)>,
+
+    /// File of NAME=VALUE secrets that macros refer to as {{secret:NAME}}
+    /// (default: ~/.config/serial-monitor/secrets)
+    #[arg(long, value_name = "FILE")]
+    secrets: Option<String>,
+
+    /// Query sent to each port when it connects, whose answer identifies the
+    /// device in the log and the session summary (e.g., ATI or M115)
+    #[arg(long, value_name = "QUERY")]
+    identify: Option<String>,
+
+    /// Regex picking the identity out of the answer to --identify: its named
+    /// groups, first group or whole match (default: the first line that isn't
+    /// OK)
+    #[arg(long, value_name = "REGEX", requires = "identify")]
+    identify_match: Option<Regex>,
+
+    /// Collapse consecutive identical received lines into one with a ×N count
+    #[arg(long)]
+    dedup: bool,
+
+    /// Show lines matching a regex at most once per interval, counting the
+    /// ones suppressed (repeatable), e.g. "10s=^heartbeat"
+    #[arg(long, value_name = "INTERVAL=REGEX", value_parser = throttle::parse_spec)]
+    throttle: Vec<ThrottleSpec>,
+
+    /// Shell command playing a sound instead of the terminal bell, e.g.
+    /// "paplay /usr/share/sounds/freedesktop/stereo/bell.oga"
+    #[arg(long, value_name = "COMMAND")]
+    bell_command: Option<String>,
+
+    /// Don't ring the bell for BEL (0x07) characters received
+    #[arg(long)]
+    no_bel: bool,
+
+    /// Mask matches of this regex in the display and logs (repeatable); with
+    /// capture groups only the groups are masked, e.g. "AT\+PWD=(.*)"
+    #[arg(long, value_name = "REGEX")]
+    mask: Vec<Regex>,
+
+    /// Appended to each line sent (--at always ends commands with CR)
+    #[arg(long, value_enum, default_value_t = LineEnding::Lf)]
+    line_ending: LineEnding,
+
+    /// Character encoding of the text sent and received
+    #[arg(long, value_enum, default_value_t = Encoding::Utf8)]
+    encoding: Encoding,
+
     /// Log file path
     #[arg(long, default_value = "serial_monitor.log")]
     log_file: String,
@@ -84,222 +825,2485 @@ struct Args {
     /// Disable logging to file
     #[arg(long)]
     no_log: bool,
+
+    /// Also log only the lines that pass the active display filters to this file
+    #[arg(long, value_name = "FILE")]
+    filtered_log: Option<String>,
+
+    /// Encrypt the log files to this age recipient (age1...) or the recipients
+    /// listed in this file (repeatable); each session gets a new .age file
+    #[arg(long, value_name = "RECIPIENT")]
+    log_encrypt: Vec<String>,
+
+    /// What to do with ANSI escape sequences and other control characters in
+    /// the text logs (the raw --capture keeps the original bytes)
+    #[arg(long, value_enum, default_value_t = LogControl::Keep)]
+    log_control: LogControl,
+
+    /// Which lines are logged to the log files and syslog: received and
+    /// sent ones, only received (rx) or only sent (tx); Ctrl+B cycles
+    #[arg(long, value_enum, default_value_t = LogDirection::Both)]
+    log_direction: LogDirection,
+
+    /// Forward received and sent lines to the local syslog/journald
+    #[arg(long)]
+    log_syslog: bool,
+
+    /// Share the port with other tools as an RFC 2217 server (e.g., 0.0.0.0:2217)
+    #[arg(long, value_name = "ADDR")]
+    rfc2217_server: Option<String>,
+
+    /// Serve an HTTP API for scripts (GET /lines, POST /send) on this address,
+    /// e.g. 127.0.0.1:8080
+    #[arg(long, value_name = "ADDR", requires = "auth_token")]
+    http: Option<String>,
+
+    /// Serve the gRPC service (Events stream, SendCommand) on this address,
+    /// e.g. 127.0.0.1:50051
+    #[arg(long, value_name = "ADDR", requires = "auth_token")]
+    grpc: Option<String>,
+
+    #[command(flatten)]
+    access: AccessArgs,
+
+    /// Set from the shared --profile and --config and the replay subcommand
+    #[arg(skip)]
+    profile: Option<String>,
+    #[arg(skip)]
+    config: Option<String>,
+    #[arg(skip)]
+    replay: Option<String>,
+    /// Options a config file change applies that were given on the command
+    /// line or restored with --session, which keeps them
+    #[arg(skip)]
+    given: Vec<&'static str>,
+    /// The session of --session, whose view and layout apply once the
+    /// monitor starts
+    #[arg(skip)]
+    restored: Option<Session>,
+}
+
+/// Parses the command line and fills options not given there from the
+/// `--session` and the selected profile: `--profile`, else the first-run
+/// setup's answers, else the `default` profile if the config file has one.
+fn parse_cli(setup: Option<Profile>) -> Result<Cli> {
+    let command = Cli::command();
+    let matches = command.clone().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches)?;
+    let from_cli = |matches: &ArgMatches, id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if let Some((mode, _)) = matches.subcommand()
+        && let Some(arg) = command.get_arguments().find(|arg| !arg.is_global_set() && from_cli(&matches, arg.get_id().as_str()))
+    {
+        anyhow::bail!("--{} can't be given before the {mode} subcommand", arg.get_long().unwrap_or_default());
+    }
+    if let Some(("replay", replay)) = matches.subcommand()
+        && (from_cli(replay, "port") || from_cli(replay, "simulate"))
+    {
+        anyhow::bail!("A replay takes its ports from the capture; --port and --simulate can't be given");
+    }
+    if !matches!(
+        cli.mode,
+        None | Some(Mode::Monitor(_) | Mode::Replay { .. } | Mode::Bridge { .. } | Mode::Run { .. })
+    ) {
+        return Ok(cli);
+    }
+    let profile = match (&cli.global.profile, setup) {
+        (Some(name), _) => Some((name.clone(), config::load(cli.global.config.as_deref())?.profile(name)?)),
+        (None, Some(profile)) => Some(("setup".to_string(), profile)),
+        (None, None) => config::load(cli.global.config.as_deref())?
+            .profiles
+            .remove(config::DEFAULT_PROFILE)
+            .map(|profile| (config::DEFAULT_PROFILE.to_string(), profile)),
+    };
+    match (&mut cli.mode, matches.subcommand()) {
+        (None, _) => {
+            if let Some((name, profile)) = &profile {
+                apply_profile(&mut cli.monitor, name, profile, &matches)?;
+            }
+            apply_preset(&mut cli.monitor, &matches);
+            cli.monitor.given = RELOADED.into_iter().filter(|id| from_cli(&matches, id)).collect();
+            apply_session(&mut cli.monitor, &matches)?;
+        }
+        (Some(Mode::Monitor(args) | Mode::Replay { monitor: args, .. }), Some((_, matches))) => {
+            if let Some((name, profile)) = &profile {
+                apply_profile(args, name, profile, matches)?;
+            }
+            apply_preset(args, matches);
+            args.given = RELOADED.into_iter().filter(|id| from_cli(matches, id)).collect();
+            apply_session(args, matches)?;
+        }
+        (Some(Mode::Bridge { line, .. } | Mode::Run { line, .. }), Some((_, matches))) => {
+            if let Some((name, profile)) = &profile {
+                apply_line_profile(line, name, profile, matches)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(cli)
+}
+
+fn apply_line_profile(line: &mut LineArgs, name: &str, profile: &Profile, matches: &ArgMatches) -> Result<()> {
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let invalid = |e: String| anyhow::anyhow!("Profile {name:?}: {e}");
+    if let Some(port) = &profile.port
+        && !from_cli("port")
+    {
+        line.port = vec![validate_port(port).map_err(invalid)?];
+    }
+    if let Some(baud_rate) = profile.baud_rate
+        && !from_cli("baud_rate")
+    {
+        line.baud_rate = validate_baud_rate(&baud_rate.to_string()).map_err(invalid)?;
+    }
+    Ok(())
+}
+
+fn apply_profile(args: &mut Args, name: &str, profile: &Profile, matches: &ArgMatches) -> Result<()> {
+    apply_line_profile(&mut args.line, name, profile, matches)?;
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let invalid = |e: String| anyhow::anyhow!("Profile {name:?}: {e}");
+    if let Some(alias) = &profile.alias
+        && !from_cli("alias")
+    {
+        args.alias = vec![alias.clone()];
+    }
+    if let Some(color) = &profile.color
+        && !from_cli("port_color")
+    {
+        args.port_color = vec![parse_color(color).map_err(invalid)?];
+    }
+    if let Some(csv_header) = &profile.csv_header
+        && !from_cli("csv_header")
+    {
+        args.csv_header = csv_header.clone();
+    }
+    if let Some(watches) = &profile.watch
+        && !from_cli("watch")
+    {
+        args.watch = watches
+            .iter()
+            .map(|spec| watch::parse_spec(spec))
+            .collect::<Result<_, _>>()
+            .map_err(invalid)?;
+    }
+    if let Some(alerts) = &profile.alert
+        && !from_cli("alert")
+    {
+        args.alert = alerts
+            .iter()
+            .map(|spec| watch::parse_alert(spec))
+            .collect::<Result<_, _>>()
+            .map_err(invalid)?;
+    }
+    if let Some(masks) = &profile.mask
+        && !from_cli("mask")
+    {
+        args.mask = masks
+            .iter()
+            .map(|pattern| Regex::new(pattern).map_err(|e| invalid(e.to_string())))
+            .collect::<Result<_>>()?;
+    }
+    if let Some(min_level) = profile.min_level
+        && !from_cli("min_level")
+    {
+        args.min_level = min_level;
+    }
+    if let Some(ending) = profile.line_ending
+        && !from_cli("line_ending")
+    {
+        args.line_ending = ending;
+    }
+    if let Some(encoding) = profile.encoding
+        && !from_cli("encoding")
+    {
+        args.encoding = encoding;
+    }
+    if let Some(no_log) = profile.no_log
+        && !from_cli("no_log")
+    {
+        args.no_log = no_log;
+    }
+    if let Some(size) = &profile.scrollback
+        && !from_cli("scrollback")
+    {
+        args.scrollback = scrollback::parse_size(size).map_err(invalid)?;
+    }
+    if let Some(pattern) = &profile.bus_address
+        && !from_cli("bus_address")
+    {
+        args.bus_address = Some(Regex::new(pattern).map_err(|e| invalid(e.to_string()))?);
+    }
+    if let Some(devices) = &profile.bus_device
+        && !from_cli("bus_device")
+    {
+        args.bus_device = devices
+            .iter()
+            .map(|spec| bus::parse_device(spec))
+            .collect::<Result<_, _>>()
+            .map_err(invalid)?;
+    }
+    if let Some(preset) = profile.preset
+        && !from_cli("preset")
+    {
+        args.preset = Some(preset);
+    }
+    if let Some(query) = &profile.identify
+        && !from_cli("identify")
+    {
+        args.identify = Some(query.clone());
+    }
+    if let Some(pattern) = &profile.identify_match
+        && !from_cli("identify_match")
+    {
+        args.identify_match = Some(Regex::new(pattern).map_err(|e| invalid(e.to_string()))?);
+    }
+    Ok(())
+}
+
+/// The options saved in the `--session` file, over those of the profile.
+fn apply_session(args: &mut Args, matches: &ArgMatches) -> Result<()> {
+    let Some(path) = args.session.clone() else {
+        return Ok(());
+    };
+    let mut session = session::load(&path)?;
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let invalid = |e: String| anyhow::anyhow!("Session {path}: {e}");
+    // The tags go with the ports they were given for
+    if !session.port.is_empty() && !from_cli("port") {
+        args.line.port = session.port.iter().map(|port| validate_port(port)).collect::<Result<_, _>>().map_err(invalid)?;
+        if !from_cli("alias") {
+            args.alias = session.alias.clone();
+        }
+        if !from_cli("port_color") {
+            args.port_color = session.color.iter().map(|color| parse_color(color)).collect::<Result<_, _>>().map_err(invalid)?;
+        }
+    }
+    if let Some(script) = &session.simulate
+        && !from_cli("simulate")
+        && !from_cli("port")
+    {
+        args.simulate = Some(script.clone());
+    }
+    if let Some(baud_rate) = session.baud_rate
+        && !from_cli("baud_rate")
+    {
+        args.line.baud_rate = validate_baud_rate(&baud_rate.to_string()).map_err(invalid)?;
+    }
+    // The rates of the ports go with the ports they were saved for
+    if from_cli("baud_rate") || from_cli("port") || session.port.is_empty() {
+        session.baud_rates.clear();
+    }
+    for rate in &session.baud_rates {
+        validate_baud_rate(&rate.to_string()).map_err(invalid)?;
+    }
+    if let Some(data_bits) = session.data_bits
+        && !from_cli("data_bits")
+    {
+        args.line.data_bits = data_bits;
+    }
+    if let Some(parity) = session.parity
+        && !from_cli("parity")
+    {
+        args.line.parity = parity;
+    }
+    if let Some(stop_bits) = session.stop_bits
+        && !from_cli("stop_bits")
+    {
+        args.line.stop_bits = stop_bits;
+    }
+    if let Some(flow_control) = session.flow_control
+        && !from_cli("flow_control")
+    {
+        args.line.flow_control = flow_control;
+    }
+    if let Some(ending) = session.line_ending
+        && !from_cli("line_ending")
+    {
+        args.line_ending = ending;
+    }
+    if let Some(encoding) = session.encoding
+        && !from_cli("encoding")
+    {
+        args.encoding = encoding;
+    }
+    if let Some(watches) = &session.watch
+        && !from_cli("watch")
+    {
+        args.watch = watches.iter().map(|spec| watch::parse_spec(spec)).collect::<Result<_, _>>().map_err(invalid)?;
+        args.given.push("watch");
+    }
+    if let Some(alerts) = &session.alert
+        && !from_cli("alert")
+    {
+        args.alert = alerts.iter().map(|spec| watch::parse_alert(spec)).collect::<Result<_, _>>().map_err(invalid)?;
+        args.given.push("alert");
+    }
+    if let Some(masks) = &session.mask
+        && !from_cli("mask")
+    {
+        args.mask = masks
+            .iter()
+            .map(|pattern| Regex::new(pattern).map_err(|e| invalid(e.to_string())))
+            .collect::<Result<_>>()?;
+        args.given.push("mask");
+    }
+    if let Some(min_level) = session.min_level
+        && !from_cli("min_level")
+    {
+        args.min_level = min_level;
+        args.given.push("min_level");
+    }
+    if let Some(dir) = &session.macro_dir
+        && !from_cli("macro_dir")
+    {
+        args.macro_dir = Some(dir.clone());
+    }
+    args.restored = Some(session);
+    Ok(())
+}
+
+/// Options of the profile that a change to the config file applies during
+/// a session.
+const RELOADED: [&str; 4] = ["watch", "alert", "mask", "min_level"];
+
+/// The profile a session runs with, from `config` as loaded anew.
+fn session_profile(args: &Args, config: &Config) -> Result<Profile> {
+    match &args.profile {
+        Some(name) => config.profile(name),
+        None => Ok(config.profiles.get(config::DEFAULT_PROFILE).cloned().unwrap_or_default()),
+    }
+}
+
+/// What a change to the config file updates, checked before any of it is
+/// applied. Only the options that changed are, so e.g. a level filter
+/// changed with Ctrl+L stays unless the file changes it.
+struct Reload {
+    watches: Option<Watches>,
+    masks: Option<Vec<Regex>>,
+    min_level: Option<Level>,
+    layout: Option<PaneLayout>,
+}
+
+impl Reload {
+    fn new(args: &Args, previous: &Profile, layout: &PaneLayout, config: &Config) -> Result<(Self, Profile)> {
+        let profile = session_profile(args, config)?;
+        let name = args.profile.as_deref().unwrap_or(config::DEFAULT_PROFILE);
+        let invalid = |e: String| anyhow::anyhow!("Profile {name:?}: {e}");
+        let given = |id: &str| args.given.contains(&id);
+        let changed = |id: &str, changed: bool| changed && !given(id);
+        let watches_changed = changed("watch", profile.watch != previous.watch) || changed("alert", profile.alert != previous.alert);
+        let watches = match watches_changed {
+            true => {
+                let watch = match given("watch") {
+                    true => args.watch.clone(),
+                    false => profile.watch.iter().flatten().map(|spec| watch::parse_spec(spec)).collect::<Result<_, _>>().map_err(invalid)?,
+                };
+                let alert = match given("alert") {
+                    true => args.alert.clone(),
+                    false => profile.alert.iter().flatten().map(|spec| watch::parse_alert(spec)).collect::<Result<_, _>>().map_err(invalid)?,
+                };
+                let polled = args.modbus_poll.iter().map(|poll| poll.name.clone()).collect();
+                Some(Watches::new(watch, polled, alert).map_err(invalid)?)
+            }
+            false => None,
+        };
+        let masks = match changed("mask", profile.mask != previous.mask) {
+            true => Some(
+                profile
+                    .mask
+                    .iter()
+                    .flatten()
+                    .map(|pattern| Regex::new(pattern).map_err(|e| invalid(e.to_string())))
+                    .collect::<Result<_>>()?,
+            ),
+            false => None,
+        };
+        let min_level = changed("min_level", profile.min_level != previous.min_level)
+            .then(|| profile.min_level.unwrap_or(Level::Trace));
+        let layout = (config.layout != *layout).then_some(config.layout);
+        Ok((Self { watches, masks, min_level, layout }, profile))
+    }
+
+    /// What is applied, e.g. `masks, layout`.
+    fn describe(&self) -> String {
+        let parts = [
+            (self.watches.is_some(), "watches and alerts"),
+            (self.masks.is_some(), "masks"),
+            (self.min_level.is_some(), "level filter"),
+            (self.layout.is_some(), "layout"),
+        ];
+        let parts: Vec<&str> = parts.into_iter().filter(|(changed, _)| *changed).map(|(_, part)| part).collect();
+        parts.join(", ")
+    }
+}
+
+/// The line settings and framing of `--preset`, where no option overrides them.
+fn apply_preset(args: &mut Args, matches: &ArgMatches) {
+    let Some(preset) = args.preset else {
+        return;
+    };
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let settings = preset.settings();
+    if !from_cli("baud_rate") {
+        args.line.baud_rate = settings.baud_rate;
+    }
+    if !from_cli("data_bits") {
+        args.line.data_bits = settings.data_bits;
+    }
+    if !from_cli("parity") {
+        args.line.parity = settings.parity;
+    }
+    if !from_cli("stop_bits") {
+        args.line.stop_bits = settings.stop_bits;
+    }
+    if !from_cli("framing") {
+        args.framing = Framing::Idle;
+    }
+    if !from_cli("frame_timeout") {
+        args.frame_timeout = settings.frame_timeout;
+    }
+    args.dedup |= settings.dedup;
+}
+
+/// Whether received frames are shown as text, so that unprintable bytes
+/// hint at a wrong baud rate.
+fn shows_text(args: &Args) -> bool {
+    let binary = args.slcan
+        || (args.defmt && args.elf.is_some())
+        || (args.proto_descriptor.is_some() && args.proto_message.is_some())
+        || matches!(args.preset, Some(Preset::Midi | Preset::Dmx));
+    !binary && matches!(args.framing, Framing::Lines | Framing::Delimiter | Framing::Idle)
+}
+
+/// Switches `port` to `rate` in the background, reporting a failure with
+/// `annotations`.
+fn set_baud_rate(port: &PortHandle, rate: u32, annotations: mpsc::UnboundedSender<OutputLine>) {
+    let port = port.clone();
+    tokio::spawn(async move {
+        if let Err(e) = port.control(Control::BaudRate(rate)).await {
+            let text = format!("Cannot switch to {rate} baud: {e}");
+            let _ = annotations.send(OutputLine::new("[Monitor] ", text, Style::default().fg(Color::Red)));
+        }
+    });
+}
+
+/// Below this terminal size only a placeholder is drawn.
+const MIN_WIDTH: u16 = 30;
+const MIN_HEIGHT: u16 = 8;
+
+/// Longest the screen goes without a redraw, for what changes with time
+/// (cursor blink, rates, throughput); otherwise it is redrawn on changes.
+const IDLE_REDRAW: Duration = Duration::from_millis(500);
+
+/// How long the cursor stays on, and off, when blinking.
+const CURSOR_BLINK: Duration = Duration::from_millis(500);
+
+/// Tag colors assigned to ports in order unless given with --port-color.
+const TAG_PALETTE: [Color; 6] = [Color::Cyan, Color::Magenta, Color::Yellow, Color::Blue, Color::LightGreen, Color::LightRed];
+
+/// Tags for a port's received and sent lines in a multi-port session: its
+/// alias, or else its short name. They are made once per name, as ports
+/// attached with Ctrl+A may come and go all session.
+fn port_tags(port: &str, alias: Option<&String>) -> (&'static str, &'static str) {
+    static TAGS: OnceLock<Mutex<HashMap<String, (&'static str, &'static str)>>> = OnceLock::new();
+    let name = match alias {
+        Some(alias) => alias.as_str(),
+        None => transport::remote_address(port)
+            .map(|(addr, _)| addr)
+            .or_else(|| port.strip_prefix(TCP_SCHEME))
+            .unwrap_or_else(|| port.rsplit(['/', '\\']).next().unwrap_or(port)),
+    };
+    let mut tags = TAGS.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    *tags
+        .entry(name.to_string())
+        .or_insert_with(|| (format!("[{name}] ").leak(), format!("> [{name}] ").leak()))
+}
+
+/// Tags of a `--sniff` tap or `--proxy` side: what arrives on the first port
+/// was sent by the first device to the second, e.g. `[A→B] `. Lines typed
+/// while sniffing aren't sent, and are shown untagged so they aren't taken
+/// for sniffed traffic; a proxy injects them into the device, e.g. `> [A] `.
+fn link_tags(alias: &[String], index: usize, proxy: bool) -> (&'static str, &'static str) {
+    let name = |i: usize| alias.get(i).map_or(["A", "B"][i], String::as_str);
+    let tx = match proxy {
+        true => format!("> [{}] ", name(index)).leak(),
+        false => "> ",
+    };
+    (format!("[{}→{}] ", name(index), name(1 - index)).leak(), tx)
+}
+
+/// Opens a port and spawns its task with the configured framing and decoding;
+/// `replayed` is the transport of a port replayed from a capture.
+async fn open_port(
+    args: &Args,
+    index: usize,
+    name: &str,
+    settings: SerialSettings,
+    events: &mpsc::UnboundedSender<PortEvent>,
+    replayed: Option<Box<dyn Transport>>,
+) -> Result<PortHandle> {
+    let replay = replayed.is_some();
+    let transport = match (replayed, &args.simulate) {
+        (Some(transport), _) => transport,
+        (None, Some(script)) => simulator::open(script)?,
+        (None, None) => transport::open(name, &settings).await?,
+    };
+    let decoder = match (&args.proto_descriptor, &args.proto_message, args.framing) {
+        _ if args.slcan => Decoder::Slcan,
+        _ if args.preset == Some(Preset::Midi) => Decoder::Midi(MidiDecoder::default()),
+        _ if args.preset == Some(Preset::Dmx) => Decoder::Dmx,
+        (Some(descriptor), Some(message), _) => Decoder::Proto(ProtoDecoder::load(descriptor, message)?),
+        (_, _, Framing::Lines | Framing::Delimiter | Framing::Idle) => Decoder::Text(args.encoding),
+        (_, _, Framing::LengthPrefixed | Framing::Fixed) => Decoder::Hex,
+    };
+    let framer = match &args.elf {
+        Some(elf) if args.defmt => Framer::defmt(DefmtDecoder::load(elf)?),
+        _ => Framer::new(args.framing, args.length_prefix)
+            .with_delimiter(args.delimiter.clone())
+            .with_length(args.frame_length)
+            .with_timeout(Duration::from_millis(args.frame_timeout))
+            .with_line_timeout(args.line_timeout.map(Duration::from_millis))
+            .with_max_line(args.max_line_length)
+            .with_encoding(args.encoding),
+    };
+    let restart = Restart {
+        policy: args.line.restart(),
+        open: {
+            let name = name.to_string();
+            let script = args.simulate.clone();
+            Box::new(move |settings| {
+                let name = name.clone();
+                let script = script.clone();
+                Box::pin(async move {
+                    match script {
+                        _ if replay => Err(anyhow::anyhow!("A replayed port can't be reopened")),
+                        Some(script) => simulator::open(&script),
+                        None => transport::open(&name, &settings).await,
+                    }
+                })
+            })
+        },
+        recover: args.line.recover(index, name).filter(|_| !replay && args.simulate.is_none()),
+    };
+    let port = port::spawn(index, transport, restart, settings, framer, decoder, events.clone());
+    if args.sniff {
+        return Ok(port.listen_only());
+    }
+    if let Some(kbit) = args.slcan_bitrate {
+        port.write(slcan::open_commands(kbit));
+    }
+    Ok(port)
+}
+
+/// Folds the JSON line or, in conversation mode, the exchange that is
+/// selected or nearest the bottom of the view.
+fn toggle_fold(entries: &mut [OutputLine], view: &View, scroll_offset: usize) {
+    if view.conversation {
+        let selected = view.selected.and_then(|i| entries[i].exchange).map(|x| x.id);
+        if let Some(id) = selected.or_else(|| output::exchange_at_bottom(entries, view, scroll_offset)) {
+            conversation::toggle(entries, id);
+        }
+    } else if let Some(i) = view
+        .selected
+        .filter(|&i| entries[i].json.is_some())
+        .or_else(|| output::json_entry_at_bottom(entries, view, scroll_offset))
+    {
+        entries[i].folded = !entries[i].folded;
+    }
+}
+
+/// Details of the selected received line, or the nearest one at or above
+/// the bottom of the view.
+fn details_popup(entries: &[OutputLine], view: &View, scroll_offset: usize) -> Option<Popup> {
+    let index = view.selected.or_else(|| output::received_entry_at_bottom(entries, view, scroll_offset));
+    match index.and_then(|i| entries[i].details_text()) {
+        Some(text) => Some(Popup::text("Line details (Esc to close)".to_string(), &text)),
+        None => {
+            toast::notify(Severity::Info, "No received line to show the details of");
+            None
+        }
+    }
+}
+
+/// The message rates pane, with the turnaround histogram below the rates.
+fn render_rates(f: &mut ratatui::Frame, area: Rect, rates: &MessageRates, turnaround: Option<&Turnaround>) {
+    let Some(turnaround) = turnaround else {
+        rates.render(f, area, Instant::now());
+        return;
+    };
+    let panels = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(turnaround.height())])
+        .split(area);
+    rates.render(f, panels[0], Instant::now());
+    turnaround.render(f, panels[1]);
+}
+
+/// Bytes sent for a line of input, terminated the way the device expects.
+fn encode_line(args: &Args, text: &str, at_tracker: Option<&mut AtTracker>) -> Result<Vec<u8>> {
+    if args.sniff {
+        anyhow::bail!("--sniff only listens");
+    }
+    if args.bus_pirate.is_some() {
+        anyhow::bail!("Bus Pirate transactions are typed in the input line");
+    }
+    if args.slcan {
+        slcan::encode(text)
+    } else if let Some(tracker) = at_tracker {
+        // Modems expect commands terminated by a carriage return
+        let bytes = args.encoding.encode(&format!("{}\r", text))?;
+        tracker.sent(text, Instant::now());
+        Ok(bytes)
+    } else {
+        args.encoding.encode(&format!("{}{}", text, args.line_ending.as_str()))
+    }
+}
+
+/// Whether a key is a Ctrl shortcut. AltGr arrives as Ctrl+Alt on Windows
+/// and types a character instead.
+fn ctrl(key: &KeyEvent) -> bool {
+    key.modifiers.contains(KeyModifiers::CONTROL) && !key.modifiers.contains(KeyModifiers::ALT)
+}
+
+/// Written data as logged: the text of a line, or its size if it is binary.
+fn tx_text(data: &[u8], encoding: Encoding) -> String {
+    match encoding.decode(data) {
+        Some(text) if !text.trim_end_matches(['\r', '\n']).contains(|c: char| c.is_control() && c != '\t') => {
+            text.trim_end_matches(['\r', '\n']).to_string()
+        }
+        _ => format!("<{} bytes>", data.len()),
+    }
+}
+
+/// Shows and logs alerts that changed state, firing the actions of those
+/// triggered with `port` as the one they act on.
+async fn report_alerts(
+    alerts: Vec<AlertEvent>,
+    port: &PortHandle,
+    logs: &Logs,
+    output_lines: &mut Vec<OutputLine>,
+    stats: &mut SessionStats,
+    annotations: &mpsc::UnboundedSender<OutputLine>,
+) {
+    for alert in alerts {
+        let style = if alert.triggered {
+            Style::default().fg(Color::White).bg(Color::Red)
+        } else {
+            Style::default().fg(Color::Cyan)
+        };
+        if alert.triggered {
+            stats.triggers += 1;
+        }
+        logs.write(&format!("[Alert] {}", alert.text), true).await;
+        output_lines.push(OutputLine::new("[Alert] ", alert.text, style));
+        if let Some(action) = &alert.action {
+            action.fire(port, annotations, &[("watch", alert.name), ("value", alert.value)]);
+        }
+    }
 }
 
-async fn log_to_file(file: &Arc<Mutex<tokio::fs::File>>, text: &str) {
-    let timestamp = Local::now().to_rfc3339();
-    let log_line = format!("[{}] {}\n", timestamp, text);
-    let mut file = file.lock().await;
-    if let Err(e) = file.write_all(log_line.as_bytes()).await {
-        eprintln!("Log write error: {e}");
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        // Reported as returning the error would, with a status scripts can tell apart
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(exit::code(&e))
+        }
+    }
+}
+
+/// Runs the subcommand given, the monitor by default.
+async fn run() -> Result<()> {
+    // Parse CLI arguments
+    // First run: ask for the settings instead of assuming /dev/ttyUSB0
+    let setup = match wizard::wanted() {
+        true => wizard::run().await?,
+        false => None,
+    };
+    let cli = parse_cli(setup)?;
+    // A capture converted for replay lives until the monitor exits
+    let imported;
+    let mut args = match cli.mode {
+        None => cli.monitor,
+        Some(Mode::Monitor(args)) => args,
+        Some(Mode::Replay {
+            file,
+            format,
+            timing,
+            mut monitor,
+        }) => {
+            imported = import::prepare(&file, format, timing.as_deref())?;
+            monitor.replay = Some(imported.path.clone());
+            monitor
+        }
+        Some(Mode::List) => {
+            let ports = discovery::list();
+            if ports.is_empty() {
+                println!("No serial ports found");
+            }
+            let width = ports.iter().map(|p| p.name.len()).max().unwrap_or(0);
+            for port in ports {
+                println!("{:<width$}  {}", port.name, port.description);
+            }
+            return Ok(());
+        }
+        Some(Mode::Log(LogCommand::Info { file })) => {
+            for (label, value) in capture::describe(&file)? {
+                println!("{:<10} {value}", format!("{label}:"));
+            }
+            return Ok(());
+        }
+        Some(Mode::Log(LogCommand::Pcap { file, output, link, gap })) => {
+            let packets = pcap::export(&file, &output, link, gap.map(|ms| ms as i64 * 1000))?;
+            eprintln!("Wrote {packets} packets to {output}");
+            return Ok(());
+        }
+        Some(Mode::Log(LogCommand::Timing { file, output, bytes, frame_gap })) => {
+            let rows = timing::export(&file, &output, bytes, frame_gap)?;
+            eprintln!("Wrote {rows} rows to {output}");
+            return Ok(());
+        }
+        Some(Mode::Log(LogCommand::Decrypt { file, identity })) => {
+            return logfile::decrypt(&file, &identity, &mut std::io::stdout().lock());
+        }
+        Some(Mode::Bridge {
+            listen,
+            pid_file,
+            status_file,
+            access,
+            line,
+        }) => {
+            let [port] = line.port.as_slice() else {
+                anyhow::bail!("bridge shares a single --port");
+            };
+            access::set_credentials(line.remote_token.as_deref(), line.remote_ca.as_deref())?;
+            let service = bridge::Service {
+                listen: &listen,
+                pid_file: pid_file.as_deref(),
+                status_file: status_file.as_deref(),
+                access: access.load()?,
+            };
+            return bridge::run(port, line.settings(), line.restart(), line.recover(0, port), service).await;
+        }
+        Some(Mode::Run {
+            script,
+            junit,
+            tap,
+            line_ending,
+            simulate,
+            secrets,
+            output,
+            line,
+        }) => {
+            access::set_credentials(line.remote_token.as_deref(), line.remote_ca.as_deref())?;
+            let test = headless::Test {
+                scripts: &script,
+                ports: &line.port,
+                simulate: simulate.as_deref(),
+                secrets: Secrets::load(secrets.as_deref())?,
+                settings: line.settings(),
+                line_ending,
+                junit: junit.as_deref(),
+                tap: tap.as_deref(),
+                output,
+            };
+            return headless::run(test).await;
+        }
+        Some(Mode::Selftest) => return selftest::run(cli.global.config.as_deref()).await,
+        Some(Mode::Completions { shell }) => {
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+            return Ok(());
+        }
+        Some(Mode::Man) => {
+            clap_mangen::Man::new(Cli::command()).render(&mut std::io::stdout())?;
+            return Ok(());
+        }
+    };
+    if args.bench_internal {
+        let options = bench::Options {
+            rate: args.bench_rate,
+            duration: Duration::from_secs(args.bench_seconds),
+            line_length: args.bench_line_length,
+        };
+        return bench::run(options).await;
     }
+    args.profile = cli.global.profile;
+    args.config = cli.global.config;
+    monitor(args).await
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Parse CLI arguments
-    let args = Args::parse();
-
-    // Open log file (if not disabled)
-    let log_file = if !args.no_log {
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&args.log_file)
-            .await?;
-        Some(Arc::new(Mutex::new(file)))
+/// Runs the terminal UI until the user quits.
+async fn monitor(mut args: Args) -> Result<()> {
+    if args.alias.len() > args.line.port.len() || args.port_color.len() > args.line.port.len() {
+        anyhow::bail!("--alias and --port-color take at most one value per --port");
+    }
+    if args.tee.len() > args.line.port.len() {
+        anyhow::bail!("--tee takes at most one named pipe per --port");
+    }
+    if args.sniff && args.line.port.len() != 2 {
+        anyhow::bail!("--sniff takes two --port values, the taps of each direction of the link");
+    }
+    if args.proxy && args.line.port.len() != 2 {
+        anyhow::bail!("--proxy takes two --port values, one for each side");
+    }
+    if args.sniff && !args.modbus_poll.is_empty() {
+        anyhow::bail!("--modbus-poll sends requests, which --sniff doesn't");
+    }
+    if args.bus_pirate.is_some() && !args.modbus_poll.is_empty() {
+        anyhow::bail!("--bus-pirate and --modbus-poll can't share the first port");
+    }
+    if args.proto_descriptor.is_some() && !matches!(args.framing, Framing::LengthPrefixed | Framing::Fixed) {
+        anyhow::bail!("--proto-descriptor decodes binary frames; give --framing length-prefixed or fixed");
+    }
+    if !args.log_encrypt.is_empty() && args.capture.is_some() {
+        anyhow::bail!("--capture would keep in plaintext what --log-encrypt encrypts");
+    }
+    let adapter = match args.bus_pirate {
+        Some(protocol) => Some(buspirate::Adapter::new(protocol, args.bus_pirate_speed, args.bus_pirate_power)?),
+        None => None,
+    };
+    // Rules of the config file and --rewrite acting on the frames a proxy passes on
+    let proxy_rules = match args.proxy {
+        true => {
+            let name = |i: usize| args.alias.get(i).map_or(["A", "B"][i], String::as_str);
+            proxy::rules(&config::load(args.config.as_deref())?.proxy, [name(0), name(1)], &args.rewrite)?
+        }
+        false => Vec::new(),
+    };
+    // Credentials for remote ports, and who may use the servers
+    access::set_credentials(args.line.remote_token.as_deref(), args.line.remote_ca.as_deref())?;
+    let server_access = args.access.load()?;
+
+    // Captured ports stand in for the devices during a replay
+    let (player, replayed): (Option<Player>, Vec<Box<dyn Transport>>) = match &args.replay {
+        Some(path) => {
+            let replay = replay::open(path)?;
+            let (names, transports) = replay.ports.into_iter().unzip();
+            args.line.port = names;
+            (Some(replay.player), transports)
+        }
+        None => (None, Vec::new()),
+    };
+
+    // Open log files (if not disabled)
+    let recipients = logfile::parse_recipients(&args.log_encrypt)?;
+    let mut logs = Logs {
+        all: if args.no_log {
+            None
+        } else {
+            Some(open_log(&args.log_file, &recipients).await?)
+        },
+        filtered: match &args.filtered_log {
+            Some(path) => Some(open_log(path, &recipients).await?),
+            None => None,
+        },
+        control: args.log_control,
+        direction: args.log_direction,
+    };
+
+    // Connect to syslog/journald (if enabled)
+    let syslogs = if args.log_syslog {
+        args.line.port.iter().map(|port| Syslog::connect(port)).collect::<Result<Vec<_>, _>>()?
     } else {
-        None
+        Vec::new()
     };
+    // Whether there is a log whose directions Ctrl+B changes
+    let logging = logs.all.is_some() || logs.filtered.is_some() || !syslogs.is_empty();
 
-    // Open serial port
-    let port = tokio_serial::new(&args.port, args.baud_rate)
-        .data_bits(DataBits::Eight)
-        .parity(Parity::None)
-        .stop_bits(StopBits::One)
-        .flow_control(FlowControl::None)
-        .open_native_async()?;
-
-    // Split into async read/write halves
-    let (mut reader, writer) = tokio::io::split(port);
-    let writer = Arc::new(Mutex::new(writer));
-
-    // Channels for data exchange between UI and serial
-    let (tx_serial, mut rx_serial) = mpsc::unbounded_channel::<String>();
-    let (tx_write, mut rx_write) = mpsc::unbounded_channel::<String>();
-
-    // Reader task (reads from Arduino)
-    tokio::spawn({
-        let tx_serial = tx_serial.clone();
-        async move {
-            let mut buf = [0u8; 512];
-            let mut line = String::new();
-            loop {
-                match reader.read(&mut buf).await {
-                    Ok(n) if n > 0 => {
-                        let chunk = String::from_utf8_lossy(&buf[..n]);
-                        for c in chunk.chars() {
-                            if c == '\n' || c == '\r' {
-                                if !line.is_empty() {
-                                    let _ = tx_serial.send(line.clone());
-                                    line.clear();
-                                }
-                            } else {
-                                line.push(c);
-                            }
-                        }
-                    }
-                    Ok(_) => continue,
-                    Err(e) => {
-                        eprintln!("Serial read error: {e}");
-                        time::sleep(Duration::from_secs(1)).await;
-                    }
-                }
-            }
+    let mut stats = SessionStats::start();
+
+    // Open serial ports (local devices or RFC 2217 servers), each at the
+    // rate a restored session had it at
+    let settings = args.line.settings();
+    let mut baud_rates: Vec<u32> = (0..args.line.port.len())
+        .map(|index| {
+            let saved = args.restored.as_ref().and_then(|session| session.baud_rates.get(index));
+            saved.copied().unwrap_or(settings.baud_rate)
+        })
+        .collect();
+    // Port tasks (read from and write to Arduino); all ports feed one event channel
+    let (tx_events, rx_events) = mpsc::unbounded_channel::<PortEvent>();
+    let mut ports = Vec::new();
+    let mut replayed = replayed.into_iter();
+    // A proxy listens from the start, so nothing read before it starts is lost
+    let mut proxied = Vec::new();
+    let mut polled = None;
+    let mut adapted = None;
+    // The hub ports of the devices are looked up while they are there, as a
+    // device that stopped enumerating can't be found to be power-cycled
+    let mut power_cycles = Vec::new();
+    for (index, name) in args.line.port.iter().enumerate() {
+        let settings = SerialSettings { baud_rate: baud_rates[index], ..settings };
+        let port = open_port(&args, index, name, settings, &tx_events, replayed.next()).await?;
+        if args.proxy {
+            proxied.push(port.raw.subscribe());
         }
-    });
+        if index == 0 && !args.modbus_poll.is_empty() {
+            polled = Some(port.raw.subscribe());
+        }
+        if index == 0 && adapter.is_some() {
+            adapted = Some(port.raw.subscribe());
+        }
+        ports.push(port);
+        power_cycles.push(args.line.power_cycle(index, name));
+    }
+    // Raw capture of every port (if enabled)
+    let capture = match &args.capture {
+        Some(path) => {
+            let header = serde_json::json!({
+                "ports": args.line.port,
+                "baud_rate": settings.baud_rate,
+                "data_bits": settings.data_bits,
+                "parity": format!("{:?}", settings.parity).to_lowercase(),
+                "stop_bits": settings.stop_bits,
+                "flow_control": format!("{:?}", settings.flow_control).to_lowercase(),
+                "started": Local::now().to_rfc3339(),
+            });
+            Some(Capture::start(path, &header)?)
+        }
+        None => None,
+    };
+    // Lines are tagged with their port (or its alias) once there is more than one
+    let tagged = args.line.port.len() > 1 || !args.alias.is_empty();
+    let (mut tags, mut tx_tags): (Vec<&'static str>, Vec<&'static str>) = args
+        .line
+        .port
+        .iter()
+        .enumerate()
+        .map(|(index, name)| match tagged {
+            _ if args.sniff || args.proxy => link_tags(&args.alias, index, args.proxy),
+            true => port_tags(name, args.alias.get(index)),
+            false => ("[Arduino] ", "> "),
+        })
+        .unzip();
+    let tag_colors: Vec<Option<Color>> = (0..args.line.port.len())
+        .map(|index| {
+            let default = tagged.then(|| TAG_PALETTE[index % TAG_PALETTE.len()]);
+            args.port_color.get(index).copied().or(default)
+        })
+        .collect();
+    let mut port_names = args.line.port.clone();
+    // Ports are connected on startup; later connections are reopens
+    let mut connected = vec![false; ports.len()];
+    // Input is sent to this port; Ctrl+N switches between ports
+    let mut active = 0;
 
-    // Writer task (sends to Arduino)
-    tokio::spawn({
-        let writer = writer.clone();
-        async move {
-            while let Some(cmd) = rx_write.recv().await {
-                let mut writer = writer.lock().await;
-                if let Err(e) = writer.write_all(cmd.as_bytes()).await {
-                    eprintln!("Serial write error: {e}");
-                } else {
-                    let _ = writer.write_all(b"\n").await;
-                }
+    // Share the first port over RFC 2217 (if enabled)
+    if let Some(addr) = &args.rfc2217_server {
+        let listener = TcpListener::bind(addr).await?;
+        rfc2217_server::spawn(listener, ports[0].clone(), server_access.clone());
+    }
+
+    // HTTP API and gRPC service for scripts (if enabled); they see the lines recorded in the journal
+    let (tx_api, rx_api) = mpsc::unbounded_channel::<SendRequest>();
+    let journal = match server_access.has_token() && (args.http.is_some() || args.grpc.is_some()) {
+        true => {
+            let journal = Arc::new(std::sync::Mutex::new(Journal::default()));
+            if let Some(addr) = &args.http {
+                let listener = TcpListener::bind(addr).await.with_context(|| format!("Cannot listen on {addr}"))?;
+                api::spawn(listener, server_access.clone(), journal.clone(), port_names.clone(), tx_api.clone());
             }
+            if let Some(addr) = &args.grpc {
+                let listener = TcpListener::bind(addr).await.with_context(|| format!("Cannot listen on {addr}"))?;
+                grpc::spawn(listener, server_access.clone(), journal.clone(), port_names.clone(), tx_api)?;
+            }
+            Some(journal)
         }
+        false => None,
+    };
+
+    // Serial devices being attached and detached; Ctrl+A switches to the last one attached
+    let (tx_hotplug, rx_hotplug) = mpsc::unbounded_channel::<Hotplug>();
+    hotplug::spawn(tx_hotplug);
+    let (tx_config, rx_config) = mpsc::unbounded_channel::<Result<Config>>();
+    config::watch(args.config.as_deref(), tx_config);
+    let mut attached: Option<String> = None;
+
+    // Lines produced asynchronously by helpers (e.g., backtrace symbolization)
+    let (tx_annotations, rx_annotations) = mpsc::unbounded_channel::<OutputLine>();
+    // Named pipes the received bytes of each port are streamed to
+    let tees = args.tee.iter().map(|path| Tee::open(path, tx_annotations.clone())).collect::<Result<Vec<_>>>()?;
+    if let Ok(received) = <[_; 2]>::try_from(proxied) {
+        let gap = Duration::from_millis(args.frame_timeout);
+        let sides = [ports[0].clone(), ports[1].clone()];
+        proxy::start(sides, received, [tags[0], tags[1]], proxy_rules, gap, tx_annotations.clone());
+    }
+    // Register polls of the Modbus master, whose values feed the watch panel
+    let (tx_modbus, rx_modbus) = mpsc::unbounded_channel::<(String, String)>();
+    if let Some(received) = polled {
+        let timeout = Duration::from_millis(args.modbus_timeout);
+        modbus::start(args.modbus_poll.clone(), ports[0].clone(), received, timeout, tx_modbus, tx_annotations.clone());
+    }
+    // A Bus Pirate running typed I2C or SPI transactions
+    let bus_pirate = adapter.zip(adapted).map(|(adapter, received)| {
+        buspirate::start(adapter, ports[0].clone(), received, tx_annotations.clone())
     });
+    let symbolizer = match &args.elf {
+        Some(elf) => {
+            metadata(elf).with_context(|| format!("Cannot read ELF file {elf}"))?;
+            Some(Symbolizer::new(elf.clone(), args.addr2line.clone(), tx_annotations.clone()))
+        }
+        None => None,
+    };
 
-    // Terminal UI
+    // Terminal UI; notifications are drawn in it from now on
+    let mut toasts = Toasts::install();
+    let mut show_notifications = false;
+    let (tx_pipe, rx_pipe) = mpsc::unbounded_channel();
+    let mut popup: Option<Popup> = None;
+    // Scrollback read from the capture file (:history)
+    let mut timeline: Option<Timeline> = None;
+    // A large paste awaiting confirmation, and one being sent
+    let mut pending_paste: Option<paste::Pending> = None;
+    let mut pasting: Option<(usize, paste::Pacer)> = None;
     enable_raw_mode()?;
     let mut stdout = std::io::stdout();
-    execute!(stdout, crossterm::terminal::EnterAlternateScreen, Hide)?;
+    execute!(stdout, crossterm::terminal::EnterAlternateScreen, EnableBracketedPaste, Hide)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     let mut input = String::new();
     let mut cursor_pos = 0;
-    let mut output_lines: Vec<Line> = Vec::new();
+    let mut output_lines: Vec<OutputLine> = Vec::new();
     let mut history: Vec<String> = Vec::new();
     let mut history_index: Option<usize> = None;
     let mut scroll_offset: usize = 0;
     let mut cursor_visible = true;
+    let mut hud = Hud::default();
+    let mut show_hud = args.hud;
     let mut last_blink = Instant::now();
-    const MAX_LINES: usize = 1000; // Memory cap for output_lines
+    // BEL received in a burst rings once
+    let mut last_bel: Option<Instant> = None;
+    let mut rates = MessageRates::default();
+    // Pane arrangement from the config file; written back by :save-layout
+    let loaded = config::load(args.config.as_deref())?;
+    let mut pane_layout = loaded.layout;
+    let mut saved_layout = pane_layout;
+    // A session's layout is saved with it rather than in the config file
+    let restored = args.restored.take();
+    if let Some(layout) = restored.as_ref().and_then(|session| session.layout) {
+        pane_layout = layout;
+    }
+    // The profile as in the file, to tell what a change to the file changed
+    let mut loaded_profile = session_profile(&args, &loaded)?;
+    // Turnaround times are shown with the rates, so timing them opens the pane
+    let mut show_rates = pane_layout.show_rates || args.turnaround;
+    let mut turnaround = args
+        .turnaround
+        .then(|| Turnaround::new(ports.len(), args.turnaround_limit.map(Duration::from_millis)));
+    let mut identify = args
+        .identify
+        .clone()
+        .map(|query| identity::Identify::new(query, args.identify_match.clone(), ports.len()));
+    // Unprintable bytes only hint at a wrong rate where text is expected
+    let mut autobaud = (player.is_none() && shows_text(&args))
+        .then(|| AutoBaud::new(baud_rates.clone(), args.encoding));
+    let mut watchdog = args
+        .idle_timeout
+        .map(|secs| Watchdog::new(Duration::from_secs(secs), ports.len(), args.keep_alive.is_some(), Instant::now()));
+    let mut activity = Activity::default();
+    let mut bus = Bus::new(args.bus_address.clone(), &args.bus_device);
+    let polled = args.modbus_poll.iter().map(|poll| poll.name.clone()).collect();
+    let mut watches = Watches::new(args.watch.clone(), polled, args.alert.clone()).map_err(anyhow::Error::msg)?;
+    let mut show_watch = !watches.is_empty() && pane_layout.show_watch && !args.quiet;
+    let mut expected = match &args.expect {
+        Some(path) => Some(Expected::load(path, args.expect_ignore.clone())?),
+        None => None,
+    };
+    let mut view = View {
+        pane: Pane::All,
+        pretty_json: false,
+        reveal: false,
+        conversation: false,
+        min_level: args.min_level,
+        width: 0,
+        height: 0,
+        selected: None,
+        device: None,
+    };
+    if let Some(restored) = &restored {
+        view.pane = restored.view.pane.unwrap_or(Pane::All);
+        view.pretty_json = restored.view.pretty_json;
+        view.reveal = restored.view.reveal;
+        view.conversation = restored.view.conversation;
+        view.device = restored.view.device.as_deref().and_then(|name| bus.find(name));
+        show_hud |= restored.view.hud;
+    }
+    let mut mode = UiMode::Input;
+    // Mode INSPECT returns to
+    let mut back_to = UiMode::Follow;
+    // Entries there were at the last iteration, to keep INSPECT's view still
+    let mut seen_lines: usize = 0;
+    let mut seen_repeats = 0;
+    // Whether anything shown changed since the last redraw
+    let mut dirty = true;
+    let mut last_draw = Instant::now();
+    let mut csv = CsvTable::new(args.csv_delimiter, args.csv_header.clone());
+    let mut show_csv = false;
+    let mut traffic = Traffic::default();
+    let mut show_traffic = false;
+    let mut stress: Option<Stress> = None;
+    let mut pinger: Option<Pinger> = None;
+    let mut at_tracker = args.at.then(|| AtTracker::new(Duration::from_millis(args.at_timeout)));
+    let secrets = Secrets::load(args.secrets.as_deref())?;
+    let mut placeholders = Placeholders::default();
+    let mut masks = Masks::new(args.mask.clone());
+    for value in secrets.values() {
+        masks.hide(value);
+    }
+    if let Some(command) = &args.bell_command {
+        trigger::set_bell_command(command.clone());
+    }
+    let mut throttles = Throttles::new(args.throttle.clone());
+    let mut conversation = Conversation::new(args.prompt.clone(), Duration::from_millis(args.reply_timeout));
+    let mut budget = Budget::new(args.scrollback);
+
+    // Lines sent are recorded while :record runs
+    let macro_dir = args.macro_dir.clone().map(PathBuf::from).or_else(macros::default_dir);
+    let mut recording: Option<macros::Recorder> = None;
+    // The macro started with :play, its port and its name
+    let mut playing: Option<(usize, String, script::Runner)> = None;
+
+    // The scrollback and history are checkpointed only if asked for, and
+    // never as plaintext for a session that isn't logged or logged encrypted
+    let checkpoint_path = match (&args.session_dir, args.resume) {
+        (None, false) => None,
+        (dir, _) => dir.clone().map(PathBuf::from).or_else(checkpoint::default_dir),
+    }
+    .map(|dir| checkpoint::path(&dir, &args.line.port));
+    if args.resume
+        && let Some(path) = &checkpoint_path
+    {
+        let text = match checkpoint::load(path)? {
+            Some(restored) => {
+                let text = format!(
+                    "Restored {} lines and {} history entries of the session checkpointed at {}",
+                    restored.lines.len(),
+                    restored.history.len(),
+                    restored.saved.format("%Y-%m-%d %H:%M:%S")
+                );
+                output_lines.extend(restored.lines);
+                history = restored.history;
+                text
+            }
+            None => format!("No checkpoint to resume at {}", path.display()),
+        };
+        output_lines.push(OutputLine::new("[Monitor] ", text, Style::default().fg(Color::Cyan)));
+    }
+    let unlogged = match (args.no_log, recipients.is_empty()) {
+        (true, _) => Some("--no-log"),
+        (false, false) => Some("--log-encrypt"),
+        (false, true) => None,
+    };
+    if checkpoint_path.is_some()
+        && let Some(option) = unlogged
+    {
+        let text = format!("Not checkpointing the session for --resume: with {option} it isn't saved as plaintext");
+        output_lines.push(OutputLine::new("[Monitor] ", text, Style::default().fg(Color::Yellow)));
+    }
+    let mut checkpointer =
+        checkpoint_path
+        .filter(|_| args.replay.is_none() && unlogged.is_none())
+        .map(|path| Checkpointer::new(path, args.log_direction.tx()));
+    // Why the monitor stopped by itself, e.g. a port lost with --fail-on-disconnect
+    let mut failure = None;
+
+    // The loop sleeps until one of these has something, input is typed or
+    // a timer is due
+    let mut rx_events = Inbox::new(rx_events);
+    let mut rx_api = Inbox::new(rx_api);
+    let mut rx_hotplug = Inbox::new(rx_hotplug);
+    let mut rx_config = Inbox::new(rx_config);
+    let mut rx_annotations = Inbox::new(rx_annotations);
+    let mut rx_modbus = Inbox::new(rx_modbus);
+    let mut rx_pipe = Inbox::new(rx_pipe);
+    let mut terminal_events = EventStream::new();
+    // What ended the last wait, if typed
+    let mut typed: Option<Event> = None;
+
+    // Sends a line typed, pasted, composed, played or posted to the API on
+    // a port: it is recorded for :record, its placeholders are expanded and
+    // it is shown as sent; evaluates to the error if it couldn't be encoded
+    macro_rules! send_line {
+        ($port:expr, $line:expr) => {{
+            let (port, line): (usize, &str) = ($port, $line);
+            stats.tx_lines += 1;
+            if let Some(recorder) = &mut recording {
+                recorder.sent(line, Instant::now());
+            }
+            let text = placeholders.expand(line);
+            let payload = encode_line(&args, &text, at_tracker.as_mut().filter(|_| port == active));
+            output_lines.push(
+                OutputLine::new(tx_tags[port], masks.apply(&text).into_owned(), Style::default().fg(Color::Yellow))
+                    .with_tag_color(tag_colors[port])
+                    .in_exchange(Some(conversation.sent(port, Local::now())))
+                    .on_device(bus.sent(&text)),
+            );
+            payload.map(|bytes| ports[port].write(bytes))
+        }};
+    }
 
     loop {
-        if last_blink.elapsed() >= Duration::from_millis(500) {
+        hud.iteration(Instant::now());
+        // The cursor is shown by ratatui in frames that place it; blinking
+        // only redraws when it flips
+        if last_blink.elapsed() >= CURSOR_BLINK {
             cursor_visible = !cursor_visible;
             last_blink = Instant::now();
+            dirty |= mode == UiMode::Input;
+        }
+
+        // Lines beyond the scrollback budget are dropped from the top
+        let dropped = budget.trim(&mut output_lines);
+        if dropped > 0 && scroll_offset > 0 {
+            scroll_offset = scroll_offset.min(output::total_height(&output_lines, &view).saturating_sub(1));
+        }
+        // INSPECT keeps the selected line where it is as new lines arrive
+        if mode == UiMode::Inspect {
+            view.selected = view.selected.map(|i| i.saturating_sub(dropped));
+            scroll_offset += output::rows_from(&output_lines, &view, seen_lines.saturating_sub(dropped));
+        }
+        // New lines, and repeats counted on the last one
+        let repeats = output_lines.last().map_or(0, |last| last.repeated);
+        dirty |= dropped > 0 || output_lines.len() != seen_lines || repeats != seen_repeats;
+        seen_lines = output_lines.len();
+        seen_repeats = repeats;
+        if let Some(checkpointer) = &mut checkpointer
+            && let Some(e) = checkpointer.poll(&output_lines, &history, &masks, budget.used())
+        {
+            toast::notify(Severity::Warning, e);
+        }
+
+        if args.quiet {
+            activity.sample(&ports, Instant::now());
         }
+        dirty |= toasts.poll(Instant::now());
+        if dirty || last_draw.elapsed() >= IDLE_REDRAW {
+            let size = terminal.size()?;
+            let too_small = size.width < MIN_WIDTH || size.height < MIN_HEIGHT;
+            let drawing = Instant::now();
+            terminal.draw(|f| {
+                if too_small {
+                    let area = f.area();
+                    let message = format!("Terminal too small ({}x{}, need {MIN_WIDTH}x{MIN_HEIGHT})", area.width, area.height);
+                    let placeholder = Paragraph::new(message)
+                        .style(Style::default().fg(Color::Yellow))
+                        .wrap(Wrap { trim: true });
+                    f.render_widget(placeholder, area);
+                    return;
+                }
+                let areas = pane_layout.split(f.area(), show_rates || show_watch);
+
+                // Optional message rate table and watch panel beside the output
+                match (areas.side, show_rates, show_watch) {
+                    (Some(side), true, true) => {
+                        let panels = Layout::default()
+                            .direction(Direction::Vertical)
+                            .constraints([Constraint::Min(3), Constraint::Length(watches.len() as u16 + 3)])
+                            .split(side);
+                        render_rates(f, panels[0], &rates, turnaround.as_ref());
+                        watches.render(f, panels[1]);
+                    }
+                    (Some(side), true, false) => render_rates(f, side, &rates, turnaround.as_ref()),
+                    (Some(side), _, _) => watches.render(f, side),
+                    (None, _, _) => {}
+                }
+                let mut output_area = areas.output;
+
+                // Sent lines get their own pane left of the received ones in the split view
+                if view.pane == Pane::Received && !args.quiet && !show_csv && !show_traffic {
+                    let panes = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+                        .split(output_area);
+                    let sent_view = View {
+                        pane: Pane::Sent,
+                        width: panes[0].width.saturating_sub(2),
+                        ..view
+                    };
+                    let (sent, skip) = output::visible_lines(&output_lines, &sent_view, panes[0].height.saturating_sub(2) as usize, 0);
+                    let sent = Paragraph::new(sent)
+                        .block(Block::default().title("Sent").borders(Borders::ALL))
+                        .wrap(Wrap { trim: false })
+                        .scroll((skip, 0));
+                    f.render_widget(sent, panes[0]);
+                    output_area = panes[1];
+                }
+
+                // Scrolling region
+                let visible_height = (output_area.height as usize).saturating_sub(2);
+                view.width = output_area.width.saturating_sub(2);
+                view.height = visible_height as u16;
+                let (visible, skip) = output::visible_lines(&output_lines, &view, visible_height, scroll_offset);
+
+                let mut title = match view.min_level {
+                    Level::Trace => "Arduino Monitor".to_string(),
+                    level => format!("Arduino Monitor [level >= {}]", level.as_str()),
+                };
+                if view.conversation {
+                    title.push_str(" [conversation]");
+                }
+                if view.reveal {
+                    title.push_str(" [control chars]");
+                }
+                if let Some(device) = view.device {
+                    title.push_str(&format!(" [bus: {device}]"));
+                }
+                if let Some(recorder) = &recording {
+                    title.push_str(&format!(" [recording {}]", recorder.name));
+                }
+                if let Some(timeline) = &mut timeline {
+                    timeline.render(f, output_area, &tags, &tx_tags, &masks);
+                } else if args.quiet {
+                    let alerts: Vec<&OutputLine> = output_lines
+                        .iter()
+                        .filter(|line| matches!(line.prefix, "[Alert] " | "[Trigger] "))
+                        .collect();
+                    activity.render(f, output_area, &format!("{title} (quiet)"), &tags, &alerts, Instant::now());
+                } else if show_traffic {
+                    traffic.render(f, output_area, &format!("{title} (traffic)"), Local::now(), scroll_offset);
+                } else if show_csv {
+                    csv.render(f, output_area, &format!("{title} (CSV)"), scroll_offset);
+                } else {
+                    let mut block = Block::default().title(title).borders(Borders::ALL);
+                    // Replay transport state in the status bar
+                    if let Some(player) = &player {
+                        let status = Span::styled(player.status().text(Instant::now()), Style::default().fg(Color::Cyan));
+                        block = block.title_bottom(Line::from(status));
+                    }
+                    if logging {
+                        let style = match logs.direction {
+                            LogDirection::Both => Style::default().fg(Color::DarkGray),
+                            _ => Style::default().fg(Color::Yellow),
+                        };
+                        block = block.title_bottom(Line::from(format!(" {} ", logs.direction.label())).style(style).right_aligned());
+                    }
+                    let monitor = Paragraph::new(visible)
+                        .block(block)
+                        .wrap(Wrap { trim: false })
+                        .scroll((skip, 0));
+                    f.render_widget(monitor, output_area);
+                }
+                if let Some(pending) = &pending_paste {
+                    pending.render(f, output_area, tags[active].trim());
+                } else if let Some(popup) = &popup {
+                    popup.render(f, output_area);
+                } else if show_notifications {
+                    toasts.render_history(f, output_area);
+                } else {
+                    toasts.render(f, output_area, Instant::now());
+                }
+                if let Some(autobaud) = &autobaud {
+                    autobaud.render(f, output_area, &port_names);
+                }
+                if show_hud {
+                    hud.render(f, output_area, &[("port events", rx_events.len()), ("annotations", rx_annotations.len())]);
+                }
 
-        terminal.draw(|f| {
-            let layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Min(1), Constraint::Length(3)])
-                .margin(1)
-                .split(f.area());
-
-            // Scrolling region
-            let visible_height = layout[0].height as usize - 2;
-            let start = output_lines
-                .len()
-                .saturating_sub(visible_height + scroll_offset);
-            let visible = output_lines[start..].to_vec();
-
-            let monitor = Paragraph::new(visible)
-                .block(Block::default().title("Arduino Monitor").borders(Borders::ALL))
-                .wrap(Wrap { trim: false });
-            f.render_widget(monitor, layout[0]);
-
-            // Input area
-            let input_area = layout[1];
-            let inner_width = input_area.width.saturating_sub(2);
-            let input_offset = (cursor_pos as u16).saturating_sub(inner_width.saturating_sub(1));
-            let input_widget = Paragraph::new(Line::from(input.as_str()))
-                .style(Style::default().fg(Color::Yellow))
-                .scroll((0, input_offset))
-                .block(Block::default().title("Input").borders(Borders::ALL));
-            f.render_widget(input_widget, input_area);
-
-            if cursor_visible {
-                let cursor_x = input_area.x + 1 + (cursor_pos as u16).saturating_sub(input_offset);
-                let cursor_y = input_area.y + 1;
-                f.set_cursor_position((cursor_x, cursor_y));
-            }
-        })?;
-
-        if cursor_visible {
-            execute!(terminal.backend_mut(), Show)?;
+                // Input area
+                let input_title = match tags.len() {
+                    _ if args.sniff => Line::from("Input (sniffing, nothing is sent)"),
+                    _ if args.proxy => Line::from(format!("Input -> {} (injected)", tx_tags[active].trim_start_matches("> ").trim())),
+                    1 => Line::from("Input"),
+                    _ => Line::from(vec![
+                        Span::raw("Input -> "),
+                        Span::styled(tags[active].trim(), tag_colors[active].map(|c| Style::default().fg(c)).unwrap_or_default()),
+                    ]),
+                };
+                let input_area = areas.input;
+                let inner_width = input_area.width.saturating_sub(2);
+                let inner_height = input_area.height.saturating_sub(2);
+                let (input_widget, cursor) = if inner_height <= 1 {
+                    // One row scrolls sideways to keep the cursor visible
+                    let column = editing::width(&input[..editing::byte_index(&input, cursor_pos)]) as u16;
+                    let input_offset = column.saturating_sub(inner_width.saturating_sub(1));
+                    let widget = Paragraph::new(Line::from(input.as_str())).scroll((0, input_offset));
+                    (widget, (column - input_offset, 0))
+                } else {
+                    // Taller boxes wrap the input and scroll down with the cursor
+                    let (rows, (column, row)) = editing::wrap(&input, cursor_pos, inner_width.max(1) as usize);
+                    let rows: Vec<Line> = rows.into_iter().map(Line::from).collect();
+                    let cursor_row = row as u16;
+                    let first_row = cursor_row.saturating_sub(inner_height - 1);
+                    let widget = Paragraph::new(rows).scroll((first_row, 0));
+                    (widget, (column as u16, cursor_row - first_row))
+                };
+                let input_widget = input_widget
+                    .style(Style::default().fg(Color::Yellow))
+                    .block(
+                        Block::default()
+                            .title(input_title)
+                            .title(Line::from(Span::styled(mode.label(), mode.style())).right_aligned())
+                            .title_bottom(Line::from(mode.hint()).right_aligned().style(Style::default().fg(Color::DarkGray)))
+                            .borders(Borders::ALL),
+                    );
+                f.render_widget(input_widget, input_area);
+
+                if cursor_visible && mode == UiMode::Input {
+                    f.set_cursor_position((input_area.x + 1 + cursor.0, input_area.y + 1 + cursor.1));
+                }
+            })?;
+            hud.frame(Instant::now(), drawing.elapsed());
+            dirty = false;
+            last_draw = Instant::now();
         } else {
-            execute!(terminal.backend_mut(), Hide)?;
+            hud.skipped(Instant::now());
+        }
+
+        // Report AT commands that never completed
+        if let Some(tracker) = &mut at_tracker {
+            for result in tracker.expire(Instant::now()) {
+                let text = masks.apply(&result.text()).into_owned();
+                // With only RX logged, the command sent isn't
+                let logged = match args.log_direction.tx() {
+                    true => text.clone(),
+                    false => result.outcome_text(),
+                };
+                logs.write(&format!("[AT] {logged}"), true).await;
+                output_lines.push(OutputLine::new("[AT] ", text, result.style()));
+            }
+        }
+
+        // Devices that didn't answer --identify
+        for port in identify.as_mut().map(|identify| identify.expire(Instant::now())).unwrap_or_default() {
+            let text = format!("{} didn't answer --identify {}", port_names[port], args.identify.as_deref().unwrap_or_default());
+            logs.write(&format!("[Monitor] {text}"), true).await;
+            toast::notify(Severity::Warning, text);
+        }
+
+        // Move the baud rate search on
+        if let Some(step) = autobaud.as_mut().and_then(|autobaud| autobaud.poll(Instant::now())) {
+            dirty = true;
+            let report = match step {
+                Step::Try(port, rate) => {
+                    set_baud_rate(&ports[port], rate, tx_annotations.clone());
+                    None
+                }
+                Step::Found(port, rate) => Some((Severity::Info, format!("{} reads as text at {rate} baud", port_names[port]))),
+                Step::Failed(port, rate) => {
+                    set_baud_rate(&ports[port], rate, tx_annotations.clone());
+                    let text = format!("No baud rate gave readable text on {}; back at {rate} baud", port_names[port]);
+                    Some((Severity::Warning, text))
+                }
+            };
+            if let Some((severity, text)) = report {
+                logs.write(&format!("[Monitor] {text}"), true).await;
+                toast::notify(severity, text);
+            }
+        }
+
+        // Poke the ports gone quiet
+        for quiet in watchdog.as_mut().map(|watchdog| watchdog.poll(Instant::now())).unwrap_or_default() {
+            let name = &port_names[quiet.port];
+            if quiet.alert {
+                stats.triggers += 1;
+                let text = format!("{name} idle: nothing received for {} s", quiet.silent.as_secs());
+                logs.write(&format!("[Alert] {text}"), true).await;
+                output_lines.push(OutputLine::new("[Alert] ", text, Style::default().fg(Color::White).bg(Color::Red)));
+                if let Some(action) = &args.on_idle {
+                    let env = [("port", name.clone()), ("idle", quiet.silent.as_secs().to_string())];
+                    action.fire(&ports[quiet.port], &tx_annotations, &env);
+                }
+            }
+            if let Some(text) = &args.keep_alive
+                && let Err(e) = send_line!(quiet.port, text)
+            {
+                toast::notify(Severity::Error, format!("Cannot send --keep-alive: {e}"));
+            }
+        }
+
+        // Send latency probes when due
+        if let Some(pinger) = &mut pinger
+            && let Some(probe) = pinger.poll(Instant::now())
+        {
+            ports[pinger.port].write(probe);
         }
 
-        // Process serial lines
-        while let Ok(line) = rx_serial.try_recv() {
-            let style = if line.contains("ERROR") {
-                Style::default().fg(Color::Red)
-            } else {
-                Style::default().fg(Color::Green)
+        // Whatever arrives from the ports and helpers may change the screen
+        dirty |= !rx_events.is_empty()
+            || !rx_hotplug.is_empty()
+            || !rx_config.is_empty()
+            || !rx_modbus.is_empty()
+            || !rx_annotations.is_empty()
+            || !rx_api.is_empty()
+            || !rx_pipe.is_empty();
+
+        // Process port events
+        // Looked at first, so the events of its last moments are handled before stopping
+        let lost = ports.iter().position(PortHandle::is_closed).filter(|_| args.line.fail_on_disconnect);
+        while let Some(PortEvent { port, time, event }) = rx_events.try_recv() {
+            let (line, ending, raw, decoded) = match event {
+                // The adapter's protocol bytes; its transactions are shown instead
+                SerialEvent::Rx { .. } if port == 0 && bus_pirate.is_some() => continue,
+                // BEL rings as it arrives rather than printing in the text,
+                // and stays in it with --no-bel
+                SerialEvent::Rx { text, ending, raw, decoded } if !args.no_bel && text.contains('\x07') => {
+                    let text = text.replace('\x07', "");
+                    if text.trim().is_empty() {
+                        continue;
+                    }
+                    (text, ending, raw, decoded)
+                }
+                SerialEvent::Rx { text, ending, raw, decoded } => (text, ending, raw, decoded),
+                SerialEvent::Chunk(data) => {
+                    if let Some(silent) = watchdog.as_mut().and_then(|watchdog| watchdog.received(port, Instant::now())) {
+                        let text = format!("{} active again after {} s", port_names[port], silent.as_secs());
+                        logs.write_at(time, &format!("[Alert] {text}"), true).await;
+                        output_lines.push(OutputLine::new("[Alert] ", text, Style::default().fg(Color::Cyan)));
+                    }
+                    traffic.bytes(time, data.len());
+                    if let Some(autobaud) = &mut autobaud
+                        && let Some(text) = autobaud.record(port, &data, &port_names, Instant::now())
+                    {
+                        logs.write_at(time, &format!("[Monitor] {text}"), true).await;
+                        toast::notify(Severity::Warning, text);
+                    }
+                    if let Some((ms, over)) = turnaround.as_mut().and_then(|t| t.received(port, time))
+                        && over
+                    {
+                        let limit = turnaround.as_ref().and_then(Turnaround::limit).unwrap_or_default().as_millis();
+                        let text = format!("{} answered in {ms:.1} ms, over the {limit} ms limit", port_names[port]);
+                        logs.write_at(time, &format!("[Turnaround] {text}"), true).await;
+                        output_lines.push(OutputLine::new("[Turnaround] ", text, Style::default().fg(Color::Red)));
+                    }
+                    if !args.no_bel
+                        && data.contains(&0x07)
+                        && last_bel.is_none_or(|at| at.elapsed() >= Duration::from_millis(250))
+                    {
+                        trigger::ring();
+                        last_bel = Some(Instant::now());
+                    }
+                    if let Some(tee) = tees.get(port) {
+                        tee.send(&data);
+                    }
+                    if let Some(capture) = &capture {
+                        capture.record(capture::Kind::Rx, port, time, data);
+                    }
+                    continue;
+                }
+                // Everything written is logged, whether typed or sent by a helper
+                SerialEvent::Tx(data) => {
+                    if let Some(turnaround) = &mut turnaround {
+                        turnaround.sent(port, time);
+                    }
+                    if let Some(capture) = &capture {
+                        capture.record(capture::Kind::Tx, port, time, data.clone());
+                    }
+                    let text = masks.apply(&tx_text(&data, args.encoding)).into_owned();
+                    logs.write_tx(time, &format!("{}{}", tx_tags[port], text)).await;
+                    if let Some(syslog) = syslogs.get(port).filter(|_| logs.direction.tx()) {
+                        syslog.send(SyslogDirection::Tx, &text);
+                    }
+                    if let Some(journal) = &journal {
+                        journal.lock().unwrap().record(time, port, true, &text);
+                    }
+                    continue;
+                }
+                SerialEvent::Connected => {
+                    if let Some(watchdog) = &mut watchdog {
+                        watchdog.connected(port, true, Instant::now());
+                    }
+                    // Asked again on a reconnect, which may be to the next board
+                    if let Some(identify) = &mut identify {
+                        match args.encoding.encode(&format!("{}{}", identify.query, args.line_ending.as_str())) {
+                            Ok(query) => {
+                                identify.sent(port, Instant::now());
+                                ports[port].write(query);
+                            }
+                            Err(e) => toast::notify(Severity::Error, format!("Cannot send --identify: {e}")),
+                        }
+                    }
+                    if std::mem::replace(&mut connected[port], true) {
+                        stats.reconnects += 1;
+                        if let Some(kbit) = args.slcan_bitrate {
+                            ports[port].write(slcan::open_commands(kbit));
+                        }
+                        let text = format!("{} reconnected", port_names[port]);
+                        logs.write_at(time, &format!("[Monitor] {text}"), true).await;
+                        toast::notify(Severity::Info, text);
+                    }
+                    continue;
+                }
+                SerialEvent::Disconnected => {
+                    if let Some(watchdog) = &mut watchdog {
+                        watchdog.connected(port, false, Instant::now());
+                    }
+                    let text = format!("{} disconnected", port_names[port]);
+                    logs.write_at(time, &format!("[Monitor] {text}"), true).await;
+                    toast::notify(Severity::Warning, text);
+                    continue;
+                }
+                SerialEvent::Error(text) => {
+                    logs.write_at(time, &format!("[Monitor] {text}"), true).await;
+                    toast::notify(Severity::Error, text);
+                    continue;
+                }
+                SerialEvent::Notice(text) => {
+                    logs.write_at(time, &format!("[Monitor] {text}"), true).await;
+                    toast::notify(Severity::Warning, text);
+                    continue;
+                }
+                // Marked inline so they can be matched to the garbled text around them
+                SerialEvent::LineErrors(errors) => {
+                    let text = format!(
+                        "{}: {} errors - check the baud rate, parity and wiring",
+                        port_names[port],
+                        errors.describe()
+                    );
+                    logs.write_at(time, &format!("[Line] {text}"), true).await;
+                    output_lines.push(OutputLine::new("[Line] ", text, Style::default().fg(Color::White).bg(Color::Red)));
+                    continue;
+                }
+            };
+            rates.record(&line, Instant::now());
+            traffic.line(time);
+            csv.record(&line);
+            let level = level::parse(&line);
+            stats.rx_lines += 1;
+            if level == Some(Level::Error) || (level.is_none() && line.contains("ERROR")) {
+                stats.error_lines += 1;
+            }
+            // Analysis sees the raw line; display, logs and syslog get secrets masked
+            let shown = masks.apply(&line).into_owned();
+            // Frames on a bus are tagged with the device that sent them
+            let (device, tag) = match bus.received(&line) {
+                Some((device, tag)) => (Some(device), tag),
+                None => (None, tags[port]),
+            };
+            let line_text = format!("{tag}{shown}");
+            let exchange = conversation.received(port, &line, time);
+            let mut entry = OutputLine::received(tag, shown.clone(), level)
+                .with_tag_color(tag_colors[port])
+                .in_exchange(exchange)
+                .on_device(device);
+            entry.ending = ending;
+            let mut matched: Vec<String> = masks.matching(&line).map(|pattern| format!("--mask {pattern}")).collect();
+            let masked = shown != line;
+            // Throttled lines and repeats of the last line aren't shown; logs keep every line
+            let throttled = match throttles.check(&line, Instant::now()) {
+                Verdict::Show(suppressed, rule) => {
+                    entry.suppressed = suppressed;
+                    matched.extend(rule.map(|rule| format!("--throttle {rule}")));
+                    false
+                }
+                Verdict::Suppress => true,
             };
-            let line_text = format!("[Arduino] {}", line);
-            output_lines.push(Line::from(Span::styled(line_text.clone(), style)));
+            let alerts = watches.record(&line, &mut matched);
+            let checked = expected.as_mut().filter(|_| port == 0).map(|expected| expected.check(&line)).unwrap_or_default();
+            entry.expected = checked.expected.map(|expected| masks.apply(&expected).into_owned());
+            for alert in &alerts {
+                let state = if alert.triggered { "triggered" } else { "cleared" };
+                matched.push(format!("--alert on {} {state}", alert.name));
+            }
+            entry.details = Some(Box::new(output::Details {
+                port: port_names[port].clone(),
+                time,
+                raw,
+                decoded,
+                matched,
+                masked,
+            }));
+            let repeat = !throttled && args.dedup && output_lines.last().is_some_and(|last| last.repeats(&entry));
+            if let Some(exchange) = exchange.filter(|_| !repeat && !throttled) {
+                entry.collapsed = conversation::count_reply(&mut output_lines, exchange.id);
+            }
             // Log to file (if enabled) and enforce memory cap
-            if let Some(log_file) = &log_file {
-                log_to_file(log_file, &line_text).await;
+            logs.write_rx(time, &line_text, !throttled && !entry.is_hidden(&view)).await;
+            match output_lines.last_mut() {
+                _ if throttled => {}
+                Some(last) if repeat => last.repeated += 1,
+                _ => output_lines.push(entry),
+            }
+            if let Some(syslog) = syslogs.get(port).filter(|_| logs.direction.rx()) {
+                syslog.send(SyslogDirection::Rx, &shown);
+            }
+            if let Some(journal) = &journal {
+                journal.lock().unwrap().record(time, port, false, &shown);
+            }
+            report_alerts(alerts, &ports[port], &logs, &mut output_lines, &mut stats, &tx_annotations).await;
+            for text in checked.missing {
+                let text = masks.apply(&text).into_owned();
+                logs.write_at(time, &format!("[Expect] {text}"), true).await;
+                output_lines.push(OutputLine::new("[Expect] ", text, Style::default().fg(Color::White).bg(Color::Red)));
+            }
+            if let Some(text) = checked.finished {
+                let passed = expected.as_ref().is_some_and(Expected::passed);
+                logs.write_at(time, &format!("[Expect] {text}"), true).await;
+                let color = if passed { Color::Green } else { Color::Red };
+                output_lines.push(OutputLine::new("[Expect] ", text.clone(), Style::default().fg(color)));
+                toast::notify(if passed { Severity::Info } else { Severity::Error }, text);
+            }
+            if let Some(report) = pinger
+                .as_mut()
+                .filter(|p| p.port == port)
+                .and_then(|p| p.received(&line, Instant::now()))
+            {
+                logs.write(&format!("[Ping] {report}"), true).await;
+                output_lines.push(OutputLine::new("[Ping] ", report, Style::default().fg(Color::Cyan)));
+            }
+            if let Some((_, name, runner)) = playing.as_mut().filter(|(playing, ..)| *playing == port)
+                && let Some(captured) = runner.received(&line, Instant::now())
+                && !captured.is_empty()
+            {
+                let captured: Vec<String> = captured.iter().map(|(name, value)| format!("{name}={value}")).collect();
+                let text = masks.apply(&format!("Macro {name} captured {}", captured.join(" "))).into_owned();
+                logs.write(&format!("[Monitor] {text}"), true).await;
+                output_lines.push(OutputLine::new("[Monitor] ", text, Style::default().fg(Color::Cyan)));
+            }
+            if let Some(identity) = identify.as_mut().and_then(|identify| identify.received(port, &line)) {
+                let text = masks.apply(&format!("{} identified as {identity}", port_names[port])).into_owned();
+                logs.write_at(time, &format!("[Monitor] {text}"), true).await;
+                output_lines.push(OutputLine::new("[Monitor] ", text, Style::default().fg(Color::Cyan)));
+                stats.identities.push(format!("{} {identity}", port_names[port]));
+            }
+            if let Some(symbolizer) = &symbolizer {
+                symbolizer.inspect(&line);
+            }
+            if let Some(result) = at_tracker
+                .as_mut()
+                .filter(|_| port == active)
+                .and_then(|t| t.received(&line, Instant::now()))
+            {
+                let text = masks.apply(&result.text()).into_owned();
+                let logged = match args.log_direction.tx() {
+                    true => text.clone(),
+                    false => result.outcome_text(),
+                };
+                logs.write(&format!("[AT] {logged}"), true).await;
+                output_lines.push(OutputLine::new("[AT] ", text, result.style()));
+            }
+        }
+        if let Some(port) = lost {
+            failure = Some(exit::failure(Status::Disconnected, format!("{} was disconnected", port_names[port])));
+            break;
+        }
+
+        // Devices attached or detached since the last iteration
+        while let Some(event) = rx_hotplug.try_recv() {
+            let (text, style) = match event {
+                Hotplug::Attached(info) => {
+                    let text = format!("{} attached{} - press Ctrl+A to switch to it", info.name, match info.description.as_str() {
+                        "" => String::new(),
+                        description => format!(" ({description})"),
+                    });
+                    attached = Some(info.name);
+                    (text, Style::default().fg(Color::Cyan))
+                }
+                Hotplug::Detached(name) => {
+                    if attached.as_ref() == Some(&name) {
+                        attached = None;
+                    }
+                    (format!("{name} detached"), Style::default().fg(Color::Yellow))
+                }
+            };
+            logs.write(&format!("[Hotplug] {text}"), true).await;
+            output_lines.push(OutputLine::new("[Hotplug] ", text, style));
+        }
+
+        // The config file was saved: its new rules apply from here on
+        while let Some(loaded) = rx_config.try_recv() {
+            match loaded.and_then(|config| Reload::new(&args, &loaded_profile, &saved_layout, &config)) {
+                Ok((reload, profile)) => {
+                    let applied = reload.describe();
+                    if let Some(reloaded) = reload.watches {
+                        watches = reloaded;
+                        show_watch = !watches.is_empty() && pane_layout.show_watch && !args.quiet;
+                    }
+                    if let Some(patterns) = reload.masks {
+                        masks.set_patterns(patterns);
+                    }
+                    if let Some(level) = reload.min_level {
+                        view.min_level = level;
+                    }
+                    if let Some(layout) = reload.layout {
+                        pane_layout = layout;
+                        saved_layout = layout;
+                        show_rates = layout.show_rates || args.turnaround;
+                        show_watch = !watches.is_empty() && layout.show_watch && !args.quiet;
+                    }
+                    loaded_profile = profile;
+                    match applied.as_str() {
+                        "" => toast::notify(Severity::Info, "Config reloaded; nothing this session uses changed".to_string()),
+                        applied => toast::notify(Severity::Info, format!("Config reloaded: {applied} updated")),
+                    }
+                }
+                Err(e) => toast::notify(Severity::Error, format!("Config not reloaded: {e:#}")),
+            }
+        }
+
+        // Values read by the Modbus master
+        while let Some((name, value)) = rx_modbus.try_recv() {
+            let alerts = watches.set(&name, &value);
+            report_alerts(alerts, &ports[0], &logs, &mut output_lines, &mut stats, &tx_annotations).await;
+        }
+
+        // Lines from background helpers
+        while let Some(annotation) = rx_annotations.try_recv() {
+            logs.write(&format!("{}{}", annotation.prefix, annotation.body), true).await;
+            output_lines.push(annotation);
+        }
+
+        // Lines sent through the HTTP API, shown like typed ones
+        while let Some(request) = rx_api.try_recv() {
+            let port = request.port.unwrap_or(active);
+            if port >= ports.len() {
+                request.respond(Err(format!("No port {port}; there are {}", ports.len())));
+                continue;
+            }
+            let sent = send_line!(port, command::unescape(&request.text));
+            request.respond(sent.map_err(|e| format!("Not sent: {e}")));
+        }
+
+        // Output of piped commands
+        while let Some(result) = rx_pipe.try_recv() {
+            popup = Some(Popup::new(result));
+        }
+
+        // Lines of a paste due to be sent
+        if let Some((port, pacer)) = &mut pasting {
+            for line in pacer.due(Instant::now()) {
+                if let Err(e) = send_line!(*port, &line) {
+                    toast::notify(Severity::Error, format!("Not sent: {e}"));
+                }
+            }
+            if pacer.remaining() == 0 {
+                pasting = None;
             }
-            if output_lines.len() > MAX_LINES {
-                output_lines.remove(0);
-                if scroll_offset > 0 {
-                    scroll_offset = scroll_offset.saturating_sub(1);
+        }
+
+        // Lines of a macro due to be sent
+        if let Some((port, name, runner)) = &mut playing {
+            let lines = match runner.poll(Instant::now()) {
+                script::Progress::Send(lines) => lines,
+                script::Progress::Pins(lines, sequence) => {
+                    let text = format!("Macro {name}: pins {}", sequence.describe());
+                    logs.write(&format!("[Monitor] {text}"), true).await;
+                    output_lines.push(OutputLine::new("[Monitor] ", text, Style::default().fg(Color::Cyan)));
+                    sequence.start(ports[*port].clone(), format!("of macro {name}"), tx_annotations.clone());
+                    lines
                 }
+                script::Progress::Failed(e) => {
+                    let text = masks.apply(&format!("Macro {name} stopped: {e}")).into_owned();
+                    logs.write(&format!("[Monitor] {text}"), true).await;
+                    output_lines.push(OutputLine::new("[Monitor] ", text, Style::default().fg(Color::Red)));
+                    Vec::new()
+                }
+            };
+            for line in lines {
+                if let Err(e) = send_line!(*port, &line) {
+                    toast::notify(Severity::Error, format!("Not sent: {e}"));
+                }
+            }
+            if runner.is_done() {
+                playing = None;
             }
         }
 
         // Handle user input
-        if event::poll(Duration::from_millis(10))? 
-            && let Event::Key(key) = event::read()? 
-        {
-                match key.code {
-                    KeyCode::Char(c) => {
-                        input.insert(cursor_pos, c);
+        if let Some(event) = typed.take() {
+            dirty = true;
+            // The cursor stays on while typing
+            cursor_visible = true;
+            last_blink = Instant::now();
+            match event {
+                // Repaint from scratch: multiplexers may leave stale cells behind
+                Event::Resize(..) => {
+                    terminal.clear()?;
+                    let total = if show_traffic {
+                        traffic.row_count(Local::now())
+                    } else if show_csv {
+                        csv.row_count()
+                    } else {
+                        output::total_height(&output_lines, &view)
+                    };
+                    scroll_offset = scroll_offset.min(total.saturating_sub(1));
+                }
+                Event::Paste(_) if pasting.is_some() => {
+                    toast::notify(Severity::Info, "Still sending the last paste; Esc stops it");
+                }
+                Event::Paste(text) if pending_paste.is_none() => {
+                    let text = paste::normalize(&text);
+                    if paste::needs_confirmation(&text, args.paste_lines, args.paste_bytes) {
+                        pending_paste = Some(paste::Pending::new(text, Duration::from_millis(args.paste_delay)));
+                    } else {
+                        // Pasted like typed: complete lines are sent, the rest stays in the input
+                        let at = editing::byte_index(&input, cursor_pos);
+                        let typed = format!("{}{text}{}", &input[..at], &input[at..]);
+                        let tail = editing::len(&input) - cursor_pos;
+                        let (lines, rest) = typed.rsplit_once('\n').unwrap_or(("", &typed));
+                        if typed.contains('\n') {
+                            pasting = Some((active, paste::Pacer::new(lines, Duration::ZERO)));
+                        }
+                        input = rest.to_string();
+                        cursor_pos = editing::len(&input) - tail;
+                        mode = UiMode::Input;
+                        view.selected = None;
+                    }
+                }
+                Event::Key(key) if key.kind != KeyEventKind::Release && pending_paste.is_some() => {
+                    if let Some(choice) = paste::Pending::choice(key.code)
+                        && let Some(pending) = pending_paste.take()
+                    {
+                        match choice {
+                            Some(paste::Strategy::AsIs) => {
+                                pasting = Some((active, paste::Pacer::new(&pending.text, Duration::ZERO)));
+                            }
+                            Some(paste::Strategy::Paced) => {
+                                let delay = Duration::from_millis(args.paste_delay);
+                                pasting = Some((active, paste::Pacer::new(&pending.text, delay)));
+                                toast::notify(Severity::Info, "Pasting line by line; Esc stops");
+                            }
+                            Some(paste::Strategy::Raw) if args.sniff => {
+                                toast::notify(Severity::Error, "Not sent: --sniff only listens");
+                            }
+                            Some(paste::Strategy::Raw) => match args.encoding.encode(&pending.text) {
+                                Ok(bytes) => {
+                                    stats.tx_lines += 1;
+                                    let text = format!("<{} pasted as a file>", scrollback::format_size(bytes.len()));
+                                    ports[active].write(bytes);
+                                    output_lines.push(
+                                        OutputLine::new(tx_tags[active], text, Style::default().fg(Color::Yellow))
+                                            .with_tag_color(tag_colors[active]),
+                                    );
+                                }
+                                Err(e) => toast::notify(Severity::Error, format!("Not sent: {e}")),
+                            },
+                            None => toast::notify(Severity::Info, "Paste cancelled"),
+                        }
+                    }
+                }
+                Event::Key(key) if key.kind != KeyEventKind::Release => match key.code {
+                    KeyCode::Char('t') if ctrl(&key) => {
+                        show_rates = !show_rates;
+                    }
+                    KeyCode::Char('s') if ctrl(&key) => match autobaud.as_mut().map(|autobaud| autobaud.start(active, &port_names, Instant::now())) {
+                        Some(Ok(Step::Try(port, rate))) => set_baud_rate(&ports[port], rate, tx_annotations.clone()),
+                        Some(Ok(_)) => {}
+                        Some(Err(text)) => toast::notify(Severity::Warning, text),
+                        None => toast::notify(Severity::Info, "Baud rates are only tried on ports shown as text"),
+                    },
+                    KeyCode::Char('b') if ctrl(&key) && !logging => {
+                        toast::notify(Severity::Info, "Nothing is logged (--no-log)");
+                    }
+                    KeyCode::Char('b') if ctrl(&key) => {
+                        logs.direction = logs.direction.next();
+                        let text = logs.direction.description();
+                        // Written regardless of the direction, so the log shows where lines are left out
+                        logs.write(&format!("[Monitor] {text}"), true).await;
+                        toast::notify(Severity::Info, text);
+                    }
+                    KeyCode::Char('w') if ctrl(&key) => {
+                        show_watch = !show_watch;
+                    }
+                    KeyCode::Char('n') if ctrl(&key) => {
+                        active = (active + 1) % ports.len();
+                    }
+                    KeyCode::Char('a') if ctrl(&key) => {
+                        if let Some(name) = attached.take() {
+                            // Replacing the handle closes the previous port
+                            let (text, style) = match open_port(&args, active, &name, settings, &tx_events, None).await {
+                                Ok(port) => {
+                                    stats.retire(&ports[active]);
+                                    ports[active] = port;
+                                    power_cycles[active] = args.line.power_cycle(active, &name);
+                                    port_names[active] = name.clone();
+                                    connected[active] = false;
+                                    baud_rates[active] = settings.baud_rate;
+                                    if let Some(autobaud) = &mut autobaud {
+                                        autobaud.reopened(active, settings.baud_rate);
+                                    }
+                                    if tagged {
+                                        (tags[active], tx_tags[active]) = port_tags(&name, args.alias.get(active));
+                                    }
+                                    (format!("Switched to {name}"), Style::default().fg(Color::Cyan))
+                                }
+                                Err(e) => (e.to_string(), Style::default().fg(Color::Red)),
+                            };
+                            for line in text.lines() {
+                                output_lines.push(OutputLine::new("[Monitor] ", line.to_string(), style));
+                            }
+                        }
+                    }
+                    KeyCode::Up if ctrl(&key) => pane_layout.resize_input(1),
+                    KeyCode::Down if ctrl(&key) => pane_layout.resize_input(-1),
+                    // Move the divider between the output and the side panels
+                    KeyCode::Left | KeyCode::Right if ctrl(&key) => {
+                        let wider = (key.code == KeyCode::Left) == (pane_layout.side_position == SidePosition::Right);
+                        pane_layout.resize_side(if wider { 4 } else { -4 });
+                    }
+                    KeyCode::Char('p') if ctrl(&key) => pane_layout.flip_input(),
+                    KeyCode::Char('k') if ctrl(&key) => pane_layout.flip_side(),
+                    KeyCode::Char('x') if ctrl(&key) => {
+                        view.pane = match view.pane {
+                            Pane::All => Pane::Received,
+                            _ => Pane::All,
+                        };
+                        scroll_offset = 0;
+                    }
+                    // Compose the next payload in an external editor
+                    KeyCode::Char('d') if ctrl(&key) => {
+                        disable_raw_mode()?;
+                        execute!(terminal.backend_mut(), crossterm::terminal::LeaveAlternateScreen, DisableBracketedPaste, Show)?;
+                        let composed = editor::compose(&input);
+                        enable_raw_mode()?;
+                        execute!(terminal.backend_mut(), crossterm::terminal::EnterAlternateScreen, EnableBracketedPaste, Hide)?;
+                        terminal.clear()?;
+                        match composed {
+                            Ok(Some(text)) => {
+                                for line in editor::lines(&text) {
+                                    if let Err(e) = send_line!(active, &line) {
+                                        toast::notify(Severity::Error, format!("Not sent: {e}"));
+                                    }
+                                }
+                                input.clear();
+                                cursor_pos = 0;
+                            }
+                            Ok(None) => toast::notify(Severity::Info, "Editor left empty; nothing sent"),
+                            Err(e) => toast::notify(Severity::Error, format!("{e:#}")),
+                        }
+                    }
+                    KeyCode::Char('e') if ctrl(&key) => {
+                        show_notifications = !show_notifications;
+                    }
+                    KeyCode::Char('r') if ctrl(&key) => {
+                        view.reveal = !view.reveal;
+                        scroll_offset = 0;
+                    }
+                    KeyCode::Char('j') if ctrl(&key) => {
+                        view.pretty_json = !view.pretty_json;
+                        scroll_offset = 0;
+                    }
+                    KeyCode::Char('g') if ctrl(&key) => {
+                        show_csv = !show_csv;
+                        scroll_offset = 0;
+                    }
+                    KeyCode::Char('l') if ctrl(&key) => {
+                        view.min_level = view.min_level.next();
+                        scroll_offset = 0;
+                    }
+                    KeyCode::Char('f') if ctrl(&key) => {
+                        view.conversation = !view.conversation;
+                        scroll_offset = 0;
+                    }
+                    // Exchanges fold in conversation mode, JSON documents otherwise
+                    KeyCode::Char('o') if ctrl(&key) => {
+                        toggle_fold(&mut output_lines, &view, scroll_offset);
+                    }
+                    KeyCode::Char('y') if ctrl(&key) => {
+                        popup = details_popup(&output_lines, &view, scroll_offset).or(popup);
+                    }
+                    KeyCode::Char('u') if ctrl(&key) && view.conversation => {
+                        conversation::toggle_all(&mut output_lines);
+                        scroll_offset = 0;
+                    }
+                    // Replay transport controls while nothing is typed
+                    KeyCode::Char(' ' | '.' | '<' | '>') | KeyCode::Left | KeyCode::Right
+                        if (input.is_empty() || mode != UiMode::Input) && player.is_some() =>
+                    {
+                        if let Some(player) = &player {
+                            player.control(match key.code {
+                                KeyCode::Char(' ') => replay::Control::TogglePause,
+                                KeyCode::Char('.') => replay::Control::Step,
+                                KeyCode::Char('<') => replay::Control::Slower,
+                                KeyCode::Char('>') => replay::Control::Faster,
+                                KeyCode::Left => replay::Control::SeekBy(-10_000_000),
+                                _ => replay::Control::SeekBy(10_000_000),
+                            });
+                        }
+                    }
+                    KeyCode::Tab => {
+                        mode = match mode {
+                            UiMode::Input => UiMode::Follow,
+                            _ => UiMode::Input,
+                        };
+                        view.selected = None;
+                        scroll_offset = 0;
+                    }
+                    // Outside INPUT, plain keys navigate the output instead of typing
+                    code if popup.is_none()
+                        && timeline.is_none()
+                        && !show_csv
+                        && !show_traffic
+                        && (mode == UiMode::Inspect
+                            || (mode == UiMode::Follow && code != KeyCode::Esc)
+                            || (mode == UiMode::Input && code == KeyCode::PageUp)) =>
+                    {
+                        let current = view
+                            .selected
+                            .or_else(|| output::shown_entry_at_bottom(&output_lines, &view, scroll_offset))
+                            .unwrap_or(output_lines.len().saturating_sub(1));
+                        let page = (view.height as isize - 1).max(1);
+                        let target = match code {
+                            _ if output_lines.is_empty() => None,
+                            KeyCode::Up | KeyCode::Char('k') if mode == UiMode::Inspect => {
+                                Some(output::step(&output_lines, &view, current, -1))
+                            }
+                            // Entering INSPECT selects the line at the bottom
+                            KeyCode::Up | KeyCode::Char('k') => Some(current),
+                            KeyCode::Down | KeyCode::Char('j') if mode == UiMode::Inspect => {
+                                Some(output::step(&output_lines, &view, current, 1))
+                            }
+                            KeyCode::PageDown if mode == UiMode::Inspect => Some(output::step(&output_lines, &view, current, page)),
+                            KeyCode::PageUp => Some(output::step(&output_lines, &view, current, -page)),
+                            KeyCode::Home | KeyCode::Char('g') => Some(output::step(&output_lines, &view, 0, 0)),
+                            _ => None,
+                        };
+                        match (target, code) {
+                            (Some(index), _) => {
+                                if mode != UiMode::Inspect {
+                                    back_to = mode;
+                                    mode = UiMode::Inspect;
+                                }
+                                view.selected = Some(index);
+                                scroll_offset = output::scroll_to(&output_lines, &view, index, scroll_offset);
+                            }
+                            (None, KeyCode::Esc | KeyCode::End | KeyCode::Char('G')) if mode == UiMode::Inspect => {
+                                mode = back_to;
+                                view.selected = None;
+                                scroll_offset = 0;
+                            }
+                            (None, KeyCode::Enter) if mode == UiMode::Inspect => {
+                                popup = details_popup(&output_lines, &view, scroll_offset).or(popup);
+                            }
+                            (None, KeyCode::Char('o')) => toggle_fold(&mut output_lines, &view, scroll_offset),
+                            (None, KeyCode::Char('i')) => {
+                                mode = UiMode::Input;
+                                view.selected = None;
+                                scroll_offset = 0;
+                            }
+                            _ => {}
+                        }
+                    }
+                    KeyCode::Char(c) if mode == UiMode::Input => {
+                        input.insert(editing::byte_index(&input, cursor_pos), c);
                         cursor_pos += 1;
                     }
-                    KeyCode::Backspace => {
+                    // Editing keys with nothing to do at the ends of the input
+                    // are still the input's
+                    #[allow(clippy::collapsible_match)]
+                    KeyCode::Backspace if mode == UiMode::Input => {
                         if cursor_pos > 0 {
-                            input.remove(cursor_pos - 1);
+                            input.remove(editing::byte_index(&input, cursor_pos - 1));
                             cursor_pos -= 1;
                         }
                     }
-                    KeyCode::Left => {
+                    KeyCode::Left if mode == UiMode::Input => {
                         cursor_pos = cursor_pos.saturating_sub(1);
                     }
-                    KeyCode::Right => {
-                        if cursor_pos < input.len() {
+                    #[allow(clippy::collapsible_match)]
+                    KeyCode::Right if mode == UiMode::Input => {
+                        if cursor_pos < editing::len(&input) {
                             cursor_pos += 1;
                         }
                     }
-                    KeyCode::Enter => {
+                    #[allow(clippy::collapsible_match)]
+                    KeyCode::Enter if mode == UiMode::Input => {
                         if !input.trim().is_empty() {
                             history.push(input.clone());
-                            let _ = tx_write.send(input.clone());
-                            let line_text = format!("> {}", input);
-                            output_lines.push(Line::from(Span::styled(
-                                line_text.clone(),
-                                Style::default().fg(Color::Yellow),
-                            )));
-                            // Log to file (if enabled) and enforce memory cap
-                            if let Some(log_file) = &log_file {
-                                log_to_file(log_file, &line_text).await;
-                            }
-                            if output_lines.len() > MAX_LINES {
-                                output_lines.remove(0);
-                                if scroll_offset > 0 {
-                                    scroll_offset = scroll_offset.saturating_sub(1);
+                            if let Some(command) = command::parse(&input) {
+                                let result = match command {
+                                    Ok(Command::Stress { pattern, rate }) => {
+                                        if let Some(previous) = stress.take() {
+                                            previous.stop();
+                                        }
+                                        stress = Some(Stress::start(ports[active].clone(), pattern, rate, tx_annotations.clone()));
+                                        Ok(format!("Stress test started: {} pattern at {rate} B/s", pattern.name()))
+                                    }
+                                    Ok(Command::StressStop) => match stress.take() {
+                                        Some(running) => {
+                                            running.stop();
+                                            Ok("Stress test stopped".to_string())
+                                        }
+                                        None => Err("No stress test running".to_string()),
+                                    },
+                                    Ok(Command::Ping(spec)) => {
+                                        let text = format!(
+                                            "Pinging every {:.2}s with {:?}",
+                                            spec.interval.as_secs_f64(),
+                                            spec.probe
+                                        );
+                                        pinger = Some(Pinger::new(spec, active));
+                                        Ok(text)
+                                    }
+                                    Ok(Command::Pipe(spec)) => {
+                                        let lines: Vec<String> = output_lines
+                                            .iter()
+                                            .filter(|line| !line.is_hidden(&view))
+                                            .map(|line| format!("{}{}\n", line.prefix, line.body))
+                                            .collect();
+                                        let lines = spec.range.select(&lines);
+                                        let text = format!("Piping {} lines through {:?}", lines.len(), spec.command);
+                                        pipe::spawn(spec.command, lines.concat(), tx_pipe.clone());
+                                        Ok(text)
+                                    }
+                                    Ok(Command::Seek(target)) => match &player {
+                                        Some(player) => {
+                                            let control = player.status().resolve(target, Instant::now());
+                                            let text = control.describe();
+                                            player.control(control);
+                                            Ok(text)
+                                        }
+                                        None => Err("Not replaying a capture".to_string()),
+                                    },
+                                    Ok(Command::History(target)) => match &args.capture {
+                                        Some(path) => {
+                                            let time = target.map(|t| t.time_from(Local::now().timestamp_micros()));
+                                            match Timeline::open(path, time) {
+                                                Ok(opened) => {
+                                                    timeline = Some(opened);
+                                                    Ok("Showing the capture; Esc returns to the live view".to_string())
+                                                }
+                                                Err(e) => Err(format!("Cannot read the capture: {e}")),
+                                            }
+                                        }
+                                        None => Err("Time travel needs a capture of the session (--capture)".to_string()),
+                                    },
+                                    Ok(Command::Scrollback(limit)) => {
+                                        if let Some(limit) = limit {
+                                            budget.set_limit(limit);
+                                            // As in the trim of each iteration, lines counted from the
+                                            // top move up by those dropped
+                                            let dropped = budget.trim(&mut output_lines);
+                                            view.selected = view.selected.map(|i| i.saturating_sub(dropped));
+                                            seen_lines = seen_lines.saturating_sub(dropped);
+                                            scroll_offset =
+                                                scroll_offset.min(output::total_height(&output_lines, &view).saturating_sub(1));
+                                        }
+                                        Ok(format!(
+                                            "Scrollback: {} lines using {} of {}",
+                                            output_lines.len(),
+                                            scrollback::format_size(budget.used()),
+                                            scrollback::format_size(budget.limit())
+                                        ))
+                                    }
+                                    Ok(Command::BusDevices) if !bus.is_enabled() => Err("No --bus-address is given".to_string()),
+                                    Ok(Command::BusDevices) => Ok(bus.describe()),
+                                    Ok(Command::BusFilter(_)) if !bus.is_enabled() => Err("No --bus-address is given".to_string()),
+                                    Ok(Command::BusFilter(None)) => {
+                                        view.device = None;
+                                        scroll_offset = 0;
+                                        Ok("Showing the frames of all devices".to_string())
+                                    }
+                                    Ok(Command::BusFilter(Some(name))) => match bus.find(&name) {
+                                        Some(device) => {
+                                            view.device = Some(device);
+                                            scroll_offset = 0;
+                                            Ok(format!("Showing only the frames of {device}; :bus all shows all"))
+                                        }
+                                        None => Err(format!("No device {name} on the bus. {}", bus.describe())),
+                                    },
+                                    Ok(Command::Record(name)) => match &recording {
+                                        Some(recorder) => Err(format!("Already recording {}; :record stop saves it", recorder.name)),
+                                        None => {
+                                            let text = format!("Recording the lines sent as macro {name}; :record stop saves it");
+                                            recording = Some(macros::Recorder::new(name));
+                                            Ok(text)
+                                        }
+                                    },
+                                    Ok(Command::RecordStop) => match (recording.take(), &macro_dir) {
+                                        (Some(recorder), Some(dir)) => {
+                                            let name = recorder.name.clone();
+                                            match recorder.save(dir) {
+                                                Ok((path, lines)) => {
+                                                    Ok(format!("Saved macro {name}: {lines} lines in {}; :play {name} sends them", path.display()))
+                                                }
+                                                Err(e) => Err(format!("{e:#}")),
+                                            }
+                                        }
+                                        (Some(_), None) => Err("No macro directory; give one with --macro-dir".to_string()),
+                                        (None, _) => Err("Not recording a macro".to_string()),
+                                    },
+                                    Ok(Command::Play(_)) if playing.is_some() => {
+                                        Err("A macro is playing; Esc or :play stop stops it".to_string())
+                                    }
+                                    Ok(Command::Play(name)) => match macro_dir.as_deref().map(|dir| macros::load(dir, &name)) {
+                                        Some(Ok(steps)) => {
+                                            let text = format!("Playing macro {name}: {} steps; Esc stops it", steps.len());
+                                            let runner = script::Runner::new(steps, secrets.clone());
+                                            for value in runner.secret_values() {
+                                                masks.hide(&value);
+                                            }
+                                            playing = Some((active, name, runner));
+                                            Ok(text)
+                                        }
+                                        Some(Err(e)) => Err(format!("{e:#}")),
+                                        None => Err("No macro directory; give one with --macro-dir".to_string()),
+                                    },
+                                    Ok(Command::PlayStop) => match playing.take() {
+                                        Some((_, name, runner)) => {
+                                            Ok(format!("Macro {name} stopped; {} steps not run", runner.remaining()))
+                                        }
+                                        None => Err("No macro playing".to_string()),
+                                    },
+                                    Ok(Command::PowerCycle) if args.replay.is_some() || args.simulate.is_some() => {
+                                        Err("Only a device on a USB port can be power-cycled".to_string())
+                                    }
+                                    Ok(Command::PowerCycle) => {
+                                        let power_cycle = power_cycles[active].clone();
+                                        let annotations = tx_annotations.clone();
+                                        tokio::spawn(async move {
+                                            let (text, color) = match power_cycle.run().await {
+                                                Ok(done) => (done, Color::Cyan),
+                                                Err(e) => (format!("{e:#}"), Color::Red),
+                                            };
+                                            let _ = annotations.send(OutputLine::new("[Monitor] ", text, Style::default().fg(color)));
+                                        });
+                                        Ok(format!("Power-cycling {}", port_names[active]))
+                                    }
+                                    Ok(Command::Hud) => {
+                                        show_hud = !show_hud;
+                                        Ok(format!("Performance HUD {}", if show_hud { "shown" } else { "hidden" }))
+                                    }
+                                    Ok(Command::Traffic) => {
+                                        show_traffic = !show_traffic;
+                                        scroll_offset = 0;
+                                        Ok(format!("Traffic per minute {}", if show_traffic { "shown" } else { "hidden" }))
+                                    }
+                                    Ok(Command::SaveLayout) => {
+                                        let mut layout = pane_layout;
+                                        if !args.turnaround {
+                                            layout.show_rates = show_rates;
+                                        }
+                                        if !watches.is_empty() && !args.quiet {
+                                            layout.show_watch = show_watch;
+                                        }
+                                        match config::save_layout(args.config.as_deref(), &layout) {
+                                            Ok(()) => {
+                                                // The file now has it, which the reload mustn't take for a change
+                                                saved_layout = layout;
+                                                Ok("Layout saved to the config file".to_string())
+                                            }
+                                            Err(e) => Err(format!("Cannot save the layout: {e:#}")),
+                                        }
+                                    }
+                                    Ok(Command::SaveSession(_)) if args.replay.is_some() => {
+                                        Err("A replay's ports are those of its capture; no session saved".to_string())
+                                    }
+                                    Ok(Command::SaveSession(path)) => {
+                                        let (watch, alert) = watches.specs();
+                                        let mut layout = pane_layout;
+                                        if !args.turnaround {
+                                            layout.show_rates = show_rates;
+                                        }
+                                        if !watches.is_empty() && !args.quiet {
+                                            layout.show_watch = show_watch;
+                                        }
+                                        // Ctrl+A and Ctrl+S change the ports and their rates
+                                        let current: Vec<u32> = (0..ports.len())
+                                            .map(|port| autobaud.as_ref().map_or(baud_rates[port], |autobaud| autobaud.rate(port)))
+                                            .collect();
+                                        let session = Session {
+                                            port: port_names.clone(),
+                                            simulate: args.simulate.clone(),
+                                            alias: args.alias.clone(),
+                                            color: args.port_color.iter().map(Color::to_string).collect(),
+                                            baud_rate: Some(args.line.baud_rate),
+                                            baud_rates: match current.iter().all(|&rate| rate == args.line.baud_rate) {
+                                                true => Vec::new(),
+                                                false => current,
+                                            },
+                                            data_bits: Some(args.line.data_bits),
+                                            parity: Some(args.line.parity),
+                                            stop_bits: Some(args.line.stop_bits),
+                                            flow_control: Some(args.line.flow_control),
+                                            line_ending: Some(args.line_ending),
+                                            encoding: Some(args.encoding),
+                                            watch: Some(watch),
+                                            alert: Some(alert),
+                                            mask: Some(masks.patterns().map(str::to_string).collect()),
+                                            min_level: Some(view.min_level),
+                                            macro_dir: macro_dir.as_ref().map(|dir| dir.display().to_string()),
+                                            view: session::View {
+                                                pane: Some(view.pane),
+                                                device: view.device.map(str::to_string),
+                                                pretty_json: view.pretty_json,
+                                                reveal: view.reveal,
+                                                conversation: view.conversation,
+                                                hud: show_hud,
+                                            },
+                                            layout: Some(layout),
+                                        };
+                                        match session.save(std::path::Path::new(&path)) {
+                                            Ok(()) => Ok(format!("Session saved to {path}; --session {path} restores it")),
+                                            Err(e) => Err(format!("{e:#}")),
+                                        }
+                                    }
+                                    Ok(Command::Reset(name)) => {
+                                        let defined = config::load(args.config.as_deref()).map(|config| config.sequences);
+                                        match (name, defined) {
+                                            (_, Err(e)) => Err(format!("{e:#}")),
+                                            (None, Ok(defined)) => {
+                                                let names: Vec<&str> = args
+                                                    .sequence
+                                                    .iter()
+                                                    .map(|(name, _)| name.as_str())
+                                                    .chain(defined.keys().map(String::as_str))
+                                                    .chain(signals::BUILT_IN.iter().map(|(name, _)| *name))
+                                                    .collect();
+                                                Ok(format!("Sequences: {}; :reset NAME runs one", names.join(", ")))
+                                            }
+                                            (Some(name), Ok(defined)) => {
+                                                let found = match args.sequence.iter().find(|(given, _)| *given == name) {
+                                                    Some((_, sequence)) => Some(Ok(sequence.clone())),
+                                                    None => defined
+                                                        .get(&name)
+                                                        .map(String::as_str)
+                                                        .or_else(|| signals::BUILT_IN.iter().find(|(known, _)| *known == name).map(|(_, steps)| *steps))
+                                                        .map(signals::parse),
+                                                };
+                                                match found {
+                                                    Some(Ok(sequence)) => {
+                                                        let text = format!("Running {name} on {}: {}", port_names[active], sequence.describe());
+                                                        sequence.start(ports[active].clone(), name, tx_annotations.clone());
+                                                        Ok(text)
+                                                    }
+                                                    Some(Err(e)) => Err(format!("Sequence {name}: {e}")),
+                                                    None => Err(format!("No sequence {name}; :reset lists them")),
+                                                }
+                                            }
+                                        }
+                                    }
+                                    Ok(Command::Macros) => {
+                                        let names = macro_dir.as_deref().map(macros::list).unwrap_or_default();
+                                        match names.is_empty() {
+                                            true => Ok("No macros saved; :record NAME records one".to_string()),
+                                            false => Ok(format!("Macros: {}", names.join(", "))),
+                                        }
+                                    }
+                                    Ok(Command::PingStop) => match pinger.take() {
+                                        Some(stopped) => Ok(format!("Ping statistics: {}", stopped.summary())),
+                                        None => Err("Ping mode is not running".to_string()),
+                                    },
+                                    Err(e) => Err(e),
+                                };
+                                let (text, style) = match result {
+                                    Ok(text) => (text, Style::default().fg(Color::Cyan)),
+                                    Err(e) => (e, Style::default().fg(Color::Red)),
+                                };
+                                logs.write(&format!("[Monitor] {text}"), true).await;
+                                output_lines.push(OutputLine::new("[Monitor] ", text, style));
+                            } else if let Some(adapter) = bus_pirate.as_ref().filter(|_| active == 0) {
+                                let text = input.trim();
+                                output_lines.push(OutputLine::new(tx_tags[0], text.to_string(), Style::default().fg(Color::Yellow)));
+                                logs.write_tx(Local::now(), &format!("{}{text}", tx_tags[0])).await;
+                                stats.tx_lines += 1;
+                                if let Some(recorder) = &mut recording {
+                                    recorder.sent(text, Instant::now());
+                                }
+                                if let Err(e) = adapter.submit(text) {
+                                    output_lines.push(OutputLine::new("[Monitor] ", format!("Not sent: {e}"), Style::default().fg(Color::Red)));
+                                }
+                            } else {
+                                if let Err(e) = send_line!(active, command::unescape(&input)) {
+                                    output_lines.push(OutputLine::new(
+                                        "[Monitor] ",
+                                        format!("Not sent: {e}"),
+                                        Style::default().fg(Color::Red),
+                                    ));
                                 }
                             }
                             input.clear();
@@ -307,7 +3311,22 @@ async fn main() -> Result<()> {
                             history_index = None;
                         }
                     }
-                    KeyCode::Up => {
+                    KeyCode::PageUp | KeyCode::PageDown | KeyCode::Up | KeyCode::Down
+                        if timeline.is_some() && popup.is_none() =>
+                    {
+                        if let Some(timeline) = &mut timeline {
+                            let moved = match key.code {
+                                KeyCode::PageUp => timeline.page(-1),
+                                KeyCode::PageDown => timeline.page(1),
+                                KeyCode::Up => timeline.scroll(-1),
+                                _ => timeline.scroll(1),
+                            };
+                            if let Err(e) = moved {
+                                toast::notify(Severity::Warning, format!("Cannot read the capture: {e}"));
+                            }
+                        }
+                    }
+                    KeyCode::Up if mode == UiMode::Input => {
                         if let Some(new_idx) = history_index.map(|i| i.saturating_sub(1)).or_else(|| {
                             if !history.is_empty() {
                                 Some(history.len() - 1)
@@ -316,15 +3335,15 @@ async fn main() -> Result<()> {
                             }
                         }) {
                             input = history[new_idx].clone();
-                            cursor_pos = input.len();
+                            cursor_pos = editing::len(&input);
                             history_index = Some(new_idx);
                         }
                     }
-                    KeyCode::Down => {
+                    KeyCode::Down if mode == UiMode::Input => {
                         if let Some(i) = history_index {
                             if i + 1 < history.len() {
                                 input = history[i + 1].clone();
-                                cursor_pos = input.len();
+                                cursor_pos = editing::len(&input);
                                 history_index = Some(i + 1);
                             } else {
                                 input.clear();
@@ -333,26 +3352,112 @@ async fn main() -> Result<()> {
                             }
                         }
                     }
+                    KeyCode::PageUp if popup.is_some() => {
+                        if let Some(popup) = &mut popup {
+                            popup.scroll(-10);
+                        }
+                    }
+                    KeyCode::PageDown if popup.is_some() => {
+                        if let Some(popup) = &mut popup {
+                            popup.scroll(10);
+                        }
+                    }
                     KeyCode::PageUp => {
-                        scroll_offset = (scroll_offset + 3).min(output_lines.len().saturating_sub(1));
+                        let total = if show_traffic {
+                            traffic.row_count(Local::now())
+                        } else if show_csv {
+                            csv.row_count()
+                        } else {
+                            output::total_height(&output_lines, &view)
+                        };
+                        scroll_offset = (scroll_offset + 3).min(total.saturating_sub(1));
                     }
                     KeyCode::PageDown => {
                         scroll_offset = scroll_offset.saturating_sub(3);
                     }
+                    KeyCode::Esc if pasting.is_some() => {
+                        if let Some((_, pacer)) = pasting.take() {
+                            toast::notify(Severity::Info, format!("Paste stopped; {} lines not sent", pacer.remaining()));
+                        }
+                    }
+                    KeyCode::Esc if playing.is_some() => {
+                        if let Some((_, name, runner)) = playing.take() {
+                            toast::notify(Severity::Info, format!("Macro {name} stopped; {} steps not run", runner.remaining()));
+                        }
+                    }
+                    KeyCode::Esc if popup.is_some() => popup = None,
+                    KeyCode::Esc if timeline.is_some() => timeline = None,
                     KeyCode::Esc => break,
                     _ => {}
-                }
-        
+                },
+                _ => {}
+            }
         }
 
-        time::sleep(Duration::from_millis(10)).await;
+        // Sleep until something arrives, a key is pressed or a timer is due:
+        // pastes and macros send on the millisecond, the rest can wait for
+        // the next redraw
+        let busy = pasting.is_some() || playing.is_some() || autobaud.as_ref().is_some_and(AutoBaud::is_searching);
+        let mut timeout = if busy { Duration::from_millis(10) } else { IDLE_REDRAW };
+        if let Some(pinger) = &pinger {
+            timeout = timeout.min(pinger.due().saturating_duration_since(Instant::now()));
+        }
+        let waiting = Instant::now();
+        tokio::select! {
+            event = terminal_events.next() => match event {
+                Some(event) => typed = Some(event?),
+                None => break,
+            },
+            _ = rx_events.ready() => {}
+            _ = rx_api.ready() => {}
+            _ = rx_hotplug.ready() => {}
+            _ = rx_config.ready() => {}
+            _ = rx_annotations.ready() => {}
+            _ = rx_modbus.ready() => {}
+            _ = rx_pipe.ready() => {}
+            _ = time::sleep(timeout) => {}
+        }
+        hud.waited(waiting.elapsed());
     }
 
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
         crossterm::terminal::LeaveAlternateScreen,
+        DisableBracketedPaste,
         Show
     )?;
-    Ok(())
+
+    if let Some(checkpointer) = checkpointer
+        && let Err(e) = checkpointer.finish(&output_lines, &history, &masks).await
+    {
+        eprintln!("{e:#}");
+    }
+
+    // Session summary for test reports
+    let mut summary = stats.summary(&ports);
+    if let Some(turnaround) = &turnaround {
+        summary.push(("Turnaround", turnaround.summary()));
+    }
+    if let Some(expected) = &expected {
+        summary.push(("Expected", expected.summary()));
+    }
+    let logged: Vec<String> = summary.iter().map(|(label, value)| format!("{label}: {value}")).collect();
+    logs.write(&format!("[Monitor] Session summary: {}", logged.join(", ")), true).await;
+    logs.finish().await?;
+    if let Some(capture) = capture {
+        capture.finish().await?;
+    }
+    println!("Session summary");
+    for (label, value) in &summary {
+        println!("  {:<12} {value}", format!("{label}:"));
+    }
+    if let Some(expected) = &expected
+        && failure.is_none()
+        && !expected.complete()
+    {
+        let message = format!("The lines received didn't match --expect: {}", expected.summary());
+        failure = Some(exit::failure(Status::TestFailed, message));
+    }
+    failure.map_or(Ok(()), Err)
 }
\ No newline at end of file
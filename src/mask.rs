@@ -0,0 +1,78 @@
+//! Masking of secrets in displayed and logged text.
+//!
+//! Each pattern's matches are replaced with asterisks; a pattern with capture
+//! groups masks only what the groups matched, so `AT\+PWD=(.*)` keeps the
+//! command visible and hides the password.
+
+use std::borrow::Cow;
+
+use regex::Regex;
+
+const MASK: &str = "****";
+
+pub struct Masks {
+    patterns: Vec<Regex>,
+    /// Values masked without being named as a pattern in line details.
+    hidden: Vec<Regex>,
+}
+
+impl Masks {
+    pub fn new(patterns: Vec<Regex>) -> Self {
+        Self { patterns, hidden: Vec::new() }
+    }
+
+    /// Replaces the patterns, keeping the hidden values.
+    pub fn set_patterns(&mut self, patterns: Vec<Regex>) {
+        self.patterns = patterns;
+    }
+
+    /// The patterns given, without the hidden values.
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        self.patterns.iter().map(Regex::as_str)
+    }
+
+    /// Masks every occurrence of `value`, e.g. a credential a macro sends.
+    pub fn hide(&mut self, value: &str) {
+        let escaped = regex::escape(value);
+        if !value.is_empty() && !self.hidden.iter().any(|hidden| hidden.as_str() == escaped) {
+            self.hidden.push(Regex::new(&escaped).expect("an escaped value is a valid regex"));
+        }
+    }
+
+    /// `text` with every secret masked.
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for pattern in self.patterns.iter().chain(&self.hidden) {
+            if !pattern.is_match(&text) {
+                continue;
+            }
+            text = Cow::Owned(mask(pattern, &text));
+        }
+        text
+    }
+
+    /// The patterns that match `text`.
+    pub fn matching<'a>(&'a self, text: &'a str) -> impl Iterator<Item = &'a str> {
+        self.patterns.iter().filter(|pattern| pattern.is_match(text)).map(Regex::as_str)
+    }
+}
+
+fn mask(pattern: &Regex, text: &str) -> String {
+    let mut masked = String::with_capacity(text.len());
+    let mut copied = 0;
+    for captures in pattern.captures_iter(text) {
+        let groups: Vec<_> = captures.iter().skip(1).flatten().collect();
+        let spans = if groups.is_empty() { captures.get(0).into_iter().collect() } else { groups };
+        for span in spans {
+            // Nested or repeated groups may overlap what is already masked
+            if span.start() < copied {
+                continue;
+            }
+            masked.push_str(&text[copied..span.start()]);
+            masked.push_str(MASK);
+            copied = span.end();
+        }
+    }
+    masked.push_str(&text[copied..]);
+    masked
+}
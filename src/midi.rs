@@ -0,0 +1,122 @@
+//! MIDI messages in received frames.
+//!
+//! Each message is shown as e.g. `Note On ch1 C4 vel 100`, several in one
+//! frame separated by `; `. Data bytes without a status byte of their own
+//! take the status of the last channel message (running status), which is
+//! why the decoder remembers it across frames.
+
+use std::{
+    iter::Peekable,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+const NOTES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// Name of note `n`, with middle C (60) as `C4`.
+fn note(n: u8) -> String {
+    format!("{}{}", NOTES[usize::from(n % 12)], i16::from(n / 12) - 1)
+}
+
+/// Data bytes following a status byte, `None` for SysEx's open-ended data.
+fn data_length(status: u8) -> Option<usize> {
+    match status {
+        0x80..=0xBF | 0xE0..=0xEF | 0xF2 => Some(2),
+        0xC0..=0xDF | 0xF1 | 0xF3 => Some(1),
+        0xF0 => None,
+        _ => Some(0),
+    }
+}
+
+fn describe(status: u8, data: &[u8]) -> String {
+    let channel = (status & 0x0F) + 1;
+    match (status, data) {
+        (0x90..=0x9F, [n, 0]) | (0x80..=0x8F, [n, _]) => format!("Note Off ch{channel} {}", note(*n)),
+        (0x90..=0x9F, [n, velocity]) => format!("Note On ch{channel} {} vel {velocity}", note(*n)),
+        (0xA0..=0xAF, [n, pressure]) => format!("Aftertouch ch{channel} {} {pressure}", note(*n)),
+        (0xB0..=0xBF, [controller, value]) => format!("CC ch{channel} {controller}={value}"),
+        (0xC0..=0xCF, [program]) => format!("Program ch{channel} {program}"),
+        (0xD0..=0xDF, [pressure]) => format!("Pressure ch{channel} {pressure}"),
+        (0xE0..=0xEF, [lsb, msb]) => {
+            let bend = (i32::from(*msb) << 7 | i32::from(*lsb)) - 8192;
+            format!("Pitch Bend ch{channel} {bend:+}")
+        }
+        (0xF0, data) => format!("SysEx {}", crate::framing::hex(data)),
+        (0xF1, [value]) => format!("MTC Quarter Frame {value:02X}"),
+        (0xF2, [lsb, msb]) => format!("Song Position {}", u16::from(*msb) << 7 | u16::from(*lsb)),
+        (0xF3, [song]) => format!("Song Select {song}"),
+        (0xF6, _) => "Tune Request".to_string(),
+        (0xF8, _) => "Clock".to_string(),
+        (0xFA, _) => "Start".to_string(),
+        (0xFB, _) => "Continue".to_string(),
+        (0xFC, _) => "Stop".to_string(),
+        (0xFE, _) => "Active Sensing".to_string(),
+        (0xFF, _) => "Reset".to_string(),
+        (status, data) => format!("<status {status:02X}> {}", crate::framing::hex(data)),
+    }
+}
+
+#[derive(Default)]
+pub struct MidiDecoder {
+    /// Status of the last channel message, for running status; 0 if there
+    /// is none, as status bytes have their top bit set.
+    running: AtomicU8,
+}
+
+impl MidiDecoder {
+    pub fn decode(&self, frame: &[u8]) -> String {
+        let mut messages = Vec::new();
+        let mut bytes = frame.iter().copied().peekable();
+        while let Some(byte) = bytes.next() {
+            // Real-time messages may come between the bytes of any other
+            if byte >= 0xF8 {
+                messages.push(describe(byte, &[]));
+                continue;
+            }
+            let (status, first) = match byte {
+                0x80..=0xEF => {
+                    self.running.store(byte, Ordering::Relaxed);
+                    (byte, None)
+                }
+                0xF0..=0xF7 => {
+                    self.running.store(0, Ordering::Relaxed);
+                    (byte, None)
+                }
+                data => match self.running.load(Ordering::Relaxed) {
+                    0 => {
+                        messages.push(format!("<data {data:02X}>"));
+                        continue;
+                    }
+                    status => (status, Some(data)),
+                },
+            };
+            messages.push(message(status, first, &mut bytes));
+        }
+        messages.join("; ")
+    }
+}
+
+/// The message of `status`, whose first data byte may already be read.
+/// A status byte cutting the message short is left for the next one.
+fn message(status: u8, first: Option<u8>, bytes: &mut Peekable<impl Iterator<Item = u8>>) -> String {
+    let mut data: Vec<u8> = first.into_iter().collect();
+    match data_length(status) {
+        Some(length) => {
+            while data.len() < length {
+                match bytes.next_if(|&byte| byte < 0x80) {
+                    Some(byte) => data.push(byte),
+                    None => return format!("<incomplete {}>", crate::framing::hex(&[status])),
+                }
+            }
+        }
+        // SysEx runs until its end byte
+        None => {
+            for byte in bytes.by_ref() {
+                if byte == 0xF7 {
+                    break;
+                }
+                data.push(byte);
+            }
+        }
+    }
+    describe(status, &data)
+}
@@ -0,0 +1,206 @@
+//! A scripted transport for testing the port task and what consumes it.
+//!
+//! A [`MockPort`] plays a script of what the device sends, with pauses
+//! between reads, read errors and a disconnect, while the [`MockDevice`]
+//! shared with it records what was written and which line settings were
+//! applied, and injects write errors, rejected settings and line errors. It
+//! can also hold writes, as a device deasserting CTS does, until released,
+//! so reconnects, flow control and logging are tested without hardware.
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{self, Sleep},
+};
+
+use crate::transport::{Control, LineErrors, Transport};
+
+enum Step {
+    Read(Vec<u8>),
+    Pause(Duration),
+    Error(io::ErrorKind),
+    Disconnect,
+}
+
+#[derive(Default)]
+struct State {
+    written: Vec<u8>,
+    controls: Vec<Control>,
+    write_errors: VecDeque<io::ErrorKind>,
+    reject_controls: bool,
+    line_errors: Option<LineErrors>,
+    /// Whether writes wait, and the writer waiting.
+    held: bool,
+    writer: Option<Waker>,
+    opened: usize,
+}
+
+/// The device end of every [`MockPort`] opened on it, reconnects included.
+#[derive(Clone, Default)]
+pub struct MockDevice {
+    state: Arc<Mutex<State>>,
+}
+
+impl MockDevice {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// A port on the device, which sends nothing until given a script.
+    pub fn open(&self) -> MockPort {
+        self.state().opened += 1;
+        MockPort {
+            device: self.clone(),
+            steps: VecDeque::new(),
+            pause: None,
+        }
+    }
+
+    /// How many ports were opened on the device.
+    pub fn opened(&self) -> usize {
+        self.state().opened
+    }
+
+    /// Everything written to the device so far.
+    pub fn written(&self) -> Vec<u8> {
+        self.state().written.clone()
+    }
+
+    /// The line settings and control signals applied, in order.
+    pub fn controls(&self) -> Vec<Control> {
+        self.state().controls.clone()
+    }
+
+    /// Fails the next write with `kind`.
+    pub fn fail_write(&self, kind: io::ErrorKind) {
+        self.state().write_errors.push_back(kind);
+    }
+
+    /// Whether line setting changes are refused, as by a driver that doesn't
+    /// support them.
+    pub fn reject_controls(&self, reject: bool) {
+        self.state().reject_controls = reject;
+    }
+
+    /// Sets the receive error counters the driver reports.
+    pub fn set_line_errors(&self, errors: LineErrors) {
+        self.state().line_errors = Some(errors);
+    }
+
+    /// Holds writes until called with `false`, as flow control does.
+    pub fn hold_writes(&self, held: bool) {
+        let mut state = self.state();
+        state.held = held;
+        if !held && let Some(writer) = state.writer.take() {
+            writer.wake();
+        }
+    }
+}
+
+/// A transport playing a script; once it runs out, reads wait forever.
+pub struct MockPort {
+    device: MockDevice,
+    steps: VecDeque<Step>,
+    pause: Option<Pin<Box<Sleep>>>,
+}
+
+impl MockPort {
+    /// The device sends `data`, in one read if the reader's buffer is large
+    /// enough.
+    pub fn read(mut self, data: &[u8]) -> Self {
+        self.steps.push_back(Step::Read(data.to_vec()));
+        self
+    }
+
+    /// The device is quiet for `duration`.
+    pub fn pause(mut self, duration: Duration) -> Self {
+        self.steps.push_back(Step::Pause(duration));
+        self
+    }
+
+    /// A read fails with `kind`.
+    pub fn error(mut self, kind: io::ErrorKind) -> Self {
+        self.steps.push_back(Step::Error(kind));
+        self
+    }
+
+    /// The device goes away: the read ends the stream.
+    pub fn disconnect(mut self) -> Self {
+        self.steps.push_back(Step::Disconnect);
+        self
+    }
+}
+
+impl AsyncRead for MockPort {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        loop {
+            if let Some(pause) = &mut self.pause {
+                if pause.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                self.pause = None;
+            }
+            match self.steps.pop_front() {
+                Some(Step::Read(data)) => {
+                    let n = data.len().min(buf.remaining());
+                    buf.put_slice(&data[..n]);
+                    if n < data.len() {
+                        self.steps.push_front(Step::Read(data[n..].to_vec()));
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+                Some(Step::Pause(duration)) => self.pause = Some(Box::pin(time::sleep(duration))),
+                Some(Step::Error(kind)) => return Poll::Ready(Err(io::Error::new(kind, "scripted read error"))),
+                Some(Step::Disconnect) => return Poll::Ready(Ok(())),
+                None => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for MockPort {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let mut state = self.device.state();
+        if let Some(kind) = state.write_errors.pop_front() {
+            return Poll::Ready(Err(io::Error::new(kind, "injected write error")));
+        }
+        if state.held {
+            state.writer = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        state.written.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Transport for MockPort {
+    fn control(&mut self, control: Control) -> io::Result<()> {
+        let mut state = self.device.state();
+        if state.reject_controls {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "the mock driver rejects line settings"));
+        }
+        state.controls.push(control);
+        Ok(())
+    }
+
+    fn line_errors(&self) -> Option<LineErrors> {
+        self.device.state().line_errors
+    }
+}
@@ -0,0 +1,252 @@
+//! Modbus RTU master polling registers into the watch panel.
+//!
+//! Each `--modbus-poll NAME=SLAVE:FUNCTION:ADDRESS[:COUNT][@INTERVAL]` reads
+//! coils (function 1), discrete inputs (2), holding registers (3) or input
+//! registers (4) from a slave on the first port every interval, one request
+//! at a time as the bus requires. The values read become watch `NAME`, with
+//! its min/max/avg and `--alert` rules like any other watch, so no separate
+//! modpoll process has to share the port. Slaves that stop answering, answer
+//! with an exception or with a corrupted frame are noted in the output, once
+//! until they answer again.
+
+use std::time::Duration;
+
+use ratatui::style::{Color, Style};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    time::{self, Instant},
+};
+
+use crate::{output::OutputLine, ping, port::PortHandle};
+
+#[derive(Debug, Clone)]
+pub struct Poll {
+    pub name: String,
+    slave: u8,
+    function: u8,
+    address: u16,
+    count: u16,
+    interval: Duration,
+}
+
+/// Parses `NAME=SLAVE:FUNCTION:ADDRESS[:COUNT][@INTERVAL]`, e.g.
+/// `temp=1:3:100@500ms`; the address may be given in hex as `0x64`.
+pub fn parse_poll(spec: &str) -> Result<Poll, String> {
+    let invalid = || {
+        format!(
+            "Invalid Modbus poll {spec:?}. Expected NAME=SLAVE:FUNCTION:ADDRESS[:COUNT][@INTERVAL], \
+             e.g. temp=1:3:100@500ms"
+        )
+    };
+    let (name, request) = spec.split_once('=').filter(|(name, _)| !name.is_empty()).ok_or_else(invalid)?;
+    let (request, interval) = match request.split_once('@') {
+        Some((request, interval)) => (request, ping::parse_interval(interval).ok_or_else(invalid)?),
+        None => (request, Duration::from_secs(1)),
+    };
+    let fields: Vec<&str> = request.split(':').collect();
+    let number = |text: &str| match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    };
+    let (slave, function, address, count) = match fields.as_slice() {
+        [slave, function, address] => (slave, function, address, "1"),
+        [slave, function, address, count] => (slave, function, address, *count),
+        _ => return Err(invalid()),
+    };
+    let slave = slave.parse::<u8>().ok().filter(|slave| (1..=247).contains(slave)).ok_or_else(invalid)?;
+    let function = function.parse::<u8>().ok().filter(|f| (1..=4).contains(f)).ok_or_else(|| {
+        format!(
+            "Invalid Modbus poll {spec:?}: function {function} is none of 1 (coils), 2 (discrete inputs), \
+             3 (holding registers) or 4 (input registers)"
+        )
+    })?;
+    let address = number(address).ok_or_else(invalid)?;
+    // The most a response frame can hold
+    let most = if function <= 2 { 2000 } else { 125 };
+    let count = number(count).filter(|count| (1..=most).contains(count)).ok_or_else(invalid)?;
+    Ok(Poll {
+        name: name.to_string(),
+        slave,
+        function,
+        address,
+        count,
+        interval,
+    })
+}
+
+/// CRC-16/MODBUS of `data`, sent low byte first.
+fn crc(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF_u16;
+    for &byte in data {
+        crc ^= u16::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+fn exception(code: u8) -> &'static str {
+    match code {
+        1 => "illegal function",
+        2 => "illegal data address",
+        3 => "illegal data value",
+        4 => "slave device failure",
+        5 => "acknowledge",
+        6 => "slave device busy",
+        0x0B => "gateway target failed to respond",
+        _ => "unknown exception",
+    }
+}
+
+impl Poll {
+    fn request(&self) -> Vec<u8> {
+        let mut frame = vec![self.slave, self.function];
+        frame.extend_from_slice(&self.address.to_be_bytes());
+        frame.extend_from_slice(&self.count.to_be_bytes());
+        frame.extend_from_slice(&crc(&frame).to_le_bytes());
+        frame
+    }
+
+    /// Bytes of a complete answer starting with `frame`, as far as they can
+    /// be told from its header.
+    fn expected(&self, frame: &[u8]) -> Option<usize> {
+        match frame {
+            [_, function, ..] if function & 0x80 != 0 => Some(5),
+            [_, _, bytes, ..] => Some(5 + usize::from(*bytes)),
+            _ => None,
+        }
+    }
+
+    /// The values in an answer, e.g. `215` or `1 0 1`.
+    fn decode(&self, frame: &[u8]) -> Result<String, String> {
+        let (body, check) = frame.split_at(frame.len() - 2);
+        if crc(body).to_le_bytes() != check {
+            return Err(format!("bad CRC in the answer from slave {}", self.slave));
+        }
+        match body {
+            [slave, ..] if *slave != self.slave => Err(format!("answer from slave {slave} instead of {}", self.slave)),
+            [_, function, code] if *function == self.function | 0x80 => {
+                Err(format!("slave {} answered exception {code} ({})", self.slave, exception(*code)))
+            }
+            [_, function, _, data @ ..] if *function == self.function => {
+                let values: Vec<String> = match self.function {
+                    1 | 2 => (0..usize::from(self.count))
+                        .map(|i| data.get(i / 8).map_or(0, |byte| (byte >> (i % 8)) & 1).to_string())
+                        .collect(),
+                    _ => data.chunks_exact(2).map(|word| u16::from_be_bytes([word[0], word[1]]).to_string()).collect(),
+                };
+                Ok(values.join(" "))
+            }
+            _ => Err(format!("unexpected answer from slave {}", self.slave)),
+        }
+    }
+}
+
+/// Starts polling through `port`, whose received bytes are subscribed to in
+/// `received`. Values go to `values` as `(NAME, VALUE)`.
+pub fn start(
+    polls: Vec<Poll>,
+    port: PortHandle,
+    received: broadcast::Receiver<Vec<u8>>,
+    timeout: Duration,
+    values: mpsc::UnboundedSender<(String, String)>,
+    annotations: mpsc::UnboundedSender<OutputLine>,
+) {
+    let master = Master {
+        due: vec![Instant::now(); polls.len()],
+        failing: vec![None; polls.len()],
+        polls,
+        port,
+        timeout,
+        values,
+        annotations,
+    };
+    tokio::spawn(master.run(received));
+}
+
+struct Master {
+    polls: Vec<Poll>,
+    /// When each poll is next sent.
+    due: Vec<Instant>,
+    /// The last failure of each poll, noted once until it succeeds again.
+    failing: Vec<Option<String>>,
+    port: PortHandle,
+    timeout: Duration,
+    values: mpsc::UnboundedSender<(String, String)>,
+    annotations: mpsc::UnboundedSender<OutputLine>,
+}
+
+impl Master {
+    async fn run(mut self, mut received: broadcast::Receiver<Vec<u8>>) {
+        loop {
+            let Some((next, &due)) = self.due.iter().enumerate().min_by_key(|(_, due)| **due) else {
+                return;
+            };
+            time::sleep_until(due).await;
+            // A poll that fell behind is sent again as soon as it can be
+            self.due[next] = (due + self.polls[next].interval).max(Instant::now());
+            // Whatever arrived since the last answer isn't part of the next one
+            loop {
+                match received.try_recv() {
+                    Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => {}
+                    Err(broadcast::error::TryRecvError::Closed) => return,
+                    Err(broadcast::error::TryRecvError::Empty) => break,
+                }
+            }
+            let poll = &self.polls[next];
+            self.port.write(poll.request());
+            let result = match self.answer(&mut received, next).await {
+                Some(frame) => poll.decode(&frame),
+                None => Err(format!("no answer from slave {} within {} ms", poll.slave, self.timeout.as_millis())),
+            };
+            self.report(next, result);
+        }
+    }
+
+    /// Bytes received until the answer to poll `index` is complete, `None`
+    /// if it isn't before the timeout.
+    async fn answer(&self, received: &mut broadcast::Receiver<Vec<u8>>, index: usize) -> Option<Vec<u8>> {
+        let poll = &self.polls[index];
+        let deadline = Instant::now() + self.timeout;
+        let mut frame = Vec::new();
+        loop {
+            if let Some(length) = poll.expected(&frame)
+                && frame.len() >= length
+            {
+                frame.truncate(length);
+                return Some(frame);
+            }
+            match time::timeout_at(deadline, received.recv()).await {
+                Ok(Ok(data)) => frame.extend_from_slice(&data),
+                Ok(Err(RecvError::Lagged(_))) => {}
+                Ok(Err(RecvError::Closed)) | Err(_) => return None,
+            }
+        }
+    }
+
+    fn report(&mut self, index: usize, result: Result<String, String>) {
+        let name = &self.polls[index].name;
+        match result {
+            Ok(value) => {
+                if self.failing[index].take().is_some() {
+                    self.note(format!("{name}: answering again"), Color::Green);
+                }
+                let _ = self.values.send((name.clone(), value));
+            }
+            Err(error) => {
+                if self.failing[index].as_ref() != Some(&error) {
+                    self.note(format!("{name}: {error}"), Color::Red);
+                    self.failing[index] = Some(error);
+                }
+            }
+        }
+    }
+
+    fn note(&self, text: String, color: Color) {
+        let _ = self.annotations.send(OutputLine::new("[Modbus] ", text, Style::default().fg(color)));
+    }
+}
@@ -0,0 +1,45 @@
+//! Whether keystrokes edit the input or navigate the output.
+//!
+//! In INPUT mode (the initial one) keys edit and send the input line. Tab
+//! switches to FOLLOW, where the output keeps showing the newest lines and
+//! plain keys navigate instead of typing; moving up (`k`, Up, PageUp) enters
+//! INSPECT, where a selected line stays put while new lines arrive below it.
+//! Esc or `G` leaves INSPECT, and Tab or `i` goes back to typing. The mode is
+//! shown at the right of the input box.
+
+use ratatui::style::{Color, Style};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiMode {
+    Input,
+    Follow,
+    Inspect,
+}
+
+impl UiMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            UiMode::Input => " INPUT ",
+            UiMode::Follow => " FOLLOW ",
+            UiMode::Inspect => " INSPECT ",
+        }
+    }
+
+    pub fn style(self) -> Style {
+        let color = match self {
+            UiMode::Input => Color::Yellow,
+            UiMode::Follow => Color::Green,
+            UiMode::Inspect => Color::Magenta,
+        };
+        Style::default().fg(Color::Black).bg(color)
+    }
+
+    /// Key hints shown next to the mode.
+    pub fn hint(self) -> &'static str {
+        match self {
+            UiMode::Input => " Tab: navigate ",
+            UiMode::Follow => " k/PgUp: inspect  i/Tab: type ",
+            UiMode::Inspect => " j/k g/G  Enter: details  Esc: back ",
+        }
+    }
+}
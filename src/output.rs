@@ -0,0 +1,503 @@
+//! Scrollback entries and their expansion into display lines.
+//!
+//! Entries keep the raw text; styling and wrapping happen each time they are
+//! drawn, so a resize or a change of view applies to the whole scrollback.
+
+use std::borrow::Cow;
+
+use chrono::{DateTime, Local};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Paragraph, Wrap},
+};
+use serde::Deserialize;
+
+use crate::{conversation::Exchange, framing, level::Level};
+
+/// Which entries an output pane shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pane {
+    All,
+    /// Lines sent to a device.
+    Sent,
+    /// Everything else.
+    Received,
+}
+
+/// Display settings that change how entries are expanded.
+#[derive(Clone, Copy)]
+pub struct View {
+    pub pane: Pane,
+    pub pretty_json: bool,
+    /// Whether exchanges can be collapsed to their command line.
+    pub conversation: bool,
+    /// Whether control characters and line endings are shown as symbols.
+    pub reveal: bool,
+    /// Entries with a lower log level are hidden.
+    pub min_level: Level,
+    /// Width the output pane wraps lines at; 0 until the first draw.
+    pub width: u16,
+    /// Rows of the output pane; 0 until the first draw.
+    pub height: u16,
+    /// Entry highlighted in INSPECT mode.
+    pub selected: Option<usize>,
+    /// Bus device whose frames alone are shown (`:bus NAME`).
+    pub device: Option<&'static str>,
+}
+
+/// One entry of the output pane.
+pub struct OutputLine {
+    /// Source tag such as `[Arduino] ` or `> `.
+    pub prefix: &'static str,
+    pub body: String,
+    /// Style of monitor messages; received lines are styled from their content.
+    style: Option<Style>,
+    /// Color of the prefix when it differs from the body, e.g. a port's color.
+    pub tag_color: Option<Color>,
+    /// Pretty-printed form of `body` when it is a JSON document.
+    pub json: Option<String>,
+    /// Whether a JSON entry is shown collapsed while pretty-printing is on.
+    pub folded: bool,
+    /// Log level recognized in a received line.
+    pub level: Option<Level>,
+    /// Command/response exchange the entry is part of.
+    pub exchange: Option<Exchange>,
+    /// Bus device a frame was sent to or received from (`--bus-address`).
+    pub device: Option<&'static str>,
+    /// Whether the entry's exchange is collapsed in conversation mode.
+    pub collapsed: bool,
+    /// Lines received in response, counted on the command entry.
+    pub replies: usize,
+    /// Identical lines received right after this one (`--dedup`).
+    pub repeated: usize,
+    /// Lines like this one held back by `--throttle` since the last shown.
+    pub suppressed: usize,
+    /// Terminator the line was received with, e.g. `"\r\n"`.
+    pub ending: &'static str,
+    /// The line `--expect` expected instead of this one.
+    pub expected: Option<String>,
+    /// How a received line came about, shown by the details popup.
+    pub details: Option<Box<Details>>,
+}
+
+/// What is known about a received line beyond its text.
+pub struct Details {
+    pub port: String,
+    pub time: DateTime<Local>,
+    /// The bytes the line was decoded from, terminator included.
+    pub raw: Vec<u8>,
+    /// How the bytes were decoded, e.g. `UTF-8 text`.
+    pub decoded: Cow<'static, str>,
+    /// Rules that matched the line, e.g. `--watch temp`.
+    pub matched: Vec<String>,
+    /// Whether a `--mask` pattern matched, which keeps the bytes hidden.
+    pub masked: bool,
+}
+
+impl OutputLine {
+    pub fn new(prefix: &'static str, body: String, style: Style) -> Self {
+        let json = pretty_json(&body);
+        Self {
+            prefix,
+            body,
+            style: Some(style),
+            tag_color: None,
+            json,
+            folded: false,
+            level: None,
+            exchange: None,
+            device: None,
+            collapsed: false,
+            replies: 0,
+            repeated: 0,
+            suppressed: 0,
+            ending: "",
+            expected: None,
+            details: None,
+        }
+    }
+
+    /// A line received from a device, at `level` if one was recognized.
+    pub fn received(prefix: &'static str, body: String, level: Option<Level>) -> Self {
+        Self {
+            style: None,
+            level,
+            ..Self::new(prefix, body, Style::default())
+        }
+    }
+
+    pub fn in_exchange(mut self, exchange: Option<Exchange>) -> Self {
+        self.exchange = exchange;
+        self
+    }
+
+    pub fn on_device(mut self, device: Option<&'static str>) -> Self {
+        self.device = device;
+        self
+    }
+
+    pub fn with_tag_color(mut self, color: Option<Color>) -> Self {
+        self.tag_color = color;
+        self
+    }
+
+    /// Whether `next` is the same received line again.
+    pub fn repeats(&self, next: &OutputLine) -> bool {
+        self.style.is_none()
+            && next.style.is_none()
+            && self.prefix == next.prefix
+            && self.body == next.body
+            && self.exchange == next.exchange
+    }
+
+    /// Whether the entry is a line received from a device, rather than
+    /// one sent or a message of the monitor.
+    pub fn is_received(&self) -> bool {
+        self.style.is_none()
+    }
+
+    /// Whether the entry is a line sent to a device.
+    pub fn is_sent(&self) -> bool {
+        self.prefix.starts_with("> ")
+    }
+
+    pub fn style(&self) -> Style {
+        self.style.unwrap_or_else(|| match self.level {
+            _ if self.expected.is_some() => Style::default().fg(Color::White).bg(Color::Red),
+            Some(level) => level.style(),
+            None if self.body.contains("ERROR") => Style::default().fg(Color::Red),
+            None => Style::default().fg(Color::Green),
+        })
+    }
+
+    pub fn is_hidden(&self, view: &View) -> bool {
+        let sent = self.is_sent();
+        let other_pane = match view.pane {
+            Pane::All => false,
+            Pane::Sent => !sent,
+            Pane::Received => sent,
+        };
+        let collapsed = view.conversation && self.collapsed && self.exchange.is_some_and(|x| !x.command);
+        // Monitor messages stay when the frames of one bus device are shown
+        let other_device = view.device.is_some_and(|device| (sent || self.style.is_none()) && self.device != Some(device));
+        other_pane || collapsed || other_device || self.level.is_some_and(|level| level < view.min_level)
+    }
+
+    /// Approximate memory held by the entry, for the scrollback budget.
+    pub fn size(&self) -> usize {
+        let details = self.details.as_ref().map_or(0, |details| {
+            std::mem::size_of::<Details>()
+                + details.port.len()
+                + details.raw.len()
+                + details.decoded.len()
+                + details.matched.iter().map(String::len).sum::<usize>()
+        });
+        let expected = self.expected.as_ref().map_or(0, String::len);
+        std::mem::size_of::<Self>() + self.body.len() + self.json.as_ref().map_or(0, String::len) + expected + details
+    }
+
+    /// The text of the details popup, `None` for lines not received from
+    /// a device.
+    pub fn details_text(&self) -> Option<String> {
+        let details = self.details.as_ref()?;
+        let terminator = match self.ending {
+            "" => "none".to_string(),
+            ending => reveal(ending),
+        };
+        let fields = [
+            ("Port", details.port.clone()),
+            ("Received", details.time.format("%Y-%m-%d %H:%M:%S%.6f %:z").to_string()),
+            ("Length", format!("{} bytes", details.raw.len())),
+            ("Terminator", terminator),
+            ("Decoded", details.decoded.to_string()),
+            ("Level", self.level.map_or("none recognized", Level::as_str).to_string()),
+        ];
+        let mut text: String = fields.iter().map(|(name, value)| format!("{:<12}{value}\n", format!("{name}:"))).collect();
+        if self.repeated > 0 {
+            text.push_str(&format!("{:<12}{} more identical lines (--dedup)\n", "Repeats:", self.repeated));
+        }
+        if let Some(expected) = &self.expected {
+            text.push_str(&format!("{:<12}{expected} (--expect)\n", "Expected:"));
+        }
+        text.push_str("\nMatched:\n");
+        if details.matched.is_empty() {
+            text.push_str("  no rules\n");
+        }
+        for rule in &details.matched {
+            text.push_str(&format!("  {rule}\n"));
+        }
+        text.push_str("\nBytes:\n");
+        if details.masked {
+            text.push_str("  hidden because a --mask pattern matched\n");
+        } else if details.raw.is_empty() {
+            text.push_str("  none; the frame was decoded while framing\n");
+        }
+        for (row, chunk) in details.raw.chunks(16).enumerate().filter(|_| !details.masked) {
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            text.push_str(&format!("  {:04X}  {:<47}  {ascii}\n", row * 16, framing::hex(chunk)));
+        }
+        Some(text)
+    }
+
+    /// Number of screen rows this entry occupies once wrapped.
+    pub fn height(&self, view: &View) -> usize {
+        let mut lines = Vec::new();
+        self.push_lines(view, &mut lines);
+        lines.iter().map(|line| wrapped_rows(line, view.width)).sum()
+    }
+
+    fn expanded_json(&self, pretty: bool) -> Option<&str> {
+        self.json.as_deref().filter(|_| pretty && !self.folded)
+    }
+
+    fn push_lines(&self, view: &View, out: &mut Vec<Line<'static>>) {
+        if self.is_hidden(view) {
+            return;
+        }
+        let pretty = view.pretty_json;
+        let style = self.style();
+        match self.expanded_json(pretty) {
+            Some(json) => {
+                let indent = " ".repeat(self.prefix.len());
+                for (i, text) in json.lines().enumerate() {
+                    let lead = if i == 0 { self.prefix_span(style) } else { Span::styled(indent.clone(), style) };
+                    let mut spans = self.gutter(view);
+                    spans.extend([lead, Span::styled(text.to_string(), style)]);
+                    out.push(Line::from(spans));
+                }
+            }
+            None => {
+                let mut spans = self.gutter(view);
+                match view.reveal {
+                    true => spans.extend([
+                        self.prefix_span(style),
+                        Span::styled(reveal(&self.body), style),
+                        Span::styled(reveal(self.ending), Style::default().fg(Color::DarkGray)),
+                    ]),
+                    false => spans.extend([self.prefix_span(style), Span::styled(printable(&self.body), style)]),
+                }
+                if pretty && self.json.is_some() {
+                    spans.push(Span::styled(" [+]", Style::default().fg(Color::DarkGray)));
+                }
+                if self.repeated > 0 {
+                    spans.push(Span::styled(format!(" ×{}", self.repeated + 1), Style::default().fg(Color::DarkGray)));
+                }
+                if self.suppressed > 0 {
+                    spans.push(Span::styled(format!(" ({} suppressed)", self.suppressed), Style::default().fg(Color::DarkGray)));
+                }
+                if let Some(expected) = &self.expected {
+                    spans.push(Span::styled(format!(" ≠ {}", printable(expected)), Style::default().fg(Color::DarkGray)));
+                }
+                if let Some(marker) = self.exchange_marker(view) {
+                    spans.push(Span::styled(marker, Style::default().fg(Color::DarkGray)));
+                }
+                out.push(Line::from(spans));
+            }
+        }
+    }
+
+    /// Bar in front of replies that ties them to their command in conversation mode.
+    fn gutter(&self, view: &View) -> Vec<Span<'static>> {
+        match self.exchange {
+            Some(exchange) if view.conversation && !exchange.command => {
+                vec![Span::styled("│ ", Style::default().fg(Color::DarkGray))]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Fold marker of a command line in conversation mode.
+    fn exchange_marker(&self, view: &View) -> Option<String> {
+        self.exchange.filter(|x| view.conversation && x.command && self.replies > 0)?;
+        Some(match (self.collapsed, self.replies) {
+            (true, 1) => " [+1 line]".to_string(),
+            (true, n) => format!(" [+{n} lines]"),
+            (false, _) => " [-]".to_string(),
+        })
+    }
+
+    fn prefix_span(&self, style: Style) -> Span<'static> {
+        match self.tag_color {
+            Some(color) => Span::styled(self.prefix, Style::default().fg(color)),
+            None => Span::styled(self.prefix, style),
+        }
+    }
+}
+
+/// `text` without the control characters that would garble the screen if
+/// written to the terminal, such as the escape starting a color sequence.
+/// Tabs are expanded to every 8 columns.
+fn printable(text: &str) -> String {
+    let mut shown = String::with_capacity(text.len());
+    let mut column = 0;
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let spaces = 8 - column % 8;
+                shown.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            c if c.is_control() => {}
+            c => {
+                shown.push(c);
+                column += 1;
+            }
+        }
+    }
+    shown
+}
+
+/// `text` with control characters as visible symbols: `␉` for a tab, `␍␊`
+/// for CR LF and `<0x1B>` for escape, which usually starts a sequence.
+fn reveal(text: &str) -> String {
+    let mut shown = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\x1b' => shown.push_str("<0x1B>"),
+            '\0'..='\x1f' => shown.extend(char::from_u32(0x2400 + c as u32)),
+            '\x7f' => shown.push('␡'),
+            c if c.is_control() => shown.push_str(&format!("<0x{:02X}>", c as u32)),
+            c => shown.push(c),
+        }
+    }
+    shown
+}
+
+/// Returns `text` pretty-printed if it is a JSON object or array.
+fn pretty_json(text: &str) -> Option<String> {
+    let trimmed = text.trim();
+    if !(trimmed.starts_with('{') || trimmed.starts_with('[')) {
+        return None;
+    }
+    let value: serde_json::Value = serde_json::from_str(trimmed).ok()?;
+    serde_json::to_string_pretty(&value).ok()
+}
+
+/// Rows `line` takes when word-wrapped like the output pane does.
+fn wrapped_rows(line: &Line<'static>, width: u16) -> usize {
+    if width == 0 {
+        return 1;
+    }
+    Paragraph::new(line.clone()).wrap(Wrap { trim: false }).line_count(width).max(1)
+}
+
+/// Builds the display lines for a view ending `scroll_offset` rows above the
+/// bottom, expanding entries from the tail until `height + scroll_offset`
+/// rows are covered. Returns the lines and the number of wrapped rows to
+/// scroll past at their top.
+pub fn visible_lines(
+    entries: &[OutputLine],
+    view: &View,
+    height: usize,
+    scroll_offset: usize,
+) -> (Vec<Line<'static>>, u16) {
+    let wanted = height + scroll_offset;
+    let mut taken = 0;
+    let mut first = entries.len();
+    while first > 0 && taken < wanted {
+        first -= 1;
+        taken += entries[first].height(view);
+    }
+    let mut lines = Vec::with_capacity(taken);
+    for (index, entry) in entries.iter().enumerate().skip(first) {
+        let start = lines.len();
+        entry.push_lines(view, &mut lines);
+        if view.selected == Some(index) {
+            for line in &mut lines[start..] {
+                line.style = Style::default().add_modifier(Modifier::REVERSED);
+            }
+        }
+    }
+    // Rows below the view are not drawn since the paragraph is cut off at the bottom
+    let skip = taken.saturating_sub(wanted);
+    (lines, skip.min(u16::MAX as usize) as u16)
+}
+
+/// Total number of wrapped rows across all entries.
+pub fn total_height(entries: &[OutputLine], view: &View) -> usize {
+    entries.iter().map(|e| e.height(view)).sum()
+}
+
+/// Index of the nearest JSON entry at or above the bottom of the view.
+pub fn json_entry_at_bottom(entries: &[OutputLine], view: &View, scroll_offset: usize) -> Option<usize> {
+    entry_at_bottom(entries, view, scroll_offset, |e| e.json.is_some())
+}
+
+/// Exchange of the nearest entry in one at or above the bottom of the view.
+pub fn exchange_at_bottom(entries: &[OutputLine], view: &View, scroll_offset: usize) -> Option<u64> {
+    let index = entry_at_bottom(entries, view, scroll_offset, |e| e.exchange.is_some())?;
+    entries[index].exchange.map(|x| x.id)
+}
+
+/// Index of the shown entry at or above the bottom of the view.
+pub fn shown_entry_at_bottom(entries: &[OutputLine], view: &View, scroll_offset: usize) -> Option<usize> {
+    entry_at_bottom(entries, view, scroll_offset, |_| true)
+}
+
+/// Index of the nearest received entry at or above the bottom of the view.
+pub fn received_entry_at_bottom(entries: &[OutputLine], view: &View, scroll_offset: usize) -> Option<usize> {
+    entry_at_bottom(entries, view, scroll_offset, |e| e.details.is_some())
+}
+
+/// The shown entry `count` entries after (or, if negative, before) `index`,
+/// stopping at the first and last.
+pub fn step(entries: &[OutputLine], view: &View, index: usize, count: isize) -> usize {
+    let shown = |i: &usize| entries.get(*i).is_some_and(|e| !e.is_hidden(view));
+    let mut index = index.min(entries.len().saturating_sub(1));
+    for _ in 0..count.unsigned_abs() {
+        let next = match count < 0 {
+            true => (0..index).rev().find(shown),
+            false => (index + 1..entries.len()).find(shown),
+        };
+        match next {
+            Some(next) => index = next,
+            None => break,
+        }
+    }
+    index
+}
+
+/// The scroll offset closest to `scroll_offset` that shows all of entry
+/// `index` (or its top, if taller than the pane).
+pub fn scroll_to(entries: &[OutputLine], view: &View, index: usize, scroll_offset: usize) -> usize {
+    let below: usize = entries.iter().skip(index + 1).map(|e| e.height(view)).sum();
+    let rows = entries.get(index).map_or(0, |e| e.height(view));
+    let height = (view.height as usize).max(1);
+    if below < scroll_offset {
+        below
+    } else if below + rows > scroll_offset + height {
+        (below + rows).saturating_sub(height)
+    } else {
+        scroll_offset
+    }
+}
+
+/// Rows taken by the entries from `index` on, e.g. those added since.
+pub fn rows_from(entries: &[OutputLine], view: &View, index: usize) -> usize {
+    entries.iter().skip(index).map(|e| e.height(view)).sum()
+}
+
+fn entry_at_bottom(
+    entries: &[OutputLine],
+    view: &View,
+    scroll_offset: usize,
+    wanted: impl Fn(&OutputLine) -> bool,
+) -> Option<usize> {
+    let mut skipped = 0;
+    let mut index = entries.len();
+    while index > 0 {
+        index -= 1;
+        skipped += entries[index].height(view);
+        if skipped > scroll_offset {
+            break;
+        }
+    }
+    (0..=index.min(entries.len().saturating_sub(1)))
+        .rev()
+        .find(|&i| entries.get(i).is_some_and(|e| wanted(e) && !e.is_hidden(view)))
+}
@@ -0,0 +1,141 @@
+//! Pasted text, and the confirmation of large pastes.
+//!
+//! Bracketed paste hands pasted text over in one piece rather than as
+//! keystrokes. A single line goes into the input at the cursor; more lines
+//! are sent like typed ones. A paste of more than `--paste-lines` lines or
+//! `--paste-bytes` bytes (most likely a misplaced Ctrl+V) is previewed first
+//! and sent only once a way of sending it is chosen: all lines at once,
+//! paced at one line per `--paste-delay`, or as a file transfer of the raw
+//! bytes with no line endings added.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crossterm::event::KeyCode;
+use ratatui::{
+    Frame,
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+use crate::scrollback::format_size;
+
+/// Lines of a paste shown in its confirmation.
+const PREVIEW_LINES: usize = 8;
+
+/// The pasted text with line breaks as `\n`; terminals send `\r` for them.
+pub fn normalize(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Whether `text` is large enough to be confirmed before sending.
+pub fn needs_confirmation(text: &str, max_lines: usize, max_bytes: usize) -> bool {
+    text.lines().count() > max_lines || text.len() > max_bytes
+}
+
+/// How a confirmed paste is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Every line at once, each with the line ending.
+    AsIs,
+    /// One line per `--paste-delay`.
+    Paced,
+    /// The bytes as pasted, in one write.
+    Raw,
+}
+
+/// A large paste waiting for confirmation.
+pub struct Pending {
+    pub text: String,
+    delay: Duration,
+}
+
+impl Pending {
+    pub fn new(text: String, delay: Duration) -> Self {
+        Self { text, delay }
+    }
+
+    /// What a key in the confirmation chooses: `Some(None)` cancels the
+    /// paste, `None` is a key without meaning here.
+    pub fn choice(code: KeyCode) -> Option<Option<Strategy>> {
+        match code {
+            KeyCode::Enter | KeyCode::Char('a') => Some(Some(Strategy::AsIs)),
+            KeyCode::Char('p') => Some(Some(Strategy::Paced)),
+            KeyCode::Char('f') => Some(Some(Strategy::Raw)),
+            KeyCode::Esc | KeyCode::Char('n') => Some(None),
+            _ => None,
+        }
+    }
+
+    pub fn render(&self, f: &mut Frame, area: Rect, port: &str) {
+        let count = self.text.lines().count();
+        let dim = Style::default().fg(Color::DarkGray);
+        let mut lines: Vec<Line> = self.text.lines().take(PREVIEW_LINES).map(Line::from).collect();
+        if count > PREVIEW_LINES {
+            lines.push(Line::styled(format!("... {} more lines", count - PREVIEW_LINES), dim));
+        }
+        lines.push(Line::default());
+        let key = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+        for (keys, what) in [
+            ("Enter/a", "send the lines as is".to_string()),
+            ("p", format!("send paced, one line every {} ms", self.delay.as_millis())),
+            ("f", "send as a file transfer: the raw bytes, no line endings".to_string()),
+            ("Esc/n", "cancel".to_string()),
+        ] {
+            lines.push(Line::from(vec![Span::styled(format!("{keys:>8}  "), key), Span::raw(what)]));
+        }
+
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let [popup] = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center).areas(area);
+        let [popup] = Layout::horizontal([Constraint::Percentage(80)]).flex(Flex::Center).areas(popup);
+        f.render_widget(Clear, popup);
+        let title = format!(" Paste {count} lines ({}) to {port}? ", format_size(self.text.len()));
+        f.render_widget(
+            Paragraph::new(lines).block(
+                Block::default()
+                    .title(title)
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::Yellow)),
+            ),
+            popup,
+        );
+    }
+}
+
+/// Lines of a paste being sent, one per interval.
+pub struct Pacer {
+    lines: VecDeque<String>,
+    interval: Duration,
+    next: Instant,
+}
+
+impl Pacer {
+    pub fn new(text: &str, interval: Duration) -> Self {
+        Self {
+            lines: text.lines().map(str::to_string).collect(),
+            interval,
+            next: Instant::now(),
+        }
+    }
+
+    /// The lines due to be sent at `now`: all of them without an interval.
+    pub fn due(&mut self, now: Instant) -> Vec<String> {
+        if self.interval.is_zero() {
+            return self.lines.drain(..).collect();
+        }
+        if now < self.next {
+            return Vec::new();
+        }
+        self.next = now + self.interval;
+        self.lines.pop_front().into_iter().collect()
+    }
+
+    /// Lines not sent yet.
+    pub fn remaining(&self) -> usize {
+        self.lines.len()
+    }
+}
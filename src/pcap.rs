@@ -0,0 +1,191 @@
+//! pcapng export of capture files, for Wireshark's protocol dissectors.
+//!
+//! Each port of the capture becomes an interface named after it, and each
+//! chunk a packet flagged inbound (RX) or outbound (TX). Serial protocols
+//! don't frame themselves on the wire, so consecutive chunks in the same
+//! direction can be joined into one packet when less than `--gap` apart,
+//! e.g. to keep Modbus RTU frames whole.
+//!
+//! The `user` link type (DLT_USER0) carries the bytes as they are; pick the
+//! dissector under Preferences > Protocols > DLT_USER. `rtac-serial` puts
+//! the SEL RTAC serial header in front, which Wireshark dissects on its own
+//! and hands to the upper layer protocol chosen in its preferences (Modbus
+//! RTU, DNP3, ...).
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+use crate::capture::{Kind, Reader, Record};
+
+const SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION: u32 = 1;
+const ENHANCED_PACKET: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_END: u16 = 0;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_DESCRIPTION: u16 = 3;
+const OPT_EPB_FLAGS: u16 = 2;
+const FLAG_INBOUND: u32 = 1;
+const FLAG_OUTBOUND: u32 = 2;
+
+/// RTAC serial event types of received and sent data.
+const RTAC_TX_START: u8 = 0x01;
+const RTAC_RX_START: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LinkType {
+    /// DLT_USER0 (147): the bytes as they are
+    User,
+    /// LINKTYPE_RTAC_SERIAL (250): a 12-byte RTAC serial header before the bytes
+    RtacSerial,
+}
+
+impl LinkType {
+    fn code(self) -> u16 {
+        match self {
+            LinkType::User => 147,
+            LinkType::RtacSerial => 250,
+        }
+    }
+}
+
+/// Writes the capture at `path` to `output` (`-` for stdout) as pcapng,
+/// joining chunks less than `gap` microseconds apart. Returns the number
+/// of packets written.
+pub fn export(path: &str, output: &str, link: LinkType, gap: Option<i64>) -> Result<usize> {
+    let mut reader = Reader::open(path)?;
+    let names: Vec<String> = match reader.header["ports"].as_array() {
+        Some(ports) if !ports.is_empty() => ports.iter().map(|p| p.as_str().unwrap_or("capture").to_string()).collect(),
+        _ => vec!["capture".to_string()],
+    };
+    let out: Box<dyn Write> = match output {
+        "-" => Box::new(io::stdout().lock()),
+        _ => Box::new(File::create(output).with_context(|| format!("Cannot create {output}"))?),
+    };
+    let mut out = BufWriter::new(out);
+
+    let mut body = Vec::new();
+    body.extend(BYTE_ORDER_MAGIC.to_le_bytes());
+    body.extend(1u16.to_le_bytes());
+    body.extend(0u16.to_le_bytes());
+    // Section length not given
+    body.extend((-1i64).to_le_bytes());
+    option(&mut body, OPT_SHB_USERAPPL, concat!("serial-monitor ", env!("CARGO_PKG_VERSION")).as_bytes());
+    option(&mut body, OPT_END, &[]);
+    block(&mut out, SECTION_HEADER, &body)?;
+    // Line settings, e.g. "57600 baud 8N1"
+    let header = &reader.header;
+    let settings = header["baud_rate"].as_u64().map(|baud| {
+        let parity = header["parity"].as_str().and_then(|p| p.chars().next()).unwrap_or('n');
+        format!("{baud} baud {}{}{}", header["data_bits"], parity.to_ascii_uppercase(), header["stop_bits"])
+    });
+    for name in &names {
+        body.clear();
+        body.extend(link.code().to_le_bytes());
+        body.extend(0u16.to_le_bytes());
+        // No snapshot length limit
+        body.extend(0u32.to_le_bytes());
+        option(&mut body, OPT_IF_NAME, name.as_bytes());
+        if let Some(settings) = &settings {
+            option(&mut body, OPT_IF_DESCRIPTION, settings.as_bytes());
+        }
+        option(&mut body, OPT_END, &[]);
+        block(&mut out, INTERFACE_DESCRIPTION, &body)?;
+    }
+
+    let mut packets = 0;
+    let mut pending: Option<(Record, i64)> = None;
+    loop {
+        let record = match reader.next_record() {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("{path} is damaged ({e}); exported up to there");
+                None
+            }
+        };
+        let Some(record) = record else {
+            break;
+        };
+        // Ports beyond the header's list (older captures) get the first interface
+        let record = Record {
+            port: if (record.port as usize) < names.len() { record.port } else { 0 },
+            ..record
+        };
+        if let Some((packet, last)) = &mut pending
+            && gap.is_some_and(|gap| record.time - *last < gap)
+            && packet.port == record.port
+            && packet.kind == record.kind
+        {
+            packet.data.extend(&record.data);
+            *last = record.time;
+            continue;
+        }
+        if let Some((packet, _)) = pending.take() {
+            packet_block(&mut out, &packet, link)?;
+            packets += 1;
+        }
+        let time = record.time;
+        pending = Some((record, time));
+    }
+    if let Some((packet, _)) = pending {
+        packet_block(&mut out, &packet, link)?;
+        packets += 1;
+    }
+    out.flush().with_context(|| format!("Cannot write {output}"))?;
+    Ok(packets)
+}
+
+fn packet_block(out: &mut impl Write, packet: &Record, link: LinkType) -> Result<()> {
+    let mut data = Vec::with_capacity(packet.data.len() + 12);
+    if link == LinkType::RtacSerial {
+        let (secs, micros) = (packet.time.div_euclid(1_000_000), packet.time.rem_euclid(1_000_000));
+        data.extend((secs as u32).to_be_bytes());
+        data.extend((micros as u32).to_be_bytes());
+        data.push(if packet.kind == Kind::Tx { RTAC_TX_START } else { RTAC_RX_START });
+        // Control line states and footer aren't captured
+        data.extend([0; 3]);
+    }
+    data.extend(&packet.data);
+
+    let mut body = Vec::with_capacity(data.len() + 32);
+    body.extend(u32::from(packet.port).to_le_bytes());
+    let time = packet.time as u64;
+    body.extend(((time >> 32) as u32).to_le_bytes());
+    body.extend((time as u32).to_le_bytes());
+    body.extend((data.len() as u32).to_le_bytes());
+    body.extend((data.len() as u32).to_le_bytes());
+    body.extend(&data);
+    pad(&mut body);
+    let flags = if packet.kind == Kind::Tx { FLAG_OUTBOUND } else { FLAG_INBOUND };
+    option(&mut body, OPT_EPB_FLAGS, &flags.to_le_bytes());
+    option(&mut body, OPT_END, &[]);
+    block(out, ENHANCED_PACKET, &body)
+}
+
+/// A block: type, total length, the (padded) body and the length again.
+fn block(out: &mut impl Write, kind: u32, body: &[u8]) -> Result<()> {
+    let length = (12 + body.len()) as u32;
+    out.write_all(&kind.to_le_bytes())?;
+    out.write_all(&length.to_le_bytes())?;
+    out.write_all(body)?;
+    out.write_all(&length.to_le_bytes())?;
+    Ok(())
+}
+
+fn option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend(code.to_le_bytes());
+    body.extend((value.len() as u16).to_le_bytes());
+    body.extend(value);
+    pad(body);
+}
+
+fn pad(body: &mut Vec<u8>) {
+    body.resize(body.len().next_multiple_of(4), 0);
+}
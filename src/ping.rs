@@ -0,0 +1,136 @@
+//! Round-trip latency measurement with periodic probes.
+//!
+//! A probe line is sent every interval and the time until a received line
+//! matches the expected response is recorded. `{seq}` in the probe is
+//! replaced with a sequence number, so echoes of late probes aren't mistaken
+//! for answers to the current one.
+
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+pub struct PingSpec {
+    pub interval: Duration,
+    pub probe: String,
+    /// Response pattern; `{seq}` is substituted like in the probe.
+    pub expect: Option<String>,
+}
+
+/// Parses `[INTERVAL] [PROBE] [=> EXPECT]` with defaults `1s` and `ping {seq}`.
+pub fn parse_spec(args: &str) -> PingSpec {
+    let (probe, expect) = match args.split_once("=>") {
+        Some((probe, expect)) => (probe.trim(), Some(expect.trim().to_string())),
+        None => (args.trim(), None),
+    };
+    let (first, rest) = probe.split_once(char::is_whitespace).unwrap_or((probe, ""));
+    let (interval, probe) = match parse_interval(first) {
+        Some(interval) => (interval, rest.trim()),
+        None => (Duration::from_secs(1), probe),
+    };
+    PingSpec {
+        interval,
+        probe: if probe.is_empty() { "ping {seq}".to_string() } else { probe.to_string() },
+        expect: expect.filter(|e| !e.is_empty()),
+    }
+}
+
+/// Parses an interval of at least 10 ms such as `500ms` or `2s`.
+pub fn parse_interval(text: &str) -> Option<Duration> {
+    let seconds = match text.strip_suffix("ms") {
+        Some(ms) => ms.parse::<f64>().ok()? / 1000.0,
+        None => text.strip_suffix('s')?.parse::<f64>().ok()?,
+    };
+    (seconds >= 0.01).then(|| Duration::from_secs_f64(seconds))
+}
+
+struct Outstanding {
+    expect: Regex,
+    sent_at: Instant,
+}
+
+pub struct Pinger {
+    spec: PingSpec,
+    /// Port the probes are sent to.
+    pub port: usize,
+    seq: u64,
+    /// Monotonic, so a change of the wall clock doesn't skew the round
+    /// trips or stop the probes.
+    next_probe: Instant,
+    outstanding: Option<Outstanding>,
+    rtts: Vec<f64>,
+}
+
+impl Pinger {
+    pub fn new(spec: PingSpec, port: usize) -> Self {
+        Self {
+            spec,
+            port,
+            seq: 0,
+            next_probe: Instant::now(),
+            outstanding: None,
+            rtts: Vec::new(),
+        }
+    }
+
+    /// When the next probe is due.
+    pub fn due(&self) -> Instant {
+        self.next_probe
+    }
+
+    /// Returns the next probe to send once it is due; a probe still
+    /// unanswered by then is given up on.
+    pub fn poll(&mut self, now: Instant) -> Option<Vec<u8>> {
+        if now < self.next_probe {
+            return None;
+        }
+        self.seq += 1;
+        let seq = self.seq.to_string();
+        let probe = self.spec.probe.replace("{seq}", &seq);
+        let expect = match &self.spec.expect {
+            Some(pattern) => Regex::new(&pattern.replace("{seq}", &seq)),
+            None => Regex::new(&format!("^{}$", regex::escape(probe.trim()))),
+        };
+        if let Ok(expect) = expect {
+            self.outstanding = Some(Outstanding { expect, sent_at: now });
+        }
+        self.next_probe = now + self.spec.interval;
+        Some(format!("{probe}\n").into_bytes())
+    }
+
+    /// Matches a received line against the outstanding probe and returns a
+    /// report line for an answer.
+    pub fn received(&mut self, line: &str, at: Instant) -> Option<String> {
+        let outstanding = self.outstanding.as_ref()?;
+        if !outstanding.expect.is_match(line.trim()) {
+            return None;
+        }
+        let rtt = at.saturating_duration_since(outstanding.sent_at).as_secs_f64() * 1000.0;
+        self.outstanding = None;
+        self.rtts.push(rtt);
+        Some(format!("seq={} time={rtt:.1} ms  ({})", self.seq, self.statistics()))
+    }
+
+    fn statistics(&self) -> String {
+        if self.rtts.is_empty() {
+            return "no replies".to_string();
+        }
+        let min = self.rtts.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = self.rtts.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let avg = self.rtts.iter().sum::<f64>() / self.rtts.len() as f64;
+        // Mean difference between consecutive round trips
+        let jitter = if self.rtts.len() > 1 {
+            self.rtts.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f64>() / (self.rtts.len() - 1) as f64
+        } else {
+            0.0
+        };
+        format!("min/avg/max/jitter = {min:.1}/{avg:.1}/{max:.1}/{jitter:.1} ms")
+    }
+
+    /// Final report when the ping mode is stopped.
+    pub fn summary(&self) -> String {
+        let sent = self.seq;
+        let received = self.rtts.len() as u64;
+        let loss = if sent > 0 { 100.0 * (sent - received) as f64 / sent as f64 } else { 0.0 };
+        format!("{sent} sent, {received} received, {loss:.0}% loss, {}", self.statistics())
+    }
+}
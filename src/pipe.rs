@@ -0,0 +1,150 @@
+//! Piping scrollback lines through a shell command, e.g. `:pipe 200 | sort | uniq -c`.
+//!
+//! The command runs in the background with the lines on its stdin; its output
+//! is shown in a popup over the output pane.
+
+use std::{process::Stdio, time::Duration};
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+use tokio::{io::AsyncWriteExt, sync::mpsc, time};
+
+use crate::trigger;
+
+/// Commands still running after this long are killed.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Scrollback lines fed to the command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Range {
+    All,
+    /// The most recent lines.
+    Last(usize),
+}
+
+pub struct PipeSpec {
+    pub range: Range,
+    pub command: String,
+}
+
+/// Parses `[all|N] | COMMAND`.
+pub fn parse_spec(args: &str) -> Result<PipeSpec, String> {
+    let usage = || "Usage: :pipe [all|N] | <command>".to_string();
+    let (range, command) = args.split_once('|').ok_or_else(usage)?;
+    let range = match range.trim() {
+        "" | "all" => Range::All,
+        count => Range::Last(count.parse().ok().filter(|&n| n > 0).ok_or_else(usage)?),
+    };
+    let command = command.trim();
+    if command.is_empty() {
+        return Err(usage());
+    }
+    Ok(PipeSpec {
+        range,
+        command: command.to_string(),
+    })
+}
+
+impl Range {
+    /// The lines of `lines` in the range.
+    pub fn select<T>(self, lines: &[T]) -> &[T] {
+        match self {
+            Range::All => lines,
+            Range::Last(count) => &lines[lines.len().saturating_sub(count)..],
+        }
+    }
+}
+
+/// Output of a finished command, or why it failed.
+pub struct PipeResult {
+    pub command: String,
+    pub output: Result<String, String>,
+}
+
+/// Runs `command` with `input` on its stdin and sends the result to `results`.
+pub fn spawn(command: String, input: String, results: mpsc::UnboundedSender<PipeResult>) {
+    tokio::spawn(async move {
+        let output = match time::timeout(TIMEOUT, run(&command, input)).await {
+            Ok(output) => output,
+            Err(_) => Err(format!("Timed out after {}s", TIMEOUT.as_secs())),
+        };
+        let _ = results.send(PipeResult { command, output });
+    });
+}
+
+async fn run(command: &str, input: String) -> Result<String, String> {
+    let mut child = trigger::shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Cannot run {command:?}: {e}"))?;
+    // The input is written while the output is read, or a command writing
+    // more than a pipe holds before it has read everything would never exit
+    let stdin = child.stdin.take();
+    let writing = async move {
+        if let Some(mut stdin) = stdin {
+            // Commands like `head` may exit before reading everything
+            let _ = stdin.write_all(input.as_bytes()).await;
+        }
+    };
+    let ((), output) = tokio::join!(writing, child.wait_with_output());
+    let output = output.map_err(|e| e.to_string())?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    if !output.status.success() {
+        text.push_str(&format!("\n[exited with {}]", output.status));
+    }
+    Ok(text)
+}
+
+/// Command output (or other text, such as a line's details) shown over the
+/// output pane until closed.
+pub struct Popup {
+    title: String,
+    lines: Vec<String>,
+    /// Rows scrolled down from the top.
+    scroll: usize,
+}
+
+impl Popup {
+    pub fn new(result: PipeResult) -> Self {
+        let (title, text) = match result.output {
+            Ok(text) => (format!("| {} (Esc to close)", result.command), text),
+            Err(e) => (format!("| {} failed (Esc to close)", result.command), e),
+        };
+        Self::text(title, &text)
+    }
+
+    pub fn text(title: String, text: &str) -> Self {
+        Self {
+            title,
+            lines: text.lines().map(str::to_string).collect(),
+            scroll: 0,
+        }
+    }
+
+    pub fn scroll(&mut self, rows: isize) {
+        self.scroll = self
+            .scroll
+            .saturating_add_signed(rows)
+            .min(self.lines.len().saturating_sub(1));
+    }
+
+    pub fn render(&self, f: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = self.lines.iter().skip(self.scroll).map(|l| Line::from(l.as_str())).collect();
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines)
+                .style(Style::default().fg(Color::White))
+                .block(Block::default().title(self.title.as_str()).borders(Borders::ALL)),
+            area,
+        );
+    }
+}
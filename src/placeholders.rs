@@ -0,0 +1,95 @@
+//! Placeholders in sent lines, filled in as each line is sent.
+//!
+//! Typed lines, lines composed in the editor and macro lines may contain
+//! `{seq}` (a counter of the lines that used it, from 1), `{unix_time}`,
+//! `{unix_ms}`, `{time}` (RFC 3339) and `{rand_hex:N}` (`N` random hex
+//! digits), e.g. `PUB test/{seq} {rand_hex:8}` for unique test payloads.
+//! Other braces are sent as typed; the line kept in the history and in a
+//! recorded macro is the one with the placeholders.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::Local;
+
+/// Placeholders without an argument.
+const NAMES: [&str; 4] = ["seq", "unix_time", "unix_ms", "time"];
+
+/// Most digits of a `{rand_hex:N}`.
+const MAX_DIGITS: usize = 256;
+
+/// Whether `{name}` is a placeholder, which macros leave to be filled in
+/// when the line is sent.
+pub fn is_placeholder(name: &str) -> bool {
+    NAMES.contains(&name)
+}
+
+pub struct Placeholders {
+    seq: u64,
+    /// Xorshift state of `{rand_hex:N}`; unique payloads need variety, not
+    /// quality.
+    random: u64,
+}
+
+impl Default for Placeholders {
+    fn default() -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64);
+        Self { seq: 0, random: seed | 1 }
+    }
+}
+
+impl Placeholders {
+    /// `text` with its placeholders filled in. The `{seq}`s of one line
+    /// share a number.
+    pub fn expand(&mut self, text: &str) -> String {
+        if !text.contains('{') {
+            return text.to_string();
+        }
+        if text.contains("{seq}") {
+            self.seq += 1;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut expanded = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+            let value = match &rest[1..end] {
+                "seq" => self.seq.to_string(),
+                "unix_time" => now.as_secs().to_string(),
+                "unix_ms" => now.as_millis().to_string(),
+                "time" => Local::now().to_rfc3339(),
+                name => match name
+                    .strip_prefix("rand_hex:")
+                    .and_then(|digits| digits.parse().ok())
+                    .filter(|&digits| digits <= MAX_DIGITS)
+                {
+                    Some(digits) => self.hex(digits),
+                    None => {
+                        expanded.push('{');
+                        rest = &rest[1..];
+                        continue;
+                    }
+                },
+            };
+            expanded.push_str(&value);
+            rest = &rest[end + 1..];
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    fn hex(&mut self, digits: usize) -> String {
+        let mut hex = String::with_capacity(digits);
+        while hex.len() < digits {
+            self.random ^= self.random << 13;
+            self.random ^= self.random >> 7;
+            self.random ^= self.random << 17;
+            hex.push_str(&format!("{:016x}", self.random));
+        }
+        hex.truncate(digits);
+        hex
+    }
+}
@@ -0,0 +1,418 @@
+//! The task that owns the open transport.
+//!
+//! Reading, writing and line setting changes all happen here, so other parts
+//! of the monitor (the UI, the RFC 2217 server) only exchange messages with it.
+
+use std::{
+    any::Any,
+    io,
+    panic::AssertUnwindSafe,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use futures::{FutureExt, future::BoxFuture};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::{broadcast, mpsc, oneshot, watch},
+    time,
+};
+
+use crate::{
+    events::{PortEvent, SerialEvent},
+    framing::{Decoder, Frame, Framer},
+    transport::{Control, LineErrors, SerialSettings, Transport},
+};
+
+/// Number of raw chunks buffered for slow raw subscribers before they lag.
+const RAW_CAPACITY: usize = 1024;
+/// How often the driver's receive error counters are checked.
+const LINE_ERRORS_EVERY: Duration = Duration::from_secs(1);
+
+pub enum PortCommand {
+    /// Sends bytes to the device as-is.
+    Write(Vec<u8>),
+    /// Sends bytes passed on from another port by `--proxy`; they were shown
+    /// as received there, so no `Tx` event repeats them.
+    Forward(Vec<u8>),
+    /// Changes a line setting or control signal and reports the outcome.
+    Control(Control, oneshot::Sender<io::Result<()>>),
+}
+
+/// Traffic totals of a port task.
+#[derive(Default)]
+pub struct PortCounters {
+    pub rx_bytes: AtomicU64,
+    pub tx_bytes: AtomicU64,
+    /// Failed reads and writes.
+    pub io_errors: AtomicU64,
+    /// Receive errors reported by the driver.
+    pub framing_errors: AtomicU64,
+    pub parity_errors: AtomicU64,
+    pub overruns: AtomicU64,
+    pub breaks: AtomicU64,
+}
+
+impl PortCounters {
+    pub fn line_errors(&self) -> LineErrors {
+        LineErrors {
+            framing: self.framing_errors.load(Ordering::Relaxed),
+            parity: self.parity_errors.load(Ordering::Relaxed),
+            overrun: self.overruns.load(Ordering::Relaxed),
+            breaks: self.breaks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Cloneable access to the port task.
+#[derive(Clone)]
+pub struct PortHandle {
+    pub commands: mpsc::UnboundedSender<PortCommand>,
+    pub raw: broadcast::Sender<Vec<u8>>,
+    pub settings: watch::Receiver<SerialSettings>,
+    pub counters: Arc<PortCounters>,
+    /// Set for the taps of `--sniff`, which must never drive the line.
+    listen_only: bool,
+}
+
+impl PortHandle {
+    pub fn write(&self, data: Vec<u8>) {
+        if !self.listen_only {
+            let _ = self.commands.send(PortCommand::Write(data));
+        }
+    }
+
+    pub fn forward(&self, data: Vec<u8>) {
+        let _ = self.commands.send(PortCommand::Forward(data));
+    }
+
+    /// Whether the port task ended, leaving the port closed for good.
+    pub fn is_closed(&self) -> bool {
+        self.commands.is_closed()
+    }
+
+    /// The handle with writes dropped, whoever makes them.
+    pub fn listen_only(self) -> Self {
+        Self {
+            listen_only: true,
+            ..self
+        }
+    }
+
+    pub async fn control(&self, control: Control) -> io::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.commands
+            .send(PortCommand::Control(control, tx))
+            .map_err(|_| io::Error::new(io::ErrorKind::NotConnected, "port closed"))?;
+        rx.await
+            .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::NotConnected, "port closed")))
+    }
+}
+
+/// What the supervisor does when the port task fails or the connection ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RestartPolicy {
+    /// Report the failure and leave the port closed.
+    Never,
+    /// Reopen the port, retrying with increasing delays.
+    OnFailure,
+}
+
+/// Opens the port's transport again with the current line settings.
+pub type Opener = Box<dyn Fn(SerialSettings) -> BoxFuture<'static, anyhow::Result<Box<dyn Transport>>> + Send + Sync>;
+
+/// Recovers a device that doesn't come back, e.g. by power-cycling it;
+/// returns what was done.
+pub type Recover = Box<dyn Fn() -> BoxFuture<'static, anyhow::Result<String>> + Send + Sync>;
+
+pub struct Restart {
+    pub policy: RestartPolicy,
+    pub open: Opener,
+    /// Run after this many failed reopen attempts in a row, and again after
+    /// as many more.
+    pub recover: Option<(u32, Recover)>,
+}
+
+/// Delay before the first reopen attempt; doubled after each failed attempt.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(10);
+
+/// Why the port loop ended.
+enum Exit {
+    /// Every handle was dropped.
+    Closed,
+    Failed(SerialEvent),
+}
+
+/// State of the port task that outlives a transport.
+struct Task {
+    index: usize,
+    framer: Framer,
+    decoder: Decoder,
+    events: mpsc::UnboundedSender<PortEvent>,
+    commands: mpsc::UnboundedReceiver<PortCommand>,
+    raw: broadcast::Sender<Vec<u8>>,
+    settings: watch::Sender<SerialSettings>,
+    counters: Arc<PortCounters>,
+}
+
+impl Task {
+    fn send(&self, event: SerialEvent) {
+        self.send_at(event, Local::now());
+    }
+
+    fn send_at(&self, event: SerialEvent, time: DateTime<Local>) {
+        let _ = self.events.send(PortEvent { port: self.index, time, event });
+    }
+
+    /// Moves data between the transport and the handles until the handles
+    /// are dropped or the transport fails.
+    async fn run(&mut self, transport: &mut Box<dyn Transport>) -> Exit {
+        let mut buf = [0u8; 512];
+        let mut frames = Vec::new();
+        // Counts from before this transport was opened aren't reported
+        let mut line_errors = transport.line_errors();
+        let mut check = time::interval(LINE_ERRORS_EVERY);
+        loop {
+            // Restarted on every event, so it fires once the line is quiet
+            let idle = self.framer.idle_timeout();
+            tokio::select! {
+                _ = time::sleep(idle.unwrap_or_default()), if idle.is_some() => {
+                    self.framer.flush(&mut frames);
+                    self.publish(&mut frames, Local::now());
+                }
+                _ = check.tick(), if line_errors.is_some() => {
+                    if let (Some(earlier), Some(now)) = (line_errors, transport.line_errors()) {
+                        let new = now.since(&earlier);
+                        if !new.is_empty() {
+                            self.count_line_errors(&new);
+                            self.send(SerialEvent::LineErrors(new));
+                        }
+                        line_errors = Some(now);
+                    }
+                }
+                read = transport.read(&mut buf) => match read {
+                    Ok(n) if n > 0 => {
+                        // Frames are stamped when their last bytes were read
+                        let time = Local::now();
+                        self.counters.rx_bytes.fetch_add(n as u64, Ordering::Relaxed);
+                        let _ = self.raw.send(buf[..n].to_vec());
+                        // Stamped as read, for the timing of captures
+                        self.send_at(SerialEvent::Chunk(buf[..n].to_vec()), time);
+                        self.framer.push(&buf[..n], &mut frames);
+                        self.publish(&mut frames, time);
+                    }
+                    // End of stream: the remote side closed the connection
+                    Ok(_) => return Exit::Failed(SerialEvent::Disconnected),
+                    Err(e) => {
+                        self.counters.io_errors.fetch_add(1, Ordering::Relaxed);
+                        let error = SerialEvent::Error(format!("Serial read error: {e}"));
+                        if is_fatal(&e) {
+                            return Exit::Failed(error);
+                        }
+                        self.send(error);
+                        time::sleep(Duration::from_secs(1)).await;
+                    }
+                },
+                command = self.commands.recv() => match command {
+                    Some(PortCommand::Write(data)) => {
+                        if let Some(exit) = self.write(transport, data, true).await {
+                            return exit;
+                        }
+                    }
+                    Some(PortCommand::Forward(data)) => {
+                        if let Some(exit) = self.write(transport, data, false).await {
+                            return exit;
+                        }
+                    }
+                    Some(PortCommand::Control(control, reply)) => {
+                        let result = transport.control(control);
+                        if result.is_ok() {
+                            self.settings.send_modify(|s| s.apply(control));
+                        }
+                        let _ = reply.send(result);
+                    }
+                    None => return Exit::Closed,
+                },
+            }
+        }
+    }
+
+    /// Writes `data`, reported as a `Tx` event if `shown`; the exit if the
+    /// transport failed for good.
+    async fn write(&self, transport: &mut Box<dyn Transport>, data: Vec<u8>, shown: bool) -> Option<Exit> {
+        match transport.write_all(&data).await {
+            Ok(()) => {
+                self.counters.tx_bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+                if shown {
+                    self.send(SerialEvent::Tx(data));
+                }
+            }
+            Err(e) => {
+                self.counters.io_errors.fetch_add(1, Ordering::Relaxed);
+                let error = SerialEvent::Error(format!("Serial write error: {e}"));
+                if is_fatal(&e) {
+                    return Some(Exit::Failed(error));
+                }
+                self.send(error);
+            }
+        }
+        None
+    }
+
+    fn publish(&self, frames: &mut Vec<Frame>, time: DateTime<Local>) {
+        for frame in frames.drain(..) {
+            let (text, decoded) = self.decoder.decode(&frame);
+            let _ = self.events.send(PortEvent {
+                port: self.index,
+                time,
+                event: SerialEvent::Rx {
+                    text,
+                    ending: frame.ending(),
+                    raw: frame.raw(),
+                    decoded,
+                },
+            });
+        }
+    }
+
+    fn count_line_errors(&self, new: &LineErrors) {
+        self.counters.framing_errors.fetch_add(new.framing, Ordering::Relaxed);
+        self.counters.parity_errors.fetch_add(new.parity, Ordering::Relaxed);
+        self.counters.overruns.fetch_add(new.overrun, Ordering::Relaxed);
+        self.counters.breaks.fetch_add(new.breaks, Ordering::Relaxed);
+    }
+
+    /// Rejects commands while the port is closed; returns `false` once the
+    /// handles are dropped.
+    async fn wait(&mut self, delay: Duration) -> bool {
+        let deadline = time::sleep(delay);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                _ = &mut deadline => return true,
+                command = self.commands.recv() => match command {
+                    Some(PortCommand::Write(_) | PortCommand::Forward(_)) => {
+                        self.send(SerialEvent::Error("Not sent: the port is closed".to_string()));
+                    }
+                    Some(PortCommand::Control(_, reply)) => {
+                        let _ = reply.send(Err(io::Error::new(io::ErrorKind::NotConnected, "port closed")));
+                    }
+                    None => return false,
+                },
+            }
+        }
+    }
+}
+
+/// Errors after which the transport is unusable, e.g. an unplugged device.
+fn is_fatal(error: &io::Error) -> bool {
+    !matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Text of a caught panic.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic.downcast_ref::<String>().map_or("unknown cause", |m| m.as_str()),
+    }
+}
+
+/// Starts the task for port number `index`. Received frames are decoded and
+/// published on `events` along with everything else happening on the port;
+/// every raw chunk is also published to subscribers of [`PortHandle::raw`].
+///
+/// The task is supervised: panics and fatal I/O errors are reported as
+/// [`SerialEvent::Error`] and, depending on the restart policy, the
+/// transport is reopened.
+pub fn spawn(
+    index: usize,
+    transport: Box<dyn Transport>,
+    restart: Restart,
+    settings: SerialSettings,
+    framer: Framer,
+    decoder: Decoder,
+    events: mpsc::UnboundedSender<PortEvent>,
+) -> PortHandle {
+    let (tx_commands, rx_commands) = mpsc::unbounded_channel();
+    let (tx_raw, _) = broadcast::channel(RAW_CAPACITY);
+    let (tx_settings, rx_settings) = watch::channel(settings);
+    let counters = Arc::new(PortCounters::default());
+
+    let mut task = Task {
+        index,
+        framer,
+        decoder,
+        events,
+        commands: rx_commands,
+        raw: tx_raw.clone(),
+        settings: tx_settings,
+        counters: counters.clone(),
+    };
+    tokio::spawn(async move {
+        let mut transport = Some(transport);
+        let mut delay = RESTART_DELAY;
+        let mut failures = 0;
+        loop {
+            let settings = *task.settings.borrow();
+            let mut current = match transport.take() {
+                Some(current) => current,
+                None => match (restart.open)(settings).await {
+                    Ok(reopened) => reopened,
+                    Err(e) => {
+                        // Only the first failure of a series is reported
+                        if delay == RESTART_DELAY {
+                            // The headline of a multi-line diagnosis
+                            let reason = e.to_string().lines().next().unwrap_or_default().to_string();
+                            task.send(SerialEvent::Error(format!("Reopening failed, retrying: {reason}")));
+                        }
+                        failures += 1;
+                        if let Some((after, recover)) = &restart.recover
+                            && failures % after == 0
+                        {
+                            match recover().await {
+                                Ok(done) => task.send(SerialEvent::Notice(done)),
+                                Err(e) => task.send(SerialEvent::Error(format!("Recovery failed: {e:#}"))),
+                            }
+                            // The device takes a while to enumerate again
+                            delay = RESTART_DELAY;
+                        }
+                        if !task.wait(delay).await {
+                            return;
+                        }
+                        delay = (delay * 2).min(MAX_RESTART_DELAY);
+                        continue;
+                    }
+                },
+            };
+            task.send(SerialEvent::Connected);
+            delay = RESTART_DELAY;
+            failures = 0;
+            match AssertUnwindSafe(task.run(&mut current)).catch_unwind().await {
+                Ok(Exit::Closed) => return,
+                Ok(Exit::Failed(event)) => task.send(event),
+                Err(panic) => task.send(SerialEvent::Error(format!("Port task panicked: {}", panic_message(&*panic)))),
+            }
+            drop(current);
+            if restart.policy == RestartPolicy::Never || !task.wait(delay).await {
+                return;
+            }
+        }
+    });
+
+    PortHandle {
+        commands: tx_commands,
+        raw: tx_raw,
+        settings: rx_settings,
+        counters,
+        listen_only: false,
+    }
+}
@@ -0,0 +1,127 @@
+//! Power cycling a device through the USB hub port it is plugged into.
+//!
+//! A board that locked up hard, or whose USB bridge stopped enumerating,
+//! only comes back when its power is removed, which an unattended test rig
+//! can't do by hand. Many USB hubs switch the power of each of their ports,
+//! and [uhubctl](https://github.com/mvp/uhubctl) drives them:
+//! `--power-cycle-after 3` runs `uhubctl -l {hub} -p {hub_port} -a cycle`
+//! once the port failed to reopen three times in a row, and `:power-cycle`
+//! runs it on demand. On Linux the hub and port are found from where the
+//! serial port sits in sysfs; elsewhere, or behind a hub uhubctl names
+//! differently, they are given with `--usb-hub-port 1-1.4:2`.
+//! `--power-cycle-command` runs another tool instead, e.g. a smart plug's.
+
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use crate::trigger;
+
+/// uhubctl switching the port off and, two seconds later, on again.
+pub const DEFAULT_COMMAND: &str = "uhubctl -l {hub} -p {hub_port} -a cycle -d 2";
+
+/// A port of a USB hub, named as uhubctl names them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HubPort {
+    /// Location of the hub, e.g. `1-1.4`.
+    pub hub: String,
+    /// Number of the port on the hub.
+    pub port: String,
+}
+
+/// Parses `HUB:PORT` as given to `--usb-hub-port`, e.g. `1-1.4:2`.
+pub fn parse_hub_port(spec: &str) -> Result<HubPort, String> {
+    let Some((hub, port)) = spec
+        .rsplit_once(':')
+        .filter(|(hub, port)| !hub.is_empty() && !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()))
+    else {
+        return Err(format!("Invalid hub port {spec:?}. Expected HUB:PORT as uhubctl lists them, e.g. 1-1.4:2"));
+    };
+    Ok(HubPort { hub: hub.to_string(), port: port.to_string() })
+}
+
+/// The hub port a USB device such as `1-1.4` is plugged into: port 4 of
+/// hub `1-1`.
+fn parent(device: &str) -> Option<HubPort> {
+    let (bus, path) = device.split_once('-')?;
+    let valid = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit() || b == b'.');
+    if !valid(bus) || !valid(path) || bus.contains('.') {
+        return None;
+    }
+    Some(match path.rsplit_once('.') {
+        Some((hub, port)) => HubPort { hub: format!("{bus}-{hub}"), port: port.to_string() },
+        // Plugged into the root hub of the bus
+        None => HubPort { hub: bus.to_string(), port: path.to_string() },
+    })
+}
+
+/// The hub port the USB device behind serial port `name` is plugged into,
+/// from sysfs.
+#[cfg(target_os = "linux")]
+pub fn find(name: &str) -> Option<HubPort> {
+    // Names such as /dev/serial/by-id/... link to the tty itself
+    let tty = std::fs::canonicalize(name).ok()?;
+    let device = std::fs::canonicalize(Path::new("/sys/class/tty").join(tty.file_name()?).join("device")).ok()?;
+    // The first USB device up from the interface: 1-1.4 above 1-1.4:1.0
+    device.ancestors().filter_map(Path::file_name).find_map(|part| parent(part.to_str()?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn find(_name: &str) -> Option<HubPort> {
+    None
+}
+
+/// How the device behind a port is power-cycled.
+#[derive(Debug, Clone)]
+pub struct PowerCycle {
+    command: String,
+    /// The serial port, for messages and `{device}`.
+    device: String,
+    hub_port: Option<HubPort>,
+}
+
+impl PowerCycle {
+    /// Runs `command` for the device behind serial port `device`, plugged
+    /// into `hub_port` or else the hub port found from sysfs.
+    pub fn new(command: &str, device: &str, hub_port: Option<HubPort>) -> Self {
+        Self {
+            command: command.to_string(),
+            device: device.to_string(),
+            hub_port: hub_port.or_else(|| find(device)),
+        }
+    }
+
+    /// The command with `{hub}`, `{hub_port}` and `{device}` filled in.
+    fn command(&self) -> Result<String> {
+        let command = self.command.replace("{device}", &self.device);
+        if !command.contains("{hub}") && !command.contains("{hub_port}") {
+            return Ok(command);
+        }
+        let Some(HubPort { hub, port }) = &self.hub_port else {
+            bail!("The USB hub port of {} isn't known; give it with --usb-hub-port HUB:PORT", self.device);
+        };
+        Ok(command.replace("{hub_port}", port).replace("{hub}", hub))
+    }
+
+    /// Runs the command, returning what was done.
+    pub async fn run(&self) -> Result<String> {
+        let command = self.command()?;
+        let output = match trigger::shell(&command).output().await {
+            Ok(output) => output,
+            Err(e) => bail!("Cannot run {command:?}: {e}"),
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            match stderr.lines().map(str::trim).find(|line| !line.is_empty()) {
+                Some(reason) => bail!("{command:?} exited with {}: {reason}", output.status),
+                None => bail!("{command:?} exited with {}", output.status),
+            }
+        }
+        Ok(match &self.hub_port {
+            Some(HubPort { hub, port }) if self.command.contains("{hub") => {
+                format!("Power-cycled {} on port {port} of USB hub {hub}", self.device)
+            }
+            _ => format!("Power-cycled {}", self.device),
+        })
+    }
+}
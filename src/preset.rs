@@ -0,0 +1,58 @@
+//! Line settings and decoders for protocols with unusual serial parameters.
+//!
+//! `--preset midi` opens the port at MIDI's 31250 baud and shows the messages
+//! received, `--preset dmx` at DMX512's 250000 baud, 8N2, and shows the
+//! channel levels of each frame. Both cut the stream into frames at the gaps
+//! between messages. Not every adapter can be set to these rates; those that
+//! can't fail to open the port. Options given on the command line take
+//! precedence over the preset's.
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::transport::ParityArg;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    Midi,
+    Dmx,
+}
+
+/// What a preset sets.
+pub struct Settings {
+    pub baud_rate: u32,
+    pub data_bits: u8,
+    pub parity: ParityArg,
+    pub stop_bits: u8,
+    /// Quiet time in milliseconds ending a frame.
+    pub frame_timeout: u64,
+    /// Whether identical frames in a row are collapsed, for protocols
+    /// repeating their state continuously.
+    pub dedup: bool,
+}
+
+impl Preset {
+    pub fn settings(self) -> Settings {
+        match self {
+            Preset::Midi => Settings {
+                baud_rate: 31250,
+                data_bits: 8,
+                parity: ParityArg::None,
+                stop_bits: 1,
+                // USB adapters pass on bytes every few milliseconds, so the
+                // gap has to be longer than that
+                frame_timeout: 5,
+                dedup: false,
+            },
+            Preset::Dmx => Settings {
+                baud_rate: 250_000,
+                data_bits: 8,
+                parity: ParityArg::None,
+                stop_bits: 2,
+                frame_timeout: 5,
+                dedup: true,
+            },
+        }
+    }
+}
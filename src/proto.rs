@@ -0,0 +1,29 @@
+//! Protobuf decoding of received frames using a user-supplied descriptor set.
+
+use anyhow::{Context, Result, anyhow};
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+
+pub struct ProtoDecoder {
+    message: MessageDescriptor,
+}
+
+impl ProtoDecoder {
+    /// Loads `message` (fully qualified, e.g. `telemetry.Sample`) from a
+    /// serialized `FileDescriptorSet` as written by `protoc --descriptor_set_out`.
+    pub fn load(descriptor_set: &str, message: &str) -> Result<Self> {
+        let bytes = std::fs::read(descriptor_set)
+            .with_context(|| format!("Failed to read descriptor set {descriptor_set}"))?;
+        let pool = DescriptorPool::decode(bytes.as_slice())
+            .with_context(|| format!("Invalid descriptor set {descriptor_set}"))?;
+        let message = pool
+            .get_message_by_name(message)
+            .ok_or_else(|| anyhow!("Message {message} not found in {descriptor_set}"))?;
+        Ok(Self { message })
+    }
+
+    /// Decodes a frame into protobuf text format (`field: value ...`).
+    pub fn decode(&self, frame: &[u8]) -> Result<String, prost_reflect::prost::DecodeError> {
+        let message = DynamicMessage::decode(self.message.clone(), frame)?;
+        Ok(format!("{} {{{}}}", self.message.name(), message))
+    }
+}
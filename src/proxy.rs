@@ -0,0 +1,312 @@
+//! Man-in-the-middle proxy between two ports.
+//!
+//! With `--proxy`, whatever arrives on one `--port` is written to the other,
+//! so the monitor sits between two devices (or a device and a program at
+//! the end of a `tcp://` socket) and shows and logs both directions the way
+//! `--sniff` does. Lines typed in the meantime are injected into the port
+//! they are sent to.
+//!
+//! Without rules bytes are passed on as soon as they are read. With rules
+//! the stream is cut into frames at each `\n` or after `--frame-timeout` of
+//! quiet, and the rules whose pattern matches a frame act on it in order
+//! before it is passed on; each frame a rule acted on is noted in the
+//! output. `--rewrite REGEX=>REPLACEMENT` adds a replacing rule, and the
+//! config file can define rules that also drop, delay or inject frames, to
+//! find out how well a device copes with a misbehaving peer:
+//!
+//! ```toml
+//! [[proxy.rules]]
+//! match = "^SET SPEED"     # regex on the frame
+//! from = "host"            # the side sending it: its --alias, or A or B
+//! delay_ms = 500
+//!
+//! [[proxy.rules]]
+//! match = "^ACK"
+//! drop = true
+//! probability = 0.2        # act on a fifth of the matching frames
+//!
+//! [[proxy.rules]]
+//! match = "^READ"
+//! inject = '\x15'          # sent after the frame, to the same side
+//! # reply = "ERR\r\n"      # sent back to the side the frame came from
+//! # replace = "READ 0"     # replaces the matches, $1 refers to a group
+//! ```
+
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use anyhow::{Result, anyhow, bail};
+use ratatui::style::{Color, Style};
+use regex::bytes::Regex;
+use serde::Deserialize;
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    time::{self, Instant},
+};
+
+use crate::{framing, output::OutputLine, port::PortHandle};
+
+/// The `[proxy]` table of the config file.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+}
+
+/// A `[[proxy.rules]]` entry, with exactly one action.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    #[serde(rename = "match")]
+    pattern: String,
+    from: Option<String>,
+    replace: Option<String>,
+    #[serde(default)]
+    drop: bool,
+    delay_ms: Option<u64>,
+    inject: Option<String>,
+    reply: Option<String>,
+    probability: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+enum Action {
+    Replace(Vec<u8>),
+    Drop,
+    Delay(Duration),
+    Inject(Vec<u8>),
+    Reply(Vec<u8>),
+}
+
+/// A rule acting on the frames its regex matches.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    regex: Regex,
+    /// The side whose frames the rule acts on; both if `None`.
+    from: Option<usize>,
+    action: Action,
+    probability: f64,
+}
+
+/// Parses a `--rewrite` rule, `REGEX=>REPLACEMENT`, where the replacement
+/// may refer to groups as `$1` and hold `\r`, `\n`, `\xNN` escapes.
+pub fn parse_rewrite(spec: &str) -> Result<Rule, String> {
+    let (pattern, replacement) = spec
+        .split_once("=>")
+        .ok_or_else(|| format!("Invalid rewrite {spec:?}. Expected REGEX=>REPLACEMENT, e.g. \"SPEED=\\d+=>SPEED=0\""))?;
+    let regex = Regex::new(pattern).map_err(|e| format!("Invalid regex for rewrite {spec:?}: {e}"))?;
+    let replacement = bytes(replacement).map_err(|_| format!("Invalid replacement in rewrite {spec:?}"))?;
+    Ok(Rule {
+        regex,
+        from: None,
+        action: Action::Replace(replacement),
+        probability: 1.0,
+    })
+}
+
+/// `text` with its escapes as bytes; unlike a delimiter it may be empty.
+fn bytes(text: &str) -> Result<Vec<u8>, String> {
+    match text {
+        "" => Ok(Vec::new()),
+        text => framing::parse_delimiter(text),
+    }
+}
+
+impl RuleConfig {
+    /// The rule, with `from` looked up in `names`, those of the two sides.
+    fn parse(&self, names: [&str; 2]) -> Result<Rule> {
+        let invalid = |e: String| anyhow!("Invalid proxy rule for {:?}: {e}", self.pattern);
+        let regex = Regex::new(&self.pattern).map_err(|e| invalid(e.to_string()))?;
+        let from = match &self.from {
+            None => None,
+            Some(side) => Some(
+                names
+                    .iter()
+                    .position(|name| name.eq_ignore_ascii_case(side))
+                    .ok_or_else(|| invalid(format!("from = {side:?} is neither {} nor {}", names[0], names[1])))?,
+            ),
+        };
+        let mut actions = Vec::new();
+        if let Some(text) = &self.replace {
+            actions.push(Action::Replace(bytes(text).map_err(invalid)?));
+        }
+        if self.drop {
+            actions.push(Action::Drop);
+        }
+        if let Some(ms) = self.delay_ms {
+            actions.push(Action::Delay(Duration::from_millis(ms)));
+        }
+        if let Some(text) = &self.inject {
+            actions.push(Action::Inject(bytes(text).map_err(invalid)?));
+        }
+        if let Some(text) = &self.reply {
+            actions.push(Action::Reply(bytes(text).map_err(invalid)?));
+        }
+        let probability = self.probability.unwrap_or(1.0);
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(invalid(format!("probability {probability} is not between 0 and 1")));
+        }
+        match <[Action; 1]>::try_from(actions) {
+            Ok([action]) => Ok(Rule { regex, from, action, probability }),
+            Err(_) => bail!(
+                "Proxy rule for {:?} needs exactly one of replace, drop, delay_ms, inject or reply",
+                self.pattern
+            ),
+        }
+    }
+}
+
+/// The rules of the config file followed by the `--rewrite` ones.
+pub fn rules(config: &Config, names: [&str; 2], rewrites: &[Rule]) -> Result<Vec<Rule>> {
+    let mut rules = config.rules.iter().map(|rule| rule.parse(names)).collect::<Result<Vec<_>>>()?;
+    rules.extend_from_slice(rewrites);
+    Ok(rules)
+}
+
+/// Starts passing on what each of `ports` receives, as subscribed to in
+/// `received`, to the other. `tags` name the directions, e.g. `[A→B] `.
+pub fn start(
+    ports: [PortHandle; 2],
+    received: [broadcast::Receiver<Vec<u8>>; 2],
+    tags: [&'static str; 2],
+    rules: Vec<Rule>,
+    gap: Duration,
+    annotations: mpsc::UnboundedSender<OutputLine>,
+) {
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(1, |d| d.as_nanos() as u64);
+    for (from, received) in received.into_iter().enumerate() {
+        let rules: Arc<[Rule]> = rules.iter().filter(|rule| rule.from.is_none_or(|side| side == from)).cloned().collect();
+        let link = Link {
+            back: ports[from].clone(),
+            to: ports[1 - from].clone(),
+            tag: tags[from],
+            rules,
+            gap,
+            annotations: annotations.clone(),
+            random: seed.rotate_left(from as u32 * 32) | 1,
+        };
+        tokio::spawn(link.run(received));
+    }
+}
+
+/// One direction of the proxy.
+struct Link {
+    /// The side frames come from, which replies go to.
+    back: PortHandle,
+    to: PortHandle,
+    tag: &'static str,
+    rules: Arc<[Rule]>,
+    gap: Duration,
+    annotations: mpsc::UnboundedSender<OutputLine>,
+    /// Xorshift state deciding whether rules with a probability act.
+    random: u64,
+}
+
+impl Link {
+    async fn run(mut self, mut raw: broadcast::Receiver<Vec<u8>>) {
+        let mut pending = Vec::new();
+        let mut last = Instant::now();
+        // Frames held back by a delay and when they are due; the frames
+        // after them wait too, so the order is kept
+        let mut held: VecDeque<(Instant, Vec<u8>)> = VecDeque::new();
+        loop {
+            let quiet = !pending.is_empty();
+            let due = held.front().map(|(at, _)| *at);
+            tokio::select! {
+                _ = time::sleep_until(due.unwrap_or(last)), if due.is_some() => {
+                    let now = Instant::now();
+                    while held.front().is_some_and(|(at, _)| *at <= now) {
+                        let (_, frame) = held.pop_front().expect("checked above");
+                        self.to.forward(frame);
+                    }
+                }
+                _ = time::sleep_until(last + self.gap), if quiet => {
+                    self.pass(std::mem::take(&mut pending), &mut held);
+                }
+                received = raw.recv() => match received {
+                    Ok(data) if self.rules.is_empty() => self.to.forward(data),
+                    Ok(data) => {
+                        last = Instant::now();
+                        pending.extend_from_slice(&data);
+                        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                            let frame = pending.drain(..=end).collect();
+                            self.pass(frame, &mut held);
+                        }
+                    }
+                    Err(RecvError::Lagged(chunks)) => self.note(
+                        format!("{}{chunks} chunks were lost, the proxy fell behind", self.tag),
+                        Color::Red,
+                    ),
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+    }
+
+    /// Applies the rules to a frame and passes on what is left of it.
+    fn pass(&mut self, mut frame: Vec<u8>, held: &mut VecDeque<(Instant, Vec<u8>)>) {
+        let shown = |data: &[u8]| String::from_utf8_lossy(data).trim_end_matches(['\r', '\n']).to_string();
+        let original = shown(&frame);
+        let mut done = Vec::new();
+        let mut delay = Duration::ZERO;
+        let mut injected = Vec::new();
+        for rule in self.rules.clone().iter() {
+            if !rule.regex.is_match(&frame) || !self.roll(rule.probability) {
+                continue;
+            }
+            match &rule.action {
+                Action::Replace(replacement) => {
+                    frame = rule.regex.replace_all(&frame, replacement.as_slice()).into_owned();
+                    done.push(format!("rewritten to {}", shown(&frame)));
+                }
+                Action::Drop => {
+                    done.push("dropped".to_string());
+                    self.note(format!("{}{original}: {}", self.tag, done.join(", ")), Color::Magenta);
+                    return;
+                }
+                Action::Delay(by) => {
+                    delay += *by;
+                    done.push(format!("delayed {} ms", by.as_millis()));
+                }
+                Action::Inject(data) => {
+                    injected.extend_from_slice(data);
+                    done.push(format!("followed by \"{}\"", data.escape_ascii()));
+                }
+                Action::Reply(data) => {
+                    self.back.forward(data.clone());
+                    done.push(format!("answered with \"{}\"", data.escape_ascii()));
+                }
+            }
+        }
+        if !done.is_empty() {
+            self.note(format!("{}{original}: {}", self.tag, done.join(", ")), Color::Magenta);
+        }
+        frame.extend_from_slice(&injected);
+        if delay.is_zero() && held.is_empty() {
+            self.to.forward(frame);
+        } else {
+            let at = Instant::now() + delay;
+            let at = held.back().map_or(at, |(before, _)| at.max(*before));
+            held.push_back((at, frame));
+        }
+    }
+
+    /// Whether a rule with `probability` acts on the current frame.
+    fn roll(&mut self, probability: f64) -> bool {
+        if probability >= 1.0 {
+            return true;
+        }
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        ((self.random >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    fn note(&self, text: String, color: Color) {
+        let _ = self.annotations.send(OutputLine::new("[Proxy] ", text, Style::default().fg(color)));
+    }
+}
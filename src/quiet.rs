@@ -0,0 +1,118 @@
+//! Minimal view showing only per-port throughput and alerts.
+//!
+//! Meant for small panes, e.g. a tmux grid of monitors during fleet testing,
+//! where the scrolling text itself is unreadable anyway.
+
+use std::{
+    collections::VecDeque,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+};
+
+use crate::{output::OutputLine, port::PortHandle};
+
+/// Window over which throughput is averaged.
+const RATE_WINDOW: Duration = Duration::from_secs(2);
+/// A port counts as active if bytes arrived this recently.
+const ACTIVE_FOR: Duration = Duration::from_millis(300);
+
+#[derive(Default)]
+struct PortActivity {
+    /// `(time, rx bytes, tx bytes)` samples of the port's counters.
+    samples: VecDeque<(Instant, u64, u64)>,
+    last_rx: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct Activity {
+    ports: Vec<PortActivity>,
+}
+
+impl Activity {
+    /// Samples the traffic counters of every port.
+    pub fn sample(&mut self, ports: &[PortHandle], now: Instant) {
+        self.ports.resize_with(ports.len(), PortActivity::default);
+        for (activity, port) in self.ports.iter_mut().zip(ports) {
+            let rx = port.counters.rx_bytes.load(Ordering::Relaxed);
+            let tx = port.counters.tx_bytes.load(Ordering::Relaxed);
+            if activity.samples.back().is_none_or(|&(_, last_rx, _)| rx != last_rx) {
+                activity.last_rx = Some(now);
+            }
+            activity.samples.push_back((now, rx, tx));
+            while activity
+                .samples
+                .front()
+                .is_some_and(|&(t, _, _)| now.duration_since(t) > RATE_WINDOW)
+            {
+                activity.samples.pop_front();
+            }
+        }
+    }
+
+    /// Renders one row per port followed by the most recent alerts.
+    pub fn render(&self, f: &mut Frame, area: Rect, title: &str, tags: &[&str], alerts: &[&OutputLine], now: Instant) {
+        let block = Block::default().title(title).borders(Borders::ALL);
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(self.ports.len() as u16), Constraint::Min(0)])
+            .split(inner);
+
+        let ports: Vec<Line> = self
+            .ports
+            .iter()
+            .zip(tags)
+            .map(|(activity, tag)| {
+                let (rx_rate, tx_rate) = activity.rates();
+                let active = activity.last_rx.is_some_and(|t| now.duration_since(t) <= ACTIVE_FOR);
+                let (dot, color) = if active { ("●", Color::Green) } else { ("○", Color::DarkGray) };
+                Line::from(vec![
+                    Span::styled(format!("{dot} "), Style::default().fg(color)),
+                    Span::raw(format!("{:<12}", tag.trim())),
+                    Span::styled(format!("RX {:>9}", format_rate(rx_rate)), Style::default().fg(Color::Green)),
+                    Span::styled(format!("  TX {:>9}", format_rate(tx_rate)), Style::default().fg(Color::Yellow)),
+                ])
+            })
+            .collect();
+        f.render_widget(Paragraph::new(ports), rows[0]);
+
+        let shown = alerts.len().saturating_sub(rows[1].height as usize);
+        let alerts: Vec<Line> = alerts[shown..]
+            .iter()
+            .map(|line| Line::from(Span::styled(format!("{}{}", line.prefix, line.body), line.style())))
+            .collect();
+        f.render_widget(Paragraph::new(alerts), rows[1]);
+    }
+}
+
+impl PortActivity {
+    /// Bytes per second received and sent over the sample window.
+    fn rates(&self) -> (f64, f64) {
+        let (Some(&(first, rx0, tx0)), Some(&(last, rx1, tx1))) = (self.samples.front(), self.samples.back()) else {
+            return (0.0, 0.0);
+        };
+        let seconds = last.duration_since(first).as_secs_f64();
+        if seconds <= 0.0 {
+            return (0.0, 0.0);
+        }
+        // Counters restart when a port is reopened
+        (rx1.saturating_sub(rx0) as f64 / seconds, tx1.saturating_sub(tx0) as f64 / seconds)
+    }
+}
+
+fn format_rate(bytes_per_second: f64) -> String {
+    if bytes_per_second >= 1024.0 {
+        format!("{:.1} KB/s", bytes_per_second / 1024.0)
+    } else {
+        format!("{bytes_per_second:.0} B/s")
+    }
+}
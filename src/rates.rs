@@ -0,0 +1,121 @@
+//! Live statistics of which line-oriented messages a device emits.
+//!
+//! Lines are grouped by their leading token (e.g. `$GPGGA` for NMEA
+//! sentences), and each group tracks its count, recent rate and last arrival.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Row, Table},
+    Frame,
+};
+
+/// Window over which the per-message rate is averaged.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+/// Prefixes longer than this are truncated so free-form text doesn't flood the table.
+const MAX_PREFIX_LEN: usize = 24;
+/// Message types tracked; beyond this the one seen least recently is
+/// dropped, so a garbled stream can't grow the table without bound.
+const MAX_ENTRIES: usize = 256;
+
+#[derive(Default)]
+struct Entry {
+    count: u64,
+    recent: VecDeque<Instant>,
+    last_seen: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct MessageRates {
+    entries: HashMap<String, Entry>,
+}
+
+/// Returns the message type of `line`: everything up to the first separator.
+fn prefix(line: &str) -> Option<&str> {
+    let end = line
+        .find(|c: char| c.is_whitespace() || matches!(c, ',' | ':' | ';' | '=' | '*'))
+        .unwrap_or(line.len());
+    let prefix = &line[..end];
+    if prefix.is_empty() {
+        return None;
+    }
+    let cut = prefix
+        .char_indices()
+        .nth(MAX_PREFIX_LEN)
+        .map_or(prefix.len(), |(i, _)| i);
+    Some(&prefix[..cut])
+}
+
+impl MessageRates {
+    pub fn record(&mut self, line: &str, now: Instant) {
+        let Some(prefix) = prefix(line.trim_start()) else {
+            return;
+        };
+        let entry = self.entries.entry(prefix.to_string()).or_default();
+        entry.count += 1;
+        entry.recent.push_back(now);
+        entry.last_seen = Some(now);
+        for entry in self.entries.values_mut() {
+            while entry
+                .recent
+                .front()
+                .is_some_and(|&t| now.duration_since(t) > RATE_WINDOW)
+            {
+                entry.recent.pop_front();
+            }
+        }
+        if self.entries.len() > MAX_ENTRIES
+            && let Some(stale) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_seen)
+                .map(|(name, _)| name.clone())
+        {
+            self.entries.remove(&stale);
+        }
+    }
+
+    pub fn render(&self, f: &mut Frame, area: Rect, now: Instant) {
+        let mut names: Vec<&String> = self.entries.keys().collect();
+        names.sort();
+        let rows = names.into_iter().map(|name| {
+            let entry = &self.entries[name];
+            let recent = entry
+                .recent
+                .iter()
+                .filter(|&&t| now.duration_since(t) <= RATE_WINDOW)
+                .count();
+            let rate = recent as f64 / RATE_WINDOW.as_secs_f64();
+            let last_seen = entry
+                .last_seen
+                .map(|t| format!("{:.1}s ago", now.duration_since(t).as_secs_f64()))
+                .unwrap_or_default();
+            Row::new([
+                name.clone(),
+                entry.count.to_string(),
+                format!("{rate:.1} Hz"),
+                last_seen,
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Min(8),
+                Constraint::Length(7),
+                Constraint::Length(8),
+                Constraint::Length(10),
+            ],
+        )
+        .header(
+            Row::new(["Message", "Count", "Rate", "Last seen"])
+                .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().title("Message Rates").borders(Borders::ALL));
+        f.render_widget(table, area);
+    }
+}
@@ -0,0 +1,309 @@
+//! Replay of capture files as if the captured ports were attached.
+//!
+//! Received chunks are fed to the monitor at their recorded pace, scaled by
+//! the playback speed. Playback can be paused, stepped one chunk at a time
+//! and moved to any time in the capture; sent data is not replayed.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, NaiveTime, TimeZone};
+use tokio::{
+    io::{AsyncWriteExt, DuplexStream, WriteHalf},
+    sync::{mpsc, watch},
+    time,
+};
+
+use crate::{
+    capture::{Kind, Reader, Record},
+    simulator,
+    toast::{self, Severity},
+    transport::Transport,
+};
+
+/// Playback speed multipliers, switched with `<` and `>`.
+const SPEEDS: [f64; 8] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0];
+
+pub enum Control {
+    TogglePause,
+    /// Delivers the next chunk and pauses.
+    Step,
+    Faster,
+    Slower,
+    /// Moves to a time in microseconds since the epoch.
+    Seek(i64),
+    /// Moves by microseconds from the current position.
+    SeekBy(i64),
+}
+
+impl Control {
+    /// Monitor message for a seek.
+    pub fn describe(&self) -> String {
+        match self {
+            Control::Seek(time) => format!("Seeking to {}", local(*time).format("%H:%M:%S%.3f")),
+            Control::SeekBy(micros) => format!("Seeking by {:+.3}s", *micros as f64 / 1e6),
+            _ => String::new(),
+        }
+    }
+}
+
+/// A `:seek` target.
+pub enum SeekTarget {
+    /// Wall clock time on the day of the current position.
+    Clock(NaiveTime),
+    /// Microseconds from the current position.
+    Relative(i64),
+}
+
+impl SeekTarget {
+    /// The time it names, in microseconds since the epoch, from `position`.
+    pub fn time_from(&self, position: i64) -> i64 {
+        match self {
+            SeekTarget::Relative(micros) => position + micros,
+            SeekTarget::Clock(clock) => {
+                let day = local(position).date_naive();
+                Local
+                    .from_local_datetime(&day.and_time(*clock))
+                    .earliest()
+                    .map_or(position, |t| t.timestamp_micros())
+            }
+        }
+    }
+}
+
+/// Parses `HH:MM:SS[.fff]` or `+N`/`-N` with an `ms`, `s` or `m` suffix.
+pub fn parse_seek(text: &str) -> Result<SeekTarget, String> {
+    let text = text.trim();
+    let usage = || format!("Invalid seek target {text:?}. Expected e.g. 12:30:05, 12:30:05.250, +30s or -2m");
+    if let Some(sign) = text.chars().next().filter(|c| matches!(c, '+' | '-')) {
+        let amount = &text[1..];
+        let (number, scale) = if let Some(ms) = amount.strip_suffix("ms") {
+            (ms, 1e3)
+        } else if let Some(s) = amount.strip_suffix('s') {
+            (s, 1e6)
+        } else if let Some(m) = amount.strip_suffix('m') {
+            (m, 60e6)
+        } else {
+            (amount, 1e6)
+        };
+        let value: f64 = number.parse().map_err(|_| usage())?;
+        let micros = (value * scale) as i64;
+        return Ok(SeekTarget::Relative(if sign == '-' { -micros } else { micros }));
+    }
+    NaiveTime::parse_from_str(text, "%H:%M:%S%.f")
+        .map(SeekTarget::Clock)
+        .map_err(|_| usage())
+}
+
+#[derive(Clone, Copy)]
+pub struct Status {
+    /// Capture time reached at `since`, in microseconds since the epoch.
+    anchor: i64,
+    since: Instant,
+    pub speed: f64,
+    pub paused: bool,
+    /// Whether every chunk has been delivered.
+    pub ended: bool,
+    /// Times of the first and last chunk.
+    pub span: (i64, i64),
+}
+
+impl Status {
+    /// Current capture time in microseconds since the epoch.
+    pub fn position(&self, now: Instant) -> i64 {
+        if self.paused || self.ended {
+            return self.anchor;
+        }
+        let elapsed = now.saturating_duration_since(self.since).as_micros() as f64 * self.speed;
+        (self.anchor + elapsed as i64).min(self.span.1)
+    }
+
+    /// Status bar text, e.g. `▶ 12:30:05.250 / 12:45:00 4x`.
+    pub fn text(&self, now: Instant) -> String {
+        let state = match (self.ended, self.paused) {
+            (true, _) => "■",
+            (false, true) => "⏸",
+            (false, false) => "▶",
+        };
+        format!(
+            "{state} {} / {}  {}x  (Space pause, . step, < > speed, ←/→ ±10s, :seek TIME)",
+            local(self.position(now)).format("%H:%M:%S%.3f"),
+            local(self.span.1).format("%H:%M:%S"),
+            self.speed
+        )
+    }
+
+    /// Resolves a `:seek` target against the current position.
+    pub fn resolve(&self, target: SeekTarget, now: Instant) -> Control {
+        match target {
+            SeekTarget::Relative(micros) => Control::SeekBy(micros),
+            SeekTarget::Clock(_) => Control::Seek(target.time_from(self.position(now))),
+        }
+    }
+}
+
+fn local(micros: i64) -> DateTime<Local> {
+    Local.timestamp_micros(micros).single().unwrap_or_default()
+}
+
+/// Handle on the running playback.
+pub struct Player {
+    commands: mpsc::UnboundedSender<Control>,
+    status: watch::Receiver<Status>,
+}
+
+impl Player {
+    pub fn control(&self, control: Control) {
+        let _ = self.commands.send(control);
+    }
+
+    pub fn status(&self) -> Status {
+        *self.status.borrow()
+    }
+}
+
+/// A started replay.
+pub struct Replay {
+    pub player: Player,
+    /// Name and transport of each captured port.
+    pub ports: Vec<(String, Box<dyn Transport>)>,
+}
+
+/// Opens the capture at `path` and starts playing it.
+pub fn open(path: &str) -> Result<Replay> {
+    let reader = Reader::open(path)?;
+    if let Some(damage) = &reader.damage {
+        toast::notify(
+            Severity::Warning,
+            format!("{path} is damaged at offset {}: {}; replaying up to there", damage.offset, damage.reason),
+        );
+    }
+    let span = reader.span.ok_or_else(|| anyhow!("{path} holds no records"))?;
+    let names: Vec<String> = match reader.header["ports"].as_array() {
+        Some(ports) if !ports.is_empty() => ports.iter().map(|p| p.as_str().unwrap_or("replay").to_string()).collect(),
+        _ => vec!["replay".to_string()],
+    };
+    let mut ports = Vec::new();
+    let mut devices = Vec::new();
+    for name in names {
+        let (transport, device) = simulator::duplex();
+        let (mut reader, writer) = tokio::io::split(device);
+        // What the monitor sends goes nowhere, but must not fill the pipe
+        tokio::spawn(async move {
+            let _ = tokio::io::copy(&mut reader, &mut tokio::io::sink()).await;
+        });
+        ports.push((name, transport));
+        devices.push(writer);
+    }
+    let status = Status {
+        anchor: span.0,
+        since: Instant::now(),
+        speed: 1.0,
+        paused: false,
+        ended: false,
+        span,
+    };
+    let (commands, rx_commands) = mpsc::unbounded_channel();
+    let (tx_status, rx_status) = watch::channel(status);
+    tokio::spawn(run(reader, devices, rx_commands, tx_status));
+    Ok(Replay {
+        player: Player {
+            commands,
+            status: rx_status,
+        },
+        ports,
+    })
+}
+
+/// The next received chunk, reporting damage as the end of the capture.
+fn next_rx(reader: &mut Reader) -> Option<Record> {
+    loop {
+        match reader.next_record() {
+            Ok(Some(record)) if record.kind == Kind::Rx => return Some(record),
+            Ok(Some(_)) => continue,
+            Ok(None) => return None,
+            Err(e) => {
+                toast::notify(Severity::Warning, format!("Replay stopped: {e}"));
+                return None;
+            }
+        }
+    }
+}
+
+async fn run(
+    mut reader: Reader,
+    mut devices: Vec<WriteHalf<DuplexStream>>,
+    mut commands: mpsc::UnboundedReceiver<Control>,
+    status: watch::Sender<Status>,
+) {
+    let mut state = *status.borrow();
+    let mut pending = next_rx(&mut reader);
+    loop {
+        state.ended = pending.is_none();
+        status.send_replace(state);
+        let due = pending.as_ref().filter(|_| !state.paused).map(|record| {
+            let wait = (record.time - state.anchor).max(0) as f64 / state.speed;
+            state.since + Duration::from_micros(wait as u64)
+        });
+        tokio::select! {
+            _ = time::sleep_until(due.unwrap_or_else(Instant::now).into()), if due.is_some() => {
+                if let Some(record) = pending.take() {
+                    deliver(&mut devices, &record).await;
+                    state.anchor = record.time;
+                    state.since = due.unwrap_or_else(Instant::now);
+                }
+                pending = next_rx(&mut reader);
+            }
+            command = commands.recv() => {
+                let Some(command) = command else {
+                    return;
+                };
+                let now = Instant::now();
+                let position = state.position(now);
+                let mut seek = |state: &mut Status, target: i64| {
+                    let target = target.clamp(state.span.0, state.span.1);
+                    state.anchor = target;
+                    match reader.seek(target) {
+                        Ok(record) => record.filter(|r| r.kind == Kind::Rx).or_else(|| next_rx(&mut reader)),
+                        Err(e) => {
+                            toast::notify(Severity::Warning, format!("Replay seek failed: {e}"));
+                            None
+                        }
+                    }
+                };
+                match command {
+                    Control::TogglePause => {
+                        state.anchor = position;
+                        state.paused = !state.paused;
+                    }
+                    Control::Step => {
+                        if let Some(record) = pending.take() {
+                            deliver(&mut devices, &record).await;
+                            state.anchor = record.time;
+                            pending = next_rx(&mut reader);
+                        }
+                        state.paused = true;
+                    }
+                    Control::Faster | Control::Slower => {
+                        let current = SPEEDS.iter().position(|&s| s == state.speed).unwrap_or(2);
+                        let next = match command {
+                            Control::Faster => (current + 1).min(SPEEDS.len() - 1),
+                            _ => current.saturating_sub(1),
+                        };
+                        state.anchor = position;
+                        state.speed = SPEEDS[next];
+                    }
+                    Control::Seek(target) => pending = seek(&mut state, target),
+                    Control::SeekBy(delta) => pending = seek(&mut state, position + delta),
+                }
+                state.since = now;
+            }
+        }
+    }
+}
+
+async fn deliver(devices: &mut [WriteHalf<DuplexStream>], record: &Record) {
+    if let Some(device) = devices.get_mut(record.port as usize) {
+        let _ = device.write_all(&record.data).await;
+    }
+}
@@ -0,0 +1,497 @@
+//! RFC 2217 (Telnet Com Port Control) protocol pieces and client.
+//!
+//! The client's telnet layer is handled by a background task that bridges the TCP
+//! connection to an in-memory duplex stream, so the rest of the monitor sees
+//! the remote port as a plain byte stream.
+
+use std::{
+    collections::HashSet,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf},
+    sync::mpsc,
+    time,
+};
+
+use crate::{
+    access::{self, Stream},
+    transport::{Control, FlowControlArg, ParityArg, SerialSettings, Transport},
+};
+
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+pub const SB: u8 = 250;
+pub const SE: u8 = 240;
+
+pub const OPT_BINARY: u8 = 0;
+pub const OPT_SGA: u8 = 3;
+pub const OPT_COM_PORT: u8 = 44;
+
+pub const SET_BAUDRATE: u8 = 1;
+pub const SET_DATASIZE: u8 = 2;
+pub const SET_PARITY: u8 = 3;
+pub const SET_STOPSIZE: u8 = 4;
+pub const SET_CONTROL: u8 = 5;
+pub const SIGNATURE: u8 = 0;
+pub const SET_LINESTATE_MASK: u8 = 10;
+pub const SET_MODEMSTATE_MASK: u8 = 11;
+pub const PURGE_DATA: u8 = 12;
+
+/// SET-CONTROL values for the control signals.
+pub const CONTROL_BREAK_ON: u8 = 5;
+pub const CONTROL_BREAK_OFF: u8 = 6;
+pub const CONTROL_DTR_ON: u8 = 8;
+pub const CONTROL_DTR_OFF: u8 = 9;
+pub const CONTROL_RTS_ON: u8 = 11;
+pub const CONTROL_RTS_OFF: u8 = 12;
+/// Offset added to a command code in server-to-client replies.
+pub const SERVER_OFFSET: u8 = 100;
+
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(3);
+const BRIDGE_BUFFER: usize = 4096;
+
+/// A decoded unit of the telnet stream.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TelnetEvent {
+    Data(u8),
+    Negotiate(u8, u8),
+    Subnegotiation(Vec<u8>),
+}
+
+#[derive(Default)]
+enum State {
+    #[default]
+    Data,
+    Iac,
+    Verb(u8),
+    Sub,
+    SubIac,
+}
+
+/// Incremental telnet protocol decoder.
+#[derive(Default)]
+pub struct TelnetParser {
+    state: State,
+    sub: Vec<u8>,
+}
+
+impl TelnetParser {
+    pub fn feed(&mut self, byte: u8) -> Option<TelnetEvent> {
+        match std::mem::take(&mut self.state) {
+            State::Data if byte == IAC => {
+                self.state = State::Iac;
+                None
+            }
+            State::Data => Some(TelnetEvent::Data(byte)),
+            State::Iac => match byte {
+                IAC => Some(TelnetEvent::Data(IAC)),
+                WILL | WONT | DO | DONT => {
+                    self.state = State::Verb(byte);
+                    None
+                }
+                SB => {
+                    self.sub.clear();
+                    self.state = State::Sub;
+                    None
+                }
+                // NOP, GA and friends carry no data we care about.
+                _ => None,
+            },
+            State::Verb(verb) => Some(TelnetEvent::Negotiate(verb, byte)),
+            State::Sub if byte == IAC => {
+                self.state = State::SubIac;
+                None
+            }
+            State::Sub => {
+                self.sub.push(byte);
+                self.state = State::Sub;
+                None
+            }
+            State::SubIac => match byte {
+                SE => Some(TelnetEvent::Subnegotiation(std::mem::take(&mut self.sub))),
+                IAC => {
+                    self.sub.push(IAC);
+                    self.state = State::Sub;
+                    None
+                }
+                _ => {
+                    self.state = State::Sub;
+                    None
+                }
+            },
+        }
+    }
+}
+
+/// Doubles IAC bytes so payload data survives the telnet layer.
+pub fn escape(data: &[u8], out: &mut Vec<u8>) {
+    for &b in data {
+        out.push(b);
+        if b == IAC {
+            out.push(IAC);
+        }
+    }
+}
+
+/// Builds a COM-PORT-OPTION subnegotiation frame.
+pub fn com_port_command(command: u8, value: &[u8]) -> Vec<u8> {
+    let mut frame = vec![IAC, SB, OPT_COM_PORT, command];
+    escape(value, &mut frame);
+    frame.extend_from_slice(&[IAC, SE]);
+    frame
+}
+
+pub fn parity_code(parity: ParityArg) -> u8 {
+    match parity {
+        ParityArg::None => 1,
+        ParityArg::Odd => 2,
+        ParityArg::Even => 3,
+    }
+}
+
+pub fn parity_from_code(code: u8) -> Option<ParityArg> {
+    match code {
+        1 => Some(ParityArg::None),
+        2 => Some(ParityArg::Odd),
+        3 => Some(ParityArg::Even),
+        _ => None,
+    }
+}
+
+pub fn control_code(flow: FlowControlArg) -> u8 {
+    match flow {
+        FlowControlArg::None => 1,
+        FlowControlArg::Software => 2,
+        FlowControlArg::Hardware => 3,
+    }
+}
+
+pub fn flow_from_code(code: u8) -> Option<FlowControlArg> {
+    match code {
+        1 => Some(FlowControlArg::None),
+        2 => Some(FlowControlArg::Software),
+        3 => Some(FlowControlArg::Hardware),
+        _ => None,
+    }
+}
+
+/// Encodes a control change as the client-side COM-PORT-OPTION command.
+fn control_command(control: Control) -> Vec<u8> {
+    match control {
+        Control::BaudRate(baud) => com_port_command(SET_BAUDRATE, &baud.to_be_bytes()),
+        Control::DataBits(bits) => com_port_command(SET_DATASIZE, &[bits]),
+        Control::Parity(parity) => com_port_command(SET_PARITY, &[parity_code(parity)]),
+        Control::StopBits(bits) => com_port_command(SET_STOPSIZE, &[bits]),
+        Control::Flow(flow) => com_port_command(SET_CONTROL, &[control_code(flow)]),
+        Control::Dtr(level) => {
+            com_port_command(SET_CONTROL, &[if level { CONTROL_DTR_ON } else { CONTROL_DTR_OFF }])
+        }
+        Control::Rts(level) => {
+            com_port_command(SET_CONTROL, &[if level { CONTROL_RTS_ON } else { CONTROL_RTS_OFF }])
+        }
+        Control::Break(on) => {
+            com_port_command(SET_CONTROL, &[if on { CONTROL_BREAK_ON } else { CONTROL_BREAK_OFF }])
+        }
+    }
+}
+
+/// Line settings as acknowledged by the server.
+#[derive(Debug, Default)]
+pub struct Negotiated {
+    pub baud_rate: Option<u32>,
+    pub data_size: Option<u8>,
+    pub parity: Option<u8>,
+    pub stop_size: Option<u8>,
+}
+
+impl Negotiated {
+    fn record(&mut self, payload: &[u8]) {
+        let [OPT_COM_PORT, command, value @ ..] = payload else {
+            return;
+        };
+        match (command.wrapping_sub(SERVER_OFFSET), value) {
+            (SET_BAUDRATE, [a, b, c, d]) => self.baud_rate = Some(u32::from_be_bytes([*a, *b, *c, *d])),
+            (SET_DATASIZE, [v]) => self.data_size = Some(*v),
+            (SET_PARITY, [v]) => self.parity = Some(*v),
+            (SET_STOPSIZE, [v]) => self.stop_size = Some(*v),
+            _ => {}
+        }
+    }
+
+    fn complete(&self) -> bool {
+        self.unconfirmed().is_empty()
+    }
+
+    /// The settings the server didn't acknowledge before the negotiation
+    /// timed out, which it may not have applied.
+    pub fn unconfirmed(&self) -> Vec<&'static str> {
+        [
+            ("baud rate", self.baud_rate.is_none()),
+            ("data bits", self.data_size.is_none()),
+            ("parity", self.parity.is_none()),
+            ("stop bits", self.stop_size.is_none()),
+        ]
+        .into_iter()
+        .filter_map(|(setting, missing)| missing.then_some(setting))
+        .collect()
+    }
+
+    /// Describes every setting the server did not accept as requested.
+    pub fn mismatches(&self, settings: &SerialSettings) -> Vec<String> {
+        let mut out = Vec::new();
+        if let Some(baud) = self.baud_rate.filter(|&b| b != settings.baud_rate) {
+            out.push(format!("baud rate {baud} instead of {}", settings.baud_rate));
+        }
+        if let Some(bits) = self.data_size.filter(|&b| b != settings.data_bits) {
+            out.push(format!("data bits {bits} instead of {}", settings.data_bits));
+        }
+        if self.parity.is_some_and(|p| p != parity_code(settings.parity)) {
+            out.push(format!("parity code {} instead of {:?}", self.parity.unwrap_or_default(), settings.parity));
+        }
+        if let Some(stop) = self.stop_size.filter(|&s| s != settings.stop_bits) {
+            out.push(format!("stop bits {stop} instead of {}", settings.stop_bits));
+        }
+        out
+    }
+}
+
+/// Tracks which options we agreed to so negotiation never loops.
+pub struct Options {
+    local: HashSet<u8>,
+    remote: HashSet<u8>,
+    /// Options the peer may enable on its side.
+    remote_supported: &'static [u8],
+}
+
+impl Options {
+    /// Options for the client role: only the client implements COM-PORT-OPTION.
+    fn client() -> Self {
+        Self {
+            local: HashSet::new(),
+            remote: HashSet::new(),
+            remote_supported: &[OPT_BINARY, OPT_SGA],
+        }
+    }
+
+    /// Options for the server role, which accepts the client's COM-PORT-OPTION.
+    pub fn server() -> Self {
+        Self {
+            local: HashSet::new(),
+            remote: HashSet::new(),
+            remote_supported: &[OPT_BINARY, OPT_SGA, OPT_COM_PORT],
+        }
+    }
+
+    /// Marks `option` as requested on our side and returns the WILL to send.
+    pub fn offer(&mut self, option: u8) -> [u8; 3] {
+        self.local.insert(option);
+        [IAC, WILL, option]
+    }
+
+    /// Marks `option` as requested on the peer side and returns the DO to send.
+    pub fn request(&mut self, option: u8) -> [u8; 3] {
+        self.remote.insert(option);
+        [IAC, DO, option]
+    }
+
+    /// Answers a peer negotiation request, returning the reply to send (if any).
+    pub fn answer(&mut self, verb: u8, option: u8) -> Option<[u8; 3]> {
+        let local_supported = matches!(option, OPT_BINARY | OPT_SGA)
+            || (option == OPT_COM_PORT && !self.remote_supported.contains(&OPT_COM_PORT));
+        match verb {
+            DO if local_supported => self.local.insert(option).then_some([IAC, WILL, option]),
+            DO => Some([IAC, WONT, option]),
+            WILL if self.remote_supported.contains(&option) => {
+                self.remote.insert(option).then_some([IAC, DO, option])
+            }
+            WILL => Some([IAC, DONT, option]),
+            DONT => self.local.remove(&option).then_some([IAC, WONT, option]),
+            WONT => self.remote.remove(&option).then_some([IAC, DONT, option]),
+            _ => None,
+        }
+    }
+}
+
+/// Connection to a remote port; control changes are forwarded to the server.
+pub struct Rfc2217Stream {
+    stream: DuplexStream,
+    commands: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl AsyncRead for Rfc2217Stream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Rfc2217Stream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+impl Transport for Rfc2217Stream {
+    fn control(&mut self, control: Control) -> io::Result<()> {
+        self.commands
+            .send(control_command(control))
+            .map_err(|_| io::Error::new(io::ErrorKind::NotConnected, "RFC 2217 connection closed"))
+    }
+}
+
+/// Connects to an RFC 2217 server at `addr` (`host:port`), over TLS if
+/// `tls`, and applies `settings`.
+pub async fn connect(addr: &str, tls: bool, settings: &SerialSettings) -> io::Result<(Rfc2217Stream, Negotiated)> {
+    let mut tcp = access::connect(addr, tls).await?;
+
+    let mut options = Options::client();
+    let mut hello = Vec::new();
+    for option in [OPT_BINARY, OPT_SGA, OPT_COM_PORT] {
+        hello.extend_from_slice(&options.offer(option));
+    }
+    for option in [OPT_BINARY, OPT_SGA] {
+        hello.extend_from_slice(&options.request(option));
+    }
+    hello.extend(com_port_command(SET_BAUDRATE, &settings.baud_rate.to_be_bytes()));
+    hello.extend(com_port_command(SET_DATASIZE, &[settings.data_bits]));
+    hello.extend(com_port_command(SET_PARITY, &[parity_code(settings.parity)]));
+    hello.extend(com_port_command(SET_STOPSIZE, &[settings.stop_bits]));
+    hello.extend(com_port_command(SET_CONTROL, &[control_code(settings.flow_control)]));
+    tcp.write_all(&hello).await?;
+
+    // Wait for the server to acknowledge the line settings, keeping any data
+    // that arrives in the meantime.
+    let mut parser = TelnetParser::default();
+    let mut negotiated = Negotiated::default();
+    let mut pending = Vec::new();
+    let mut buf = [0u8; 512];
+    let deadline = time::Instant::now() + NEGOTIATION_TIMEOUT;
+    while !negotiated.complete() {
+        let n = match time::timeout_at(deadline, tcp.read(&mut buf)).await {
+            Ok(read) => read?,
+            Err(_) => break,
+        };
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "server closed the connection (wrong or missing --remote-token?)",
+            ));
+        }
+        for &byte in &buf[..n] {
+            match parser.feed(byte) {
+                Some(TelnetEvent::Data(b)) => pending.push(b),
+                Some(TelnetEvent::Negotiate(verb, option)) => {
+                    if option == OPT_COM_PORT && matches!(verb, DONT | WONT) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::Unsupported,
+                            "server refused the COM-PORT-OPTION (not an RFC 2217 server?)",
+                        ));
+                    }
+                    if let Some(reply) = options.answer(verb, option) {
+                        tcp.write_all(&reply).await?;
+                    }
+                }
+                Some(TelnetEvent::Subnegotiation(payload)) => negotiated.record(&payload),
+                None => {}
+            }
+        }
+    }
+
+    let (app_side, bridge_side) = tokio::io::duplex(BRIDGE_BUFFER);
+    let (tx_commands, rx_commands) = mpsc::unbounded_channel();
+    tokio::spawn(bridge(tcp, bridge_side, parser, options, pending, tx_commands.clone(), rx_commands));
+    let stream = Rfc2217Stream {
+        stream: app_side,
+        commands: tx_commands,
+    };
+    Ok((stream, negotiated))
+}
+
+/// Shuttles bytes between the connection and the duplex stream.
+async fn bridge(
+    tcp: Box<dyn Stream>,
+    local: DuplexStream,
+    mut parser: TelnetParser,
+    mut options: Options,
+    pending: Vec<u8>,
+    tx_reply: mpsc::UnboundedSender<Vec<u8>>,
+    mut rx_reply: mpsc::UnboundedReceiver<Vec<u8>>,
+) {
+    let (mut tcp_read, mut tcp_write) = tokio::io::split(tcp);
+    let (mut local_read, mut local_write) = tokio::io::split(local);
+
+    let inbound = async move {
+        if local_write.write_all(&pending).await.is_err() {
+            return;
+        }
+        let mut buf = [0u8; 512];
+        let mut data = Vec::with_capacity(buf.len());
+        loop {
+            let n = match tcp_read.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            };
+            data.clear();
+            for &byte in &buf[..n] {
+                match parser.feed(byte) {
+                    Some(TelnetEvent::Data(b)) => data.push(b),
+                    Some(TelnetEvent::Negotiate(verb, option)) => {
+                        if let Some(reply) = options.answer(verb, option) {
+                            let _ = tx_reply.send(reply.to_vec());
+                        }
+                    }
+                    // Line/modem state notifications are not shown yet.
+                    Some(TelnetEvent::Subnegotiation(_)) | None => {}
+                }
+            }
+            if local_write.write_all(&data).await.is_err() {
+                return;
+            }
+        }
+    };
+
+    let outbound = async move {
+        let mut buf = [0u8; 512];
+        let mut escaped = Vec::with_capacity(buf.len() * 2);
+        loop {
+            tokio::select! {
+                read = local_read.read(&mut buf) => {
+                    let n = match read {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => n,
+                    };
+                    escaped.clear();
+                    escape(&buf[..n], &mut escaped);
+                    if tcp_write.write_all(&escaped).await.is_err() {
+                        return;
+                    }
+                }
+                Some(reply) = rx_reply.recv() => {
+                    if tcp_write.write_all(&reply).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = inbound => {}
+        _ = outbound => {}
+    }
+}
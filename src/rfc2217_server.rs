@@ -0,0 +1,153 @@
+//! RFC 2217 server exposing the locally opened port to other tools.
+//!
+//! Every connected client receives the device output and may write to the
+//! device; line setting changes requested by a client are applied to the
+//! local port and acknowledged with the values actually in effect. Clients
+//! authenticate and connect over TLS as [`crate::access`] requires.
+
+use std::sync::Arc;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+
+use crate::{
+    access::Access,
+    port::PortHandle,
+    rfc2217::{
+        com_port_command, control_code, escape, flow_from_code, parity_code, parity_from_code, Options,
+        TelnetEvent, TelnetParser, CONTROL_BREAK_OFF, CONTROL_BREAK_ON, CONTROL_DTR_OFF, CONTROL_DTR_ON,
+        CONTROL_RTS_OFF, CONTROL_RTS_ON, OPT_BINARY, OPT_COM_PORT, OPT_SGA, PURGE_DATA, SERVER_OFFSET,
+        SET_BAUDRATE, SET_CONTROL, SET_DATASIZE, SET_LINESTATE_MASK, SET_MODEMSTATE_MASK, SET_PARITY,
+        SET_STOPSIZE, SIGNATURE,
+    },
+    transport::Control,
+};
+
+/// Accepts clients on `listener` for as long as the monitor runs.
+pub fn spawn(listener: TcpListener, port: PortHandle, access: Arc<Access>) {
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve_client(stream, port.clone(), access.clone()));
+        }
+    });
+}
+
+async fn serve_client(stream: TcpStream, port: PortHandle, access: Arc<Access>) {
+    let _ = stream.set_nodelay(true);
+    let Ok(mut stream) = access.accept(stream).await else {
+        return;
+    };
+    if !access.login(&mut stream).await {
+        return;
+    }
+    let (mut tcp_read, mut tcp_write) = tokio::io::split(stream);
+    let mut raw = port.raw.subscribe();
+
+    let mut options = Options::server();
+    let mut hello = Vec::new();
+    for option in [OPT_BINARY, OPT_SGA] {
+        hello.extend_from_slice(&options.offer(option));
+    }
+    for option in [OPT_BINARY, OPT_SGA, OPT_COM_PORT] {
+        hello.extend_from_slice(&options.request(option));
+    }
+    if tcp_write.write_all(&hello).await.is_err() {
+        return;
+    }
+
+    let mut parser = TelnetParser::default();
+    let mut buf = [0u8; 512];
+    let mut data = Vec::with_capacity(buf.len());
+    let mut out = Vec::with_capacity(buf.len() * 2);
+    loop {
+        out.clear();
+        tokio::select! {
+            read = tcp_read.read(&mut buf) => {
+                let n = match read {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => n,
+                };
+                data.clear();
+                for &byte in &buf[..n] {
+                    match parser.feed(byte) {
+                        Some(TelnetEvent::Data(b)) => data.push(b),
+                        Some(TelnetEvent::Negotiate(verb, option)) => {
+                            if let Some(reply) = options.answer(verb, option) {
+                                out.extend_from_slice(&reply);
+                            }
+                        }
+                        Some(TelnetEvent::Subnegotiation(payload)) => {
+                            if let Some(reply) = handle_command(&payload, &port).await {
+                                out.extend(reply);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                if !data.is_empty() {
+                    port.write(data.clone());
+                }
+            }
+            chunk = raw.recv() => match chunk {
+                Ok(chunk) => escape(&chunk, &mut out),
+                // A slow client misses data rather than stalling the device.
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+        }
+        if !out.is_empty() && tcp_write.write_all(&out).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Executes a client COM-PORT-OPTION command, returning the server reply.
+async fn handle_command(payload: &[u8], port: &PortHandle) -> Option<Vec<u8>> {
+    let [OPT_COM_PORT, command, value @ ..] = payload else {
+        return None;
+    };
+    let command = *command;
+    let byte = value.first().copied().unwrap_or(0);
+
+    // A value of zero is a query; anything else is a change request. Failed
+    // changes are reported implicitly by replying with the current value.
+    let requested = match command {
+        SET_BAUDRATE => match value {
+            [a, b, c, d] => Some(u32::from_be_bytes([*a, *b, *c, *d])).filter(|&baud| baud != 0).map(Control::BaudRate),
+            _ => None,
+        },
+        SET_DATASIZE => matches!(byte, 5..=8).then_some(Control::DataBits(byte)),
+        SET_PARITY => parity_from_code(byte).map(Control::Parity),
+        SET_STOPSIZE => matches!(byte, 1 | 2).then_some(Control::StopBits(byte)),
+        SET_CONTROL => match byte {
+            CONTROL_BREAK_ON => Some(Control::Break(true)),
+            CONTROL_BREAK_OFF => Some(Control::Break(false)),
+            CONTROL_DTR_ON => Some(Control::Dtr(true)),
+            CONTROL_DTR_OFF => Some(Control::Dtr(false)),
+            CONTROL_RTS_ON => Some(Control::Rts(true)),
+            CONTROL_RTS_OFF => Some(Control::Rts(false)),
+            code => flow_from_code(code).map(Control::Flow),
+        },
+        _ => None,
+    };
+    if let Some(control) = requested {
+        let _ = port.control(control).await;
+    }
+
+    let settings = *port.settings.borrow();
+    let reply_value = match command {
+        SIGNATURE => concat!("serial-monitor ", env!("CARGO_PKG_VERSION")).as_bytes().to_vec(),
+        SET_BAUDRATE => settings.baud_rate.to_be_bytes().to_vec(),
+        SET_DATASIZE => vec![settings.data_bits],
+        SET_PARITY => vec![parity_code(settings.parity)],
+        SET_STOPSIZE => vec![settings.stop_bits],
+        SET_CONTROL if byte == 0 || flow_from_code(byte).is_some() => vec![control_code(settings.flow_control)],
+        SET_CONTROL => vec![byte],
+        SET_LINESTATE_MASK | SET_MODEMSTATE_MASK | PURGE_DATA => value.to_vec(),
+        _ => return None,
+    };
+    Some(com_port_command(command + SERVER_OFFSET, &reply_value))
+}
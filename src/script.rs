@@ -0,0 +1,320 @@
+//! Steps of a macro, run against a port.
+//!
+//! Besides lines to send, each after a delay in milliseconds, a macro may
+//! wait for the device's response and capture parts of it:
+//!
+//! ```text
+//! 0 AT+GSN
+//! expect 2s ^(?<serial>\d{15})$
+//! 0 AT+REGISTER={serial}
+//! ```
+//!
+//! `expect [TIMEOUT] REGEX` waits up to `TIMEOUT` (default 5s) for a line
+//! matching `REGEX`; its named groups become variables that later lines
+//! refer to as `{name}`. The steps after an `expect` only run once it
+//! matched: a timeout stops the macro, so a registration is never sent
+//! without the serial number it needs. `check NAME MIN..MAX` stops it
+//! unless variable `NAME` is a number in the range (either end may be left
+//! out), e.g. `check vbat 3.3..4.2`. `pins STEPS` drives the control lines
+//! through a sequence (see [`crate::signals`]), e.g. `pins rts=1 100ms rts=0`
+//! to reset the device, and the next step's delay counts from its end.
+//! Credentials are referred to as `{{env:NAME}}` or `{{secret:NAME}}`
+//! (see [`crate::secrets`]), and
+//! `{seq}` and the other placeholders of [`crate::placeholders`] are filled
+//! in unless a variable has their name.
+//!
+//! Each `expect` and `check` is an assertion whose outcome is kept for the
+//! reports of `serial-monitor run`.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow};
+use regex::Regex;
+
+use crate::{
+    ping, placeholders,
+    secrets::{self, Secrets},
+    signals::{self, Sequence},
+};
+
+/// How long an `expect` waits without a timeout of its own.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// A line sent after a delay, with `{name}` placeholders.
+    Send { delay: Duration, text: String },
+    /// A line to wait for, its named groups captured.
+    Expect { regex: Regex, timeout: Duration },
+    /// A captured variable that must be a number in a range.
+    Check { name: String, min: Option<f64>, max: Option<f64> },
+    /// Control line changes.
+    Pins(Sequence),
+}
+
+impl Step {
+    /// The step as written, naming it in reports.
+    pub fn describe(&self) -> String {
+        match self {
+            Step::Send { text, .. } => format!("send {text}"),
+            Step::Expect { regex, .. } => format!("expect {regex}"),
+            Step::Check { name, min, max } => format!("check {name} {}", range(*min, *max)),
+            Step::Pins(sequence) => format!("pins {}", sequence.describe()),
+        }
+    }
+
+    fn is_assertion(&self) -> bool {
+        matches!(self, Step::Expect { .. } | Step::Check { .. })
+    }
+}
+
+fn range(min: Option<f64>, max: Option<f64>) -> String {
+    let bound = |bound: Option<f64>| bound.map(|bound| bound.to_string()).unwrap_or_default();
+    format!("{}..{}", bound(min), bound(max))
+}
+
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    /// Not run, as an earlier step failed.
+    Skipped,
+}
+
+/// The outcome of an `expect` or `check`.
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    pub name: String,
+    pub outcome: Outcome,
+    /// From the end of the step before to the outcome.
+    pub took: Duration,
+}
+
+/// Parses the steps in `text`, naming `origin` in errors.
+pub fn parse(text: &str, origin: &str) -> Result<Vec<Step>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| parse_step(line).with_context(|| format!("{origin}:{}", number + 1)))
+        .collect()
+}
+
+fn parse_step(line: &str) -> Result<Step> {
+    if let Some(rest) = line.strip_prefix("expect ") {
+        let rest = rest.trim_start();
+        let (timeout, pattern) = match rest.split_once(' ').and_then(|(first, pattern)| {
+            ping::parse_interval(first).map(|timeout| (timeout, pattern.trim_start()))
+        }) {
+            Some((timeout, pattern)) => (timeout, pattern),
+            None => (DEFAULT_TIMEOUT, rest),
+        };
+        let regex = Regex::new(pattern).map_err(|e| anyhow!("invalid regex in expect: {e}"))?;
+        return Ok(Step::Expect { regex, timeout });
+    }
+    if let Some(rest) = line.strip_prefix("check ") {
+        let usage = || anyhow!("expected check NAME MIN..MAX, e.g. check vbat 3.3..4.2");
+        let (name, range) = rest.trim().split_once(' ').ok_or_else(usage)?;
+        let (min, max) = range.trim().split_once("..").ok_or_else(usage)?;
+        let bound = |bound: &str| match bound {
+            "" => Ok(None),
+            bound => bound.parse().map(Some).map_err(|_| usage()),
+        };
+        return Ok(Step::Check { name: name.to_string(), min: bound(min)?, max: bound(max)? });
+    }
+    if let Some(spec) = line.strip_prefix("pins ") {
+        return signals::parse(spec).map(Step::Pins).map_err(|e| anyhow!(e));
+    }
+    let (delay, text) = line.split_once(' ').unwrap_or((line, ""));
+    let delay = delay
+        .parse()
+        .map_err(|_| anyhow!("expected the delay in milliseconds before the line, expect [TIMEOUT] REGEX or pins STEPS"))?;
+    Ok(Step::Send { delay: Duration::from_millis(delay), text: text.to_string() })
+}
+
+/// What running a macro led to.
+pub enum Progress {
+    /// Lines due to be sent.
+    Send(Vec<String>),
+    /// Lines due to be sent, then control line changes to make.
+    Pins(Vec<String>, Sequence),
+    /// The macro stopped at a step, e.g. an `expect` timing out.
+    Failed(String),
+}
+
+/// A macro being run, polled for the lines due and fed the lines received.
+pub struct Runner {
+    steps: VecDeque<Step>,
+    variables: BTreeMap<String, String>,
+    assertions: Vec<Assertion>,
+    secrets: Secrets,
+    /// When the last step completed, which the next one's delay or timeout
+    /// counts from.
+    last: Instant,
+}
+
+impl Runner {
+    pub fn new(steps: Vec<Step>, secrets: Secrets) -> Self {
+        Self {
+            steps: steps.into(),
+            variables: BTreeMap::new(),
+            assertions: Vec::new(),
+            secrets,
+            last: Instant::now(),
+        }
+    }
+
+    /// The lines due at `now`, up to the next `expect` still waiting or
+    /// `pins`.
+    pub fn poll(&mut self, now: Instant) -> Progress {
+        let mut due = Vec::new();
+        while let Some(step) = self.steps.front() {
+            match step {
+                Step::Send { delay, text } if now >= self.last + *delay => {
+                    match self.expand(text) {
+                        Ok(text) => due.push(text),
+                        Err(e) => return self.fail(e, now),
+                    }
+                    self.steps.pop_front();
+                    self.last = now;
+                }
+                Step::Expect { regex, timeout } if now >= self.last + *timeout => {
+                    let e = format!("nothing matching {regex} arrived within {:.1}s", timeout.as_secs_f64());
+                    return self.fail(e, now);
+                }
+                Step::Check { name, min, max } => {
+                    let value = self.variables.get(name).ok_or_else(|| format!("no variable {name} was captured"));
+                    let checked = value.and_then(|value| {
+                        let number: f64 = value.trim().parse().map_err(|_| format!("{name} = {value} is not a number"))?;
+                        match min.is_none_or(|min| number >= min) && max.is_none_or(|max| number <= max) {
+                            true => Ok(()),
+                            false => Err(format!("{name} = {value} is outside {}", range(*min, *max))),
+                        }
+                    });
+                    match checked {
+                        Ok(()) => self.pass(now),
+                        Err(e) => return self.fail(e, now),
+                    }
+                }
+                Step::Pins(sequence) => {
+                    let sequence = sequence.clone();
+                    self.steps.pop_front();
+                    self.last = now + sequence.duration();
+                    return Progress::Pins(due, sequence);
+                }
+                _ => break,
+            }
+        }
+        Progress::Send(due)
+    }
+
+    /// Checks a received line against the `expect` being waited for,
+    /// returning the variables it captured if it matched.
+    pub fn received(&mut self, line: &str, now: Instant) -> Option<Vec<(String, String)>> {
+        let Some(Step::Expect { regex, .. }) = self.steps.front() else {
+            return None;
+        };
+        let captures = regex.captures(line)?;
+        let captured: Vec<(String, String)> = regex
+            .capture_names()
+            .flatten()
+            .filter_map(|name| Some((name.to_string(), captures.name(name)?.as_str().to_string())))
+            .collect();
+        self.variables.extend(captured.iter().cloned());
+        self.pass(now);
+        Some(captured)
+    }
+
+    /// Records the assertion at the front as passed and moves on.
+    fn pass(&mut self, now: Instant) {
+        if let Some(step) = self.steps.pop_front() {
+            self.assertions.push(Assertion { name: step.describe(), outcome: Outcome::Passed, took: now - self.last });
+        }
+        self.last = now;
+    }
+
+    /// Stops at the step at the front, skipping the assertions after it.
+    fn fail(&mut self, e: String, now: Instant) -> Progress {
+        let took = now - self.last;
+        let mut steps = self.steps.drain(..);
+        if let Some(step) = steps.next() {
+            self.assertions.push(Assertion { name: step.describe(), outcome: Outcome::Failed(e.clone()), took });
+        }
+        let skipped = steps
+            .filter(Step::is_assertion)
+            .map(|step| Assertion { name: step.describe(), outcome: Outcome::Skipped, took: Duration::ZERO });
+        self.assertions.extend(skipped.collect::<Vec<_>>());
+        Progress::Failed(e)
+    }
+
+    /// The outcomes of the assertions run or skipped so far.
+    pub fn assertions(&self) -> &[Assertion] {
+        &self.assertions
+    }
+
+    /// `text` with its `{name}` placeholders replaced by the variables and
+    /// its `{{KIND:NAME}}` references by their values; braces around anything
+    /// else, as in JSON, are kept.
+    fn expand(&self, text: &str) -> Result<String, String> {
+        let mut expanded = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            if let Some(reference) = secrets::reference(&rest[start..]) {
+                expanded.push_str(&rest[..start]);
+                expanded.push_str(&self.secrets.lookup(reference)?);
+                rest = &rest[start + reference.len() + 4..];
+                continue;
+            }
+            let name = rest[start + 1..].split('}').next().filter(|name| {
+                name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            });
+            let name = match name {
+                Some(name) if rest[start + 1 + name.len()..].starts_with('}') => name,
+                _ => {
+                    expanded.push_str(&rest[..=start]);
+                    rest = &rest[start + 1..];
+                    continue;
+                }
+            };
+            let value = match self.variables.get(name) {
+                Some(value) => value,
+                // Filled in as the line is sent
+                None if placeholders::is_placeholder(name) => &rest[start..start + name.len() + 2],
+                None => return Err(format!("no variable {{{name}}} was captured before {text:?}")),
+            };
+            expanded.push_str(&rest[..start]);
+            expanded.push_str(value);
+            rest = &rest[start + name.len() + 2..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    /// The values of the references in the lines still to be sent, to be
+    /// masked where the lines are shown.
+    pub fn secret_values(&self) -> Vec<String> {
+        self.steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Send { text, .. } => Some(secrets::references(text)),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|reference| self.secrets.lookup(reference).ok())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+
+    /// Whether every step ran.
+    pub fn is_done(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Steps not run yet.
+    pub fn remaining(&self) -> usize {
+        self.steps.len()
+    }
+}
@@ -0,0 +1,90 @@
+//! Memory budget of the output pane's scrollback.
+//!
+//! Entries are counted by the memory they hold (text, pretty-printed JSON,
+//! raw bytes kept for the details popup) rather than by number, so a session
+//! of short lines keeps a long history and one of huge lines doesn't grow
+//! without bound. Once the budget is exceeded, the oldest entries are dropped
+//! until a sixteenth of it is free again, so trimming doesn't shift the
+//! whole scrollback on every new line.
+
+use crate::output::OutputLine;
+
+/// Parses sizes such as `512KB`, `8MB`, `1GB` or a plain byte count.
+pub fn parse_size(text: &str) -> Result<usize, String> {
+    let upper = text.trim().to_ascii_uppercase();
+    let (number, scale) = if let Some(n) = upper.strip_suffix("KB") {
+        (n, 1024)
+    } else if let Some(n) = upper.strip_suffix("MB") {
+        (n, 1024 * 1024)
+    } else if let Some(n) = upper.strip_suffix("GB") {
+        (n, 1024 * 1024 * 1024)
+    } else {
+        (upper.strip_suffix('B').unwrap_or(&upper), 1)
+    };
+    match number.trim().parse::<usize>() {
+        Ok(n) if n > 0 => n.checked_mul(scale).ok_or_else(|| format!("Size {text:?} is too large")),
+        _ => Err(format!("Invalid size {text:?}. Expected e.g. 512KB or 8MB")),
+    }
+}
+
+/// `bytes` in the largest unit that keeps it above 1, e.g. `1.5 MB`.
+pub fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1048576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        1048576..1073741824 => format!("{:.1} MB", bytes as f64 / 1048576.0),
+        _ => format!("{:.1} GB", bytes as f64 / 1073741824.0),
+    }
+}
+
+pub struct Budget {
+    limit: usize,
+    /// Bytes held by the first `counted` entries.
+    used: usize,
+    counted: usize,
+}
+
+impl Budget {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: 0,
+            counted: 0,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// Bytes held by the entries as of the last trim.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Counts entries added since the last call and drops the oldest ones
+    /// if over the budget. Returns the number dropped.
+    pub fn trim(&mut self, entries: &mut Vec<OutputLine>) -> usize {
+        // Entries are only appended, or dropped from the front by a trim
+        self.counted = self.counted.min(entries.len());
+        self.used += entries[self.counted..].iter().map(OutputLine::size).sum::<usize>();
+        self.counted = entries.len();
+        if self.used <= self.limit {
+            return 0;
+        }
+        let target = self.limit - self.limit / 16;
+        // The newest entry is kept even if it alone exceeds the budget
+        let mut dropped = 0;
+        while self.used > target && dropped + 1 < entries.len() {
+            self.used -= entries[dropped].size();
+            dropped += 1;
+        }
+        entries.drain(..dropped);
+        self.counted -= dropped;
+        dropped
+    }
+}
@@ -0,0 +1,89 @@
+//! Credentials that macros refer to instead of holding them.
+//!
+//! A macro line may contain `{{env:NAME}}`, replaced by environment variable
+//! `NAME` when the line is sent, or `{{secret:NAME}}`, replaced by `NAME`
+//! from the secrets file (`--secrets`, by default `secrets` next to the
+//! config file), so a macro can be shared and kept in git while the Wi-Fi
+//! password differs per machine. The file holds one `NAME=VALUE` per line,
+//! with `#` comments and optional quotes around the value:
+//!
+//! ```text
+//! WIFI_SSID=lab
+//! WIFI_PASS="correct horse"
+//! ```
+//!
+//! Values either way are masked like `--mask` matches wherever the lines
+//! sent are shown or logged.
+
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+/// `secrets` next to the config file, e.g. `~/.config/serial-monitor/secrets`.
+pub fn default_path() -> Option<PathBuf> {
+    crate::config::default_path().and_then(|path| path.parent().map(|dir| dir.join("secrets")))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Secrets {
+    file: BTreeMap<String, String>,
+}
+
+impl Secrets {
+    /// Reads `path`, or the default file if it exists.
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let text = fs::read_to_string(&path).with_context(|| format!("Cannot read the secrets file {}", path.display()))?;
+        let mut file = BTreeMap::new();
+        for (number, line) in text.lines().enumerate().map(|(number, line)| (number + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((name, value)) = line.split_once('=') else {
+                bail!("{}:{number}: expected NAME=VALUE", path.display());
+            };
+            let value = value.trim();
+            let value = match value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+                Some(quoted) => quoted,
+                None => value.strip_prefix('\'').and_then(|value| value.strip_suffix('\'')).unwrap_or(value),
+            };
+            file.insert(name.trim().to_string(), value.to_string());
+        }
+        Ok(Self { file })
+    }
+
+    /// The values in the secrets file.
+    pub fn values(&self) -> impl Iterator<Item = &str> {
+        self.file.values().map(String::as_str).filter(|value| !value.is_empty())
+    }
+
+    /// The value of a `{{KIND:NAME}}` reference, given as `KIND:NAME`.
+    pub fn lookup(&self, reference: &str) -> Result<String, String> {
+        match reference.split_once(':') {
+            Some(("env", name)) => std::env::var(name).map_err(|_| format!("environment variable {name} is not set")),
+            Some(("secret", name)) => {
+                self.file.get(name).cloned().ok_or_else(|| format!("no secret {name} in the secrets file"))
+            }
+            _ => Err(format!("unknown reference {{{{{reference}}}}}, expected {{{{env:NAME}}}} or {{{{secret:NAME}}}}")),
+        }
+    }
+}
+
+/// The `KIND:NAME` of a reference at the start of `text`.
+pub fn reference(text: &str) -> Option<&str> {
+    let (reference, _) = text.strip_prefix("{{")?.split_once("}}")?;
+    let (kind, name) = reference.split_once(':')?;
+    let word = |word: &str| !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    (word(kind) && word(name)).then_some(reference)
+}
+
+/// The `KIND:NAME` references in `text`, in order.
+pub fn references(text: &str) -> impl Iterator<Item = &str> {
+    text.match_indices("{{").filter_map(|(start, _)| reference(&text[start..]))
+}
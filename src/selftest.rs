@@ -0,0 +1,144 @@
+//! `serial-monitor selftest`: checks the installation without hardware.
+//!
+//! A simulated device exchanges lines with a port task, the same port is
+//! reached through the RFC 2217 server and client on loopback, and a capture
+//! is written, read back and seeked, so a broken build or environment shows
+//! up before a debugging session depends on it.
+
+use std::{future::Future, time::Duration};
+
+use anyhow::{Result, anyhow, bail};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::mpsc,
+    time::timeout,
+};
+
+use crate::{
+    capture::{Kind, Reader, Record, Writer},
+    config, discovery,
+    events::{PortEvent, SerialEvent},
+    framing::{Decoder, Framer, Framing, LengthPrefix},
+    port::{self, PortHandle, Restart, RestartPolicy},
+    rfc2217_server, simulator,
+    transport::{self, Encoding, SerialSettings},
+};
+
+/// Longest wait for data in any check.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Runs every check, printing one line each; fails if any did.
+pub async fn run(config_path: Option<&str>) -> Result<()> {
+    let mut failed = 0;
+    let mut report = |name: &str, outcome: Result<String>| match outcome {
+        Ok(detail) => println!("ok      {name}: {detail}"),
+        Err(e) => {
+            failed += 1;
+            println!("FAILED  {name}: {e:#}");
+        }
+    };
+    report("config", check_config(config_path));
+    report("ports", Ok(format!("{} found", discovery::list().len())));
+    report("port task", check_port_task().await);
+    report("rfc2217", check_rfc2217().await);
+    report("capture", check_capture());
+    if failed > 0 {
+        bail!("{failed} check(s) failed");
+    }
+    Ok(())
+}
+
+fn check_config(path: Option<&str>) -> Result<String> {
+    let config = config::load(path)?;
+    Ok(format!("{} profile(s)", config.profiles.len()))
+}
+
+/// A port task on a simulated device, as the monitor runs it.
+fn spawn_port(events: mpsc::UnboundedSender<PortEvent>) -> (PortHandle, tokio::io::DuplexStream) {
+    let (transport, device) = simulator::duplex();
+    let restart = Restart {
+        policy: RestartPolicy::Never,
+        open: Box::new(|_| Box::pin(async { Err(anyhow!("The self-test port can't be reopened")) })),
+        recover: None,
+    };
+    let framer = Framer::new(Framing::Lines, LengthPrefix::Varint);
+    let port = port::spawn(0, transport, restart, SerialSettings::default(), framer, Decoder::Text(Encoding::Utf8), events);
+    (port, device)
+}
+
+async fn within<T>(what: &str, future: impl Future<Output = T>) -> Result<T> {
+    timeout(TIMEOUT, future).await.map_err(|_| anyhow!("no {what} within {TIMEOUT:?}"))
+}
+
+async fn check_port_task() -> Result<String> {
+    let (tx_events, mut rx_events) = mpsc::unbounded_channel();
+    let (port, mut device) = spawn_port(tx_events);
+    device.write_all(b"ping\r\n").await?;
+    within("received line", async {
+        while let Some(event) = rx_events.recv().await {
+            if let SerialEvent::Rx { text, .. } = event.event {
+                return if text == "ping" { Ok(()) } else { Err(anyhow!("received {text:?}")) };
+            }
+        }
+        Err(anyhow!("the port task ended"))
+    })
+    .await??;
+    port.write(b"pong\n".to_vec());
+    let mut reply = [0; 5];
+    within("written line", device.read_exact(&mut reply)).await??;
+    if &reply != b"pong\n" {
+        bail!("device got {:?}", String::from_utf8_lossy(&reply));
+    }
+    Ok("ping received, pong sent".to_string())
+}
+
+async fn check_rfc2217() -> Result<String> {
+    let (tx_events, _rx_events) = mpsc::unbounded_channel();
+    let (port, mut device) = spawn_port(tx_events);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    rfc2217_server::spawn(listener, port, Default::default());
+    let mut client = within("connection", transport::open(&format!("rfc2217://{addr}"), &SerialSettings::default())).await??;
+    client.write_all(b"hello\n").await?;
+    let mut written = [0; 6];
+    within("data from the client", device.read_exact(&mut written)).await??;
+    device.write_all(b"world\n").await?;
+    let mut read = [0; 6];
+    within("data for the client", client.read_exact(&mut read)).await??;
+    if &written != b"hello\n" || &read != b"world\n" {
+        bail!("data was altered on the way");
+    }
+    Ok(format!("round trip through {addr}"))
+}
+
+fn check_capture() -> Result<String> {
+    let path = std::env::temp_dir().join(format!("serial-monitor-selftest-{}.cap", std::process::id()));
+    let path = path.to_str().ok_or_else(|| anyhow!("temporary directory path is not UTF-8"))?;
+    let result = capture_round_trip(path);
+    let _ = std::fs::remove_file(path);
+    result
+}
+
+fn capture_round_trip(path: &str) -> Result<String> {
+    const RECORDS: i64 = 100;
+    let mut writer = Writer::create(path, &serde_json::json!({ "ports": ["selftest"] }))?;
+    for second in 0..RECORDS {
+        writer.write(&Record {
+            kind: if second % 2 == 0 { Kind::Rx } else { Kind::Tx },
+            port: 0,
+            time: second * 1_000_000,
+            data: format!("record {second}\n").into_bytes(),
+        })?;
+    }
+    writer.finish()?;
+    let mut reader = Reader::open(path)?;
+    if let Some(damage) = &reader.damage {
+        bail!("damaged at offset {}: {}", damage.offset, damage.reason);
+    }
+    let found = reader.seek(RECORDS / 2 * 1_000_000)?.ok_or_else(|| anyhow!("seek found nothing"))?;
+    if found.data != format!("record {}\n", RECORDS / 2).as_bytes() {
+        bail!("seek found {:?}", String::from_utf8_lossy(&found.data));
+    }
+    Ok(format!("{RECORDS} records written, indexed and seeked"))
+}
@@ -0,0 +1,191 @@
+//! Saved workspaces for `--session`.
+//!
+//! `:save-session FILE` writes what a debugging setup is built from to a
+//! TOML file: the open ports with their tags and line settings, the watches,
+//! alerts and masks in use, the level filter, the macro directory, the view
+//! (pane, bus device, JSON, reveal and conversation modes, HUD) and the
+//! layout. `--session FILE` starts the monitor with all of it again, so the
+//! setup of each project is built once instead of by hand every time:
+//!
+//! ```toml
+//! port = ["/dev/ttyUSB0", "/dev/ttyUSB1"]
+//! alias = ["esp", "gps"]
+//! color = ["Magenta", "#ff8800"]
+//! baud_rate = 115200
+//! baud_rates = [115200, 9600]
+//! data_bits = 8
+//! parity = "none"
+//! stop_bits = 1
+//! flow_control = "none"
+//! line_ending = "crlf"
+//! encoding = "utf-8"
+//! watch = ["rssi=RSSI: (-?[0-9]+)"]
+//! alert = ["rssi < -80 => bell"]
+//! mask = ["AT\\+PWD=(.*)"]
+//! min_level = "info"
+//! macro_dir = "/home/me/project/macros"
+//!
+//! [view]
+//! pane = "received"
+//! device = "pump"
+//! pretty_json = true
+//! hud = false
+//!
+//! [layout]
+//! input_height = 3
+//! side_width = 48
+//! ```
+//!
+//! `port` holds the ports open when the session was saved, those attached
+//! with Ctrl+A included, and `baud_rates` the rate each was at when one was
+//! switched from `baud_rate`, e.g. by a Ctrl+S search.
+//!
+//! A session takes precedence over the profile, and options given on the
+//! command line over both.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::{
+    layout::PaneLayout,
+    level::Level,
+    output::Pane,
+    transport::{Encoding, FlowControlArg, LineEnding, ParityArg},
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub port: Vec<String>,
+    /// The script of the simulated device run instead of the port.
+    pub simulate: Option<String>,
+    /// Tags of the ports' lines, in `port` order.
+    pub alias: Vec<String>,
+    /// Colors of the ports' tags, in `port` order.
+    pub color: Vec<String>,
+    pub baud_rate: Option<u32>,
+    /// Rates of the ports, in `port` order, when they differ from `baud_rate`.
+    pub baud_rates: Vec<u32>,
+    pub data_bits: Option<u8>,
+    pub parity: Option<ParityArg>,
+    pub stop_bits: Option<u8>,
+    pub flow_control: Option<FlowControlArg>,
+    pub line_ending: Option<LineEnding>,
+    pub encoding: Option<Encoding>,
+    pub watch: Option<Vec<String>>,
+    pub alert: Option<Vec<String>>,
+    pub mask: Option<Vec<String>>,
+    pub min_level: Option<Level>,
+    pub macro_dir: Option<String>,
+    pub view: View,
+    pub layout: Option<PaneLayout>,
+}
+
+/// How the output was shown.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct View {
+    pub pane: Option<Pane>,
+    /// The bus device whose frames alone were shown.
+    pub device: Option<String>,
+    pub pretty_json: bool,
+    pub reveal: bool,
+    pub conversation: bool,
+    pub hud: bool,
+}
+
+/// Reads the session saved at `path`.
+pub fn load(path: &str) -> Result<Session> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read session file {path}"))?;
+    toml::from_str(&text).with_context(|| format!("Invalid session file {path}"))
+}
+
+/// The name a value has on the command line, which is also its name in the file.
+fn name(value: impl ValueEnum) -> String {
+    value.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+}
+
+fn strings(values: &[String]) -> toml_edit::Item {
+    toml_edit::value(values.iter().collect::<toml_edit::Array>())
+}
+
+impl Session {
+    /// Writes the session to `path`, replacing what was saved there.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut document = toml_edit::DocumentMut::new();
+        document["port"] = strings(&self.port);
+        if let Some(script) = &self.simulate {
+            document["simulate"] = toml_edit::value(script.as_str());
+        }
+        if !self.alias.is_empty() {
+            document["alias"] = strings(&self.alias);
+        }
+        if !self.color.is_empty() {
+            document["color"] = strings(&self.color);
+        }
+        if let Some(baud_rate) = self.baud_rate {
+            document["baud_rate"] = toml_edit::value(i64::from(baud_rate));
+        }
+        if !self.baud_rates.is_empty() {
+            document["baud_rates"] = toml_edit::value(self.baud_rates.iter().map(|&rate| i64::from(rate)).collect::<toml_edit::Array>());
+        }
+        if let Some(data_bits) = self.data_bits {
+            document["data_bits"] = toml_edit::value(i64::from(data_bits));
+        }
+        if let Some(parity) = self.parity {
+            document["parity"] = toml_edit::value(name(parity));
+        }
+        if let Some(stop_bits) = self.stop_bits {
+            document["stop_bits"] = toml_edit::value(i64::from(stop_bits));
+        }
+        if let Some(flow_control) = self.flow_control {
+            document["flow_control"] = toml_edit::value(name(flow_control));
+        }
+        if let Some(ending) = self.line_ending {
+            document["line_ending"] = toml_edit::value(name(ending));
+        }
+        if let Some(encoding) = self.encoding {
+            document["encoding"] = toml_edit::value(name(encoding));
+        }
+        for (key, values) in [("watch", &self.watch), ("alert", &self.alert), ("mask", &self.mask)] {
+            if let Some(values) = values {
+                document[key] = strings(values);
+            }
+        }
+        if let Some(level) = self.min_level {
+            document["min_level"] = toml_edit::value(name(level));
+        }
+        if let Some(dir) = &self.macro_dir {
+            document["macro_dir"] = toml_edit::value(dir.as_str());
+        }
+        let mut view = toml_edit::Table::new();
+        if let Some(pane) = self.view.pane {
+            view["pane"] = toml_edit::value(match pane {
+                Pane::All => "all",
+                Pane::Sent => "sent",
+                Pane::Received => "received",
+            });
+        }
+        if let Some(device) = &self.view.device {
+            view["device"] = toml_edit::value(device.as_str());
+        }
+        view["pretty_json"] = toml_edit::value(self.view.pretty_json);
+        view["reveal"] = toml_edit::value(self.view.reveal);
+        view["conversation"] = toml_edit::value(self.view.conversation);
+        view["hud"] = toml_edit::value(self.view.hud);
+        document["view"] = toml_edit::Item::Table(view);
+        if let Some(layout) = &self.layout {
+            let mut table = toml_edit::Table::new();
+            layout.store(&mut table);
+            document["layout"] = toml_edit::Item::Table(table);
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, document.to_string())
+            .with_context(|| format!("Failed to write session file {}", path.display()))
+    }
+}
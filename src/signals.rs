@@ -0,0 +1,144 @@
+//! Timed sequences of the DTR, RTS and break control lines.
+//!
+//! Development boards wire DTR and RTS to their reset and boot-mode pins,
+//! and a waveform on them resets the chip or enters its bootloader. A
+//! sequence is written as steps separated by spaces: `dtr=1` asserts DTR
+//! and `dtr=0` releases it (likewise `rts` and `break`), and a duration
+//! such as `100ms` or `1s` waits before the next change. The ESP32's
+//! bootloader is entered with
+//!
+//! ```text
+//! dtr=0 rts=1 100ms dtr=1 rts=0 50ms dtr=0
+//! ```
+//!
+//! Sequences are named with `--sequence NAME=STEPS` or in the `[sequences]`
+//! table of the config file and run on the active port with `:reset NAME`;
+//! `esp32`, `esp32-run` and `arduino` are built in. Macros change the lines
+//! with `pins STEPS`.
+
+use std::{io, time::Duration};
+
+use ratatui::style::{Color, Style};
+use tokio::{sync::mpsc, time};
+
+use crate::{output::OutputLine, port::PortHandle, transport::Control};
+
+/// Sequences known without being defined.
+pub const BUILT_IN: [(&str, &str); 3] = [
+    // esptool's classic reset into the bootloader: GPIO0 held low while EN rises
+    ("esp32", "dtr=0 rts=1 100ms dtr=1 rts=0 50ms dtr=0"),
+    // A pulse on EN restarts the application
+    ("esp32-run", "dtr=0 rts=1 100ms rts=0"),
+    // The auto-reset capacitor of an Uno turns a DTR edge into a reset pulse
+    ("arduino", "dtr=0 rts=0 250ms dtr=1 rts=1 50ms"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// A control line set or released.
+    Set(Control),
+    Wait(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequence {
+    pub signals: Vec<Signal>,
+}
+
+/// A wait such as `50ms` or `1.5s`.
+fn parse_wait(text: &str) -> Option<Duration> {
+    let seconds = match text.strip_suffix("ms") {
+        Some(ms) => ms.parse::<f64>().ok()? / 1000.0,
+        None => text.strip_suffix('s')?.parse::<f64>().ok()?,
+    };
+    (seconds.is_finite() && seconds >= 0.0).then(|| Duration::from_secs_f64(seconds))
+}
+
+/// Parses the steps of a sequence, e.g. `rts=1 100ms rts=0`.
+pub fn parse(spec: &str) -> Result<Sequence, String> {
+    let signals = spec
+        .split_whitespace()
+        .map(|step| {
+            if let Some(wait) = parse_wait(step) {
+                return Ok(Signal::Wait(wait));
+            }
+            let invalid = || format!("Invalid step {step:?}. Expected dtr=0|1, rts=0|1, break=0|1 or a wait such as 100ms");
+            let (line, level) = step.split_once('=').ok_or_else(invalid)?;
+            let level = match level {
+                "1" => true,
+                "0" => false,
+                _ => return Err(invalid()),
+            };
+            match line.to_ascii_lowercase().as_str() {
+                "dtr" => Ok(Signal::Set(Control::Dtr(level))),
+                "rts" => Ok(Signal::Set(Control::Rts(level))),
+                "break" => Ok(Signal::Set(Control::Break(level))),
+                _ => Err(invalid()),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if !signals.iter().any(|signal| matches!(signal, Signal::Set(_))) {
+        return Err(format!("The sequence {spec:?} changes no line"));
+    }
+    Ok(Sequence { signals })
+}
+
+/// Parses `NAME=STEPS` as given to `--sequence`.
+pub fn parse_named(spec: &str) -> Result<(String, Sequence), String> {
+    let Some((name, steps)) = spec.split_once('=').filter(|(name, _)| !name.is_empty() && !name.contains(' ')) else {
+        return Err(format!("Invalid sequence {spec:?}. Expected NAME=STEPS, e.g. boot=\"rts=1 100ms rts=0\""));
+    };
+    Ok((name.to_string(), parse(steps)?))
+}
+
+impl Sequence {
+    /// The steps as written, e.g. `rts=1 100ms rts=0`.
+    pub fn describe(&self) -> String {
+        let level = |level: bool| if level { 1 } else { 0 };
+        let steps: Vec<String> = self
+            .signals
+            .iter()
+            .map(|signal| match signal {
+                Signal::Set(Control::Dtr(on)) => format!("dtr={}", level(*on)),
+                Signal::Set(Control::Rts(on)) => format!("rts={}", level(*on)),
+                Signal::Set(Control::Break(on)) => format!("break={}", level(*on)),
+                Signal::Set(control) => format!("{control:?}"),
+                Signal::Wait(wait) => format!("{}ms", wait.as_secs_f64() * 1000.0),
+            })
+            .collect();
+        steps.join(" ")
+    }
+
+    /// How long the sequence takes.
+    pub fn duration(&self) -> Duration {
+        self.signals
+            .iter()
+            .map(|signal| match signal {
+                Signal::Wait(wait) => *wait,
+                Signal::Set(_) => Duration::ZERO,
+            })
+            .sum()
+    }
+
+    /// Drives the lines of `port` through the sequence.
+    pub async fn run(&self, port: &PortHandle) -> io::Result<()> {
+        for signal in &self.signals {
+            match signal {
+                Signal::Set(control) => port.control(*control).await?,
+                Signal::Wait(wait) => time::sleep(*wait).await,
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the sequence in the background, reporting a failure to drive
+    /// the lines of `port` with `annotations`.
+    pub fn start(self, port: PortHandle, name: String, annotations: mpsc::UnboundedSender<OutputLine>) {
+        tokio::spawn(async move {
+            if let Err(e) = self.run(&port).await {
+                let text = format!("Sequence {name} failed: {e}");
+                let _ = annotations.send(OutputLine::new("[Monitor] ", text, Style::default().fg(Color::Red)));
+            }
+        });
+    }
+}
@@ -0,0 +1,300 @@
+//! Scripted fake device for development and demos without hardware.
+//!
+//! A script is a list of directives, one per line (`#` starts a comment):
+//!
+//! ```text
+//! say Booting sensor v1.2
+//! every 1s T:{rand 20.0..25.0} RSSI: -{rand 40..70} n={counter}
+//! on ^AT$ => OK
+//! on ^get (\w+)$ => $1=42\nOK
+//! latency 20ms
+//! noise 0.001
+//! echo
+//! ```
+//!
+//! `say` lines are sent once at startup, `every` lines periodically, and `on`
+//! rules answer received lines matching a regex (`$1` expands captures, `\n`
+//! separates reply lines). `latency` delays replies, `noise` corrupts each
+//! sent byte with the given probability and `echo` sends input back.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use regex::Regex;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf, WriteHalf},
+    time,
+};
+
+use crate::transport::{Control, Transport};
+
+struct Periodic {
+    period: Duration,
+    template: String,
+}
+
+struct Rule {
+    pattern: Regex,
+    reply: String,
+}
+
+#[derive(Default)]
+struct Script {
+    banner: Vec<String>,
+    periodic: Vec<Periodic>,
+    rules: Vec<Rule>,
+    latency: Duration,
+    noise: f64,
+    echo: bool,
+}
+
+fn parse_duration(text: &str) -> Result<Duration> {
+    let (number, scale) = if let Some(ms) = text.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(s) = text.strip_suffix('s') {
+        (s, 1.0)
+    } else {
+        bail!("invalid duration {text:?}, expected e.g. 500ms or 2s");
+    };
+    let value: f64 = number.parse().map_err(|_| anyhow!("invalid duration {text:?}"))?;
+    Ok(Duration::from_secs_f64(value * scale))
+}
+
+fn parse_script(text: &str) -> Result<Script> {
+    let mut script = Script::default();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (directive, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let parsed: Result<()> = (|| {
+            match directive {
+                "say" => script.banner.push(rest.to_string()),
+                "every" => {
+                    let (period, template) = rest
+                        .split_once(char::is_whitespace)
+                        .ok_or_else(|| anyhow!("expected: every <duration> <text>"))?;
+                    script.periodic.push(Periodic {
+                        period: parse_duration(period)?,
+                        template: template.trim().to_string(),
+                    });
+                }
+                "on" => {
+                    let (pattern, reply) = rest
+                        .split_once("=>")
+                        .ok_or_else(|| anyhow!("expected: on <regex> => <reply>"))?;
+                    script.rules.push(Rule {
+                        pattern: Regex::new(pattern.trim())?,
+                        reply: reply.trim().to_string(),
+                    });
+                }
+                "latency" => script.latency = parse_duration(rest)?,
+                "noise" => {
+                    script.noise = rest
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|p| (0.0..=1.0).contains(p))
+                        .ok_or_else(|| anyhow!("noise must be a probability between 0 and 1"))?;
+                }
+                "echo" => script.echo = true,
+                _ => bail!("unknown directive {directive:?}"),
+            }
+            Ok(())
+        })();
+        parsed.with_context(|| format!("line {}", number + 1))?;
+    }
+    Ok(script)
+}
+
+/// Small xorshift generator; the simulator needs variety, not quality.
+struct Random(u64);
+
+impl Random {
+    fn new() -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64);
+        Self(seed | 1)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Expands `{rand A..B}`, `{counter}` and `{uptime}` placeholders.
+fn render(template: &str, counter: u64, started: Instant, random: &mut Random) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        // An unclosed brace is kept as text with the rest
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let placeholder = &rest[start + 1..start + end];
+        match placeholder.split_once(' ') {
+            _ if placeholder == "counter" => out.push_str(&counter.to_string()),
+            _ if placeholder == "uptime" => out.push_str(&started.elapsed().as_millis().to_string()),
+            Some(("rand", range)) if range.contains("..") => {
+                let (low, high) = range.split_once("..").unwrap_or_default();
+                let value = match (low.trim().parse::<f64>(), high.trim().parse::<f64>()) {
+                    (Ok(low), Ok(high)) => low + (high - low) * random.next_f64(),
+                    _ => 0.0,
+                };
+                // Integer bounds give integer values
+                if low.contains('.') || high.contains('.') {
+                    let decimals = [low, high]
+                        .iter()
+                        .map(|bound| bound.split_once('.').map_or(0, |(_, d)| d.trim().len()))
+                        .max()
+                        .unwrap_or(1)
+                        .min(6);
+                    out.push_str(&format!("{value:.decimals$}"));
+                } else {
+                    out.push_str(&(value.round() as i64).to_string());
+                }
+            }
+            _ => out.push_str(&rest[start..start + end + 1]),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The monitor's end of the simulated device.
+pub struct SimulatedPort {
+    stream: DuplexStream,
+}
+
+impl AsyncRead for SimulatedPort {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for SimulatedPort {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+impl Transport for SimulatedPort {
+    /// Line settings have no effect on a simulated device.
+    fn control(&mut self, _control: Control) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Loads `path` and starts the simulated device.
+pub fn open(path: &str) -> Result<Box<dyn Transport>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read simulation script {path}"))?;
+    let script = parse_script(&text).with_context(|| format!("Invalid simulation script {path}"))?;
+    let (port, device) = duplex();
+    tokio::spawn(run(script, device));
+    Ok(port)
+}
+
+/// A transport for the monitor connected to the returned device end.
+pub fn duplex() -> (Box<dyn Transport>, DuplexStream) {
+    let (monitor, device) = tokio::io::duplex(64 * 1024);
+    (Box::new(SimulatedPort { stream: monitor }), device)
+}
+
+/// Sends one line, corrupting bytes with probability `noise`; false once the
+/// monitor has gone away.
+async fn send(writer: &mut WriteHalf<DuplexStream>, random: &mut Random, noise: f64, text: &str) -> bool {
+    let mut bytes = format!("{text}\r\n").into_bytes();
+    if noise > 0.0 {
+        for byte in &mut bytes {
+            if random.next_f64() < noise {
+                *byte ^= 1 << (random.next_f64() * 8.0) as u8;
+            }
+        }
+    }
+    writer.write_all(&bytes).await.is_ok()
+}
+
+async fn run(script: Script, device: DuplexStream) {
+    let (mut reader, mut writer) = tokio::io::split(device);
+    let started = Instant::now();
+    let mut random = Random::new();
+    let mut counter = 0u64;
+    let mut next_due: Vec<Instant> = script.periodic.iter().map(|p| started + p.period).collect();
+    let mut input = Vec::new();
+    let mut buf = [0u8; 512];
+
+    for line in &script.banner {
+        if !send(&mut writer, &mut random, script.noise, line).await {
+            return;
+        }
+    }
+    loop {
+        let wake = next_due.iter().min().copied().unwrap_or(Instant::now() + Duration::from_secs(3600));
+        tokio::select! {
+            _ = time::sleep_until(wake.into()) => {
+                let now = Instant::now();
+                for (periodic, due) in script.periodic.iter().zip(&mut next_due) {
+                    if *due <= now {
+                        counter += 1;
+                        let text = render(&periodic.template, counter, started, &mut random);
+                        if !send(&mut writer, &mut random, script.noise, &text).await {
+                            return;
+                        }
+                        *due += periodic.period;
+                    }
+                }
+            }
+            read = reader.read(&mut buf) => {
+                let n = match read {
+                    Ok(n) if n > 0 => n,
+                    _ => return,
+                };
+                if script.echo && writer.write_all(&buf[..n]).await.is_err() {
+                    return;
+                }
+                input.extend_from_slice(&buf[..n]);
+                while let Some(end) = input.iter().position(|&b| b == b'\n' || b == b'\r') {
+                    let line = String::from_utf8_lossy(&input[..end]).into_owned();
+                    input.drain(..=end);
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let Some((rule, captures)) = script
+                        .rules
+                        .iter()
+                        .find_map(|rule| rule.pattern.captures(&line).map(|c| (rule, c)))
+                    else {
+                        continue;
+                    };
+                    let mut reply = String::new();
+                    captures.expand(&rule.reply, &mut reply);
+                    let reply = render(&reply, counter, started, &mut random);
+                    time::sleep(script.latency).await;
+                    for text in reply.split("\\n") {
+                        if !send(&mut writer, &mut random, script.noise, text).await {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! slcan (LAWICEL) CAN-over-serial adapter support.
+//!
+//! Received frames such as `t1232AABB` are shown as `0x123 [2] AA BB`, which
+//! also makes the message rate table group traffic per CAN ID. Frames are
+//! sent with `cansend` syntax (`123#AABB`, `12345678#01`, `123#R`).
+
+use anyhow::{Result, bail};
+
+/// slcan `S<n>` setup codes by bitrate in kbit/s.
+const BITRATES: &[(u32, u8)] = &[
+    (10, 0),
+    (20, 1),
+    (50, 2),
+    (100, 3),
+    (125, 4),
+    (250, 5),
+    (500, 6),
+    (800, 7),
+    (1000, 8),
+];
+
+pub fn validate_bitrate(kbit: &str) -> Result<u32, String> {
+    let kbit: u32 = kbit.parse().map_err(|_| "Bitrate must be a number in kbit/s".to_string())?;
+    if BITRATES.iter().any(|&(rate, _)| rate == kbit) {
+        Ok(kbit)
+    } else {
+        let valid: Vec<u32> = BITRATES.iter().map(|&(rate, _)| rate).collect();
+        Err(format!("Invalid CAN bitrate: {kbit}. Must be one of {valid:?}"))
+    }
+}
+
+/// Commands that close, configure and reopen the CAN channel.
+pub fn open_commands(kbit: u32) -> Vec<u8> {
+    let code = BITRATES
+        .iter()
+        .find(|&&(rate, _)| rate == kbit)
+        .map_or(6, |&(_, code)| code);
+    format!("C\rS{code}\rO\r").into_bytes()
+}
+
+/// Decodes an slcan frame line; anything else (acks, version replies) is
+/// returned unchanged.
+pub fn decode(line: &str) -> String {
+    decode_frame(line).unwrap_or_else(|| line.to_string())
+}
+
+fn decode_frame(line: &str) -> Option<String> {
+    let kind = line.chars().next()?;
+    let (id_len, remote) = match kind {
+        't' => (3, false),
+        'T' => (8, false),
+        'r' => (3, true),
+        'R' => (8, true),
+        _ => return None,
+    };
+    let rest = line.get(1..)?;
+    let id = u32::from_str_radix(rest.get(..id_len)?, 16).ok()?;
+    let dlc = rest.get(id_len..id_len + 1)?.parse::<usize>().ok().filter(|&d| d <= 8)?;
+    let id_text = if id_len == 3 { format!("0x{id:03X}") } else { format!("0x{id:08X}") };
+    if remote {
+        return Some(format!("{id_text} [{dlc}] RTR"));
+    }
+    let data = rest.get(id_len + 1..id_len + 1 + dlc * 2)?;
+    let bytes: Vec<String> = (0..dlc)
+        .map(|i| data.get(i * 2..i * 2 + 2).map(str::to_uppercase))
+        .collect::<Option<_>>()?;
+    if !bytes.iter().all(|b| u8::from_str_radix(b, 16).is_ok()) {
+        return None;
+    }
+    // A trailing 4-digit field is the adapter's millisecond timestamp.
+    let timestamp = rest
+        .get(id_len + 1 + dlc * 2..)
+        .filter(|t| t.len() == 4)
+        .and_then(|t| u16::from_str_radix(t, 16).ok())
+        .map(|ms| format!("  @{ms}ms"))
+        .unwrap_or_default();
+    Some(format!("{id_text} [{dlc}] {}{timestamp}", bytes.join(" ")))
+}
+
+/// Encodes user input for the adapter: `cansend` syntax becomes an slcan
+/// frame, anything else is passed through as a raw slcan command.
+pub fn encode(input: &str) -> Result<Vec<u8>> {
+    let input = input.trim();
+    let Some((id, data)) = input.split_once('#') else {
+        return Ok(format!("{input}\r").into_bytes());
+    };
+    let extended = id.len() > 3;
+    let Some(id) = u32::from_str_radix(id, 16).ok().filter(|_| id.len() <= 8) else {
+        bail!("invalid CAN ID {id:?}");
+    };
+    if extended && id > 0x1FFF_FFFF || !extended && id > 0x7FF {
+        bail!("CAN ID 0x{id:X} out of range");
+    }
+    let id_text = if extended { format!("{id:08X}") } else { format!("{id:03X}") };
+    if data.eq_ignore_ascii_case("R") {
+        let kind = if extended { 'R' } else { 'r' };
+        return Ok(format!("{kind}{id_text}0\r").into_bytes());
+    }
+    let data: String = data.chars().filter(|c| *c != '.').collect();
+    if !data.len().is_multiple_of(2) || data.len() > 16 || !data.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("CAN data must be up to 8 hex bytes");
+    }
+    let kind = if extended { 'T' } else { 't' };
+    Ok(format!("{kind}{id_text}{}{}\r", data.len() / 2, data.to_uppercase()).into_bytes())
+}
@@ -0,0 +1,77 @@
+//! Session totals, printed and logged on exit for attaching to test reports.
+
+use std::sync::atomic::Ordering;
+
+use chrono::{DateTime, Local};
+
+use crate::{port::PortHandle, transport::LineErrors};
+
+pub struct SessionStats {
+    started: DateTime<Local>,
+    pub rx_lines: u64,
+    pub tx_lines: u64,
+    /// Times a port was reopened, e.g. on a new device after hotplug.
+    pub reconnects: u64,
+    /// Received lines at ERROR level (or containing `ERROR`).
+    pub error_lines: u64,
+    /// Alerts that went out of range.
+    pub triggers: u64,
+    /// Answers to `--identify`, each `PORT IDENTITY`.
+    pub identities: Vec<String>,
+    /// Counters of ports closed during the session.
+    rx_bytes: u64,
+    tx_bytes: u64,
+    io_errors: u64,
+    line_errors: LineErrors,
+}
+
+impl SessionStats {
+    pub fn start() -> Self {
+        Self {
+            started: Local::now(),
+            rx_lines: 0,
+            tx_lines: 0,
+            reconnects: 0,
+            error_lines: 0,
+            triggers: 0,
+            identities: Vec::new(),
+            rx_bytes: 0,
+            tx_bytes: 0,
+            io_errors: 0,
+            line_errors: LineErrors::default(),
+        }
+    }
+
+    /// Keeps the traffic of a port that is about to be replaced.
+    pub fn retire(&mut self, port: &PortHandle) {
+        self.rx_bytes += port.counters.rx_bytes.load(Ordering::Relaxed);
+        self.tx_bytes += port.counters.tx_bytes.load(Ordering::Relaxed);
+        self.io_errors += port.counters.io_errors.load(Ordering::Relaxed);
+        self.line_errors = self.line_errors + port.counters.line_errors();
+        self.reconnects += 1;
+    }
+
+    /// Labelled totals including the traffic of the open ports.
+    pub fn summary(&self, ports: &[PortHandle]) -> Vec<(&'static str, String)> {
+        let total = |counter: fn(&PortHandle) -> u64| ports.iter().map(counter).sum::<u64>();
+        let rx_bytes = self.rx_bytes + total(|p| p.counters.rx_bytes.load(Ordering::Relaxed));
+        let tx_bytes = self.tx_bytes + total(|p| p.counters.tx_bytes.load(Ordering::Relaxed));
+        let io_errors = self.io_errors + total(|p| p.counters.io_errors.load(Ordering::Relaxed));
+        let line_errors = ports.iter().fold(self.line_errors, |sum, p| sum + p.counters.line_errors());
+        let seconds = (Local::now() - self.started).num_seconds().max(0);
+        let mut summary = vec![
+            ("Started", self.started.to_rfc3339()),
+            ("Duration", format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)),
+            ("RX", format!("{rx_bytes} bytes, {} lines", self.rx_lines)),
+            ("TX", format!("{tx_bytes} bytes, {} lines", self.tx_lines)),
+            ("Reconnects", self.reconnects.to_string()),
+            ("Errors", format!("{} error lines, {io_errors} I/O errors", self.error_lines)),
+            ("Line errors", line_errors.describe()),
+            ("Triggers", format!("{} alerts fired", self.triggers)),
+        ];
+        if !self.identities.is_empty() {
+            summary.push(("Identity", self.identities.join(", ")));
+        }
+        summary
+    }
+}
@@ -0,0 +1,212 @@
+//! Transmit test-pattern generator with echo verification.
+//!
+//! Generated bytes are written at a fixed rate; when the device echoes them
+//! back, the received stream is compared against what was sent so corrupted
+//! and lost bytes are counted.
+
+use std::{collections::VecDeque, time::Duration};
+
+use ratatui::style::{Color, Style};
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc, oneshot},
+    time::{self, Instant},
+};
+
+use crate::{output::OutputLine, port::PortHandle};
+
+/// How often bytes are written.
+const TICK: Duration = Duration::from_millis(50);
+/// How often progress is reported.
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+/// How far ahead a received byte is searched for after a mismatch.
+const RESYNC_WINDOW: usize = 64;
+/// Sent bytes kept for comparison; older ones are counted as unverified
+/// rather than lost, as a device may not echo at all.
+const MAX_PENDING: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Pattern {
+    Random,
+    /// 0x00, 0x01, ... 0xFF, repeating.
+    Incremental,
+    /// 0x55, 0xAA, repeating.
+    Alternating,
+}
+
+impl Pattern {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "random" => Ok(Pattern::Random),
+            "incremental" | "inc" => Ok(Pattern::Incremental),
+            "55aa" | "0x55/0xaa" => Ok(Pattern::Alternating),
+            _ => Err(format!("Unknown pattern {name:?}. Expected random, incremental or 55aa")),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pattern::Random => "random",
+            Pattern::Incremental => "incremental",
+            Pattern::Alternating => "0x55/0xAA",
+        }
+    }
+}
+
+/// Parses rates such as `1KB/s`, `512B/s`, `2MB/s` or a plain byte count.
+pub fn parse_rate(text: &str) -> Result<u64, String> {
+    let upper = text.to_ascii_uppercase();
+    let number = upper.strip_suffix("/S").unwrap_or(&upper);
+    let (number, scale) = if let Some(n) = number.strip_suffix("KB") {
+        (n, 1024)
+    } else if let Some(n) = number.strip_suffix("MB") {
+        (n, 1024 * 1024)
+    } else {
+        (number.strip_suffix('B').unwrap_or(number), 1)
+    };
+    match number.trim().parse::<u64>() {
+        Ok(n) if n > 0 => Ok(n * scale),
+        _ => Err(format!("Invalid rate {text:?}. Expected e.g. 1KB/s")),
+    }
+}
+
+struct Generator {
+    pattern: Pattern,
+    counter: u64,
+    random: u64,
+}
+
+impl Generator {
+    fn next(&mut self) -> u8 {
+        self.counter += 1;
+        match self.pattern {
+            Pattern::Random => {
+                self.random ^= self.random << 13;
+                self.random ^= self.random >> 7;
+                self.random ^= self.random << 17;
+                self.random as u8
+            }
+            Pattern::Incremental => (self.counter - 1) as u8,
+            Pattern::Alternating => if self.counter.is_multiple_of(2) { 0xAA } else { 0x55 },
+        }
+    }
+}
+
+#[derive(Default)]
+struct Verifier {
+    pending: VecDeque<u8>,
+    matched: u64,
+    corrupted: u64,
+    lost: u64,
+    /// Sent bytes dropped before an echo could be compared with them.
+    unverified: u64,
+}
+
+impl Verifier {
+    fn sent(&mut self, data: &[u8]) {
+        self.pending.extend(data);
+        while self.pending.len() > MAX_PENDING {
+            self.pending.pop_front();
+            self.unverified += 1;
+        }
+    }
+
+    fn received(&mut self, data: &[u8]) {
+        for &byte in data {
+            match self.pending.iter().take(RESYNC_WINDOW).position(|&b| b == byte) {
+                Some(skipped) => {
+                    self.pending.drain(..=skipped);
+                    self.lost += skipped as u64;
+                    self.matched += 1;
+                }
+                None => {
+                    self.pending.pop_front();
+                    self.corrupted += 1;
+                }
+            }
+        }
+    }
+}
+
+/// A running generator; stopping or dropping it ends the run with a final report.
+pub struct Stress {
+    stop: oneshot::Sender<()>,
+}
+
+impl Stress {
+    pub fn start(port: PortHandle, pattern: Pattern, rate: u64, annotations: mpsc::UnboundedSender<OutputLine>) -> Self {
+        let (stop, stopped) = oneshot::channel();
+        tokio::spawn(run(port, pattern, rate, annotations, stopped));
+        Self { stop }
+    }
+
+    pub fn stop(self) {
+        let _ = self.stop.send(());
+    }
+}
+
+async fn run(
+    port: PortHandle,
+    pattern: Pattern,
+    rate: u64,
+    annotations: mpsc::UnboundedSender<OutputLine>,
+    mut stopped: oneshot::Receiver<()>,
+) {
+    let seed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64);
+    let mut generator = Generator {
+        pattern,
+        counter: 0,
+        random: seed | 1,
+    };
+    let mut verifier = Verifier::default();
+    let mut raw = port.raw.subscribe();
+    let started = Instant::now();
+    let mut ticks = time::interval(TICK);
+    let mut reports = time::interval_at(started + REPORT_INTERVAL, REPORT_INTERVAL);
+    let mut sent = 0u64;
+
+    let report = |sent: u64, verifier: &Verifier, done: bool| {
+        let elapsed = started.elapsed().as_secs_f64().max(0.001);
+        let mut text = format!(
+            "{} {} bytes in {:.1}s ({:.0} B/s)",
+            if done { "Stopped:" } else { "Sent" },
+            sent,
+            elapsed,
+            sent as f64 / elapsed
+        );
+        let echoed = verifier.matched + verifier.corrupted;
+        if echoed > 0 {
+            text.push_str(&format!(
+                ", echo ok {}, corrupted {}, lost {}",
+                verifier.matched, verifier.corrupted, verifier.lost
+            ));
+        }
+        if verifier.unverified > 0 {
+            text.push_str(&format!(", unverified {}", verifier.unverified));
+        }
+        let color = if verifier.corrupted + verifier.lost > 0 { Color::Red } else { Color::Cyan };
+        let _ = annotations.send(OutputLine::new("[Stress] ", text, Style::default().fg(color)));
+    };
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                // Catch up on the byte budget so far instead of rounding every tick
+                let due = (rate as f64 * started.elapsed().as_secs_f64()) as u64;
+                if due > sent {
+                    let chunk: Vec<u8> = (sent..due).map(|_| generator.next()).collect();
+                    verifier.sent(&chunk);
+                    sent = due;
+                    port.write(chunk);
+                }
+            }
+            received = raw.recv() => match received {
+                Ok(data) => verifier.received(&data),
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            _ = reports.tick() => report(sent, &verifier, false),
+            _ = &mut stopped => break,
+        }
+    }
+    report(sent, &verifier, true);
+}
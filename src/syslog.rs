@@ -0,0 +1,137 @@
+//! Forwarding of monitor traffic to the local syslog daemon / systemd journal.
+//!
+//! When the journal's native socket is available, entries are sent with
+//! dedicated `SERIAL_PORT` and `SERIAL_DIRECTION` fields so they can be
+//! queried with `journalctl SERIAL_PORT=/dev/ttyUSB0`. Otherwise an RFC 5424
+//! message with the same information as structured data is written to
+//! `/dev/log`.
+
+use std::io;
+
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_SOCKET: &str = "/dev/log";
+const APP_NAME: &str = "serial-monitor";
+/// syslog facility `user` (1) combined with severity `info` (6).
+const PRIORITY_INFO: u8 = 8 + 6;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Rx => "rx",
+            Direction::Tx => "tx",
+        }
+    }
+}
+
+#[cfg(unix)]
+enum Target {
+    Journal(UnixDatagram),
+    Syslog(UnixDatagram),
+}
+
+pub struct Syslog {
+    #[cfg(unix)]
+    target: Target,
+    #[cfg(unix)]
+    port: String,
+}
+
+impl Syslog {
+    #[cfg(unix)]
+    pub fn connect(port: &str) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        let target = if socket.connect(JOURNAL_SOCKET).is_ok() {
+            Target::Journal(socket)
+        } else {
+            socket.connect(SYSLOG_SOCKET)?;
+            Target::Syslog(socket)
+        };
+        Ok(Self {
+            target,
+            port: port.to_string(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect(_port: &str) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "syslog forwarding is only available on Unix-like systems",
+        ))
+    }
+
+    #[cfg(unix)]
+    pub fn send(&self, direction: Direction, text: &str) {
+        // Delivery is best effort: a full or restarted daemon must not stall the UI.
+        let _ = match &self.target {
+            Target::Journal(socket) => socket.send(&self.journal_entry(direction, text)),
+            Target::Syslog(socket) => socket.send(self.rfc5424_entry(direction, text).as_bytes()),
+        };
+    }
+
+    #[cfg(not(unix))]
+    pub fn send(&self, _direction: Direction, _text: &str) {}
+
+    /// Encodes an entry in the journal's native datagram protocol.
+    #[cfg(unix)]
+    fn journal_entry(&self, direction: Direction, text: &str) -> Vec<u8> {
+        let mut entry = Vec::new();
+        let priority = (PRIORITY_INFO & 7).to_string();
+        for (key, value) in [
+            ("MESSAGE", text),
+            ("PRIORITY", priority.as_str()),
+            ("SYSLOG_IDENTIFIER", APP_NAME),
+            ("SERIAL_PORT", self.port.as_str()),
+            ("SERIAL_DIRECTION", direction.as_str()),
+        ] {
+            entry.extend_from_slice(key.as_bytes());
+            if value.contains('\n') {
+                // Values with newlines use the length-prefixed binary form.
+                entry.push(b'\n');
+                entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            } else {
+                entry.push(b'=');
+            }
+            entry.extend_from_slice(value.as_bytes());
+            entry.push(b'\n');
+        }
+        entry
+    }
+
+    #[cfg(unix)]
+    fn rfc5424_entry(&self, direction: Direction, text: &str) -> String {
+        let timestamp = chrono::Local::now().to_rfc3339();
+        format!(
+            "<{}>1 {} - {} {} - [serial@32473 port=\"{}\" direction=\"{}\"] {}",
+            PRIORITY_INFO,
+            timestamp,
+            APP_NAME,
+            std::process::id(),
+            escape_param(&self.port),
+            direction.as_str(),
+            text
+        )
+    }
+}
+
+/// Escapes the characters RFC 5424 reserves inside SD-PARAM values.
+#[cfg(unix)]
+fn escape_param(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
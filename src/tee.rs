@@ -0,0 +1,115 @@
+//! `--tee FIFO`: the bytes received, streamed to a named pipe as they arrive.
+//!
+//! Another program on the machine, such as a custom parser or an audio
+//! decoder, reads the raw stream from the pipe while the monitor shows it,
+//! without sharing the port. Nothing is kept for a reader that isn't there:
+//! the bytes arriving while the pipe has no reader are dropped, and so are
+//! those a slow reader doesn't make room for, so the monitor never waits
+//! on it. A reader may come and go at any time.
+
+use std::{io, path::Path};
+
+use anyhow::{Result, bail};
+use ratatui::style::{Color, Style};
+use tokio::sync::mpsc;
+
+use crate::output::OutputLine;
+
+/// The named pipe a port's received bytes are written to.
+pub struct Tee {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl Tee {
+    /// Starts writing to the named pipe at `path`, created if it doesn't
+    /// exist; bytes dropped for a slow reader are reported with `annotations`.
+    pub fn open(path: &str, annotations: mpsc::UnboundedSender<OutputLine>) -> Result<Self> {
+        prepare(Path::new(path))?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(write(path.to_string(), rx, annotations));
+        Ok(Self { tx })
+    }
+
+    /// Passes on bytes received.
+    pub fn send(&self, data: &[u8]) {
+        let _ = self.tx.send(data.to_vec());
+    }
+}
+
+/// Checks that `path` is a named pipe, creating one if nothing is there.
+#[cfg(unix)]
+fn prepare(path: &Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.file_type().is_fifo() => Ok(()),
+        Ok(_) => bail!("--tee {} isn't a named pipe", path.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => create(path),
+        Err(e) => bail!("Cannot use --tee {}: {e}", path.display()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn create(path: &Path) -> Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let name = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `name` is a NUL-terminated path that outlives the call
+    if unsafe { libc::mkfifo(name.as_ptr(), 0o600) } != 0 {
+        bail!("Cannot create the named pipe {}: {}", path.display(), io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn create(path: &Path) -> Result<()> {
+    bail!("No named pipe at {0}; create it with mkfifo {0}", path.display())
+}
+
+#[cfg(not(unix))]
+fn prepare(_path: &Path) -> Result<()> {
+    bail!("--tee needs a Unix named pipe")
+}
+
+#[cfg(unix)]
+async fn write(path: String, mut rx: mpsc::UnboundedReceiver<Vec<u8>>, annotations: mpsc::UnboundedSender<OutputLine>) {
+    use std::{fs::File, io::Write};
+    use tokio::net::unix::pipe;
+
+    let mut pipe: Option<File> = None;
+    // Whether the current reader was told it is losing bytes
+    let mut warned = false;
+    while let Some(data) = rx.recv().await {
+        if pipe.is_none() {
+            // Fails while the pipe has no reader; writes fail instead of
+            // waiting once it is full
+            let sender = pipe::OpenOptions::new().open_sender(&path);
+            pipe = sender.and_then(pipe::Sender::into_nonblocking_fd).map(File::from).ok();
+            warned = false;
+        }
+        let Some(file) = &mut pipe else {
+            continue;
+        };
+        let mut rest = data.as_slice();
+        while !rest.is_empty() {
+            match file.write(rest) {
+                Ok(n) => rest = &rest[n..],
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if !std::mem::replace(&mut warned, true) {
+                        let text = format!("The reader of --tee {path} doesn't keep up; bytes are dropped");
+                        let _ = annotations.send(OutputLine::new("[Monitor] ", text, Style::default().fg(Color::Yellow)));
+                    }
+                    break;
+                }
+                // The reader went away; the next one opens the pipe again
+                Err(_) => {
+                    pipe = None;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn write(_path: String, _rx: mpsc::UnboundedReceiver<Vec<u8>>, _annotations: mpsc::UnboundedSender<OutputLine>) {}
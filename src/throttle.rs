@@ -0,0 +1,77 @@
+//! Rate limits on displaying chatty lines.
+//!
+//! Each rule is `INTERVAL=REGEX`: a matching received line is shown at most
+//! once per interval, and the next one shown carries the number of lines
+//! suppressed in between. Log files still get every line.
+
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+#[derive(Debug, Clone)]
+pub struct ThrottleSpec {
+    interval: Duration,
+    regex: Regex,
+    /// The rule as given, e.g. `10s=^heartbeat`.
+    text: String,
+}
+
+pub fn parse_spec(spec: &str) -> Result<ThrottleSpec, String> {
+    let invalid = || format!("Invalid throttle {spec:?}. Expected INTERVAL=REGEX, e.g. 10s=^heartbeat");
+    let (interval, pattern) = spec.split_once('=').ok_or_else(invalid)?;
+    let (number, scale) = match interval.trim().strip_suffix("ms") {
+        Some(ms) => (ms, 0.001),
+        None => (interval.trim().strip_suffix('s').ok_or_else(invalid)?, 1.0),
+    };
+    let seconds = number.parse::<f64>().ok().filter(|s| *s > 0.0).ok_or_else(invalid)? * scale;
+    let regex = Regex::new(pattern).map_err(|e| format!("Invalid regex for throttle {spec:?}: {e}"))?;
+    Ok(ThrottleSpec {
+        interval: Duration::from_secs_f64(seconds),
+        regex,
+        text: spec.to_string(),
+    })
+}
+
+struct Rule {
+    spec: ThrottleSpec,
+    shown_at: Option<Instant>,
+    suppressed: usize,
+}
+
+pub enum Verdict<'a> {
+    /// Shown, after this many lines were suppressed since the last one by
+    /// the matching rule, if any.
+    Show(usize, Option<&'a str>),
+    Suppress,
+}
+
+pub struct Throttles {
+    rules: Vec<Rule>,
+}
+
+impl Throttles {
+    pub fn new(specs: Vec<ThrottleSpec>) -> Self {
+        let rules = specs
+            .into_iter()
+            .map(|spec| Rule {
+                spec,
+                shown_at: None,
+                suppressed: 0,
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Decides whether `line` is shown; the first matching rule applies.
+    pub fn check(&mut self, line: &str, now: Instant) -> Verdict<'_> {
+        let Some(rule) = self.rules.iter_mut().find(|rule| rule.spec.regex.is_match(line)) else {
+            return Verdict::Show(0, None);
+        };
+        if rule.shown_at.is_some_and(|at| now.duration_since(at) < rule.spec.interval) {
+            rule.suppressed += 1;
+            return Verdict::Suppress;
+        }
+        rule.shown_at = Some(now);
+        Verdict::Show(std::mem::take(&mut rule.suppressed), Some(&rule.spec.text))
+    }
+}
@@ -0,0 +1,249 @@
+//! Time travel through the session's capture file.
+//!
+//! `:history` shows the lines captured around a point in time, so scrolling
+//! back isn't limited to the lines the monitor keeps in memory. The capture
+//! is read in windows as the view moves and the window is trimmed behind it;
+//! scrolling past the end picks up what was captured since. Esc returns to
+//! the live tail.
+
+use std::{collections::VecDeque, io};
+
+use anyhow::{Result, anyhow};
+use chrono::{Local, TimeZone};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+use crate::{
+    capture::{Kind, Reader, Record},
+    mask::Masks,
+};
+
+/// Lines read ahead when scrolling down.
+const WINDOW: usize = 2000;
+/// Lines kept in memory.
+const MAX_ENTRIES: usize = 10_000;
+/// How far back each read goes when scrolling up, in microseconds.
+const STEP: i64 = 30_000_000;
+
+struct Entry {
+    /// Time of the record that completed the line.
+    time: i64,
+    port: u8,
+    sent: bool,
+    text: String,
+}
+
+/// Splits received chunks into lines; each sent chunk is a line of its own.
+#[derive(Default)]
+struct Decoder {
+    partial: Vec<Vec<u8>>,
+}
+
+impl Decoder {
+    fn push(&mut self, record: &Record, entries: &mut Vec<Entry>) {
+        let entry = |bytes: &[u8], sent| Entry {
+            time: record.time,
+            port: record.port,
+            sent,
+            text: String::from_utf8_lossy(bytes).trim_end_matches(['\r', '\n']).to_string(),
+        };
+        match record.kind {
+            Kind::Rx => {
+                let port = record.port as usize;
+                if self.partial.len() <= port {
+                    self.partial.resize(port + 1, Vec::new());
+                }
+                for &byte in &record.data {
+                    if byte == b'\n' {
+                        entries.push(entry(&self.partial[port], false));
+                        self.partial[port].clear();
+                    } else {
+                        self.partial[port].push(byte);
+                    }
+                }
+            }
+            Kind::Tx => entries.push(entry(&record.data, true)),
+            Kind::Index => {}
+        }
+    }
+}
+
+pub struct Timeline {
+    /// Reads on from the end of the window.
+    forward: Reader,
+    /// Record found by a seek but not decoded yet.
+    pending: Option<Record>,
+    decoder: Decoder,
+    /// Reads the records before the window.
+    backward: Reader,
+    entries: VecDeque<Entry>,
+    /// Time of the first record in the window.
+    start: i64,
+    /// Time of the first record in the capture.
+    first: i64,
+    /// First visible entry, clamped to the last page when rendering.
+    top: usize,
+    /// Rows shown at the last render.
+    height: usize,
+}
+
+impl Timeline {
+    /// Opens the capture at `path` at `time` (µs since the epoch), or at its
+    /// end to carry on from the live view.
+    pub fn open(path: &str, time: Option<i64>) -> Result<Self> {
+        let mut forward = Reader::open(path)?;
+        let span = forward.span.ok_or_else(|| anyhow!("{path} holds no records yet"))?;
+        let at = time.unwrap_or(span.1 - STEP).clamp(span.0, span.1);
+        let pending = forward.seek(at)?;
+        let mut timeline = Self {
+            start: pending.as_ref().map_or(at, |record| record.time),
+            forward,
+            pending,
+            decoder: Decoder::default(),
+            backward: Reader::open(path)?,
+            entries: VecDeque::new(),
+            first: span.0,
+            top: if time.is_some() { 0 } else { usize::MAX },
+            height: 0,
+        };
+        if time.is_some() {
+            timeline.load_forward(WINDOW)?;
+        } else {
+            while timeline.load_forward(WINDOW)? > 0 {}
+            // Enough to scroll back through right away
+            while timeline.entries.len() < WINDOW / 10 && timeline.load_backward()? > 0 {}
+        }
+        Ok(timeline)
+    }
+
+    /// Scrolls by `rows`, reading the capture when the window runs out.
+    pub fn scroll(&mut self, rows: isize) -> io::Result<()> {
+        self.top = self.top.min(self.bottom());
+        let distance = rows.unsigned_abs();
+        if rows < 0 {
+            while self.top < distance && self.load_backward()? > 0 {}
+            self.top = self.top.saturating_sub(distance);
+        } else {
+            self.top += distance;
+            while self.top > self.bottom() && self.load_forward(WINDOW)? > 0 {}
+            self.top = self.top.min(self.bottom());
+        }
+        Ok(())
+    }
+
+    /// Scrolls by a page.
+    pub fn page(&mut self, pages: isize) -> io::Result<()> {
+        self.scroll(pages * self.height.max(1) as isize)
+    }
+
+    fn bottom(&self) -> usize {
+        self.entries.len().saturating_sub(self.height)
+    }
+
+    /// Decodes up to `count` more lines; returns how many were added.
+    fn load_forward(&mut self, count: usize) -> io::Result<usize> {
+        let mut lines = Vec::new();
+        let mut refreshed = false;
+        while lines.len() < count {
+            let record = match self.pending.take() {
+                Some(record) => record,
+                None => match self.forward.next_record()? {
+                    Some(record) => record,
+                    None if refreshed => break,
+                    None => {
+                        self.forward.refresh()?;
+                        refreshed = true;
+                        continue;
+                    }
+                },
+            };
+            self.decoder.push(&record, &mut lines);
+        }
+        let added = lines.len();
+        self.entries.extend(lines);
+        if self.entries.len() > MAX_ENTRIES {
+            // Lines of one record go together, so a later read restores them all
+            let mut dropped = self.entries.len() - MAX_ENTRIES;
+            while self.entries.get(dropped).is_some_and(|e| e.time == self.entries[dropped - 1].time) {
+                dropped += 1;
+            }
+            self.entries.drain(..dropped);
+            self.top = self.top.saturating_sub(dropped);
+            self.start = self.entries.front().map_or(self.start, |e| e.time);
+        }
+        Ok(added)
+    }
+
+    /// Decodes the lines before the window, going further back until some
+    /// are found; returns how many were added.
+    fn load_backward(&mut self) -> io::Result<usize> {
+        self.backward.refresh()?;
+        let mut lines = Vec::new();
+        while lines.is_empty() && self.start > self.first {
+            let from = self.start - STEP;
+            let mut decoder = Decoder::default();
+            let mut record = self.backward.seek(from)?;
+            while let Some(found) = record.filter(|r| r.time < self.start) {
+                decoder.push(&found, &mut lines);
+                record = self.backward.next_record()?;
+            }
+            self.start = from;
+        }
+        let added = lines.len();
+        for line in lines.into_iter().rev() {
+            self.entries.push_front(line);
+        }
+        self.top = self.top.saturating_add(added);
+        if self.entries.len() > MAX_ENTRIES {
+            // Reading forward again resumes at the first record dropped
+            let cut = self.entries[MAX_ENTRIES].time;
+            while self.entries.back().is_some_and(|e| e.time >= cut) {
+                self.entries.pop_back();
+            }
+            self.pending = self.forward.seek(cut)?;
+            self.decoder = Decoder::default();
+        }
+        Ok(added)
+    }
+
+    pub fn render(&mut self, f: &mut Frame, area: Rect, tags: &[&str], tx_tags: &[&str], masks: &Masks) {
+        self.height = (area.height as usize).saturating_sub(2);
+        self.top = self.top.min(self.bottom());
+        let visible: Vec<&Entry> = self.entries.iter().skip(self.top).take(self.height).collect();
+        let clock = |time: i64| Local.timestamp_micros(time).single().unwrap_or_default();
+        let lines: Vec<Line> = visible
+            .iter()
+            .map(|entry| {
+                let (tag, color) = match entry.sent {
+                    true => (tx_tags.get(entry.port as usize), Color::Yellow),
+                    false => (tags.get(entry.port as usize), Color::Green),
+                };
+                Line::from(vec![
+                    Span::styled(
+                        clock(entry.time).format("%H:%M:%S%.3f ").to_string(),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::styled(format!("{}{}", tag.unwrap_or(&""), masks.apply(&entry.text)), Style::default().fg(color)),
+                ])
+            })
+            .collect();
+        let title = match (visible.first(), visible.last()) {
+            (Some(first), Some(last)) => format!(
+                "History {} to {} (PageUp/PageDown/Up/Down scroll, Esc back to live)",
+                clock(first.time).format("%H:%M:%S"),
+                clock(last.time).format("%H:%M:%S")
+            ),
+            _ => "History: nothing captured here (Esc back to live)".to_string(),
+        };
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().title(title).borders(Borders::ALL)),
+            area,
+        );
+    }
+}
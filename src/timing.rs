@@ -0,0 +1,163 @@
+//! Chunk and byte timing of capture files, exported as CSV.
+//!
+//! Protocols such as Modbus RTU frame their messages by silence on the
+//! line: a gap of 3.5 character times ends a frame. A capture stamps every
+//! chunk read with the microsecond its read returned, and
+//! `serial-monitor log timing` lists the chunks with the idle time before
+//! each, in microseconds and in character times at the capture's line
+//! settings, flagging the gaps long enough to start a frame. The bytes of
+//! a chunk arrived back to back before its read returned, so the time a
+//! chunk started arriving is estimated from its length, and `--bytes`
+//! lists every byte with the time it arrived.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use anyhow::{Context, Result};
+use chrono::{Local, TimeZone};
+
+use crate::{
+    capture::{Kind, Reader},
+    framing::hex,
+};
+
+/// Gap in character times that starts a Modbus RTU frame.
+pub const FRAME_GAP: f64 = 3.5;
+
+/// Microseconds one character takes on the wire with the line settings of
+/// a capture header, if they are known.
+fn char_time(header: &serde_json::Value) -> Option<f64> {
+    let baud = header["baud_rate"].as_u64().filter(|baud| *baud > 0)?;
+    let data_bits = header["data_bits"].as_u64().unwrap_or(8);
+    let parity = header["parity"].as_str().is_some_and(|parity| parity != "none");
+    let stop_bits = header["stop_bits"].as_u64().unwrap_or(1);
+    let bits = 1 + data_bits + parity as u64 + stop_bits;
+    Some(bits as f64 * 1e6 / baud as f64)
+}
+
+/// The idle time before a byte, as the `gap_us`, `gap_chars` and
+/// `frame_start` columns.
+struct Gap {
+    /// Microseconds, unknown for the first byte on a line.
+    micros: Option<f64>,
+    chars: Option<f64>,
+    frame_start: bool,
+}
+
+impl Gap {
+    /// The gap before a byte arriving `since` microseconds after the one
+    /// before it, which took `char_time` itself.
+    fn new(since: f64, char_time: Option<f64>, frame_gap: f64) -> Self {
+        let micros = (!since.is_nan()).then(|| (since - char_time.unwrap_or(0.0)).max(0.0));
+        let chars = micros.zip(char_time).map(|(micros, char_time)| micros / char_time);
+        let frame_start = match (micros, chars) {
+            (None, _) => true,
+            (_, Some(chars)) => chars >= frame_gap,
+            (_, None) => false,
+        };
+        Self { micros, chars, frame_start }
+    }
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(micros) = self.micros {
+            write!(f, "{micros:.0}")?;
+        }
+        f.write_str(",")?;
+        if let Some(chars) = self.chars {
+            write!(f, "{chars:.2}")?;
+        }
+        write!(f, ",{}", self.frame_start as u8)
+    }
+}
+
+/// The local time `offset` microseconds after `start`.
+fn timestamp(start: i64, offset: f64) -> String {
+    let time = Local.timestamp_micros(start + offset.round() as i64).single().unwrap_or_default();
+    time.format("%Y-%m-%dT%H:%M:%S%.6f").to_string()
+}
+
+/// `text` as a CSV field.
+fn field(text: &str) -> String {
+    match text.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    }
+}
+
+/// Writes the timing of the capture at `path` to `output` (`-` for stdout)
+/// as CSV, a row per chunk or, with `bytes`, per byte, flagging gaps of at
+/// least `frame_gap` character times. Returns the number of rows written.
+pub fn export(path: &str, output: &str, bytes: bool, frame_gap: f64) -> Result<usize> {
+    let mut reader = Reader::open(path)?;
+    let names: Vec<String> = match reader.header["ports"].as_array() {
+        Some(ports) => ports.iter().map(|p| p.as_str().unwrap_or("capture").to_string()).collect(),
+        None => Vec::new(),
+    };
+    let char_time = char_time(&reader.header);
+    if char_time.is_none() {
+        eprintln!("{path} doesn't record the baud rate; gaps are given in microseconds only");
+    }
+    let out: Box<dyn Write> = match output {
+        "-" => Box::new(io::stdout().lock()),
+        _ => Box::new(File::create(output).with_context(|| format!("Cannot create {output}"))?),
+    };
+    let mut out = BufWriter::new(out);
+    match bytes {
+        true => writeln!(out, "time,offset_us,port,direction,gap_us,gap_chars,frame_start,byte")?,
+        false => writeln!(out, "time,offset_us,port,direction,length,gap_us,gap_chars,frame_start,data")?,
+    }
+
+    let mut rows = 0;
+    let mut first = None;
+    // When the last byte on each line, one per port and direction, arrived
+    let mut last: HashMap<(u8, bool), f64> = HashMap::new();
+    loop {
+        let record = match reader.next_record() {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("{path} is damaged ({e}); exported up to there");
+                None
+            }
+        };
+        let Some(record) = record else {
+            break;
+        };
+        if record.data.is_empty() {
+            continue;
+        }
+        let char_us = char_time.unwrap_or(0.0);
+        // Offsets count from the first byte of the capture
+        let start = *first.get_or_insert(record.time - ((record.data.len() - 1) as f64 * char_us).round() as i64);
+        let port = names.get(record.port as usize).cloned().unwrap_or_else(|| format!("port {}", record.port));
+        let port = field(&port);
+        let rx = record.kind != Kind::Tx;
+        let direction = if rx { "rx" } else { "tx" };
+        // The read returned with the stop bit of the last byte
+        let end = (record.time - start) as f64;
+        let arrived = |index: usize| end - (record.data.len() - 1 - index) as f64 * char_us;
+        let line = last.entry((record.port, rx)).or_insert(f64::NAN);
+        if bytes {
+            for (index, byte) in record.data.iter().enumerate() {
+                let at = arrived(index);
+                let gap = Gap::new(at - std::mem::replace(line, at), char_time, frame_gap);
+                writeln!(out, "{},{at:.0},{port},{direction},{gap},{byte:02X}", timestamp(start, at))?;
+                rows += 1;
+            }
+        } else {
+            let at = arrived(0);
+            let gap = Gap::new(at - std::mem::replace(line, end), char_time, frame_gap);
+            let data = hex(&record.data).replace(' ', "");
+            let length = record.data.len();
+            writeln!(out, "{},{at:.0},{port},{direction},{length},{gap},{data}", timestamp(start, at))?;
+            rows += 1;
+        }
+    }
+    out.flush()?;
+    Ok(rows)
+}
@@ -0,0 +1,152 @@
+//! Transient notifications drawn over the output pane.
+//!
+//! Anything can post a notification with [`notify`]; while the TUI is not
+//! running yet, notifications are printed to stderr instead. Every
+//! notification is kept in a history that can be opened with a key.
+
+use std::{
+    collections::VecDeque,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+use tokio::sync::mpsc;
+
+/// How long a notification stays on screen.
+const SHOW_FOR: Duration = Duration::from_secs(4);
+/// Notifications kept for the history view.
+const MAX_HISTORY: usize = 200;
+const WIDTH: u16 = 60;
+
+static SENDER: OnceLock<mpsc::UnboundedSender<Toast>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn color(self) -> Color {
+        match self {
+            Severity::Info => Color::Cyan,
+            Severity::Warning => Color::Yellow,
+            Severity::Error => Color::Red,
+        }
+    }
+}
+
+pub struct Toast {
+    pub time: DateTime<Local>,
+    pub severity: Severity,
+    pub text: String,
+}
+
+/// Posts a notification to the UI, or prints it if the UI isn't running.
+pub fn notify(severity: Severity, text: impl Into<String>) {
+    let toast = Toast {
+        time: Local::now(),
+        severity,
+        text: text.into(),
+    };
+    match SENDER.get() {
+        Some(sender) => {
+            let _ = sender.send(toast);
+        }
+        None => eprintln!("{}", toast.text),
+    }
+}
+
+pub struct Toasts {
+    receiver: mpsc::UnboundedReceiver<Toast>,
+    history: VecDeque<(Instant, Toast)>,
+    /// Notifications on screen at the last poll.
+    showing: usize,
+}
+
+impl Toasts {
+    /// Routes notifications to the returned queue from now on.
+    pub fn install() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let _ = SENDER.set(sender);
+        Self {
+            receiver,
+            history: VecDeque::new(),
+            showing: 0,
+        }
+    }
+
+    /// Takes in notifications posted since the last call, returning whether
+    /// those on screen changed: one arrived or timed out.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        let mut arrived = false;
+        while let Ok(toast) = self.receiver.try_recv() {
+            self.history.push_back((now, toast));
+            if self.history.len() > MAX_HISTORY {
+                self.history.pop_front();
+            }
+            arrived = true;
+        }
+        let showing = self.history.iter().rev().take_while(|(posted, _)| now.duration_since(*posted) < SHOW_FOR).count();
+        arrived || std::mem::replace(&mut self.showing, showing) != showing
+    }
+
+    /// Draws the notifications still on screen in the top right of `area`.
+    pub fn render(&self, f: &mut Frame, area: Rect, now: Instant) {
+        let current: Vec<&Toast> = self
+            .history
+            .iter()
+            .filter(|(posted, _)| now.duration_since(*posted) < SHOW_FOR)
+            .map(|(_, toast)| toast)
+            .collect();
+        let width = WIDTH.min(area.width.saturating_sub(2));
+        let mut y = area.y + 1;
+        for toast in current.iter().rev() {
+            let text = Paragraph::new(toast.text.as_str())
+                .style(Style::default().fg(toast.severity.color()))
+                .wrap(Wrap { trim: true });
+            let height = (text.line_count(width.saturating_sub(2)) as u16 + 2).min(area.height);
+            if y + height > area.bottom() {
+                break;
+            }
+            let rect = Rect::new(area.right().saturating_sub(width + 1), y, width, height);
+            f.render_widget(Clear, rect);
+            f.render_widget(
+                text.block(Block::default().borders(Borders::ALL).border_style(Style::default().fg(toast.severity.color()))),
+                rect,
+            );
+            y += height;
+        }
+    }
+
+    /// Draws every notification of the session over `area`.
+    pub fn render_history(&self, f: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        let skip = self.history.len().saturating_sub(height);
+        let lines: Vec<Line> = self
+            .history
+            .iter()
+            .skip(skip)
+            .map(|(_, toast)| {
+                Line::from(vec![
+                    Span::styled(toast.time.format("%H:%M:%S ").to_string(), Style::default().fg(Color::DarkGray)),
+                    Span::styled(toast.text.clone(), Style::default().fg(toast.severity.color())),
+                ])
+            })
+            .collect();
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().title("Notifications (Ctrl+E to close)").borders(Borders::ALL)),
+            area,
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Received traffic per minute over the whole session.
+//!
+//! For long unattended sessions, `:traffic` replaces the output pane with a
+//! row per hour and a column per minute, each cell shaded by the lines
+//! received in that minute, so the night the device went quiet stands out
+//! without scrolling through the log. The hour's lines and bytes follow
+//! each row, and the longest silence is named above them.
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, DurationRound, Local, Timelike};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+};
+
+/// Minutes kept: a week.
+const MAX_MINUTES: usize = 7 * 24 * 60;
+
+/// Shades of a minute by its share of the busiest minute.
+const SHADES: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Clone, Copy, Default)]
+struct Minute {
+    lines: u64,
+    bytes: u64,
+}
+
+#[derive(Default)]
+pub struct Traffic {
+    /// The start of the first minute kept.
+    first: Option<DateTime<Local>>,
+    minutes: VecDeque<Minute>,
+}
+
+/// `time` with the seconds dropped.
+fn minute(time: DateTime<Local>) -> DateTime<Local> {
+    time.duration_trunc(Duration::minutes(1)).unwrap_or(time)
+}
+
+/// A byte count such as `12.3 KB`.
+fn size(bytes: u64) -> String {
+    match bytes {
+        0..1_000 => format!("{bytes} B"),
+        1_000..1_000_000 => format!("{:.1} KB", bytes as f64 / 1e3),
+        _ => format!("{:.1} MB", bytes as f64 / 1e6),
+    }
+}
+
+impl Traffic {
+    /// The minute holding `time`, with the quiet minutes since the last
+    /// one added.
+    fn at(&mut self, time: DateTime<Local>) -> Option<&mut Minute> {
+        let start = minute(time);
+        let first = *self.first.get_or_insert(start);
+        let index = usize::try_from((start - first).num_minutes()).ok()?;
+        if index >= self.minutes.len() {
+            self.minutes.resize(index + 1, Minute::default());
+        }
+        let index = match self.minutes.len().checked_sub(MAX_MINUTES) {
+            Some(excess) if excess > 0 => {
+                self.minutes.drain(..excess);
+                self.first = Some(first + Duration::minutes(excess as i64));
+                index - excess
+            }
+            _ => index,
+        };
+        self.minutes.get_mut(index)
+    }
+
+    /// Counts a line received at `time`.
+    pub fn line(&mut self, time: DateTime<Local>) {
+        if let Some(minute) = self.at(time) {
+            minute.lines += 1;
+        }
+    }
+
+    /// Counts `bytes` received at `time`.
+    pub fn bytes(&mut self, time: DateTime<Local>, bytes: usize) {
+        if let Some(minute) = self.at(time) {
+            minute.bytes += bytes as u64;
+        }
+    }
+
+    /// The first minute and length of the longest run of minutes without a
+    /// line, up to `now`.
+    fn longest_silence(&self, now: DateTime<Local>) -> Option<(DateTime<Local>, usize)> {
+        let first = self.first?;
+        let elapsed = usize::try_from((minute(now) - first).num_minutes()).ok()?;
+        let mut longest: Option<(usize, usize)> = None;
+        let mut run = None;
+        for index in 0..elapsed {
+            let quiet = self.minutes.get(index).is_none_or(|minute| minute.lines == 0);
+            match (quiet, run) {
+                (true, None) => run = Some(index),
+                (false, Some(start)) => {
+                    longest = longest.max(Some((index - start, start)));
+                    run = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = run {
+            longest = longest.max(Some((elapsed - start, start)));
+        }
+        longest.map(|(length, start)| (first + Duration::minutes(start as i64), length))
+    }
+
+    /// Hours shown up to `now`, for scrolling.
+    pub fn row_count(&self, now: DateTime<Local>) -> usize {
+        let Some(first) = self.first else {
+            return 0;
+        };
+        let minutes = (minute(now) - first).num_minutes().max(0) as usize + 1;
+        (first.minute() as usize + minutes).div_ceil(60)
+    }
+
+    /// Draws a row per hour, the latest `scroll_offset` hours up from the
+    /// bottom.
+    pub fn render(&self, f: &mut Frame, area: Rect, title: &str, now: DateTime<Local>, scroll_offset: usize) {
+        let block = Block::default().title(title.to_string()).borders(Borders::ALL);
+        let Some(first) = self.first else {
+            let waiting = Line::styled("Nothing received yet", Style::default().fg(Color::DarkGray));
+            f.render_widget(Paragraph::new(waiting).block(block), area);
+            return;
+        };
+        let busiest = self.minutes.iter().map(|minute| minute.lines).max().unwrap_or(0).max(1);
+        // Label and totals take 26 columns; narrow panes join minutes
+        let room = (area.width as usize).saturating_sub(2 + 26);
+        let per_cell = [1, 2, 3, 4, 5, 6, 10, 12, 15, 20, 30, 60].into_iter().find(|n| 60 / n <= room).unwrap_or(60);
+        let dim = Style::default().fg(Color::DarkGray);
+
+        let mut lines = vec![match self.longest_silence(now) {
+            Some((start, length)) => Line::styled(
+                format!(
+                    "Longest silence: {length} min from {} to {}",
+                    start.format("%a %H:%M"),
+                    (start + Duration::minutes(length as i64)).format("%a %H:%M")
+                ),
+                Style::default().fg(Color::Yellow),
+            ),
+            None => Line::styled("No silence of a minute or more", Style::default().fg(Color::Green)),
+        }];
+        lines.push(Line::styled(format!("{:<9} one cell per {per_cell} min, shaded by lines received", "Hour"), dim));
+
+        let hour = first.duration_trunc(Duration::hours(1)).unwrap_or(first);
+        let height = (area.height as usize).saturating_sub(2 + lines.len());
+        let total = self.row_count(now);
+        let end = total.saturating_sub(scroll_offset.min(total.saturating_sub(1)));
+        for row in end.saturating_sub(height)..end {
+            let start = hour + Duration::hours(row as i64);
+            let mut spans = vec![Span::styled(format!("{:<9} ", start.format("%a %H:00")), dim)];
+            let (mut lines_in_hour, mut bytes_in_hour) = (0, 0);
+            for cell in 0..60 / per_cell {
+                let cell_start = start + Duration::minutes((cell * per_cell) as i64);
+                let offset = (cell_start - first).num_minutes();
+                let minutes: Vec<Minute> = (offset..offset + per_cell as i64)
+                    .filter_map(|index| usize::try_from(index).ok().and_then(|index| self.minutes.get(index)).copied())
+                    .collect();
+                if cell_start > now || offset + (per_cell as i64) <= 0 {
+                    spans.push(Span::raw(" "));
+                    continue;
+                }
+                let lines = minutes.iter().map(|minute| minute.lines).sum::<u64>();
+                lines_in_hour += lines;
+                bytes_in_hour += minutes.iter().map(|minute| minute.bytes).sum::<u64>();
+                spans.push(match lines {
+                    0 => Span::styled("·", Style::default().fg(Color::Red)),
+                    _ => {
+                        let share = lines as f64 / (busiest * per_cell as u64) as f64;
+                        let shade = ((share * SHADES.len() as f64).ceil() as usize).clamp(1, SHADES.len()) - 1;
+                        Span::styled(SHADES[shade].to_string(), Style::default().fg(Color::Cyan))
+                    }
+                });
+            }
+            spans.push(Span::raw(format!(" {lines_in_hour:>6} lines {:>9}", size(bytes_in_hour))));
+            lines.push(Line::from(spans));
+        }
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
@@ -0,0 +1,383 @@
+//! Byte transports the monitor can talk to: a local serial port or a remote
+//! RFC 2217 serial server.
+
+use std::{borrow::Cow, io};
+
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_serial::{DataBits, FlowControl, Parity, SerialPort, SerialPortBuilderExt, SerialStream, StopBits};
+
+use crate::{
+    diagnostics, discovery, exit, rfc2217,
+    toast::{self, Severity},
+};
+
+/// URL scheme selecting the RFC 2217 client transport.
+pub const RFC2217_SCHEME: &str = "rfc2217://";
+/// URL scheme selecting the RFC 2217 client transport over TLS.
+pub const RFC2217S_SCHEME: &str = "rfc2217s://";
+/// URL scheme selecting a plain TCP socket, e.g. to a program standing in
+/// for a device.
+pub const TCP_SCHEME: &str = "tcp://";
+
+/// Baud rates accepted by `--baud-rate`.
+pub const VALID_BAUD_RATES: &[u32] = &[300, 1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200];
+
+/// The `host:port` of a remote port and whether it uses TLS.
+pub fn remote_address(port: &str) -> Option<(&str, bool)> {
+    port.strip_prefix(RFC2217_SCHEME)
+        .map(|addr| (addr, false))
+        .or_else(|| port.strip_prefix(RFC2217S_SCHEME).map(|addr| (addr, true)))
+}
+
+/// Checks a `--port` value: a device name or the URL of a remote port.
+pub fn validate_port(port: &str) -> Result<String, String> {
+    // Plain TCP sockets are given as tcp://host:port
+    if let Some(addr) = port.strip_prefix(TCP_SCHEME) {
+        return match addr.rsplit_once(':') {
+            Some((host, tcp_port)) if !host.is_empty() && tcp_port.parse::<u16>().is_ok() => Ok(port.to_string()),
+            _ => Err(format!("Invalid TCP address: {port}. Expected {TCP_SCHEME}host:port")),
+        };
+    }
+    // Remote ports are given as rfc2217://host:port, or rfc2217s:// over TLS
+    if let Some((addr, _)) = remote_address(port) {
+        return match addr.rsplit_once(':') {
+            Some((host, tcp_port)) if !host.is_empty() && tcp_port.parse::<u16>().is_ok() => {
+                Ok(port.to_string())
+            }
+            _ => Err(format!(
+                "Invalid RFC 2217 address: {}. Expected {}host:port or {}host:port",
+                port, RFC2217_SCHEME, RFC2217S_SCHEME
+            )),
+        };
+    }
+
+    // Check if port matches Unix-like (/dev/tty*) or Windows (COM*, \\.\COM*) patterns
+    let is_valid_pattern = 
+        port.starts_with("/dev/tty") || // Unix-like systems (Linux/macOS)
+        discovery::device_name(port).to_uppercase().starts_with("COM"); // Windows (e.g., COM1, \\.\COM12)
+    
+    if !is_valid_pattern {
+        return Err(format!(
+            "Invalid port: {}. Must start with '/dev/tty' (Unix), 'COM' (Windows), '{}' or '{}'",
+            port, RFC2217_SCHEME, TCP_SCHEME
+        ));
+    }
+
+    // Missing or inaccessible ports are diagnosed when opening them
+
+    Ok(port.to_string())
+}
+
+/// A runtime change to the line settings or control signals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    BaudRate(u32),
+    DataBits(u8),
+    Parity(ParityArg),
+    StopBits(u8),
+    Flow(FlowControlArg),
+    Dtr(bool),
+    Rts(bool),
+    Break(bool),
+}
+
+/// Receive errors counted by the UART driver since the port was opened
+/// (or the system booted, depending on the driver).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineErrors {
+    pub framing: u64,
+    pub parity: u64,
+    /// Bytes lost because the UART or the driver's buffer was full.
+    pub overrun: u64,
+    pub breaks: u64,
+}
+
+impl LineErrors {
+    /// Errors counted since `earlier`.
+    pub fn since(&self, earlier: &LineErrors) -> LineErrors {
+        LineErrors {
+            framing: self.framing.saturating_sub(earlier.framing),
+            parity: self.parity.saturating_sub(earlier.parity),
+            overrun: self.overrun.saturating_sub(earlier.overrun),
+            breaks: self.breaks.saturating_sub(earlier.breaks),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == LineErrors::default()
+    }
+
+    /// E.g. `3 framing, 1 parity, 0 overrun, 0 break`.
+    pub fn describe(&self) -> String {
+        format!(
+            "{} framing, {} parity, {} overrun, {} break",
+            self.framing, self.parity, self.overrun, self.breaks
+        )
+    }
+}
+
+impl std::ops::Add for LineErrors {
+    type Output = LineErrors;
+
+    fn add(self, other: LineErrors) -> LineErrors {
+        LineErrors {
+            framing: self.framing + other.framing,
+            parity: self.parity + other.parity,
+            overrun: self.overrun + other.overrun,
+            breaks: self.breaks + other.breaks,
+        }
+    }
+}
+
+/// Anything the port task can exchange bytes with.
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin {
+    /// Applies a line setting or control signal change.
+    fn control(&mut self, control: Control) -> io::Result<()>;
+
+    /// The driver's receive error counters, where the platform reports them.
+    fn line_errors(&self) -> Option<LineErrors> {
+        None
+    }
+}
+
+impl Transport for TcpStream {
+    fn control(&mut self, _: Control) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "a TCP socket has no line settings or control signals"))
+    }
+}
+
+impl Transport for SerialStream {
+    fn control(&mut self, control: Control) -> io::Result<()> {
+        // Reuse the CLI-to-driver conversions for the requested value.
+        let mut requested = SerialSettings::default();
+        requested.apply(control);
+        match control {
+            Control::BaudRate(baud) => self.set_baud_rate(baud)?,
+            Control::DataBits(_) => self.set_data_bits(requested.data_bits())?,
+            Control::Parity(_) => self.set_parity(requested.parity())?,
+            Control::StopBits(_) => self.set_stop_bits(requested.stop_bits())?,
+            Control::Flow(_) => self.set_flow_control(requested.flow_control())?,
+            Control::Dtr(level) => self.write_data_terminal_ready(level)?,
+            Control::Rts(level) => self.write_request_to_send(level)?,
+            Control::Break(true) => self.set_break()?,
+            Control::Break(false) => self.clear_break()?,
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn line_errors(&self) -> Option<LineErrors> {
+        use std::os::fd::AsRawFd;
+
+        // struct serial_icounter_struct from <linux/serial.h>
+        #[repr(C)]
+        #[derive(Default)]
+        struct Counters {
+            cts: i32,
+            dsr: i32,
+            rng: i32,
+            dcd: i32,
+            rx: i32,
+            tx: i32,
+            frame: i32,
+            overrun: i32,
+            parity: i32,
+            brk: i32,
+            buf_overrun: i32,
+            reserved: [i32; 9],
+        }
+        let mut counters = Counters::default();
+        // USB adapters without the ioctl (and pseudo terminals) fail here
+        // SAFETY: TIOCGICOUNT fills a serial_icounter_struct, which `Counters` mirrors
+        let result = unsafe { libc::ioctl(self.as_raw_fd(), libc::TIOCGICOUNT, &mut counters) };
+        let count = |n: i32| n.max(0) as u64;
+        (result == 0).then(|| LineErrors {
+            framing: count(counters.frame),
+            parity: count(counters.parity),
+            overrun: count(counters.overrun) + count(counters.buf_overrun),
+            breaks: count(counters.brk),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParityArg {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowControlArg {
+    None,
+    Software,
+    Hardware,
+}
+
+/// What is appended to each line typed and sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    Lf,
+    Cr,
+    Crlf,
+    None,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Cr => "\r",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::None => "",
+        }
+    }
+}
+
+/// Character encoding of the text typed and shown. Latin-1 (ISO 8859-1)
+/// maps each byte to one character, for devices that predate UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+pub enum Encoding {
+    #[value(name = "utf-8", alias = "utf8")]
+    #[serde(rename = "utf-8", alias = "utf8")]
+    Utf8,
+    #[value(alias = "iso-8859-1")]
+    #[serde(rename = "latin1", alias = "iso-8859-1")]
+    Latin1,
+}
+
+impl Encoding {
+    /// The bytes of `text`; fails on characters Latin-1 has no byte for.
+    pub fn encode(self, text: &str) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            Encoding::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(c).map_err(|_| anyhow!("{c:?} has no Latin-1 encoding")))
+                .collect(),
+        }
+    }
+
+    /// The text of `data`, or `None` if it isn't valid in this encoding.
+    pub fn decode(self, data: &[u8]) -> Option<Cow<'_, str>> {
+        match self {
+            Encoding::Utf8 => std::str::from_utf8(data).ok().map(Cow::Borrowed),
+            Encoding::Latin1 => Some(data.iter().map(|&b| char::from(b)).collect::<String>().into()),
+        }
+    }
+}
+
+/// Line settings shared by every transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialSettings {
+    pub baud_rate: u32,
+    pub data_bits: u8,
+    pub parity: ParityArg,
+    pub stop_bits: u8,
+    pub flow_control: FlowControlArg,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        Self {
+            baud_rate: 9600,
+            data_bits: 8,
+            parity: ParityArg::None,
+            stop_bits: 1,
+            flow_control: FlowControlArg::None,
+        }
+    }
+}
+
+impl SerialSettings {
+    /// Records a successfully applied line setting change.
+    pub fn apply(&mut self, control: Control) {
+        match control {
+            Control::BaudRate(baud) => self.baud_rate = baud,
+            Control::DataBits(bits) => self.data_bits = bits,
+            Control::Parity(parity) => self.parity = parity,
+            Control::StopBits(bits) => self.stop_bits = bits,
+            Control::Flow(flow) => self.flow_control = flow,
+            Control::Dtr(_) | Control::Rts(_) | Control::Break(_) => {}
+        }
+    }
+
+    fn data_bits(&self) -> DataBits {
+        match self.data_bits {
+            5 => DataBits::Five,
+            6 => DataBits::Six,
+            7 => DataBits::Seven,
+            _ => DataBits::Eight,
+        }
+    }
+
+    fn parity(&self) -> Parity {
+        match self.parity {
+            ParityArg::None => Parity::None,
+            ParityArg::Odd => Parity::Odd,
+            ParityArg::Even => Parity::Even,
+        }
+    }
+
+    fn stop_bits(&self) -> StopBits {
+        match self.stop_bits {
+            2 => StopBits::Two,
+            _ => StopBits::One,
+        }
+    }
+
+    fn flow_control(&self) -> FlowControl {
+        match self.flow_control {
+            FlowControlArg::None => FlowControl::None,
+            FlowControlArg::Software => FlowControl::Software,
+            FlowControlArg::Hardware => FlowControl::Hardware,
+        }
+    }
+}
+
+/// Opens the transport named by `port`.
+pub async fn open(port: &str, settings: &SerialSettings) -> Result<Box<dyn Transport>> {
+    if let Some((addr, tls)) = remote_address(port) {
+        let (stream, negotiated) = rfc2217::connect(addr, tls, settings)
+            .await
+            .with_context(|| format!("Failed to connect to RFC 2217 server {addr}"))?;
+        for mismatch in negotiated.mismatches(settings) {
+            toast::notify(Severity::Warning, format!("RFC 2217 server set {mismatch}"));
+        }
+        let unconfirmed = negotiated.unconfirmed();
+        if !unconfirmed.is_empty() {
+            let text = format!("RFC 2217 server didn't confirm the {} in time; they may not apply", unconfirmed.join(", "));
+            toast::notify(Severity::Warning, text);
+        }
+        return Ok(Box::new(stream));
+    }
+    if let Some(addr) = port.strip_prefix(TCP_SCHEME) {
+        let stream = TcpStream::connect(addr).await.with_context(|| format!("Failed to connect to {addr}"))?;
+        stream.set_nodelay(true)?;
+        return Ok(Box::new(stream));
+    }
+
+    let port = tokio_serial::new(discovery::device_name(port), settings.baud_rate)
+        .data_bits(settings.data_bits())
+        .parity(settings.parity())
+        .stop_bits(settings.stop_bits())
+        .flow_control(settings.flow_control())
+        .open_native_async()
+        .map_err(|e| {
+            let diagnosis = diagnostics::diagnose(port, &e);
+            match diagnosis.status {
+                Some(status) => exit::failure(status, diagnosis.report(port)),
+                None => anyhow!(diagnosis.report(port)),
+            }
+        })?;
+    Ok(Box::new(port))
+}
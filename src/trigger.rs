@@ -0,0 +1,106 @@
+//! Actions fired when a monitored condition is met.
+//!
+//! `bell` rings the terminal bell, `send:TEXT` writes a line to the device and
+//! `exec:COMMAND` runs a shell command with details in its environment.
+//! The bell can be swapped for a sound with `--bell-command`.
+
+use std::{io::Write, process::Stdio, sync::OnceLock};
+
+use ratatui::style::{Color, Style};
+use tokio::{process::Command, sync::mpsc};
+
+use crate::{output::OutputLine, port::PortHandle};
+
+static BELL_COMMAND: OnceLock<String> = OnceLock::new();
+
+/// Makes the bell run `command` instead of ringing the terminal bell.
+pub fn set_bell_command(command: String) {
+    let _ = BELL_COMMAND.set(command);
+}
+
+/// Rings the terminal bell, or plays the `--bell-command` sound.
+pub fn ring() {
+    match BELL_COMMAND.get() {
+        Some(command) => {
+            let mut child = shell(command);
+            child.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+            tokio::spawn(async move {
+                let _ = child.status().await;
+            });
+        }
+        None => {
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Action {
+    Bell,
+    Send(String),
+    Exec(String),
+}
+
+pub fn parse_action(spec: &str) -> Result<Action, String> {
+    let spec = spec.trim();
+    if spec == "bell" {
+        return Ok(Action::Bell);
+    }
+    match spec.split_once(':') {
+        Some(("send", text)) => Ok(Action::Send(text.to_string())),
+        Some(("exec", command)) if !command.trim().is_empty() => Ok(Action::Exec(command.to_string())),
+        _ => Err(format!("Invalid action {spec:?}. Expected bell, send:TEXT or exec:COMMAND")),
+    }
+}
+
+/// Command that runs `command` in the platform shell.
+pub fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+impl Action {
+    /// The action as written after `=>`, e.g. `send:AT+RST`.
+    pub fn spec(&self) -> String {
+        match self {
+            Action::Bell => "bell".to_string(),
+            Action::Send(text) => format!("send:{text}"),
+            Action::Exec(command) => format!("exec:{command}"),
+        }
+    }
+
+    /// Runs the action; `env` is exported to `exec` commands as
+    /// `SERIAL_MONITOR_<KEY>` variables.
+    pub fn fire(&self, port: &PortHandle, annotations: &mpsc::UnboundedSender<OutputLine>, env: &[(&str, String)]) {
+        match self {
+            Action::Bell => ring(),
+            Action::Send(text) => port.write(format!("{text}\n").into_bytes()),
+            Action::Exec(command) => {
+                let mut child = shell(command);
+                for (key, value) in env {
+                    child.env(format!("SERIAL_MONITOR_{}", key.to_uppercase()), value);
+                }
+                let annotations = annotations.clone();
+                let command = command.clone();
+                tokio::spawn(async move {
+                    let error = match child.output().await {
+                        Ok(output) if output.status.success() => return,
+                        Ok(output) => format!("{command:?} exited with {}", output.status),
+                        Err(e) => format!("Cannot run {command:?}: {e}"),
+                    };
+                    let _ = annotations.send(OutputLine::new("[Trigger] ", error, Style::default().fg(Color::Red)));
+                });
+            }
+        }
+    }
+}
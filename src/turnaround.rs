@@ -0,0 +1,167 @@
+//! Turnaround times of request/response exchanges.
+//!
+//! On a half-duplex link a device answers each frame written to it, and the
+//! time from the end of the write to the first bytes read back from the same
+//! port is its turnaround. With `--turnaround` every one is recorded in a
+//! histogram shown under the message rates (Ctrl+T) and summed up on exit;
+//! with `--turnaround-limit` the answers slower than a device is required to
+//! give them are marked in the output.
+
+use std::{collections::VecDeque, time::Duration};
+
+use chrono::{DateTime, Local};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+};
+
+/// Upper bounds of the histogram buckets in milliseconds; the last bucket
+/// takes everything slower.
+const BUCKETS: [f64; 12] = [1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0];
+
+/// Answers the 95th percentile is taken over, the latest ones, so a long
+/// session doesn't keep every time.
+const RECENT: usize = 10_000;
+
+pub struct Turnaround {
+    limit: Option<Duration>,
+    /// End of the last write to each port still waiting for an answer.
+    waiting: Vec<Option<DateTime<Local>>>,
+    /// Answers timed.
+    answers: u64,
+    /// Sum, fastest and slowest of the times in milliseconds.
+    total: f64,
+    min: f64,
+    max: f64,
+    /// The latest times in milliseconds.
+    recent: VecDeque<f64>,
+    counts: [u64; BUCKETS.len() + 1],
+    over_limit: u64,
+}
+
+impl Turnaround {
+    pub fn new(ports: usize, limit: Option<Duration>) -> Self {
+        Self {
+            limit,
+            waiting: vec![None; ports],
+            answers: 0,
+            total: 0.0,
+            min: f64::INFINITY,
+            max: 0.0,
+            recent: VecDeque::new(),
+            counts: [0; BUCKETS.len() + 1],
+            over_limit: 0,
+        }
+    }
+
+    /// Notes a write to `port`; an answer is timed from the last write.
+    pub fn sent(&mut self, port: usize, time: DateTime<Local>) {
+        if let Some(waiting) = self.waiting.get_mut(port) {
+            *waiting = Some(time);
+        }
+    }
+
+    /// Times the first bytes read from `port` after a write, returning the
+    /// milliseconds taken and whether that is over the limit.
+    pub fn received(&mut self, port: usize, time: DateTime<Local>) -> Option<(f64, bool)> {
+        let sent = self.waiting.get_mut(port)?.take()?;
+        let ms = (time - sent).num_microseconds().unwrap_or(0).max(0) as f64 / 1000.0;
+        self.answers += 1;
+        self.total += ms;
+        self.min = self.min.min(ms);
+        self.max = self.max.max(ms);
+        if self.recent.len() == RECENT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(ms);
+        self.counts[BUCKETS.iter().position(|&bound| ms < bound).unwrap_or(BUCKETS.len())] += 1;
+        let over = self.limit.is_some_and(|limit| ms > limit.as_secs_f64() * 1000.0);
+        if over {
+            self.over_limit += 1;
+        }
+        Some((ms, over))
+    }
+
+    pub fn limit(&self) -> Option<Duration> {
+        self.limit
+    }
+
+    /// `min/avg/p95/max` of the times, and how many were over the limit.
+    fn statistics(&self) -> Option<(String, Option<String>)> {
+        if self.answers == 0 {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.recent.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let avg = self.total / self.answers as f64;
+        let p95 = sorted[(sorted.len() * 95).div_ceil(100) - 1];
+        let times = format!("min/avg/p95/max {:.1}/{avg:.1}/{p95:.1}/{:.1} ms", self.min, self.max);
+        let over = self.limit.map(|limit| format!("{} over {} ms", self.over_limit, limit.as_millis()));
+        Some((times, over))
+    }
+
+    /// Line for the session summary.
+    pub fn summary(&self) -> String {
+        match self.statistics() {
+            Some((times, Some(over))) => format!("{} answers, {times}, {over}", self.answers),
+            Some((times, None)) => format!("{} answers, {times}", self.answers),
+            None => "no answers".to_string(),
+        }
+    }
+
+    /// Rows the histogram takes, including its border.
+    pub fn height(&self) -> u16 {
+        match self.range() {
+            Some((first, last)) => (last - first + 1) as u16 + 3 + u16::from(self.limit.is_some()),
+            None => 3,
+        }
+    }
+
+    /// The buckets from the fastest to the slowest one with answers.
+    fn range(&self) -> Option<(usize, usize)> {
+        let first = self.counts.iter().position(|&count| count > 0)?;
+        let last = self.counts.iter().rposition(|&count| count > 0)?;
+        Some((first, last))
+    }
+
+    pub fn render(&self, f: &mut Frame, area: Rect) {
+        let title = format!("Turnaround ({})", self.answers);
+        let block = Block::default().title(title).borders(Borders::ALL);
+        let Some((first, last)) = self.range() else {
+            let waiting = Line::styled("No answers yet", Style::default().fg(Color::DarkGray));
+            f.render_widget(Paragraph::new(waiting).block(block), area);
+            return;
+        };
+        let (times, over) = self.statistics().unwrap_or_default();
+        let mut lines = vec![Line::styled(times, Style::default().fg(Color::Cyan))];
+        if let Some(over) = over {
+            let color = if self.over_limit > 0 { Color::Red } else { Color::Green };
+            lines.push(Line::styled(over, Style::default().fg(color)));
+        }
+        let most = self.counts.iter().copied().max().unwrap_or(1).max(1);
+        // Label, count and the spaces between them take 16 columns
+        let width = (area.width as usize).saturating_sub(2 + 16);
+        for bucket in first..=last {
+            let label = match BUCKETS.get(bucket) {
+                Some(bound) => format!("<{bound:>5} ms"),
+                None => format!(">={:>4} ms", BUCKETS[BUCKETS.len() - 1]),
+            };
+            let count = self.counts[bucket];
+            let bar = "█".repeat((count as usize * width).div_ceil(most as usize));
+            // Buckets past the limit are the ones failing the requirement
+            let slow = self.limit.is_some_and(|limit| {
+                bucket > 0 && BUCKETS[bucket - 1] >= limit.as_secs_f64() * 1000.0
+            });
+            let color = if slow { Color::Red } else { Color::Green };
+            lines.push(Line::from(vec![
+                Span::raw(format!("{label} ")),
+                Span::styled(bar, Style::default().fg(color)),
+                Span::raw(format!(" {count}")),
+            ]));
+        }
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
@@ -0,0 +1,290 @@
+//! Named value extractors with a live summary panel.
+//!
+//! Each watch is `NAME=REGEX`; the first capture group (or the whole match)
+//! of every matching received line becomes the watch's latest value, and
+//! numeric values are folded into min/max/average.
+//!
+//! Watches can also be fed without a regex, with the values a
+//! `--modbus-poll` reads.
+//!
+//! Alerts such as `temp>80 => exec:./cut-power.sh` mark a watch out of range
+//! and fire their action each time its value leaves the allowed range.
+
+use ratatui::{
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Row, Table},
+    Frame,
+};
+use regex::Regex;
+
+use crate::trigger::{self, Action};
+
+#[derive(Debug, Clone)]
+pub struct WatchSpec {
+    pub name: String,
+    pub regex: Regex,
+}
+
+pub fn parse_spec(spec: &str) -> Result<WatchSpec, String> {
+    let Some((name, pattern)) = spec.split_once('=').filter(|(name, _)| !name.is_empty()) else {
+        return Err(format!("Invalid watch {spec:?}. Expected NAME=REGEX"));
+    };
+    let regex = Regex::new(pattern).map_err(|e| format!("Invalid regex for watch {name:?}: {e}"))?;
+    Ok(WatchSpec {
+        name: name.to_string(),
+        regex,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparison {
+    Above,
+    AtLeast,
+    Below,
+    AtMost,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertSpec {
+    pub name: String,
+    comparison: Comparison,
+    limit: f64,
+    pub action: Option<Action>,
+}
+
+/// Parses `NAME<OP>LIMIT[ => ACTION]` with `OP` one of `>`, `>=`, `<`, `<=`.
+pub fn parse_alert(spec: &str) -> Result<AlertSpec, String> {
+    let (condition, action) = match spec.split_once("=>") {
+        Some((condition, action)) => (condition, Some(trigger::parse_action(action)?)),
+        None => (spec, None),
+    };
+    let invalid = || format!("Invalid alert {spec:?}. Expected NAME>LIMIT, NAME<=LIMIT, ... [=> ACTION]");
+    let op_start = condition.find(['<', '>']).ok_or_else(invalid)?;
+    let (name, rest) = condition.split_at(op_start);
+    let (comparison, limit) = if let Some(limit) = rest.strip_prefix(">=") {
+        (Comparison::AtLeast, limit)
+    } else if let Some(limit) = rest.strip_prefix("<=") {
+        (Comparison::AtMost, limit)
+    } else if let Some(limit) = rest.strip_prefix('>') {
+        (Comparison::Above, limit)
+    } else {
+        (Comparison::Below, &rest[1..])
+    };
+    let name = name.trim();
+    let limit: f64 = limit.trim().parse().map_err(|_| invalid())?;
+    if name.is_empty() {
+        return Err(invalid());
+    }
+    Ok(AlertSpec {
+        name: name.to_string(),
+        comparison,
+        limit,
+        action,
+    })
+}
+
+impl AlertSpec {
+    fn is_triggered(&self, value: f64) -> bool {
+        match self.comparison {
+            Comparison::Above => value > self.limit,
+            Comparison::AtLeast => value >= self.limit,
+            Comparison::Below => value < self.limit,
+            Comparison::AtMost => value <= self.limit,
+        }
+    }
+
+    fn condition(&self) -> String {
+        let op = match self.comparison {
+            Comparison::Above => ">",
+            Comparison::AtLeast => ">=",
+            Comparison::Below => "<",
+            Comparison::AtMost => "<=",
+        };
+        format!("{} {op} {}", self.name, self.limit)
+    }
+
+    /// The rule as `parse_alert` takes it.
+    pub fn spec(&self) -> String {
+        match &self.action {
+            Some(action) => format!("{} => {}", self.condition(), action.spec()),
+            None => self.condition(),
+        }
+    }
+}
+
+struct Alert {
+    spec: AlertSpec,
+    active: bool,
+}
+
+/// An alert that changed state on the latest value.
+pub struct AlertEvent {
+    pub text: String,
+    pub triggered: bool,
+    pub name: String,
+    pub value: String,
+    pub action: Option<Action>,
+}
+
+struct Watch {
+    name: String,
+    /// `None` for a watch fed with `set`.
+    regex: Option<Regex>,
+    alerts: Vec<Alert>,
+    latest: Option<String>,
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: u64,
+}
+
+impl Watch {
+    /// Takes a new value, pushing the alerts it changed the state of.
+    fn update(&mut self, value: &str, events: &mut Vec<AlertEvent>) {
+        if let Ok(number) = value.parse::<f64>() {
+            self.min = self.min.min(number);
+            self.max = self.max.max(number);
+            self.sum += number;
+            self.count += 1;
+            for alert in &mut self.alerts {
+                let triggered = alert.spec.is_triggered(number);
+                if triggered == alert.active {
+                    continue;
+                }
+                alert.active = triggered;
+                let text = if triggered {
+                    format!("{} (value {value})", alert.spec.condition())
+                } else {
+                    format!("{} cleared (value {value})", alert.spec.condition())
+                };
+                events.push(AlertEvent {
+                    text,
+                    triggered,
+                    name: self.name.clone(),
+                    value: value.to_string(),
+                    action: alert.spec.action.clone().filter(|_| triggered),
+                });
+            }
+        }
+        self.latest = Some(value.to_string());
+    }
+}
+
+pub struct Watches {
+    watches: Vec<Watch>,
+}
+
+impl Watches {
+    /// Watches extracted from received lines with `specs`, and watches named
+    /// `fed` whose values are `set`.
+    pub fn new(specs: Vec<WatchSpec>, fed: Vec<String>, alerts: Vec<AlertSpec>) -> Result<Self, String> {
+        let watched: Vec<(String, Option<Regex>)> = specs
+            .into_iter()
+            .map(|spec| (spec.name, Some(spec.regex)))
+            .chain(fed.into_iter().map(|name| (name, None)))
+            .collect();
+        if let Some(alert) = alerts.iter().find(|a| !watched.iter().any(|(name, _)| *name == a.name)) {
+            return Err(format!("Alert on unknown watch {:?}", alert.name));
+        }
+        let watches = watched
+            .into_iter()
+            .map(|(name, regex)| Watch {
+                alerts: alerts
+                    .iter()
+                    .filter(|a| a.name == name)
+                    .map(|a| Alert {
+                        spec: a.clone(),
+                        active: false,
+                    })
+                    .collect(),
+                name,
+                regex,
+                latest: None,
+                min: f64::INFINITY,
+                max: f64::NEG_INFINITY,
+                sum: 0.0,
+                count: 0,
+            })
+            .collect();
+        Ok(Self { watches })
+    }
+
+    pub fn len(&self) -> usize {
+        self.watches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    /// The watches extracted from received lines as `NAME=REGEX`, and the
+    /// rules of their alerts.
+    pub fn specs(&self) -> (Vec<String>, Vec<String>) {
+        let extracted = self.watches.iter().filter_map(|watch| Some((watch, watch.regex.as_ref()?)));
+        let watches = extracted.clone().map(|(watch, regex)| format!("{}={}", watch.name, regex.as_str())).collect();
+        let alerts = extracted.flat_map(|(watch, _)| watch.alerts.iter().map(|alert| alert.spec.spec())).collect();
+        (watches, alerts)
+    }
+
+    /// Updates matching watches and reports alerts entering or leaving
+    /// their triggered state. The names of the matching watches are added
+    /// to `matched`.
+    pub fn record(&mut self, line: &str, matched: &mut Vec<String>) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for watch in &mut self.watches {
+            let Some(captures) = watch.regex.as_ref().and_then(|regex| regex.captures(line)) else {
+                continue;
+            };
+            matched.push(format!("--watch {}", watch.name));
+            let Some(value) = captures.get(1).or_else(|| captures.get(0)) else {
+                continue;
+            };
+            watch.update(value.as_str().trim(), &mut events);
+        }
+        events
+    }
+
+    /// Sets the value of watch `name` and reports its alerts like `record`.
+    pub fn set(&mut self, name: &str, value: &str) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        if let Some(watch) = self.watches.iter_mut().find(|watch| watch.name == name) {
+            watch.update(value, &mut events);
+        }
+        events
+    }
+
+    pub fn render(&self, f: &mut Frame, area: Rect) {
+        let rows = self.watches.iter().map(|watch| {
+            let stat = |value: f64| if watch.count > 0 { format!("{value:.2}") } else { String::new() };
+            Row::new([
+                watch.name.clone(),
+                watch.latest.clone().unwrap_or_else(|| "-".to_string()),
+                stat(watch.min),
+                stat(watch.max),
+                stat(watch.sum / watch.count.max(1) as f64),
+            ])
+            .style(if watch.alerts.iter().any(|a| a.active) {
+                Style::default().fg(Color::White).bg(Color::Red)
+            } else {
+                Style::default()
+            })
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Min(8),
+                Constraint::Length(9),
+                Constraint::Length(8),
+                Constraint::Length(8),
+                Constraint::Length(8),
+            ],
+        )
+        .header(
+            Row::new(["Name", "Latest", "Min", "Max", "Avg"])
+                .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().title("Watch").borders(Borders::ALL));
+        f.render_widget(table, area);
+    }
+}
@@ -0,0 +1,316 @@
+//! First-run setup.
+//!
+//! Started without arguments and without a config file, the monitor asks for
+//! the port, baud rate, line ending and logging in a few TUI steps, and can
+//! save the answers as the `default` profile so the next start goes straight
+//! to the monitor. The baud rate can be detected by listening at each rate
+//! for text.
+
+use std::{
+    io::IsTerminal,
+    time::Duration,
+};
+
+use anyhow::Result;
+use crossterm::{
+    cursor::{Hide, Show},
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+};
+use ratatui::{
+    Frame, Terminal,
+    backend::CrosstermBackend,
+    layout::{Constraint, Flex, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
+};
+use tokio::{io::AsyncReadExt, time};
+
+use crate::{
+    config::{self, DEFAULT_PROFILE, Profile},
+    discovery,
+    transport::{self, LineEnding, SerialSettings, VALID_BAUD_RATES},
+};
+
+/// How long each rate is listened to while detecting the baud rate.
+const LISTEN_PER_RATE: Duration = Duration::from_millis(400);
+/// Rates tried by auto-detection, the most common first.
+const DETECT_ORDER: [u32; 9] = [115200, 9600, 57600, 38400, 19200, 4800, 2400, 1200, 300];
+
+/// Whether this is a first run: no arguments, no config file (the user's or
+/// the project's) and a terminal to ask on.
+pub fn wanted() -> bool {
+    std::env::args_os().len() == 1
+        && std::io::stdout().is_terminal()
+        && config::default_path().is_some_and(|path| !path.exists())
+        && config::project_path().is_none()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Port,
+    /// Typing a port that wasn't detected.
+    PortName,
+    Baud,
+    Ending,
+    Logging,
+    Save,
+}
+
+struct Wizard {
+    step: Step,
+    ports: Vec<(String, String)>,
+    choice: ListState,
+    typed: String,
+    note: Option<String>,
+    profile: Profile,
+}
+
+impl Wizard {
+    fn question(&self) -> &'static str {
+        match self.step {
+            Step::Port => "Which port is the device on?",
+            Step::PortName => "Port name (e.g. /dev/ttyACM0, COM3 or rfc2217://host:port)",
+            Step::Baud => "Baud rate",
+            Step::Ending => "Line ending appended to what you send",
+            Step::Logging => "Log the session to serial_monitor.log?",
+            Step::Save => "Save these settings as the default profile?",
+        }
+    }
+
+    fn options(&self) -> Vec<String> {
+        match self.step {
+            Step::Port => self
+                .ports
+                .iter()
+                .map(|(name, description)| match description.is_empty() {
+                    true => name.clone(),
+                    false => format!("{name}  {description}"),
+                })
+                .chain(std::iter::once("Other: type the port name".to_string()))
+                .collect(),
+            Step::PortName => vec![format!("{}_", self.typed)],
+            Step::Baud => std::iter::once("Auto-detect".to_string())
+                .chain(VALID_BAUD_RATES.iter().map(|baud| baud.to_string()))
+                .collect(),
+            Step::Ending => ["LF (\\n)", "CR (\\r)", "CR LF (\\r\\n)", "None"].map(String::from).to_vec(),
+            Step::Logging => ["Yes", "No"].map(String::from).to_vec(),
+            Step::Save => {
+                let path = config::default_path().map(|path| path.display().to_string()).unwrap_or_default();
+                vec![format!("Yes, to {path}"), "No, just for this session".to_string()]
+            }
+        }
+    }
+
+    /// The option preselected when entering a step.
+    fn preselected(&self) -> usize {
+        match self.step {
+            Step::Baud => 1 + VALID_BAUD_RATES.iter().position(|&baud| baud == 57600).unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    fn enter(&mut self, step: Step) {
+        self.step = step;
+        self.choice.select(Some(self.preselected()));
+    }
+
+    fn back(&mut self) {
+        self.note = None;
+        match self.step {
+            Step::Port => {}
+            Step::PortName | Step::Baud => self.enter(Step::Port),
+            Step::Ending => self.enter(Step::Baud),
+            Step::Logging => self.enter(Step::Ending),
+            Step::Save => self.enter(Step::Logging),
+        }
+    }
+
+    fn render(&mut self, f: &mut Frame) {
+        let options = self.options();
+        let height = (options.len() as u16 + 7).min(f.area().height);
+        let [area] = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center).areas(f.area());
+        let [area] = Layout::horizontal([Constraint::Length(64.min(f.area().width))]).flex(Flex::Center).areas(area);
+        f.render_widget(Clear, area);
+        let block = Block::default()
+            .title(" serial-monitor setup ")
+            .title_bottom(" Enter choose, Backspace back, Esc skip ")
+            .borders(Borders::ALL);
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        let [question, list, note] =
+            Layout::vertical([Constraint::Length(2), Constraint::Min(1), Constraint::Length(2)]).areas(inner);
+        f.render_widget(
+            Paragraph::new(Line::from(self.question()).style(Style::default().add_modifier(Modifier::BOLD))),
+            question,
+        );
+        let items: Vec<ListItem> = options.into_iter().map(ListItem::new).collect();
+        let list_widget = List::new(items)
+            .highlight_style(Style::default().fg(Color::Black).bg(Color::Cyan))
+            .highlight_symbol("> ");
+        f.render_stateful_widget(list_widget, list, &mut self.choice);
+        if let Some(text) = &self.note {
+            f.render_widget(
+                Paragraph::new(text.as_str()).style(Style::default().fg(Color::Yellow)).wrap(Wrap { trim: true }),
+                note,
+            );
+        }
+    }
+}
+
+/// Asks for the settings; `None` if the user skipped the setup.
+pub async fn run() -> Result<Option<Profile>> {
+    let mut ports: Vec<(String, String)> = discovery::list().into_iter().map(|port| (port.name, port.description)).collect();
+    if ports.is_empty() {
+        ports.push(("/dev/ttyUSB0".to_string(), "(no ports detected)".to_string()));
+    }
+    let mut wizard = Wizard {
+        step: Step::Port,
+        ports,
+        choice: ListState::default().with_selected(Some(0)),
+        typed: String::new(),
+        note: None,
+        profile: Profile::default(),
+    };
+
+    enable_raw_mode()?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, EnterAlternateScreen, Hide)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+    let result = ask(&mut terminal, &mut wizard).await;
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen, Show)?;
+    let save = match result? {
+        Some(save) => save,
+        None => return Ok(None),
+    };
+    if save {
+        config::save_profile(None, DEFAULT_PROFILE, &wizard.profile)?;
+        if let Some(path) = config::default_path() {
+            println!("Saved the default profile to {}", path.display());
+        }
+    }
+    Ok(Some(wizard.profile))
+}
+
+/// Runs the steps; `Some(save)` once they are all answered.
+async fn ask(terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>, wizard: &mut Wizard) -> Result<Option<bool>> {
+    loop {
+        terminal.draw(|f| wizard.render(f))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        if wizard.step == Step::PortName {
+            match key.code {
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Char(c) => wizard.typed.push(c),
+                KeyCode::Backspace if !wizard.typed.is_empty() => {
+                    wizard.typed.pop();
+                }
+                KeyCode::Backspace => wizard.back(),
+                KeyCode::Enter => match crate::transport::validate_port(wizard.typed.trim()) {
+                    Ok(port) => {
+                        wizard.note = None;
+                        wizard.profile.port = Some(port);
+                        wizard.enter(Step::Baud);
+                    }
+                    Err(e) => wizard.note = Some(e),
+                },
+                _ => {}
+            }
+            continue;
+        }
+        let count = wizard.options().len();
+        let selected = wizard.choice.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Up => wizard.choice.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down => wizard.choice.select(Some((selected + 1).min(count - 1))),
+            KeyCode::Backspace | KeyCode::Left => wizard.back(),
+            KeyCode::Enter => {
+                wizard.note = None;
+                match wizard.step {
+                    Step::Port if selected == wizard.ports.len() => wizard.enter(Step::PortName),
+                    Step::Port => {
+                        wizard.profile.port = Some(wizard.ports[selected].0.clone());
+                        wizard.enter(Step::Baud);
+                    }
+                    Step::PortName => {}
+                    Step::Baud if selected == 0 => {
+                        let port = wizard.profile.port.clone().unwrap_or_default();
+                        wizard.note = Some(format!("Listening on {port} at each rate..."));
+                        terminal.draw(|f| wizard.render(f))?;
+                        let detected = detect(&port).await;
+                        // Keys pressed while listening were meant for the old screen
+                        while event::poll(Duration::ZERO)? {
+                            event::read()?;
+                        }
+                        match detected {
+                            Ok(Some(baud)) => {
+                                wizard.profile.baud_rate = Some(baud);
+                                wizard.enter(Step::Ending);
+                                wizard.note = Some(format!("Detected {baud} baud"));
+                            }
+                            Ok(None) => {
+                                wizard.note = Some(format!(
+                                    "No readable text from {port} at any rate; is the device sending? Pick a rate instead."
+                                ));
+                            }
+                            Err(e) => wizard.note = Some(format!("Cannot open {port}: {e:#}")),
+                        }
+                    }
+                    Step::Baud => {
+                        wizard.profile.baud_rate = Some(VALID_BAUD_RATES[selected - 1]);
+                        wizard.enter(Step::Ending);
+                    }
+                    Step::Ending => {
+                        let endings = [LineEnding::Lf, LineEnding::Cr, LineEnding::Crlf, LineEnding::None];
+                        wizard.profile.line_ending = Some(endings[selected]);
+                        wizard.enter(Step::Logging);
+                    }
+                    Step::Logging => {
+                        wizard.profile.no_log = Some(selected == 1);
+                        wizard.enter(Step::Save);
+                    }
+                    Step::Save => return Ok(Some(selected == 0)),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The rate at which `port` sends the most plausible text, if any.
+async fn detect(port: &str) -> Result<Option<u32>> {
+    let mut best: Option<(u32, f64)> = None;
+    for baud in DETECT_ORDER {
+        let settings = SerialSettings {
+            baud_rate: baud,
+            ..SerialSettings::default()
+        };
+        let mut transport = transport::open(port, &settings).await?;
+        let mut data = Vec::new();
+        let mut buf = [0; 256];
+        let deadline = time::Instant::now() + LISTEN_PER_RATE;
+        while data.len() < 512 {
+            match time::timeout_at(deadline, transport.read(&mut buf)).await {
+                Ok(Ok(n)) if n > 0 => data.extend_from_slice(&buf[..n]),
+                _ => break,
+            }
+        }
+        // A wrong rate yields bytes outside printable ASCII and whitespace
+        if data.len() >= 8 {
+            let text = data.iter().filter(|&&b| b.is_ascii_graphic() || b.is_ascii_whitespace()).count();
+            let score = text as f64 / data.len() as f64;
+            if score > 0.9 && best.is_none_or(|(_, best)| score > best) {
+                best = Some((baud, score));
+            }
+        }
+    }
+    Ok(best.map(|(baud, _)| baud))
+}
@@ -0,0 +1,196 @@
+//! Properties of the frame assembler: however the bytes of a stream are split
+//! across reads, the same frames come out, and what goes in comes out.
+//! Framing bugs otherwise only show up with hardware that happens to split
+//! its writes at the wrong byte.
+
+use proptest::prelude::*;
+use serial_monitor::{
+    framing::{Decoder, Frame, Framer, Framing, LengthPrefix},
+    transport::Encoding,
+};
+
+/// Frames of `data` pushed in pieces split at `cuts`, then flushed as the
+/// line going quiet would.
+fn frames(mut framer: Framer, data: &[u8], cuts: &[usize]) -> Vec<Frame> {
+    let mut cuts: Vec<usize> = cuts.iter().map(|cut| cut % (data.len() + 1)).collect();
+    cuts.sort();
+    let mut out = Vec::new();
+    let mut start = 0;
+    for cut in cuts.into_iter().chain([data.len()]) {
+        framer.push(&data[start..cut], &mut out);
+        start = cut;
+    }
+    framer.flush(&mut out);
+    out
+}
+
+fn lines() -> Framer {
+    Framer::new(Framing::Lines, LengthPrefix::Varint)
+}
+
+/// Bytes of a noisy line: mostly text, line endings and binary garbage.
+fn noise() -> impl Strategy<Value = Vec<u8>> {
+    let byte = prop_oneof![4 => 0x20u8..0x7f, 2 => Just(b'\r'), 2 => Just(b'\n'), 1 => any::<u8>()];
+    prop::collection::vec(byte, 0..512)
+}
+
+fn cuts() -> impl Strategy<Value = Vec<usize>> {
+    prop::collection::vec(any::<usize>(), 0..16)
+}
+
+fn payload(frame: &Frame) -> &[u8] {
+    match frame {
+        Frame::Data(data) | Frame::Line(data, _) | Frame::Part(data) => data,
+        Frame::Invalid(_) | Frame::Text(_) => &[],
+    }
+}
+
+/// The payloads of `frames` back to back.
+fn joined(frames: &[Frame]) -> Vec<u8> {
+    frames.iter().flat_map(payload).copied().collect()
+}
+
+proptest! {
+    #[test]
+    fn lines_ignore_read_boundaries(data in noise(), cuts in cuts()) {
+        prop_assert_eq!(frames(lines(), &data, &cuts), frames(lines(), &data, &[]));
+    }
+
+    #[test]
+    fn lines_keep_every_byte_but_terminators(data in noise(), cuts in cuts()) {
+        let frames = frames(lines(), &data, &cuts);
+        let text: Vec<u8> = data.iter().copied().filter(|b| !matches!(b, b'\r' | b'\n')).collect();
+        prop_assert_eq!(joined(&frames), text);
+        for frame in &frames {
+            prop_assert!(!payload(frame).iter().any(|b| matches!(b, b'\r' | b'\n')));
+        }
+    }
+
+    #[test]
+    fn lines_come_out_with_their_endings(
+        sent in prop::collection::vec(
+            (prop::collection::vec(any::<u8>().prop_filter("no terminator", |b| !matches!(b, b'\r' | b'\n')), 1..80),
+             prop::sample::select(vec!["\r\n", "\n", "\r"])),
+            0..32,
+        ),
+        cuts in cuts(),
+    ) {
+        let data: Vec<u8> = sent.iter().flat_map(|(line, ending)| [line.as_slice(), ending.as_bytes()].concat()).collect();
+        let expected: Vec<Frame> = sent.into_iter().map(|(line, ending)| Frame::Line(line, ending)).collect();
+        prop_assert_eq!(frames(lines(), &data, &cuts), expected);
+    }
+
+    #[test]
+    fn long_lines_are_split_into_parts(data in noise(), max in 1usize..64, cuts in cuts()) {
+        let framer = || lines().with_max_line(max);
+        let frames = frames(framer(), &data, &cuts);
+        prop_assert_eq!(&frames, &self::frames(framer(), &data, &[]));
+        prop_assert_eq!(joined(&frames), self::frames(lines(), &data, &[]).iter().flat_map(payload).copied().collect::<Vec<_>>());
+        for frame in &frames {
+            prop_assert!(payload(frame).len() <= max);
+            if let Frame::Part(part) = frame {
+                prop_assert_eq!(part.len(), max);
+            }
+        }
+    }
+
+    #[test]
+    fn long_text_is_split_between_characters(
+        text in "[^\r\n]{0,64}",
+        framing in prop::sample::select(vec![Framing::Lines, Framing::Delimiter, Framing::Idle]),
+        max in 4usize..16,
+        cuts in cuts(),
+    ) {
+        let framer = || Framer::new(framing, LengthPrefix::Varint).with_max_line(max).with_encoding(Encoding::Utf8);
+        let frames = frames(framer(), text.as_bytes(), &cuts);
+        prop_assert_eq!(&frames, &self::frames(framer(), text.as_bytes(), &[]));
+        prop_assert_eq!(joined(&frames), text.as_bytes());
+        for frame in &frames {
+            prop_assert!(payload(frame).len() <= max);
+            prop_assert!(std::str::from_utf8(payload(frame)).is_ok());
+        }
+    }
+
+    #[test]
+    fn characters_split_across_reads_decode_whole(sent in prop::collection::vec("[^\r\n]+", 0..16), cuts in cuts()) {
+        let data = sent.iter().map(|line| format!("{line}\n")).collect::<String>().into_bytes();
+        let decoder = Decoder::Text(Encoding::Utf8);
+        let decoded: Vec<String> = frames(lines(), &data, &cuts).iter().map(|frame| decoder.decode(frame).0).collect();
+        prop_assert_eq!(decoded, sent);
+    }
+
+    #[test]
+    fn fixed_frames_ignore_read_boundaries(data in noise(), length in 1usize..64, cuts in cuts()) {
+        let framer = || Framer::new(Framing::Fixed, LengthPrefix::Varint).with_length(length);
+        let frames = frames(framer(), &data, &cuts);
+        prop_assert_eq!(joined(&frames), data.clone());
+        for frame in frames.iter().take(data.len() / length) {
+            prop_assert_eq!(payload(frame).len(), length);
+        }
+    }
+
+    #[test]
+    fn delimited_frames_end_with_the_delimiter(
+        data in noise(),
+        delimiter in prop::collection::vec(any::<u8>(), 1..4),
+        cuts in cuts(),
+    ) {
+        let framer = || Framer::new(Framing::Delimiter, LengthPrefix::Varint).with_delimiter(delimiter.clone());
+        let frames = frames(framer(), &data, &cuts);
+        prop_assert_eq!(&frames, &self::frames(framer(), &data, &[]));
+        prop_assert_eq!(joined(&frames), data);
+        for frame in frames.iter().rev().skip(1) {
+            prop_assert!(payload(frame).ends_with(&delimiter));
+        }
+    }
+
+    #[test]
+    fn length_prefixed_frames_round_trip(
+        sent in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..256), 0..16),
+        prefix in prop::sample::select(vec![
+            LengthPrefix::Varint,
+            LengthPrefix::U8,
+            LengthPrefix::U16Be,
+            LengthPrefix::U16Le,
+            LengthPrefix::U32Be,
+            LengthPrefix::U32Le,
+        ]),
+        cuts in cuts(),
+    ) {
+        let mut data = Vec::new();
+        for payload in &sent {
+            data.extend(encode(prefix, payload.len()));
+            data.extend_from_slice(payload);
+        }
+        let expected: Vec<Frame> = sent.into_iter().map(Frame::Data).collect();
+        prop_assert_eq!(frames(Framer::new(Framing::LengthPrefixed, prefix), &data, &cuts), expected);
+    }
+
+    #[test]
+    fn length_prefixed_frames_resync_after_a_bad_length(
+        sent in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..256), 1..16),
+        cuts in cuts(),
+    ) {
+        let mut data = vec![0xff; 4];
+        for payload in &sent {
+            data.extend(encode(LengthPrefix::U32Be, payload.len()));
+            data.extend_from_slice(payload);
+        }
+        let frames = frames(Framer::new(Framing::LengthPrefixed, LengthPrefix::U32Be), &data, &cuts);
+        prop_assert!(matches!(frames.first(), Some(Frame::Invalid(_))));
+        let received: Vec<Frame> = frames.into_iter().filter(|frame| !matches!(frame, Frame::Invalid(_))).collect();
+        prop_assert_eq!(received, sent.into_iter().map(Frame::Data).collect::<Vec<_>>());
+    }
+}
+
+/// The length prefix of a payload of `len` bytes, below 256.
+fn encode(prefix: LengthPrefix, len: usize) -> Vec<u8> {
+    match prefix {
+        LengthPrefix::Varint if len >= 0x80 => vec![len as u8 | 0x80, (len >> 7) as u8],
+        LengthPrefix::Varint | LengthPrefix::U8 => vec![len as u8],
+        LengthPrefix::U16Be => (len as u16).to_be_bytes().to_vec(),
+        LengthPrefix::U16Le => (len as u16).to_le_bytes().to_vec(),
+        LengthPrefix::U32Be => (len as u32).to_be_bytes().to_vec(),
+        LengthPrefix::U32Le => (len as u32).to_le_bytes().to_vec(),
+    }
+}
@@ -0,0 +1,378 @@
+//! The port task against a [`MockPort`]: scripted reads arrive as lines,
+//! disconnects and failed writes reconnect, line settings reach the driver
+//! and held writes go out in order, and what arrives is logged as the
+//! monitor logs it.
+
+use std::{
+    fs,
+    io,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use serial_monitor::{
+    events::{PortEvent, SerialEvent},
+    framing::{Decoder, Framer, Framing, LengthPrefix},
+    logfile::{self, LogControl, LogDirection, Logs, open_log},
+    mock::{MockDevice, MockPort},
+    port::{self, Opener, PortHandle, Recover, Restart, RestartPolicy},
+    signals,
+    transport::{Control, Encoding, FlowControlArg, LineErrors, SerialSettings, Transport},
+};
+use tokio::{sync::mpsc, time};
+
+/// Longest wait for an event; the restart delays are a second or two.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn spawn(port: MockPort, policy: RestartPolicy, open: Opener) -> (PortHandle, mpsc::UnboundedReceiver<PortEvent>) {
+    restarting(port, Restart { policy, open, recover: None })
+}
+
+fn restarting(port: MockPort, restart: Restart) -> (PortHandle, mpsc::UnboundedReceiver<PortEvent>) {
+    let (tx_events, rx_events) = mpsc::unbounded_channel();
+    let framer = Framer::new(Framing::Lines, LengthPrefix::Varint);
+    let handle = port::spawn(0, Box::new(port), restart, SerialSettings::default(), framer, Decoder::Text(Encoding::Utf8), tx_events);
+    (handle, rx_events)
+}
+
+/// Reopens ports on `device` playing `script`.
+fn reopen(device: &MockDevice, script: fn(MockPort) -> MockPort) -> Opener {
+    let device = device.clone();
+    Box::new(move |_| {
+        let port = script(device.open());
+        Box::pin(async move { Ok(Box::new(port) as Box<dyn Transport>) })
+    })
+}
+
+/// An opener for ports that are never reopened.
+fn never() -> Opener {
+    Box::new(|_| Box::pin(async { Err(anyhow::anyhow!("not reopened")) }))
+}
+
+/// E.g. `rx hello`, `tx AT`, `connected` or `error Serial read error: …`.
+fn describe(event: &SerialEvent) -> String {
+    match event {
+        SerialEvent::Rx { text, .. } => format!("rx {text}"),
+        SerialEvent::Chunk(data) => format!("chunk {}", String::from_utf8_lossy(data)),
+        SerialEvent::Tx(data) => format!("tx {}", String::from_utf8_lossy(data)),
+        SerialEvent::Connected => "connected".to_string(),
+        SerialEvent::Disconnected => "disconnected".to_string(),
+        SerialEvent::Error(e) => format!("error {e}"),
+        SerialEvent::Notice(text) => format!("notice {text}"),
+        SerialEvent::LineErrors(errors) => format!("line errors {}", errors.describe()),
+    }
+}
+
+/// The next `count` events but raw chunks, described.
+async fn events(rx: &mut mpsc::UnboundedReceiver<PortEvent>, count: usize) -> Vec<String> {
+    let mut events = Vec::new();
+    while events.len() < count {
+        match time::timeout(TIMEOUT, rx.recv()).await {
+            Ok(Some(PortEvent { event: SerialEvent::Chunk(_), .. })) => {}
+            Ok(Some(event)) => events.push(describe(&event.event)),
+            Ok(None) => panic!("the port task ended after {events:?}"),
+            Err(_) => panic!("no event within {TIMEOUT:?} after {events:?}"),
+        }
+    }
+    events
+}
+
+/// The next `count` lines received or sent, with when they were.
+async fn received(rx: &mut mpsc::UnboundedReceiver<PortEvent>, count: usize) -> Vec<PortEvent> {
+    let mut lines = Vec::new();
+    while lines.len() < count {
+        let event = time::timeout(TIMEOUT, rx.recv()).await.expect("a received line").expect("an open port");
+        if matches!(event.event, SerialEvent::Rx { .. } | SerialEvent::Tx(_)) {
+            lines.push(event);
+        }
+    }
+    lines
+}
+
+/// An empty directory of its own for a test's files.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("serial-monitor-test-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("a scratch directory");
+    dir
+}
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[tokio::test]
+async fn scripted_reads_arrive_as_lines() {
+    let device = MockDevice::default();
+    let port = device.open().read(b"hel").pause(ms(50)).read(b"lo\r").read(b"\nworld\n");
+    let started = Instant::now();
+    let (_port, mut rx) = spawn(port, RestartPolicy::Never, never());
+    assert_eq!(events(&mut rx, 3).await, ["connected", "rx hello", "rx world"]);
+    assert!(started.elapsed() >= ms(50));
+}
+
+#[tokio::test]
+async fn reconnects_after_a_disconnect() {
+    let device = MockDevice::default();
+    let port = device.open().read(b"first boot\n").disconnect();
+    let (_port, mut rx) = spawn(port, RestartPolicy::OnFailure, reopen(&device, |port| port.read(b"second boot\n")));
+    assert_eq!(
+        events(&mut rx, 5).await,
+        ["connected", "rx first boot", "disconnected", "connected", "rx second boot"]
+    );
+    assert_eq!(device.opened(), 2);
+}
+
+#[tokio::test]
+async fn keeps_reopening_until_the_device_is_back() {
+    let device = MockDevice::default();
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let reopened = device.clone();
+    let open: Opener = Box::new(move |_| {
+        let attempt = attempts.fetch_add(1, Ordering::Relaxed);
+        let port = reopened.open().read(b"back\n");
+        Box::pin(async move {
+            match attempt {
+                0 => Err(anyhow::anyhow!("No such device")),
+                _ => Ok(Box::new(port) as Box<dyn Transport>),
+            }
+        })
+    });
+    let (_port, mut rx) = spawn(device.open().disconnect(), RestartPolicy::OnFailure, open);
+    assert_eq!(
+        events(&mut rx, 5).await,
+        ["connected", "disconnected", "error Reopening failed, retrying: No such device", "connected", "rx back"]
+    );
+}
+
+#[tokio::test]
+async fn a_device_that_stays_away_is_recovered() {
+    let device = MockDevice::default();
+    let power_cycled = Arc::new(AtomicUsize::new(0));
+    let (reopened, cycled) = (device.clone(), power_cycled.clone());
+    let open: Opener = Box::new(move |_| {
+        let back = cycled.load(Ordering::Relaxed) > 0;
+        let port = reopened.open();
+        Box::pin(async move {
+            match back {
+                false => Err(anyhow::anyhow!("No such device")),
+                true => Ok(Box::new(port) as Box<dyn Transport>),
+            }
+        })
+    });
+    let cycled = power_cycled.clone();
+    let recover: Recover = Box::new(move || {
+        cycled.fetch_add(1, Ordering::Relaxed);
+        Box::pin(async { Ok("Power-cycled the board".to_string()) })
+    });
+    let restart = Restart { policy: RestartPolicy::OnFailure, open, recover: Some((2, recover)) };
+    let (_port, mut rx) = restarting(device.open().disconnect(), restart);
+    assert_eq!(
+        events(&mut rx, 4).await,
+        ["connected", "disconnected", "error Reopening failed, retrying: No such device", "notice Power-cycled the board"]
+    );
+    assert_eq!(events(&mut rx, 1).await, ["connected"]);
+    assert_eq!(power_cycled.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn stays_closed_without_a_restart_policy() {
+    let device = MockDevice::default();
+    let (port, mut rx) = spawn(device.open().disconnect(), RestartPolicy::Never, never());
+    assert_eq!(events(&mut rx, 2).await, ["connected", "disconnected"]);
+    assert!(matches!(time::timeout(TIMEOUT, rx.recv()).await, Ok(None)));
+    assert!(port.is_closed());
+    assert_eq!(device.opened(), 1);
+}
+
+#[tokio::test]
+async fn transient_read_errors_keep_the_port_open() {
+    let device = MockDevice::default();
+    let port = device.open().read(b"before\n").error(io::ErrorKind::TimedOut).read(b"after\n");
+    let (_port, mut rx) = spawn(port, RestartPolicy::OnFailure, reopen(&device, |port| port));
+    assert_eq!(
+        events(&mut rx, 4).await,
+        ["connected", "rx before", "error Serial read error: scripted read error", "rx after"]
+    );
+    assert_eq!(device.opened(), 1);
+}
+
+#[tokio::test]
+async fn a_failed_write_reconnects() {
+    let device = MockDevice::default();
+    let (port, mut rx) = spawn(device.open(), RestartPolicy::OnFailure, reopen(&device, |port| port));
+    device.fail_write(io::ErrorKind::BrokenPipe);
+    port.write(b"AT\r\n".to_vec());
+    assert_eq!(
+        events(&mut rx, 3).await,
+        ["connected", "error Serial write error: injected write error", "connected"]
+    );
+    port.write(b"AT\r\n".to_vec());
+    assert_eq!(events(&mut rx, 1).await, ["tx AT\r\n"]);
+    assert_eq!(device.written(), b"AT\r\n");
+}
+
+#[tokio::test]
+async fn line_errors_are_reported_as_the_driver_counts_them() {
+    let device = MockDevice::default();
+    device.set_line_errors(LineErrors::default());
+    let (port, mut rx) = spawn(device.open(), RestartPolicy::Never, never());
+    assert_eq!(events(&mut rx, 1).await, ["connected"]);
+    device.set_line_errors(LineErrors { framing: 2, overrun: 1, ..LineErrors::default() });
+    assert_eq!(events(&mut rx, 1).await, ["line errors 2 framing, 0 parity, 1 overrun, 0 break"]);
+    assert_eq!(port.counters.line_errors().framing, 2);
+}
+
+#[tokio::test]
+async fn flow_control_reaches_the_driver() {
+    let device = MockDevice::default();
+    let (port, _rx) = spawn(device.open(), RestartPolicy::Never, never());
+    port.control(Control::Flow(FlowControlArg::Hardware)).await.expect("flow control set");
+    assert_eq!(device.controls(), [Control::Flow(FlowControlArg::Hardware)]);
+    assert_eq!(port.settings.borrow().flow_control, FlowControlArg::Hardware);
+}
+
+#[tokio::test]
+async fn a_rejected_setting_keeps_the_old_one() {
+    let device = MockDevice::default();
+    device.reject_controls(true);
+    let (port, _rx) = spawn(device.open(), RestartPolicy::Never, never());
+    assert!(port.control(Control::Flow(FlowControlArg::Software)).await.is_err());
+    assert_eq!(*port.settings.borrow(), SerialSettings::default());
+    assert!(device.controls().is_empty());
+}
+
+#[tokio::test]
+async fn a_sequence_drives_the_lines_in_order_and_time() {
+    let device = MockDevice::default();
+    let (port, _rx) = spawn(device.open(), RestartPolicy::Never, never());
+    let sequence = signals::parse("dtr=0 rts=1 100ms dtr=1 rts=0").expect("valid sequence");
+    let started = Instant::now();
+    sequence.run(&port).await.expect("lines set");
+    assert!(started.elapsed() >= ms(100));
+    assert_eq!(device.controls(), [Control::Dtr(false), Control::Rts(true), Control::Dtr(true), Control::Rts(false)]);
+}
+
+#[tokio::test]
+async fn held_writes_go_out_in_order_once_released() {
+    let device = MockDevice::default();
+    let (port, mut rx) = spawn(device.open(), RestartPolicy::Never, never());
+    assert_eq!(events(&mut rx, 1).await, ["connected"]);
+    device.hold_writes(true);
+    port.write(b"one\n".to_vec());
+    port.write(b"two\n".to_vec());
+    time::sleep(ms(100)).await;
+    assert!(device.written().is_empty());
+    device.hold_writes(false);
+    assert_eq!(events(&mut rx, 2).await, ["tx one\n", "tx two\n"]);
+    assert_eq!(device.written(), b"one\ntwo\n");
+}
+
+#[tokio::test]
+async fn reopens_with_the_current_settings() {
+    let device = MockDevice::default();
+    let reopened_at = Arc::new(Mutex::new(None));
+    let (reopened, seen) = (device.clone(), reopened_at.clone());
+    let open: Opener = Box::new(move |settings| {
+        *seen.lock().expect("settings") = Some(settings.flow_control);
+        let port = reopened.open();
+        Box::pin(async move { Ok(Box::new(port) as Box<dyn Transport>) })
+    });
+    let (port, mut rx) = spawn(device.open().pause(ms(100)).disconnect(), RestartPolicy::OnFailure, open);
+    port.control(Control::Flow(FlowControlArg::Software)).await.expect("flow control set");
+    assert_eq!(events(&mut rx, 3).await, ["connected", "disconnected", "connected"]);
+    assert_eq!(*reopened_at.lock().expect("settings"), Some(FlowControlArg::Software));
+}
+
+#[tokio::test]
+async fn received_lines_are_logged_stamped_and_stripped() {
+    let dir = scratch("logged");
+    let path = dir.join("session.log").to_string_lossy().into_owned();
+    let logs = Logs {
+        all: Some(open_log(&path, &[]).await.expect("a log")),
+        filtered: None,
+        control: LogControl::Strip,
+        direction: LogDirection::Both,
+    };
+    let device = MockDevice::default();
+    let (_port, mut rx) = spawn(device.open().read(b"\x1b[32mI (12) boot: ok\x1b[0m\r\nplain\n"), RestartPolicy::Never, never());
+    let lines = received(&mut rx, 2).await;
+    for line in &lines {
+        if let SerialEvent::Rx { text, .. } = &line.event {
+            logs.write_rx(line.time, text, true).await;
+        }
+    }
+    logs.finish().await.expect("a finished log");
+    let expected: Vec<String> = lines
+        .iter()
+        .zip(["I (12) boot: ok", "plain"])
+        .map(|(line, text)| format!("[{}] {text}", line.time.to_rfc3339()))
+        .collect();
+    assert_eq!(fs::read_to_string(&path).expect("the log").lines().collect::<Vec<_>>(), expected);
+}
+
+#[tokio::test]
+async fn direction_and_filters_pick_what_is_logged() {
+    let dir = scratch("direction");
+    let (all, filtered) = (dir.join("all.log"), dir.join("filtered.log"));
+    let logs = Logs {
+        all: Some(open_log(&all.to_string_lossy(), &[]).await.expect("a log")),
+        filtered: Some(open_log(&filtered.to_string_lossy(), &[]).await.expect("a log")),
+        control: LogControl::Keep,
+        direction: LogDirection::Rx,
+    };
+    let device = MockDevice::default();
+    let (port, mut rx) = spawn(device.open().read(b"shown\nhidden\n"), RestartPolicy::Never, never());
+    port.write(b"password\n".to_vec());
+    for line in received(&mut rx, 3).await {
+        match line.event {
+            SerialEvent::Rx { text, .. } => logs.write_rx(line.time, &text, text != "hidden").await,
+            SerialEvent::Tx(data) => logs.write_tx(line.time, &String::from_utf8_lossy(&data)).await,
+            _ => {}
+        }
+    }
+    logs.finish().await.expect("finished logs");
+    let bodies = |path: &PathBuf| -> Vec<String> {
+        let text = fs::read_to_string(path).expect("the log");
+        text.lines().map(|line| line.split_once("] ").expect("a timestamp").1.to_string()).collect()
+    };
+    assert_eq!(bodies(&all), ["shown", "hidden"]);
+    assert_eq!(bodies(&filtered), ["shown"]);
+}
+
+#[tokio::test]
+async fn an_encrypted_log_decrypts_once_finished() {
+    use age::secrecy::ExposeSecret;
+
+    let dir = scratch("encrypted");
+    let identity = age::x25519::Identity::generate();
+    let key = dir.join("key.txt");
+    fs::write(&key, identity.to_string().expose_secret()).expect("a key file");
+    let path = dir.join("session.log").to_string_lossy().into_owned();
+    let logs = Logs {
+        all: Some(open_log(&path, &[identity.to_public()]).await.expect("an encrypted log")),
+        filtered: None,
+        control: LogControl::Keep,
+        direction: LogDirection::Both,
+    };
+    let device = MockDevice::default();
+    let (_port, mut rx) = spawn(device.open().read(b"secret reading 42\n"), RestartPolicy::Never, never());
+    for line in received(&mut rx, 1).await {
+        if let SerialEvent::Rx { text, .. } = &line.event {
+            logs.write_rx(line.time, text, true).await;
+        }
+    }
+    logs.finish().await.expect("a finished log");
+    let encrypted = fs::read_dir(&dir)
+        .expect("the scratch directory")
+        .map(|entry| entry.expect("an entry").path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "age"))
+        .expect("an encrypted log");
+    let mut plaintext = Vec::new();
+    logfile::decrypt(&encrypted.to_string_lossy(), &key.to_string_lossy(), &mut plaintext).expect("a readable log");
+    assert!(String::from_utf8_lossy(&plaintext).trim_end().ends_with("] secret reading 42"));
+}
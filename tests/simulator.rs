@@ -0,0 +1,34 @@
+//! Replies of the simulated device: placeholders are expanded and the text
+//! around them, braces that aren't placeholders included, is sent as written.
+
+use std::{fs, time::Duration};
+
+use serial_monitor::simulator;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time,
+};
+
+/// The lines the device playing `script` answers `ping` with.
+async fn replies(name: &str, script: &str, count: usize) -> Vec<String> {
+    let dir = std::env::temp_dir().join(format!("serial-monitor-test-{}-{name}", std::process::id()));
+    fs::create_dir_all(&dir).expect("a scratch directory");
+    let path = dir.join("device.sim");
+    fs::write(&path, script).expect("the script written");
+    let mut port = simulator::open(path.to_str().unwrap()).expect("the simulator started");
+    port.write_all(b"ping\n").await.unwrap();
+    let mut received = Vec::new();
+    let mut buf = [0u8; 256];
+    while received.iter().filter(|&&b| b == b'\n').count() < count {
+        let n = time::timeout(Duration::from_secs(5), port.read(&mut buf)).await.expect("a reply").unwrap();
+        received.extend_from_slice(&buf[..n]);
+    }
+    let _ = fs::remove_dir_all(&dir);
+    String::from_utf8(received).unwrap().lines().map(str::to_string).collect()
+}
+
+#[tokio::test]
+async fn unclosed_braces_are_sent_once() {
+    let lines = replies("braces", "on ^ping$ => {x} {counter}:{\\nabc{def\n", 2).await;
+    assert_eq!(lines, ["{x} 0:{", "abc{def"]);
+}